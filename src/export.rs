@@ -0,0 +1,300 @@
+//! Preparing constraint programs for formats other than the crate's own.
+use std::fmt;
+
+use crate::expressions::integer::{
+    BooleanIntegerNumberExpression, IntegerNumber, IntegerNumberDomainExpression,
+    IntegerNumberExpression,
+};
+use crate::expressions::tuple::{TupleComponent, TupleExpression};
+use crate::expressions::{ConstraintLogicExpression, ConstraintProgramExpression};
+
+mod cnf;
+mod linear;
+mod smtlib;
+
+pub use cnf::{cnf_size_estimate, from_dimacs, to_dimacs, CnfError, CnfEstimate, CnfSize};
+pub use linear::{to_linear_system, LinearError, LinearSystem, Relation};
+pub use smtlib::{to_smtlib, to_smtlib_with};
+
+/// How `eliminate_nan` deals with operations that can evaluate to `NaN`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NanExportPolicy {
+    /// Add constraints ruling out the operands for which they do.
+    GuardDivisions,
+    /// Refuse programs containing any such operation.
+    Reject,
+}
+
+/// Reasons a program cannot be exported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportError {
+    /// The program contains a `NaN` literal, which no guard can remove.
+    NanLiteral,
+    /// The expression can evaluate to `NaN` and the policy forbids it.
+    NanProducing(IntegerNumberExpression),
+    /// A construct the format cannot express, as written in the program.
+    Unsupported(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::NanLiteral => write!(f, "the program contains a NaN literal"),
+            ExportError::NanProducing(expr) => write!(f, "`{}` can evaluate to NaN", expr),
+            ExportError::Unsupported(construct) => write!(f, "`{}` cannot be exported", construct),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+pub(crate) fn visit_program<'a>(
+    program: &'a ConstraintProgramExpression,
+    visit: &mut impl FnMut(&'a IntegerNumberExpression),
+) {
+    for constraint in crate::solver::constraints(program) {
+        match constraint {
+            ConstraintLogicExpression::Boolean(_) | ConstraintLogicExpression::OfRational(_) => (),
+            ConstraintLogicExpression::OfIntegerNumber(expr) => visit_comparison(expr, visit),
+            ConstraintLogicExpression::AllDifferent(exprs) => {
+                for expr in exprs {
+                    visit_integer(expr, visit);
+                }
+            }
+        }
+    }
+}
+
+fn visit_comparison<'a>(
+    expr: &'a BooleanIntegerNumberExpression,
+    visit: &mut impl FnMut(&'a IntegerNumberExpression),
+) {
+    use BooleanIntegerNumberExpression::*;
+    match expr {
+        Equals(expr_a, expr_b)
+        | Different(expr_a, expr_b)
+        | Greater(expr_a, expr_b)
+        | Less(expr_a, expr_b) => {
+            visit_integer(expr_a, visit);
+            visit_integer(expr_b, visit);
+        }
+        In(expr, domain) => {
+            visit_integer(expr, visit);
+            visit_domain(domain, visit);
+        }
+    }
+}
+
+fn visit_domain<'a>(
+    expr: &'a IntegerNumberDomainExpression,
+    visit: &mut impl FnMut(&'a IntegerNumberExpression),
+) {
+    use IntegerNumberDomainExpression::*;
+    match expr {
+        Universe | Empty => (),
+        ClosedRange(expr_a, expr_b)
+        | OpenRange(expr_a, expr_b)
+        | OpenLeftClosedRightRange(expr_a, expr_b)
+        | ClosedLeftOpenRightRange(expr_a, expr_b) => {
+            visit_integer(expr_a, visit);
+            visit_integer(expr_b, visit);
+        }
+        ExplicitSet(elements) => {
+            for element in elements {
+                visit_integer(element, visit);
+            }
+        }
+        Union(expr_a, expr_b) | Intersection(expr_a, expr_b) | Difference(expr_a, expr_b) => {
+            visit_domain(expr_a, visit);
+            visit_domain(expr_b, visit);
+        }
+        Complement(expr) => visit_domain(expr, visit),
+    }
+}
+
+/// Calls `visit` with every integer subexpression, outermost first.
+fn visit_integer<'a>(
+    expr: &'a IntegerNumberExpression,
+    visit: &mut impl FnMut(&'a IntegerNumberExpression),
+) {
+    use IntegerNumberExpression::*;
+    visit(expr);
+    match expr {
+        Add(expr_a, expr_b)
+        | Minus(expr_a, expr_b)
+        | Times(expr_a, expr_b)
+        | Divide(expr_a, expr_b)
+        | Modulo(expr_a, expr_b)
+        | Pow(expr_a, expr_b)
+        | Let(_, expr_a, expr_b)
+        | IfThenElse(_, expr_a, expr_b) => {
+            visit_integer(expr_a, visit);
+            visit_integer(expr_b, visit);
+        }
+        PowMod(expr_a, expr_b, expr_c) => {
+            visit_integer(expr_a, visit);
+            visit_integer(expr_b, visit);
+            visit_integer(expr_c, visit);
+        }
+        Negate(expr) | Parenthesis(expr) | Abs(expr) => visit_integer(expr, visit),
+        Sum(exprs) | Product(exprs) => {
+            for expr in exprs {
+                visit_integer(expr, visit);
+            }
+        }
+        TupleGet(tuple, _) => visit_tuple(tuple, visit),
+        IntegerNumberValue(_) | IntegerNumberVariable(_) | OfBoolean(_) => (),
+    }
+}
+
+fn visit_tuple<'a>(expr: &'a TupleExpression, visit: &mut impl FnMut(&'a IntegerNumberExpression)) {
+    let TupleExpression::Tuple(components) = expr else {
+        return;
+    };
+    for component in components {
+        match component {
+            TupleComponent::Integer(expr) => visit_integer(expr, visit),
+            TupleComponent::Tuple(expr) => visit_tuple(expr, visit),
+            TupleComponent::Boolean(_) | TupleComponent::Rational(_) => (),
+        }
+    }
+}
+
+fn literal(expr: &IntegerNumberExpression) -> Option<i128> {
+    match expr {
+        IntegerNumberExpression::IntegerNumberValue(IntegerNumber::Value(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+fn constant(n: i128) -> Box<IntegerNumberExpression> {
+    Box::new(IntegerNumberExpression::IntegerNumberValue(
+        IntegerNumber::Value(n),
+    ))
+}
+
+/// The constraints under which `expr` itself cannot be `NaN`, given
+/// operands that are not. Overflow is not covered.
+fn guards(
+    expr: &IntegerNumberExpression,
+    divisions_only: bool,
+) -> Vec<BooleanIntegerNumberExpression> {
+    use BooleanIntegerNumberExpression::*;
+    use IntegerNumberExpression::*;
+    let nonzero = |divisor: &IntegerNumberExpression| match literal(divisor) {
+        Some(n) if n != 0 => None,
+        _ => Some(Different(Box::new(divisor.clone()), constant(0))),
+    };
+    let non_negative = |exponent: &IntegerNumberExpression| match literal(exponent) {
+        Some(n) if n >= 0 => None,
+        _ => Some(Greater(Box::new(exponent.clone()), constant(-1))),
+    };
+    match expr {
+        Divide(_, divisor) | Modulo(_, divisor) => nonzero(divisor).into_iter().collect(),
+        Pow(_, exponent) if !divisions_only => non_negative(exponent).into_iter().collect(),
+        PowMod(_, exponent, modulus) if !divisions_only => {
+            let positive = match literal(modulus) {
+                Some(n) if n > 0 => None,
+                _ => Some(Greater(Box::new((**modulus).clone()), constant(0))),
+            };
+            non_negative(exponent).into_iter().chain(positive).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn guarded(
+    program: ConstraintProgramExpression,
+    divisions_only: bool,
+) -> ConstraintProgramExpression {
+    let mut found: Vec<BooleanIntegerNumberExpression> = Vec::new();
+    visit_program(&program, &mut |expr| {
+        for guard in guards(expr, divisions_only) {
+            if !found.contains(&guard) {
+                found.push(guard);
+            }
+        }
+    });
+    found.into_iter().rev().fold(program, |rest, guard| {
+        ConstraintProgramExpression::ConstrainAnd(
+            Box::new(ConstraintLogicExpression::OfIntegerNumber(Box::new(guard))),
+            Box::new(rest),
+        )
+    })
+}
+
+/// Prefixes the program with a `d != 0` constraint for every distinct
+/// divisor `d` of a `/` or `%` that is not a non-zero literal. The
+/// guards apply to the whole program, so a division inside a branch
+/// that would otherwise not matter still excludes a zero divisor.
+pub fn guard_divisions(program: ConstraintProgramExpression) -> ConstraintProgramExpression {
+    guarded(program, true)
+}
+
+/// Rewrites the program so that none of its operations can produce
+/// `NaN`, for export to formats without it. Under `GuardDivisions`
+/// divisions are guarded as by `guard_divisions`, and powers gain the
+/// constraints that their exponent is not negative and their modulus
+/// positive; under `Reject` an operation that would need such a guard
+/// is an error, while `x / 2` or `x ^ 2` are left as they are. `NaN`
+/// literals are an error under either policy. Overflow of `i128` is not
+/// considered: formats with unbounded integers have none.
+pub fn eliminate_nan(
+    program: ConstraintProgramExpression,
+    policy: NanExportPolicy,
+) -> Result<ConstraintProgramExpression, ExportError> {
+    use IntegerNumberExpression::*;
+    let mut error = None;
+    visit_program(&program, &mut |expr| {
+        if error.is_some() {
+            return;
+        }
+        match expr {
+            IntegerNumberValue(IntegerNumber::NaN) => error = Some(ExportError::NanLiteral),
+            _ if policy == NanExportPolicy::Reject && !guards(expr, false).is_empty() => {
+                error = Some(ExportError::NanProducing(expr.clone()))
+            }
+            _ => (),
+        }
+    });
+    match error {
+        Some(error) => Err(error),
+        None if policy == NanExportPolicy::Reject => Ok(program),
+        None => Ok(guarded(program, false)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{eliminate_nan, ExportError, NanExportPolicy};
+    use crate::expressions::parser::{parse_program, to_source};
+
+    #[test]
+    fn divisions_gain_guards() {
+        let program = parse_program("x % 3 = 1;\nx in [0, 9 / z];\nsatisfy x / y = 2;").unwrap();
+        let guarded = eliminate_nan(program, NanExportPolicy::GuardDivisions).unwrap();
+        assert_eq!(
+            to_source(&guarded),
+            "z != 0;\ny != 0;\nx % 3 = 1;\nx in [0, 9 / z];\nsatisfy x / y = 2;"
+        );
+    }
+
+    #[test]
+    fn reject_refuses_divisions_and_any_policy_nan_literals() {
+        let program = parse_program("satisfy x / y = 2;").unwrap();
+        assert!(matches!(
+            eliminate_nan(program, NanExportPolicy::Reject),
+            Err(ExportError::NanProducing(_))
+        ));
+        let program = parse_program("x % 3 = 1;\nsatisfy x / 2 = x ^ 2;").unwrap();
+        assert_eq!(
+            eliminate_nan(program.clone(), NanExportPolicy::Reject),
+            Ok(program)
+        );
+        let program = parse_program("satisfy x = NaN;").unwrap();
+        assert_eq!(
+            eliminate_nan(program, NanExportPolicy::GuardDivisions).err(),
+            Some(ExportError::NanLiteral)
+        );
+    }
+}
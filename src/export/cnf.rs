@@ -0,0 +1,503 @@
+//! Conjunctive normal form of boolean expressions.
+use std::fmt;
+
+use crate::expressions::boolean::{BooleanExpression, BooleanValue};
+use crate::expressions::{
+    ConstraintLogicExpression, ConstraintProgramExpression, FreeVariable, Symbol,
+};
+
+/// The size of a formula in conjunctive normal form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CnfSize {
+    /// Saturates at `u128::MAX`.
+    pub clauses: u128,
+    pub variables: u128,
+}
+
+/// The size of the CNF of an expression under each conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CnfEstimate {
+    /// Pushing negations to the variables and distributing `or` over
+    /// `and`, which needs no new variables but can grow exponentially.
+    pub naive: CnfSize,
+    /// Naming every connective with a fresh variable, which grows
+    /// linearly.
+    pub tseitin: CnfSize,
+}
+
+/// The clause counts of the naive CNF of `expr` and of its negation.
+fn distributed(expr: &BooleanExpression) -> (u128, u128) {
+    use BooleanExpression::*;
+    match expr {
+        And(expr_a, expr_b) => {
+            let ((pos_a, neg_a), (pos_b, neg_b)) = (distributed(expr_a), distributed(expr_b));
+            (pos_a.saturating_add(pos_b), neg_a.saturating_mul(neg_b))
+        }
+        Or(expr_a, expr_b) => {
+            let ((pos_a, neg_a), (pos_b, neg_b)) = (distributed(expr_a), distributed(expr_b));
+            (pos_a.saturating_mul(pos_b), neg_a.saturating_add(neg_b))
+        }
+        Implies(expr_a, expr_b) => {
+            let ((pos_a, neg_a), (pos_b, neg_b)) = (distributed(expr_a), distributed(expr_b));
+            (neg_a.saturating_mul(pos_b), pos_a.saturating_add(neg_b))
+        }
+        Equals(expr_a, expr_b) => {
+            let ((pos_a, neg_a), (pos_b, neg_b)) = (distributed(expr_a), distributed(expr_b));
+            (
+                neg_a
+                    .saturating_mul(pos_b)
+                    .saturating_add(neg_b.saturating_mul(pos_a)),
+                pos_a
+                    .saturating_mul(pos_b)
+                    .saturating_add(neg_a.saturating_mul(neg_b)),
+            )
+        }
+        Xor(expr_a, expr_b) => {
+            let ((pos_a, neg_a), (pos_b, neg_b)) = (distributed(expr_a), distributed(expr_b));
+            (
+                pos_a
+                    .saturating_mul(pos_b)
+                    .saturating_add(neg_a.saturating_mul(neg_b)),
+                neg_a
+                    .saturating_mul(pos_b)
+                    .saturating_add(neg_b.saturating_mul(pos_a)),
+            )
+        }
+        // `ite(c, a, b)` is `(not c or a) and (c or b)`.
+        IfThenElse(condition, then, otherwise) => {
+            let (pos_c, neg_c) = distributed(condition);
+            let ((pos_a, neg_a), (pos_b, neg_b)) = (distributed(then), distributed(otherwise));
+            (
+                neg_c
+                    .saturating_mul(pos_a)
+                    .saturating_add(pos_c.saturating_mul(pos_b)),
+                neg_c
+                    .saturating_mul(neg_a)
+                    .saturating_add(pos_c.saturating_mul(neg_b)),
+            )
+        }
+        Parenthesis(expr) => distributed(expr),
+        Not(expr) => {
+            let (pos, neg) = distributed(expr);
+            (neg, pos)
+        }
+        BooleanVariable(_) => (1, 1),
+        // `true` is the empty conjunction and `false` the empty clause.
+        BooleanValue(self::BooleanValue::True) => (0, 1),
+        BooleanValue(self::BooleanValue::False) => (1, 0),
+    }
+}
+
+/// The fresh variables and clauses the Tseitin encoding of `expr`
+/// defines, not counting the unit clause asserting the root.
+fn named(expr: &BooleanExpression) -> (u128, u128) {
+    use BooleanExpression::*;
+    let binary = |expr_a, expr_b, clauses| {
+        let ((vars_a, clauses_a), (vars_b, clauses_b)) = (named(expr_a), named(expr_b));
+        (vars_a + vars_b + 1, clauses_a + clauses_b + clauses)
+    };
+    match expr {
+        And(expr_a, expr_b) | Or(expr_a, expr_b) | Implies(expr_a, expr_b) => {
+            binary(expr_a, expr_b, 3)
+        }
+        Equals(expr_a, expr_b) | Xor(expr_a, expr_b) => binary(expr_a, expr_b, 4),
+        IfThenElse(condition, then, otherwise) => {
+            let (vars_c, clauses_c) = named(condition);
+            let (vars, clauses) = binary(then, otherwise, 4);
+            (vars_c + vars, clauses_c + clauses)
+        }
+        // Negation flips the literal naming the operand.
+        Parenthesis(expr) | Not(expr) => named(expr),
+        BooleanVariable(_) => (0, 0),
+        // A constant is a fresh variable fixed by a unit clause.
+        BooleanValue(_) => (1, 1),
+    }
+}
+
+/// Estimates the size of the CNF of `expr` under the naive and the
+/// Tseitin conversion without building either, so callers can choose
+/// between them or give up before converting.
+pub fn cnf_size_estimate(expr: &BooleanExpression) -> CnfEstimate {
+    let mut names: Vec<String> = expr
+        .get_free()
+        .iter()
+        .map(|variable| variable.name().name().to_string())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    let variables = names.len() as u128;
+    let (fresh, definitions) = named(expr);
+    CnfEstimate {
+        naive: CnfSize {
+            clauses: distributed(expr).0,
+            variables,
+        },
+        tseitin: CnfSize {
+            clauses: definitions + 1,
+            variables: variables + fresh,
+        },
+    }
+}
+
+/// Reasons DIMACS input could not be read. Lines are counted from one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CnfError {
+    /// A clause came before the `p cnf` header, or there was none.
+    MissingHeader,
+    /// The header is not of the form `p cnf <variables> <clauses>`.
+    BadHeader(usize),
+    /// A token that is not an integer literal.
+    BadLiteral { line: usize, token: String },
+    /// A literal naming a variable beyond the count in the header.
+    UnknownVariable { line: usize, variable: u64 },
+    /// The input ended inside a clause, before its terminating `0`.
+    UnterminatedClause,
+    /// The number of clauses differs from the count in the header.
+    ClauseCount { expected: usize, found: usize },
+    /// A constraint over integers, which has no CNF.
+    Unsupported(ConstraintLogicExpression),
+}
+
+impl fmt::Display for CnfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CnfError::MissingHeader => write!(f, "missing `p cnf` header"),
+            CnfError::BadHeader(line) => write!(f, "line {}: malformed `p cnf` header", line),
+            CnfError::BadLiteral { line, token } => {
+                write!(f, "line {}: `{}` is not a literal", line, token)
+            }
+            CnfError::UnknownVariable { line, variable } => {
+                write!(f, "line {}: variable {} is not declared", line, variable)
+            }
+            CnfError::UnterminatedClause => write!(f, "the last clause is not terminated by 0"),
+            CnfError::ClauseCount { expected, found } => {
+                write!(f, "expected {} clauses, found {}", expected, found)
+            }
+            CnfError::Unsupported(constraint) => {
+                write!(f, "`{}` is not a boolean constraint", constraint)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CnfError {}
+
+/// Reads a CNF instance in DIMACS format as a conjunction of clauses,
+/// each a disjunction of literals, both grouped to the left. Variable `n`
+/// becomes the boolean variable `vn` and a negative literal its `not`.
+/// Comment lines starting with `c` are skipped and a line starting with
+/// `%` ends the input, as in the SATLIB benchmarks. An empty clause is
+/// `false` and an instance without clauses `true`.
+pub fn from_dimacs(input: &str) -> Result<BooleanExpression, CnfError> {
+    use BooleanExpression::*;
+    let mut header: Option<(u64, usize)> = None;
+    let mut clauses: Vec<BooleanExpression> = Vec::new();
+    let mut clause: Vec<BooleanExpression> = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+        if line.starts_with('%') {
+            break;
+        }
+        if line.starts_with('p') {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            header = match fields[..] {
+                ["p", "cnf", variables, count] if header.is_none() => {
+                    match (variables.parse(), count.parse()) {
+                        (Ok(variables), Ok(count)) => Some((variables, count)),
+                        _ => return Err(CnfError::BadHeader(number)),
+                    }
+                }
+                _ => return Err(CnfError::BadHeader(number)),
+            };
+            continue;
+        }
+        let (variables, _) = header.ok_or(CnfError::MissingHeader)?;
+        for token in line.split_whitespace() {
+            let literal: i64 = token.parse().map_err(|_| CnfError::BadLiteral {
+                line: number,
+                token: token.to_string(),
+            })?;
+            if literal == 0 {
+                let disjunction = clause
+                    .drain(..)
+                    .reduce(|expr_a, expr_b| Or(Box::new(expr_a), Box::new(expr_b)));
+                clauses.push(disjunction.unwrap_or(BooleanValue(self::BooleanValue::False)));
+                continue;
+            }
+            let variable = literal.unsigned_abs();
+            if variable > variables {
+                return Err(CnfError::UnknownVariable {
+                    line: number,
+                    variable,
+                });
+            }
+            let atom = BooleanVariable(Symbol::new(format!("v{}", variable)));
+            clause.push(if literal < 0 {
+                Not(Box::new(atom))
+            } else {
+                atom
+            });
+        }
+    }
+    let (_, expected) = header.ok_or(CnfError::MissingHeader)?;
+    if !clause.is_empty() {
+        return Err(CnfError::UnterminatedClause);
+    }
+    if clauses.len() != expected {
+        return Err(CnfError::ClauseCount {
+            expected,
+            found: clauses.len(),
+        });
+    }
+    Ok(clauses
+        .into_iter()
+        .reduce(|expr_a, expr_b| And(Box::new(expr_a), Box::new(expr_b)))
+        .unwrap_or(BooleanValue(self::BooleanValue::True)))
+}
+
+/// The Tseitin encoding of a program: clauses over numbered variables,
+/// where the named variables come first.
+struct Encoding {
+    names: Vec<Symbol>,
+    next: u64,
+    clauses: Vec<Vec<i64>>,
+}
+
+impl Encoding {
+    fn fresh(&mut self) -> i64 {
+        self.next += 1;
+        self.next as i64
+    }
+
+    /// A literal equivalent to `expr`, defined by the clauses added for
+    /// its connectives.
+    fn literal(&mut self, expr: &BooleanExpression) -> i64 {
+        use BooleanExpression::*;
+        match expr {
+            BooleanVariable(symbol) => {
+                let index = self.names.iter().position(|name| name == symbol);
+                index.expect("every variable is numbered") as i64 + 1
+            }
+            Parenthesis(expr) => self.literal(expr),
+            Not(expr) => -self.literal(expr),
+            BooleanValue(value) => {
+                let name = self.fresh();
+                let unit = match value {
+                    self::BooleanValue::True => name,
+                    self::BooleanValue::False => -name,
+                };
+                self.clauses.push(vec![unit]);
+                name
+            }
+            And(expr_a, expr_b) => {
+                let (a, b) = (self.literal(expr_a), self.literal(expr_b));
+                let name = self.fresh();
+                self.clauses.push(vec![-name, a]);
+                self.clauses.push(vec![-name, b]);
+                self.clauses.push(vec![name, -a, -b]);
+                name
+            }
+            Or(expr_a, expr_b) => {
+                let (a, b) = (self.literal(expr_a), self.literal(expr_b));
+                let name = self.fresh();
+                self.clauses.push(vec![name, -a]);
+                self.clauses.push(vec![name, -b]);
+                self.clauses.push(vec![-name, a, b]);
+                name
+            }
+            Implies(expr_a, expr_b) => {
+                let (a, b) = (self.literal(expr_a), self.literal(expr_b));
+                let name = self.fresh();
+                self.clauses.push(vec![name, a]);
+                self.clauses.push(vec![name, -b]);
+                self.clauses.push(vec![-name, -a, b]);
+                name
+            }
+            Equals(expr_a, expr_b) => {
+                let (a, b) = (self.literal(expr_a), self.literal(expr_b));
+                let name = self.fresh();
+                self.clauses.push(vec![-name, -a, b]);
+                self.clauses.push(vec![-name, a, -b]);
+                self.clauses.push(vec![name, a, b]);
+                self.clauses.push(vec![name, -a, -b]);
+                name
+            }
+            Xor(expr_a, expr_b) => {
+                let (a, b) = (self.literal(expr_a), self.literal(expr_b));
+                let name = self.fresh();
+                self.clauses.push(vec![-name, a, b]);
+                self.clauses.push(vec![-name, -a, -b]);
+                self.clauses.push(vec![name, -a, b]);
+                self.clauses.push(vec![name, a, -b]);
+                name
+            }
+            IfThenElse(condition, then, otherwise) => {
+                let c = self.literal(condition);
+                let (a, b) = (self.literal(then), self.literal(otherwise));
+                let name = self.fresh();
+                self.clauses.push(vec![-name, -c, a]);
+                self.clauses.push(vec![-name, c, b]);
+                self.clauses.push(vec![name, -c, -a]);
+                self.clauses.push(vec![name, c, -b]);
+                name
+            }
+        }
+    }
+}
+
+/// Writes the constraints of a boolean program in DIMACS format, by the
+/// Tseitin encoding that `cnf_size_estimate` counts, asserting each
+/// constraint with a unit clause. The named variables are numbered from
+/// 1 in the order they first appear, and a comment line `c <n> <name>`
+/// before the header records each, so that a model can be mapped back;
+/// the variables after them name connectives. Goals count as
+/// constraints, as they do for `to_linear_system`.
+pub fn to_dimacs(program: &ConstraintProgramExpression) -> Result<String, CnfError> {
+    let mut formulas = Vec::new();
+    for constraint in crate::solver::constraints(program) {
+        match constraint {
+            ConstraintLogicExpression::Boolean(expr) => formulas.push(expr.as_ref()),
+            ConstraintLogicExpression::OfIntegerNumber(_)
+            | ConstraintLogicExpression::OfRational(_)
+            | ConstraintLogicExpression::AllDifferent(_) => {
+                return Err(CnfError::Unsupported(constraint.clone()))
+            }
+        }
+    }
+    let mut names: Vec<Symbol> = Vec::new();
+    for variable in formulas.iter().flat_map(|expr| expr.get_free()) {
+        if !names.contains(variable.name()) {
+            names.push(variable.name().clone());
+        }
+    }
+    let mut encoding = Encoding {
+        next: names.len() as u64,
+        names,
+        clauses: Vec::new(),
+    };
+    for expr in formulas {
+        let root = encoding.literal(expr);
+        encoding.clauses.push(vec![root]);
+    }
+    let mut text = String::new();
+    for (index, name) in encoding.names.iter().enumerate() {
+        text.push_str(&format!("c {} {}\n", index + 1, name));
+    }
+    text.push_str(&format!(
+        "p cnf {} {}\n",
+        encoding.next,
+        encoding.clauses.len()
+    ));
+    for clause in &encoding.clauses {
+        for literal in clause {
+            text.push_str(&format!("{} ", literal));
+        }
+        text.push_str("0\n");
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cnf_size_estimate, from_dimacs, CnfError, CnfSize};
+    use crate::expressions::parser::parse_program;
+    use crate::expressions::ConstraintLogicExpression;
+
+    #[test]
+    fn distributing_or_over_and_blows_up() {
+        let terms: Vec<String> = (0..10).map(|i| format!("(a{} and b{})", i, i)).collect();
+        let program = parse_program(&format!("satisfy {};", terms.join(" or "))).unwrap();
+        let ConstraintLogicExpression::Boolean(expr) = crate::solver::constraints(&program)[0]
+        else {
+            panic!("expected a boolean constraint");
+        };
+        let estimate = cnf_size_estimate(expr);
+        assert_eq!(
+            estimate.naive,
+            CnfSize {
+                clauses: 1 << 10,
+                variables: 20
+            }
+        );
+        assert_eq!(
+            estimate.tseitin,
+            CnfSize {
+                clauses: 3 * 19 + 1,
+                variables: 20 + 19
+            }
+        );
+    }
+
+    #[test]
+    fn dimacs_clauses_become_a_conjunction_of_disjunctions() {
+        let input = "c a small instance\np cnf 3 3\n1 -3 0\n2 3\n-1 0\n-2 0\n";
+        let expr = from_dimacs(input).unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "(v1 or not v3) and (v2 or v3 or not v1) and not v2"
+        );
+        assert_eq!(cnf_size_estimate(&expr).naive.clauses, 3);
+        assert_eq!(cnf_size_estimate(&expr).naive.variables, 3);
+        assert_eq!(
+            from_dimacs("p cnf 2 1\n1 3 0\n"),
+            Err(CnfError::UnknownVariable {
+                line: 2,
+                variable: 3
+            })
+        );
+        assert_eq!(
+            from_dimacs("p cnf 2 2\n1 2 0\n"),
+            Err(CnfError::ClauseCount {
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn dimacs_export_keeps_the_models() {
+        use super::to_dimacs;
+        use crate::expressions::{AssignedValue, Assignment, Symbol};
+        use crate::solver::{
+            count_models, ground, is_satisfied_ground, program_of, solve, Solution,
+        };
+        let program =
+            parse_program("p or not q;\nq implies r;\nsatisfy not (p and r) equals q;").unwrap();
+        let dimacs = to_dimacs(&program).unwrap();
+        let names: Vec<(String, String)> = dimacs
+            .lines()
+            .filter_map(|line| line.strip_prefix("c "))
+            .map(|line| {
+                let (number, name) = line.split_once(' ').unwrap();
+                (format!("v{}", number), name.to_string())
+            })
+            .collect();
+        assert_eq!(names.len(), 3);
+        let cnf = program_of(vec![ConstraintLogicExpression::Boolean(Box::new(
+            from_dimacs(&dimacs).unwrap(),
+        ))]);
+        assert_eq!(count_models(&cnf), count_models(&program));
+        let model: Vec<Assignment> = solve(cnf)
+            .into_iter()
+            .filter_map(|binding| match binding {
+                Solution::Variable(symbol, value @ AssignedValue::Boolean(_)) => names
+                    .iter()
+                    .find(|(number, _)| number == symbol.name())
+                    .map(|(_, name)| Assignment::new(Symbol::new(name.clone()), value)),
+                _ => None,
+            })
+            .collect();
+        assert!(is_satisfied_ground(&ground(&program, &model)));
+        let mixed = parse_program("p;\nsatisfy x > 1;").unwrap();
+        assert!(matches!(
+            to_dimacs(&mixed),
+            Err(CnfError::Unsupported(
+                ConstraintLogicExpression::OfIntegerNumber(_)
+            ))
+        ));
+    }
+}
@@ -0,0 +1,347 @@
+//! Linear integer constraints as a sparse coefficient matrix.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::expressions::integer::{
+    BooleanIntegerNumberExpression, IntegerNumber, IntegerNumberDomainExpression,
+    IntegerNumberExpression,
+};
+use crate::expressions::{
+    ConstraintLogicExpression, ConstraintProgramExpression, Evaluate, Symbol,
+};
+
+/// How the left hand side of a row compares to its right hand side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+}
+
+/// Rows of the form `sum(coefficient * variable) relation rhs`. Row `i`
+/// is `coefficients[i]`, `relations[i]` and `rhs[i]`; a coefficient is
+/// given as a column and a value, and column `j` is `variables[j]`.
+/// Columns without an entry in a row have coefficient zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinearSystem {
+    pub variables: Vec<Symbol>,
+    pub coefficients: Vec<Vec<(usize, i128)>>,
+    pub relations: Vec<Relation>,
+    pub rhs: Vec<i128>,
+}
+
+/// Reasons a program has no linear system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinearError {
+    /// The constraint multiplies variables together or applies a
+    /// non-linear operation to one.
+    NonLinear(ConstraintLogicExpression),
+    /// The constraint is not a comparison a row can express, such as a
+    /// boolean formula, `!=`, or membership of a domain other than a range.
+    Unsupported(ConstraintLogicExpression),
+    /// A coefficient or constant does not fit in an `i128`.
+    Overflow(ConstraintLogicExpression),
+}
+
+impl fmt::Display for LinearError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinearError::NonLinear(constraint) => write!(f, "`{}` is not linear", constraint),
+            LinearError::Unsupported(constraint) => {
+                write!(f, "`{}` is not a linear relation", constraint)
+            }
+            LinearError::Overflow(constraint) => {
+                write!(f, "the coefficients of `{}` overflow", constraint)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LinearError {}
+
+/// Why a single expression has no linear form.
+enum Failure {
+    NonLinear,
+    Overflow,
+}
+
+/// `sum(terms[v] * v) + constant`.
+struct Linear {
+    terms: BTreeMap<Symbol, i128>,
+    constant: i128,
+}
+
+impl Linear {
+    fn constant(n: i128) -> Linear {
+        Linear {
+            terms: BTreeMap::new(),
+            constant: n,
+        }
+    }
+
+    fn scaled(self, factor: i128) -> Result<Linear, Failure> {
+        let mut terms = BTreeMap::new();
+        for (symbol, coefficient) in self.terms {
+            terms.insert(
+                symbol,
+                coefficient.checked_mul(factor).ok_or(Failure::Overflow)?,
+            );
+        }
+        Ok(Linear {
+            terms,
+            constant: self.constant.checked_mul(factor).ok_or(Failure::Overflow)?,
+        })
+    }
+
+    fn plus(mut self, other: Linear) -> Result<Linear, Failure> {
+        for (symbol, coefficient) in other.terms {
+            let sum = self
+                .terms
+                .get(&symbol)
+                .unwrap_or(&0)
+                .checked_add(coefficient);
+            self.terms.insert(symbol, sum.ok_or(Failure::Overflow)?);
+        }
+        self.constant = self
+            .constant
+            .checked_add(other.constant)
+            .ok_or(Failure::Overflow)?;
+        Ok(self)
+    }
+
+    fn minus(self, other: Linear) -> Result<Linear, Failure> {
+        self.plus(other.scaled(-1)?)
+    }
+}
+
+fn linear(expr: &IntegerNumberExpression) -> Result<Linear, Failure> {
+    use IntegerNumberExpression::*;
+    match expr {
+        IntegerNumberVariable(symbol) => Ok(Linear {
+            terms: BTreeMap::from([(symbol.clone(), 1)]),
+            constant: 0,
+        }),
+        Add(expr_a, expr_b) => linear(expr_a)?.plus(linear(expr_b)?),
+        Sum(exprs) => exprs
+            .iter()
+            .try_fold(Linear::constant(0), |sum, expr| sum.plus(linear(expr)?)),
+        Minus(expr_a, expr_b) => linear(expr_a)?.minus(linear(expr_b)?),
+        Negate(expr) => linear(expr)?.scaled(-1),
+        Parenthesis(expr) => linear(expr),
+        Times(expr_a, expr_b) => {
+            let (expr_a, expr_b) = (linear(expr_a)?, linear(expr_b)?);
+            if expr_a.terms.is_empty() {
+                expr_b.scaled(expr_a.constant)
+            } else if expr_b.terms.is_empty() {
+                expr_a.scaled(expr_b.constant)
+            } else {
+                Err(Failure::NonLinear)
+            }
+        }
+        Product(exprs) => exprs.iter().try_fold(Linear::constant(1), |product, expr| {
+            let factor = linear(expr)?;
+            if product.terms.is_empty() {
+                factor.scaled(product.constant)
+            } else if factor.terms.is_empty() {
+                product.scaled(factor.constant)
+            } else {
+                Err(Failure::NonLinear)
+            }
+        }),
+        // Anything else is linear only when it is a constant.
+        _ => match expr.evaluate(&[]) {
+            Ok(IntegerNumber::Value(n)) => Ok(Linear::constant(n)),
+            _ => Err(Failure::NonLinear),
+        },
+    }
+}
+
+type Rows<'e> = Vec<(
+    &'e IntegerNumberExpression,
+    &'e IntegerNumberExpression,
+    Relation,
+    i128,
+)>;
+
+/// The rows expressing one constraint as `a - b relation rhs`.
+fn rows<'e>(constraint: &'e ConstraintLogicExpression) -> Option<Rows<'e>> {
+    use BooleanIntegerNumberExpression::*;
+    use IntegerNumberDomainExpression::*;
+    use Relation::*;
+    let ConstraintLogicExpression::OfIntegerNumber(expr) = constraint else {
+        return None;
+    };
+    Some(
+        match expr.as_ref() {
+            Equals(expr_a, expr_b) => vec![(expr_a, expr_b, Equal, 0)],
+            Less(expr_a, expr_b) => vec![(expr_a, expr_b, LessOrEqual, -1)],
+            Greater(expr_a, expr_b) => vec![(expr_a, expr_b, GreaterOrEqual, 1)],
+            In(expr, domain) => match domain.as_ref() {
+                ClosedRange(low, high) => {
+                    vec![(expr, low, GreaterOrEqual, 0), (expr, high, LessOrEqual, 0)]
+                }
+                OpenRange(low, high) => vec![
+                    (expr, low, GreaterOrEqual, 1),
+                    (expr, high, LessOrEqual, -1),
+                ],
+                OpenLeftClosedRightRange(low, high) => {
+                    vec![(expr, low, GreaterOrEqual, 1), (expr, high, LessOrEqual, 0)]
+                }
+                ClosedLeftOpenRightRange(low, high) => {
+                    vec![
+                        (expr, low, GreaterOrEqual, 0),
+                        (expr, high, LessOrEqual, -1),
+                    ]
+                }
+                _ => return None,
+            },
+            Different(_, _) => return None,
+        }
+        .into_iter()
+        .map(|(expr_a, expr_b, relation, rhs)| (expr_a.as_ref(), expr_b.as_ref(), relation, rhs))
+        .collect(),
+    )
+}
+
+/// Extracts the constraints of the program, in `constraints` order, as
+/// a linear system. `=`, `<` and `>` each give one row, with the strict
+/// comparisons tightened by one, and membership of a range gives a row
+/// per bound. Multiplication and division of constants are folded, so
+/// `2 * (x + 3)` is linear while `x * y` and `x / 2` are not.
+pub fn to_linear_system(
+    program: &ConstraintProgramExpression,
+) -> Result<LinearSystem, LinearError> {
+    let mut system = LinearSystem {
+        variables: Vec::new(),
+        coefficients: Vec::new(),
+        relations: Vec::new(),
+        rhs: Vec::new(),
+    };
+    for constraint in crate::solver::constraints(program) {
+        let rows = rows(constraint).ok_or_else(|| LinearError::Unsupported(constraint.clone()))?;
+        for (expr_a, expr_b, relation, rhs) in rows {
+            let row = linear(expr_a)
+                .and_then(|expr_a| expr_a.minus(linear(expr_b)?))
+                .and_then(|row| {
+                    let rhs = rhs.checked_sub(row.constant).ok_or(Failure::Overflow)?;
+                    Ok((row, rhs))
+                });
+            let (row, rhs) = match row {
+                Ok(row) => row,
+                Err(Failure::NonLinear) => return Err(LinearError::NonLinear(constraint.clone())),
+                Err(Failure::Overflow) => return Err(LinearError::Overflow(constraint.clone())),
+            };
+            let mut coefficients = Vec::new();
+            for (symbol, coefficient) in row.terms {
+                if coefficient == 0 {
+                    continue;
+                }
+                let column = match system.variables.iter().position(|known| *known == symbol) {
+                    Some(column) => column,
+                    None => {
+                        system.variables.push(symbol);
+                        system.variables.len() - 1
+                    }
+                };
+                coefficients.push((column, coefficient));
+            }
+            coefficients.sort_unstable();
+            system.coefficients.push(coefficients);
+            system.relations.push(relation);
+            system.rhs.push(rhs);
+        }
+    }
+    Ok(system)
+}
+
+impl LinearSystem {
+    /// The column of the named variable.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.variables
+            .iter()
+            .position(|symbol| symbol.name() == name)
+    }
+
+    /// The rows of the system as constraints, one per row and in order.
+    pub fn to_constraints(&self) -> Vec<ConstraintLogicExpression> {
+        use IntegerNumberExpression::*;
+        let value = |n| Box::new(IntegerNumberValue(IntegerNumber::Value(n)));
+        self.coefficients
+            .iter()
+            .zip(&self.relations)
+            .zip(&self.rhs)
+            .map(|((row, relation), rhs)| {
+                let term = |(column, coefficient): &(usize, i128)| {
+                    let variable = IntegerNumberVariable(self.variables[*column].clone());
+                    match coefficient {
+                        1 => variable,
+                        -1 => Negate(Box::new(variable)),
+                        _ => Times(value(*coefficient), Box::new(variable)),
+                    }
+                };
+                let sum = match row.split_first() {
+                    Some((first, rest)) => rest.iter().fold(term(first), |sum, entry| {
+                        Add(Box::new(sum), Box::new(term(entry)))
+                    }),
+                    None => IntegerNumberValue(IntegerNumber::Value(0)),
+                };
+                let sum = Box::new(sum);
+                let comparison = match relation {
+                    Relation::Equal => BooleanIntegerNumberExpression::Equals(sum, value(*rhs)),
+                    // The comparisons are strict, so the bound moves by one
+                    // unless it is already at the end of the range.
+                    Relation::LessOrEqual => match rhs.checked_add(1) {
+                        Some(bound) => BooleanIntegerNumberExpression::Less(sum, value(bound)),
+                        None => BooleanIntegerNumberExpression::In(
+                            sum,
+                            Box::new(IntegerNumberDomainExpression::Universe),
+                        ),
+                    },
+                    Relation::GreaterOrEqual => match rhs.checked_sub(1) {
+                        Some(bound) => BooleanIntegerNumberExpression::Greater(sum, value(bound)),
+                        None => BooleanIntegerNumberExpression::In(
+                            sum,
+                            Box::new(IntegerNumberDomainExpression::Universe),
+                        ),
+                    },
+                };
+                ConstraintLogicExpression::OfIntegerNumber(Box::new(comparison))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_linear_system, LinearError, Relation};
+    use crate::expressions::parser::parse_program;
+
+    #[test]
+    fn two_linear_constraints_give_two_rows() {
+        let program = parse_program("2 * x + 3 * y < 12;\nsatisfy x - (y - 4) = 2 * y;").unwrap();
+        let system = to_linear_system(&program).unwrap();
+        let (x, y) = (system.column("x").unwrap(), system.column("y").unwrap());
+        let mut first = vec![(x, 2), (y, 3)];
+        let mut second = vec![(x, 1), (y, -3)];
+        first.sort_unstable();
+        second.sort_unstable();
+        assert_eq!(system.coefficients, vec![first, second]);
+        assert_eq!(
+            system.relations,
+            vec![Relation::LessOrEqual, Relation::Equal]
+        );
+        assert_eq!(system.rhs, vec![11, -4]);
+        let round_trip = system.to_constraints();
+        assert_eq!(round_trip[0].to_string(), "2 * x + 3 * y < 12");
+        assert_eq!(round_trip[1].to_string(), "x + -3 * y = -4");
+    }
+
+    #[test]
+    fn products_of_variables_are_not_linear() {
+        let program = parse_program("satisfy x * y = 6;").unwrap();
+        assert!(matches!(
+            to_linear_system(&program),
+            Err(LinearError::NonLinear(_))
+        ));
+    }
+}
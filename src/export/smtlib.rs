@@ -0,0 +1,536 @@
+//! SMT-LIB 2 scripts over the theory of integers.
+use crate::expressions::boolean::{BooleanExpression, BooleanValue};
+use crate::expressions::integer::{
+    BooleanIntegerNumberExpression, DivisionMode, IntegerNumber, IntegerNumberDomainExpression,
+    IntegerNumberExpression,
+};
+use crate::expressions::{
+    ConstraintLogicExpression, ConstraintProgramExpression, Domain, FreeVariable, Symbol,
+};
+
+use super::ExportError;
+
+/// Symbols SMT-LIB gives a meaning of their own: its reserved words, the
+/// names of its commands and the functions of the core and integer
+/// theories. Quoting does not make them free to declare, since `|div|`
+/// and `div` are the same symbol.
+const RESERVED: &[&str] = &[
+    "!",
+    "_",
+    "as",
+    "BINARY",
+    "DECIMAL",
+    "exists",
+    "HEXADECIMAL",
+    "forall",
+    "let",
+    "match",
+    "NUMERAL",
+    "par",
+    "STRING",
+    "assert",
+    "check-sat",
+    "check-sat-assuming",
+    "declare-const",
+    "declare-datatype",
+    "declare-datatypes",
+    "declare-fun",
+    "declare-sort",
+    "define-fun",
+    "define-fun-rec",
+    "define-funs-rec",
+    "define-sort",
+    "echo",
+    "exit",
+    "get-assertions",
+    "get-assignment",
+    "get-info",
+    "get-model",
+    "get-option",
+    "get-proof",
+    "get-unsat-assumptions",
+    "get-unsat-core",
+    "get-value",
+    "pop",
+    "push",
+    "reset",
+    "reset-assertions",
+    "set-info",
+    "set-logic",
+    "set-option",
+    "true",
+    "false",
+    "not",
+    "=>",
+    "and",
+    "or",
+    "xor",
+    "=",
+    "distinct",
+    "ite",
+    "-",
+    "+",
+    "*",
+    "div",
+    "mod",
+    "abs",
+    "<=",
+    "<",
+    ">=",
+    ">",
+];
+
+/// The prefix of variables renamed to stay clear of `RESERVED`. Names
+/// that already begin with it are renamed as well, so that no renamed
+/// variable meets another.
+const RENAMED: &str = "clp_";
+
+/// Whether SMT-LIB reads `name` as a symbol without quoting it.
+fn is_simple(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || "~!@$%^&*_-+=<>.?/".contains(c))
+        && name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+}
+
+/// The variable as SMT-LIB reads it: renamed if its name is reserved or
+/// left to solvers, as those beginning with `@` or `.` are, and quoted
+/// between bars if it is not a simple symbol.
+fn name(symbol: &Symbol) -> String {
+    let name = symbol.name();
+    let name =
+        if RESERVED.contains(&name) || name.starts_with(RENAMED) || name.starts_with(['@', '.']) {
+            format!("{}{}", RENAMED, name)
+        } else {
+            name.to_string()
+        };
+    if is_simple(&name) {
+        name
+    } else {
+        format!("|{}|", name)
+    }
+}
+
+fn apply(operator: &str, operands: &[String]) -> String {
+    format!("({} {})", operator, operands.join(" "))
+}
+
+fn boolean(expr: &BooleanExpression) -> String {
+    use BooleanExpression::*;
+    match expr {
+        BooleanVariable(symbol) => name(symbol),
+        BooleanValue(self::BooleanValue::True) => "true".to_string(),
+        BooleanValue(self::BooleanValue::False) => "false".to_string(),
+        Parenthesis(expr) => boolean(expr),
+        Not(expr) => apply("not", &[boolean(expr)]),
+        And(expr_a, expr_b) => apply("and", &[boolean(expr_a), boolean(expr_b)]),
+        Or(expr_a, expr_b) => apply("or", &[boolean(expr_a), boolean(expr_b)]),
+        Implies(expr_a, expr_b) => apply("=>", &[boolean(expr_a), boolean(expr_b)]),
+        Equals(expr_a, expr_b) => apply("=", &[boolean(expr_a), boolean(expr_b)]),
+        Xor(expr_a, expr_b) => apply("xor", &[boolean(expr_a), boolean(expr_b)]),
+        IfThenElse(condition, then, otherwise) => apply(
+            "ite",
+            &[boolean(condition), boolean(then), boolean(otherwise)],
+        ),
+    }
+}
+
+/// `a / b`, or `a % b` if `remainder`, rounded as `division` says. SMT-LIB's
+/// `div` and `mod` round as `DivisionMode::EuclideanRemainder` does, so
+/// the other modes divide the magnitudes, or both operands negated, and
+/// fix the signs.
+fn quotient(a: String, b: String, division: DivisionMode, remainder: bool) -> String {
+    let operator = if remainder { "mod" } else { "div" };
+    let negative = |x: &String| apply("<", &[x.clone(), "0".to_string()]);
+    let negate = |x: String| apply("-", &[x]);
+    match division {
+        DivisionMode::EuclideanRemainder => apply(operator, &[a, b]),
+        DivisionMode::TruncateTowardZero => {
+            let magnitude = apply(
+                operator,
+                &[
+                    apply("abs", std::slice::from_ref(&a)),
+                    apply("abs", std::slice::from_ref(&b)),
+                ],
+            );
+            let flipped = if remainder {
+                negative(&a)
+            } else {
+                apply("xor", &[negative(&a), negative(&b)])
+            };
+            apply("ite", &[flipped, negate(magnitude.clone()), magnitude])
+        }
+        DivisionMode::FloorDivision => {
+            let mirrored = apply(operator, &[negate(a.clone()), negate(b.clone())]);
+            let mirrored = if remainder {
+                negate(mirrored)
+            } else {
+                mirrored
+            };
+            apply("ite", &[negative(&b), mirrored, apply(operator, &[a, b])])
+        }
+    }
+}
+
+fn integer(expr: &IntegerNumberExpression, division: DivisionMode) -> Result<String, ExportError> {
+    use IntegerNumberExpression::*;
+    let binary = |operator, expr_a, expr_b| {
+        Ok(apply(
+            operator,
+            &[integer(expr_a, division)?, integer(expr_b, division)?],
+        ))
+    };
+    // `+` and `*` need two operands, so fewer are their identity or the
+    // one operand itself.
+    let nary = |operator, identity: &str, exprs: &[IntegerNumberExpression]| match exprs {
+        [] => Ok(identity.to_string()),
+        [expr] => integer(expr, division),
+        _ => Ok(apply(
+            operator,
+            &exprs
+                .iter()
+                .map(|expr| integer(expr, division))
+                .collect::<Result<Vec<String>, _>>()?,
+        )),
+    };
+    match expr {
+        IntegerNumberVariable(symbol) => Ok(name(symbol)),
+        IntegerNumberValue(IntegerNumber::Value(n)) if *n < 0 => {
+            Ok(apply("-", &[n.unsigned_abs().to_string()]))
+        }
+        IntegerNumberValue(IntegerNumber::Value(n)) => Ok(n.to_string()),
+        IntegerNumberValue(IntegerNumber::NaN) => Err(ExportError::NanLiteral),
+        Parenthesis(expr) => integer(expr, division),
+        Negate(expr) => Ok(apply("-", &[integer(expr, division)?])),
+        Abs(expr) => Ok(apply("abs", &[integer(expr, division)?])),
+        Add(expr_a, expr_b) => binary("+", expr_a, expr_b),
+        Minus(expr_a, expr_b) => binary("-", expr_a, expr_b),
+        Times(expr_a, expr_b) => binary("*", expr_a, expr_b),
+        Divide(expr_a, expr_b) => Ok(quotient(
+            integer(expr_a, division)?,
+            integer(expr_b, division)?,
+            division,
+            false,
+        )),
+        Modulo(expr_a, expr_b) => Ok(quotient(
+            integer(expr_a, division)?,
+            integer(expr_b, division)?,
+            division,
+            true,
+        )),
+        Sum(exprs) => nary("+", "0", exprs),
+        Product(exprs) => nary("*", "1", exprs),
+        Let(symbol, value, body) => Ok(format!(
+            "(let (({} {})) {})",
+            name(symbol),
+            integer(value, division)?,
+            integer(body, division)?
+        )),
+        OfBoolean(expr) => Ok(apply(
+            "ite",
+            &[boolean(expr), "1".to_string(), "0".to_string()],
+        )),
+        IfThenElse(condition, then, otherwise) => Ok(apply(
+            "ite",
+            &[
+                boolean(condition),
+                integer(then, division)?,
+                integer(otherwise, division)?,
+            ],
+        )),
+        Pow(_, _) | PowMod(_, _, _) | TupleGet(_, _) => {
+            Err(ExportError::Unsupported(expr.to_string()))
+        }
+    }
+}
+
+/// The condition that `x`, already written, is a member of `domain`.
+fn membership(
+    x: &str,
+    domain: &IntegerNumberDomainExpression,
+    division: DivisionMode,
+) -> Result<String, ExportError> {
+    use IntegerNumberDomainExpression::*;
+    let x = x.to_string();
+    let range = |low, high, below, above| {
+        Ok(apply(
+            "and",
+            &[
+                apply(below, &[integer(low, division)?, x.clone()]),
+                apply(above, &[x.clone(), integer(high, division)?]),
+            ],
+        ))
+    };
+    match domain {
+        Universe => Ok("true".to_string()),
+        Empty => Ok("false".to_string()),
+        ClosedRange(low, high) => range(low, high, "<=", "<="),
+        OpenRange(low, high) => range(low, high, "<", "<"),
+        OpenLeftClosedRightRange(low, high) => range(low, high, "<", "<="),
+        ClosedLeftOpenRightRange(low, high) => range(low, high, "<=", "<"),
+        ExplicitSet(elements) if elements.is_empty() => Ok("false".to_string()),
+        ExplicitSet(elements) => {
+            let equalities = elements
+                .iter()
+                .map(|element| Ok(apply("=", &[x.clone(), integer(element, division)?])))
+                .collect::<Result<Vec<String>, ExportError>>()?;
+            Ok(match &equalities[..] {
+                [equality] => equality.clone(),
+                _ => apply("or", &equalities),
+            })
+        }
+        Union(expr_a, expr_b) => Ok(apply(
+            "or",
+            &[
+                membership(&x, expr_a, division)?,
+                membership(&x, expr_b, division)?,
+            ],
+        )),
+        Intersection(expr_a, expr_b) => Ok(apply(
+            "and",
+            &[
+                membership(&x, expr_a, division)?,
+                membership(&x, expr_b, division)?,
+            ],
+        )),
+        Difference(expr_a, expr_b) => Ok(apply(
+            "and",
+            &[
+                membership(&x, expr_a, division)?,
+                apply("not", &[membership(&x, expr_b, division)?]),
+            ],
+        )),
+        Complement(expr) => Ok(apply("not", &[membership(&x, expr, division)?])),
+    }
+}
+
+fn comparison(
+    expr: &BooleanIntegerNumberExpression,
+    division: DivisionMode,
+) -> Result<String, ExportError> {
+    use BooleanIntegerNumberExpression::*;
+    let binary = |operator, expr_a, expr_b| {
+        Ok(apply(
+            operator,
+            &[integer(expr_a, division)?, integer(expr_b, division)?],
+        ))
+    };
+    match expr {
+        Equals(expr_a, expr_b) => binary("=", expr_a, expr_b),
+        Different(expr_a, expr_b) => binary("distinct", expr_a, expr_b),
+        Greater(expr_a, expr_b) => binary(">", expr_a, expr_b),
+        Less(expr_a, expr_b) => binary("<", expr_a, expr_b),
+        In(expr, domain) => membership(&integer(expr, division)?, domain, division),
+    }
+}
+
+/// Writes the program as an SMT-LIB 2 script: a `declare-const` of sort
+/// `Int` or `Bool` for each free variable in the order they first
+/// appear, an `assert` for each constraint, then `(check-sat)` and
+/// `(get-model)`. Goals count as constraints, as they do for
+/// `to_linear_system`. Ranges become bounds with `<=` at closed ends and
+/// `<` at open ones, and other domains the equivalent connectives.
+/// Variables named like a symbol of SMT-LIB's own, such as `div`, are
+/// renamed with the prefix `clp_`.
+///
+/// `/` and `%` round toward zero, as they do by default in the solver;
+/// `to_smtlib_with` writes them for other division modes. SMT-LIB leaves
+/// division by zero unspecified, so a program whose divisors may be zero
+/// should pass through `eliminate_nan` first. `NaN` literals, powers,
+/// tuples and variables of other sorts cannot be written.
+pub fn to_smtlib(program: &ConstraintProgramExpression) -> Result<String, ExportError> {
+    to_smtlib_with(program, DivisionMode::default())
+}
+
+/// As `to_smtlib`, with `/` and `%` rounding as `division` says.
+pub fn to_smtlib_with(
+    program: &ConstraintProgramExpression,
+    division: DivisionMode,
+) -> Result<String, ExportError> {
+    let mut script = String::new();
+    let mut declared: Vec<&Symbol> = Vec::new();
+    let free = program.get_free();
+    for variable in &free {
+        if declared.contains(&variable.name()) {
+            continue;
+        }
+        declared.push(variable.name());
+        let sort = match variable.domain() {
+            Domain::Boolean(_) => "Bool",
+            Domain::Integer(_) => "Int",
+            _ => return Err(ExportError::Unsupported(variable.name().to_string())),
+        };
+        script.push_str(&format!(
+            "(declare-const {} {})\n",
+            name(variable.name()),
+            sort
+        ));
+    }
+    for constraint in crate::solver::constraints(program) {
+        let assertion = match constraint {
+            ConstraintLogicExpression::Boolean(expr) => boolean(expr),
+            ConstraintLogicExpression::OfIntegerNumber(expr) => comparison(expr, division)?,
+            // `distinct` needs two operands, and fewer are always distinct.
+            ConstraintLogicExpression::AllDifferent(exprs) if exprs.len() < 2 => "true".to_string(),
+            ConstraintLogicExpression::AllDifferent(exprs) => apply(
+                "distinct",
+                &exprs
+                    .iter()
+                    .map(|expr| integer(expr, division))
+                    .collect::<Result<Vec<String>, _>>()?,
+            ),
+            ConstraintLogicExpression::OfRational(_) => {
+                return Err(ExportError::Unsupported(constraint.to_string()))
+            }
+        };
+        script.push_str(&format!("(assert {})\n", assertion));
+    }
+    script.push_str("(check-sat)\n(get-model)\n");
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_smtlib;
+    use crate::export::ExportError;
+    use crate::expressions::parser::parse_program;
+
+    /// The value of an SMT-LIB term over `x` and `y`, for the operators
+    /// `quotient` writes, with `false` and `true` as 0 and 1.
+    fn value(tokens: &[&str], next: &mut usize, x: i128, y: i128) -> i128 {
+        *next += 1;
+        match tokens[*next - 1] {
+            "(" => {
+                let operator = tokens[*next];
+                *next += 1;
+                let mut operands = Vec::new();
+                while tokens[*next] != ")" {
+                    operands.push(value(tokens, next, x, y));
+                }
+                *next += 1;
+                match (operator, &operands[..]) {
+                    ("-", [a]) => -a,
+                    ("abs", [a]) => a.abs(),
+                    ("<", [a, b]) => i128::from(a < b),
+                    ("xor", [a, b]) => i128::from(a != b),
+                    ("div", [a, b]) => a.div_euclid(*b),
+                    ("mod", [a, b]) => a.rem_euclid(*b),
+                    ("ite", [c, a, b]) => {
+                        if *c == 1 {
+                            *a
+                        } else {
+                            *b
+                        }
+                    }
+                    _ => panic!("unexpected operator {}", operator),
+                }
+            }
+            "x" => x,
+            "y" => y,
+            n => n.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn division_rounds_as_the_mode_says() {
+        use super::quotient;
+        use crate::expressions::integer::{DivisionMode, IntegerNumber, IntegerNumberExpression};
+        use crate::expressions::{AssignedValue, Assignment, Evaluate, Symbol};
+        let variable = |name: &str| {
+            Box::new(IntegerNumberExpression::IntegerNumberVariable(Symbol::new(
+                name.to_string(),
+            )))
+        };
+        let int = |name: &str, n| {
+            Assignment::new(
+                Symbol::new(name.to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(n)),
+            )
+        };
+        for division in [
+            DivisionMode::TruncateTowardZero,
+            DivisionMode::FloorDivision,
+            DivisionMode::EuclideanRemainder,
+        ] {
+            for remainder in [false, true] {
+                let term = quotient("x".to_string(), "y".to_string(), division, remainder);
+                let spaced = term.replace('(', " ( ").replace(')', " ) ");
+                let tokens: Vec<&str> = spaced.split_whitespace().collect();
+                let expr = if remainder {
+                    IntegerNumberExpression::Modulo(variable("x"), variable("y"))
+                } else {
+                    IntegerNumberExpression::Divide(variable("x"), variable("y"))
+                };
+                for (x, y) in [
+                    (7, 2),
+                    (-7, 2),
+                    (7, -2),
+                    (-7, -2),
+                    (6, 3),
+                    (-6, -3),
+                    (0, -5),
+                ] {
+                    assert_eq!(
+                        Ok(IntegerNumber::Value(value(&tokens, &mut 0, x, y))),
+                        expr.evaluate_with(&[int("x", x), int("y", y)], division),
+                        "{} at x = {}, y = {}",
+                        term,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn programs_become_declarations_and_assertions() {
+        let program = parse_program(
+            "x in (0, 10];\n\
+             y in union({-1, 2}, [5, 7));\n\
+             p implies not div;\n\
+             satisfy 2 * (x - y) % 3 != - x;",
+        )
+        .unwrap();
+        assert_eq!(
+            to_smtlib(&program).unwrap(),
+            "(declare-const x Int)\n\
+             (declare-const y Int)\n\
+             (declare-const p Bool)\n\
+             (declare-const clp_div Bool)\n\
+             (assert (and (< 0 x) (<= x 10)))\n\
+             (assert (or (or (= y (- 1)) (= y 2)) (and (<= 5 y) (< y 7))))\n\
+             (assert (=> p (not clp_div)))\n\
+             (assert (distinct (ite (< (* 2 (- x y)) 0) (- (mod (abs (* 2 (- x y))) (abs 3))) \
+             (mod (abs (* 2 (- x y))) (abs 3))) (- x)))\n\
+             (check-sat)\n\
+             (get-model)\n"
+        );
+        let program = parse_program("satisfy alldifferent(x, y + 1, 2);").unwrap();
+        assert_eq!(
+            to_smtlib(&program).unwrap(),
+            "(declare-const x Int)\n\
+             (declare-const y Int)\n\
+             (assert (distinct x (+ y 1) 2))\n\
+             (check-sat)\n\
+             (get-model)\n"
+        );
+        let program = parse_program("satisfy x ^ 2 = 4;").unwrap();
+        assert_eq!(
+            to_smtlib(&program),
+            Err(ExportError::Unsupported("x ^ 2".to_string()))
+        );
+    }
+
+    #[test]
+    fn variables_named_like_theory_symbols_are_renamed() {
+        let program = parse_program("div in [0, 3];\nclp_div > 1;\nsatisfy mod != div;").unwrap();
+        let script = to_smtlib(&program).unwrap();
+        assert!(script.starts_with(
+            "(declare-const clp_div Int)\n\
+             (declare-const clp_clp_div Int)\n\
+             (declare-const clp_mod Int)\n"
+        ));
+        assert!(script.contains("(assert (distinct clp_mod clp_div))\n"));
+        assert!(!script.contains('|'));
+    }
+}
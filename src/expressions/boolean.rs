@@ -1,11 +1,18 @@
+use std::fmt;
+use std::ops::ControlFlow;
+
+use super::visit::{FreeVariables, Visitor};
+
 /// The logic base type values.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BooleanValue {
     False,
     True,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BooleanValueDomainExpression {
     Universe,
     Empty,
@@ -17,67 +24,918 @@ pub enum BooleanValueDomainExpression {
 /// and all constraints are from some type to the BooleanExpression
 /// type. All constraints are also considered to be in an implicit
 /// conjugation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BooleanExpression {
     And(Box<BooleanExpression>, Box<BooleanExpression>),
     Or(Box<BooleanExpression>, Box<BooleanExpression>),
     Implies(Box<BooleanExpression>, Box<BooleanExpression>),
     Equals(Box<BooleanExpression>, Box<BooleanExpression>),
+    /// Exclusive or: true when exactly one operand is.
+    Xor(Box<BooleanExpression>, Box<BooleanExpression>),
+    /// `IfThenElse(condition, then, otherwise)` is `then` when the
+    /// condition is true and `otherwise` when it is false.
+    IfThenElse(
+        Box<BooleanExpression>,
+        Box<BooleanExpression>,
+        Box<BooleanExpression>,
+    ),
     Parenthesis(Box<BooleanExpression>),
     Not(Box<BooleanExpression>),
     BooleanVariable(super::Symbol),
     BooleanValue(BooleanValue),
 }
 
+impl BooleanExpression {
+    /// Binding strength in the concrete syntax, from `equals` (loosest)
+    /// through `implies`, `or`, `xor`, `and` and `not` to atoms.
+    fn precedence(&self) -> u8 {
+        use BooleanExpression::*;
+        match self {
+            Equals(_, _) => 1,
+            Implies(_, _) => 2,
+            Or(_, _) => 3,
+            Xor(_, _) => 4,
+            And(_, _) => 5,
+            Not(_) => 6,
+            IfThenElse(_, _, _) | Parenthesis(_) | BooleanVariable(_) | BooleanValue(_) => 7,
+        }
+    }
+
+    /// Writes the expression, in parentheses if it binds looser than `min`.
+    fn fmt_at(&self, f: &mut fmt::Formatter, min: u8) -> fmt::Result {
+        if self.precedence() < min {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl fmt::Display for BooleanValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BooleanValue::False => write!(f, "false"),
+            BooleanValue::True => write!(f, "true"),
+        }
+    }
+}
+
+/// Infix syntax with parentheses only where precedence requires them;
+/// `and`, `or`, `xor` and `equals` group to the left and `implies` to the
+/// right.
+impl fmt::Display for BooleanExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use BooleanExpression::*;
+        let binary = |f: &mut fmt::Formatter, a: &BooleanExpression, op, b: &BooleanExpression| {
+            let precedence = self.precedence();
+            let (left, right) = match self {
+                Implies(_, _) => (precedence + 1, precedence),
+                _ => (precedence, precedence + 1),
+            };
+            a.fmt_at(f, left)?;
+            write!(f, " {} ", op)?;
+            b.fmt_at(f, right)
+        };
+        match self {
+            And(expr_a, expr_b) => binary(f, expr_a, "and", expr_b),
+            Or(expr_a, expr_b) => binary(f, expr_a, "or", expr_b),
+            Implies(expr_a, expr_b) => binary(f, expr_a, "implies", expr_b),
+            Equals(expr_a, expr_b) => binary(f, expr_a, "equals", expr_b),
+            Xor(expr_a, expr_b) => binary(f, expr_a, "xor", expr_b),
+            IfThenElse(condition, then, otherwise) => {
+                write!(f, "ite({}, {}, {})", condition, then, otherwise)
+            }
+            Parenthesis(expr) => write!(f, "({})", expr),
+            Not(expr) => {
+                write!(f, "not ")?;
+                expr.fmt_at(f, self.precedence())
+            }
+            BooleanVariable(symbol) => write!(f, "{}", symbol),
+            BooleanValue(value) => write!(f, "{}", value),
+        }
+    }
+}
+
 impl super::FreeVariable for BooleanExpression {
     fn get_free(&self) -> Vec<super::Variable> {
-        use super::Variable;
+        FreeVariables::of(|free| free.visit_boolean(self))
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        use BooleanExpression::*;
+        match self {
+            BooleanVariable(symbol) => visit(symbol),
+            Not(expr) | Parenthesis(expr) => expr.each_free(visit),
+            And(expr_a, expr_b)
+            | Or(expr_a, expr_b)
+            | Implies(expr_a, expr_b)
+            | Equals(expr_a, expr_b)
+            | Xor(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
+            }
+            IfThenElse(condition, then, otherwise) => {
+                condition.each_free(visit)?;
+                then.each_free(visit)?;
+                otherwise.each_free(visit)
+            }
+            BooleanValue(_) => ControlFlow::Continue(()),
+        }
+    }
+}
+
+impl BooleanValue {
+    pub(crate) fn from_bool(value: bool) -> BooleanValue {
+        if value {
+            BooleanValue::True
+        } else {
+            BooleanValue::False
+        }
+    }
+}
+
+/// Three valued conjunction: a known `False` on either side decides the
+/// result even when the other side cannot be evaluated.
+pub(crate) fn and(
+    a: Result<BooleanValue, super::EvalError>,
+    b: Result<BooleanValue, super::EvalError>,
+) -> Result<BooleanValue, super::EvalError> {
+    match (a, b) {
+        (Ok(BooleanValue::False), _) | (_, Ok(BooleanValue::False)) => Ok(BooleanValue::False),
+        (Err(error), _) | (_, Err(error)) => Err(error),
+        _ => Ok(BooleanValue::True),
+    }
+}
+
+/// Three valued disjunction: a known `True` on either side decides the
+/// result even when the other side cannot be evaluated.
+pub(crate) fn or(
+    a: Result<BooleanValue, super::EvalError>,
+    b: Result<BooleanValue, super::EvalError>,
+) -> Result<BooleanValue, super::EvalError> {
+    match (a, b) {
+        (Ok(BooleanValue::True), _) | (_, Ok(BooleanValue::True)) => Ok(BooleanValue::True),
+        (Err(error), _) | (_, Err(error)) => Err(error),
+        _ => Ok(BooleanValue::False),
+    }
+}
+
+pub(crate) fn not(
+    a: Result<BooleanValue, super::EvalError>,
+) -> Result<BooleanValue, super::EvalError> {
+    a.map(|value| BooleanValue::from_bool(value == BooleanValue::False))
+}
+
+impl BooleanExpression {
+    /// The value under `env`, without cloning any part of the expression.
+    pub fn evaluate_ref(&self, env: &super::Environment) -> Result<BooleanValue, super::EvalError> {
+        use BooleanExpression::*;
+        match self {
+            BooleanVariable(symbol) => env
+                .boolean(symbol)
+                .cloned()
+                .ok_or_else(|| super::EvalError::Unbound(symbol.clone())),
+            BooleanValue(value) => Ok(value.clone()),
+            Not(expr) => not(expr.evaluate_ref(env)),
+            Parenthesis(expr) => expr.evaluate_ref(env),
+            And(expr_a, expr_b) => and(expr_a.evaluate_ref(env), expr_b.evaluate_ref(env)),
+            Or(expr_a, expr_b) => or(expr_a.evaluate_ref(env), expr_b.evaluate_ref(env)),
+            Implies(expr_a, expr_b) => or(not(expr_a.evaluate_ref(env)), expr_b.evaluate_ref(env)),
+            Equals(expr_a, expr_b) => {
+                let a = expr_a.evaluate_ref(env)?;
+                let b = expr_b.evaluate_ref(env)?;
+                Ok(self::BooleanValue::from_bool(a == b))
+            }
+            Xor(expr_a, expr_b) => {
+                let a = expr_a.evaluate_ref(env)?;
+                let b = expr_b.evaluate_ref(env)?;
+                Ok(self::BooleanValue::from_bool(a != b))
+            }
+            IfThenElse(condition, then, otherwise) => match condition.evaluate_ref(env)? {
+                self::BooleanValue::True => then.evaluate_ref(env),
+                self::BooleanValue::False => otherwise.evaluate_ref(env),
+            },
+        }
+    }
+}
+
+/// Boolean expressions hold no integers, so `division` makes no
+/// difference to them.
+impl super::Evaluate for BooleanExpression {
+    type Value = BooleanValue;
+    fn evaluate_with(
+        &self,
+        env: &[super::Assignment],
+        _division: super::integer::DivisionMode,
+    ) -> Result<BooleanValue, super::EvalError> {
+        self.evaluate_ref(&super::Environment::new(env))
+    }
+}
+
+impl super::Apply for BooleanExpression {
+    fn apply_noting(&self, state: &[super::Assignment], substituted: &mut bool) -> Self {
+        use BooleanExpression::*;
+        match self {
+            BooleanVariable(symbol) => match super::boolean_binding(state, symbol) {
+                Some(value) => {
+                    *substituted = true;
+                    BooleanValue(value.clone())
+                }
+                None => BooleanVariable(symbol.clone()),
+            },
+            BooleanValue(value) => BooleanValue(value.clone()),
+            Not(expr) => Not(Box::new(expr.apply_noting(state, substituted))),
+            Parenthesis(expr) => Parenthesis(Box::new(expr.apply_noting(state, substituted))),
+            And(expr_a, expr_b) => And(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Or(expr_a, expr_b) => Or(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Implies(expr_a, expr_b) => Implies(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Equals(expr_a, expr_b) => Equals(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Xor(expr_a, expr_b) => Xor(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            IfThenElse(condition, then, otherwise) => IfThenElse(
+                Box::new(condition.apply_noting(state, substituted)),
+                Box::new(then.apply_noting(state, substituted)),
+                Box::new(otherwise.apply_noting(state, substituted)),
+            ),
+        }
+    }
+}
+
+impl super::Substitute for BooleanExpression {
+    fn substitute_with(&self, substitution: &super::Substitution) -> Self {
+        use BooleanExpression::*;
+        let substituted = |expr: &BooleanExpression| Box::new(expr.substitute_with(substitution));
+        match self {
+            BooleanVariable(symbol) => match substitution {
+                super::Substitution::Boolean(name, replacement) if *name == symbol => {
+                    (*replacement).clone()
+                }
+                _ => self.clone(),
+            },
+            BooleanValue(_) => self.clone(),
+            Not(expr) => Not(substituted(expr)),
+            Parenthesis(expr) => Parenthesis(substituted(expr)),
+            And(expr_a, expr_b) => And(substituted(expr_a), substituted(expr_b)),
+            Or(expr_a, expr_b) => Or(substituted(expr_a), substituted(expr_b)),
+            Implies(expr_a, expr_b) => Implies(substituted(expr_a), substituted(expr_b)),
+            Equals(expr_a, expr_b) => Equals(substituted(expr_a), substituted(expr_b)),
+            Xor(expr_a, expr_b) => Xor(substituted(expr_a), substituted(expr_b)),
+            IfThenElse(condition, then, otherwise) => IfThenElse(
+                substituted(condition),
+                substituted(then),
+                substituted(otherwise),
+            ),
+        }
+    }
+}
+
+/// The two associative connectives that `factor` and `distribute`
+/// rearrange.
+#[derive(Clone, Copy)]
+enum Connective {
+    And,
+    Or,
+}
+
+impl Connective {
+    fn dual(self) -> Connective {
+        match self {
+            Connective::And => Connective::Or,
+            Connective::Or => Connective::And,
+        }
+    }
+
+    fn join(self, a: BooleanExpression, b: BooleanExpression) -> BooleanExpression {
+        match self {
+            Connective::And => BooleanExpression::And(Box::new(a), Box::new(b)),
+            Connective::Or => BooleanExpression::Or(Box::new(a), Box::new(b)),
+        }
+    }
+
+    /// Joins the operands left to right; there must be at least one.
+    fn join_all(self, operands: Vec<BooleanExpression>) -> BooleanExpression {
+        let mut operands = operands.into_iter();
+        let first = operands.next().expect("at least one operand");
+        operands.fold(first, |joined, operand| self.join(joined, operand))
+    }
+
+    /// The operands of a chain of this connective, left to right.
+    fn operands(self, expr: BooleanExpression) -> Vec<BooleanExpression> {
+        let mut operands = Vec::new();
+        let mut pending = vec![expr];
+        while let Some(expr) = pending.pop() {
+            match (self, expr) {
+                (Connective::And, BooleanExpression::And(expr_a, expr_b))
+                | (Connective::Or, BooleanExpression::Or(expr_a, expr_b)) => {
+                    pending.push(*expr_b);
+                    pending.push(*expr_a);
+                }
+                (_, expr) => operands.push(expr),
+            }
+        }
+        operands
+    }
+}
+
+/// Pulls the operands shared by every operand of an `outer` chain out in
+/// front of it, e.g. `(a and b) or (a and c)` becomes `a and (b or c)`.
+fn pull_common(
+    outer: Connective,
+    expr_a: BooleanExpression,
+    expr_b: BooleanExpression,
+) -> BooleanExpression {
+    let inner = outer.dual();
+    let parts: Vec<Vec<BooleanExpression>> = outer
+        .operands(outer.join(expr_a.clone(), expr_b.clone()))
+        .into_iter()
+        .map(|part| inner.operands(part))
+        .collect();
+    let mut common: Vec<BooleanExpression> = Vec::new();
+    for candidate in &parts[0] {
+        if !common.contains(candidate) && parts[1..].iter().all(|part| part.contains(candidate)) {
+            common.push(candidate.clone());
+        }
+    }
+    if common.is_empty() {
+        return outer.join(expr_a, expr_b);
+    }
+    let mut rests = Vec::new();
+    for part in parts {
+        let rest: Vec<BooleanExpression> = part
+            .into_iter()
+            .filter(|operand| !common.contains(operand))
+            .collect();
+        if rest.is_empty() {
+            // One operand is exactly the common part, which absorbs the rest.
+            return inner.join_all(common);
+        }
+        rests.push(inner.join_all(rest));
+    }
+    inner.join(inner.join_all(common), outer.join_all(rests))
+}
+
+/// Factors operands shared by all branches of a conjunction or
+/// disjunction out of it, turning `(a and b) or (a and c)` into
+/// `a and (b or c)` and `(a or b) and (a or c)` into `a or (b and c)`.
+/// Operands are compared by structural equality, so `Parenthesis` is
+/// significant. The result has the same truth function as `expr`.
+pub fn factor(expr: BooleanExpression) -> BooleanExpression {
+    use BooleanExpression::*;
+    match expr {
+        And(expr_a, expr_b) => pull_common(Connective::And, factor(*expr_a), factor(*expr_b)),
+        Or(expr_a, expr_b) => pull_common(Connective::Or, factor(*expr_a), factor(*expr_b)),
+        Implies(expr_a, expr_b) => Implies(Box::new(factor(*expr_a)), Box::new(factor(*expr_b))),
+        Equals(expr_a, expr_b) => Equals(Box::new(factor(*expr_a)), Box::new(factor(*expr_b))),
+        Xor(expr_a, expr_b) => Xor(Box::new(factor(*expr_a)), Box::new(factor(*expr_b))),
+        IfThenElse(condition, then, otherwise) => IfThenElse(
+            Box::new(factor(*condition)),
+            Box::new(factor(*then)),
+            Box::new(factor(*otherwise)),
+        ),
+        Parenthesis(expr) => Parenthesis(Box::new(factor(*expr))),
+        Not(expr) => Not(Box::new(factor(*expr))),
+        leaf => leaf,
+    }
+}
+
+fn distribute_and(expr_a: BooleanExpression, expr_b: BooleanExpression) -> BooleanExpression {
+    use BooleanExpression::*;
+    match (expr_a, expr_b) {
+        (Or(expr_a1, expr_a2), expr_b) => Or(
+            Box::new(distribute_and(*expr_a1, expr_b.clone())),
+            Box::new(distribute_and(*expr_a2, expr_b)),
+        ),
+        (expr_a, Or(expr_b1, expr_b2)) => Or(
+            Box::new(distribute_and(expr_a.clone(), *expr_b1)),
+            Box::new(distribute_and(expr_a, *expr_b2)),
+        ),
+        (expr_a, expr_b) => And(Box::new(expr_a), Box::new(expr_b)),
+    }
+}
+
+/// Distributes conjunction over disjunction, the inverse of `factor`:
+/// `a and (b or c)` becomes `(a and b) or (a and c)`. The result has the
+/// same truth function as `expr` but can be exponentially larger, so this
+/// is only ever applied on request.
+pub fn distribute(expr: BooleanExpression) -> BooleanExpression {
+    use BooleanExpression::*;
+    match expr {
+        And(expr_a, expr_b) => distribute_and(distribute(*expr_a), distribute(*expr_b)),
+        Or(expr_a, expr_b) => Or(Box::new(distribute(*expr_a)), Box::new(distribute(*expr_b))),
+        Implies(expr_a, expr_b) => {
+            Implies(Box::new(distribute(*expr_a)), Box::new(distribute(*expr_b)))
+        }
+        Equals(expr_a, expr_b) => {
+            Equals(Box::new(distribute(*expr_a)), Box::new(distribute(*expr_b)))
+        }
+        Xor(expr_a, expr_b) => Xor(Box::new(distribute(*expr_a)), Box::new(distribute(*expr_b))),
+        IfThenElse(condition, then, otherwise) => IfThenElse(
+            Box::new(distribute(*condition)),
+            Box::new(distribute(*then)),
+            Box::new(distribute(*otherwise)),
+        ),
+        Parenthesis(expr) => Parenthesis(Box::new(distribute(*expr))),
+        Not(expr) => Not(Box::new(distribute(*expr))),
+        leaf => leaf,
+    }
+}
+
+/// The negation normal form of `expr` if `positive`, otherwise of its
+/// negation.
+fn nnf(expr: &BooleanExpression, positive: bool) -> BooleanExpression {
+    use BooleanExpression::*;
+    let join = |connective: Connective, a, b| {
+        if positive {
+            connective.join(a, b)
+        } else {
+            connective.dual().join(a, b)
+        }
+    };
+    match expr {
+        And(expr_a, expr_b) => join(
+            Connective::And,
+            nnf(expr_a, positive),
+            nnf(expr_b, positive),
+        ),
+        Or(expr_a, expr_b) => join(Connective::Or, nnf(expr_a, positive), nnf(expr_b, positive)),
+        Implies(expr_a, expr_b) => join(
+            Connective::Or,
+            nnf(expr_a, !positive),
+            nnf(expr_b, positive),
+        ),
+        // `a equals b` is `(a and b) or (not a and not b)`, and its
+        // negation `(a and not b) or (not a and b)`.
+        Equals(expr_a, expr_b) => Or(
+            Box::new(And(
+                Box::new(nnf(expr_a, true)),
+                Box::new(nnf(expr_b, positive)),
+            )),
+            Box::new(And(
+                Box::new(nnf(expr_a, false)),
+                Box::new(nnf(expr_b, !positive)),
+            )),
+        ),
+        // `a xor b` is `not (a equals b)`.
+        Xor(expr_a, expr_b) => Or(
+            Box::new(And(
+                Box::new(nnf(expr_a, true)),
+                Box::new(nnf(expr_b, !positive)),
+            )),
+            Box::new(And(
+                Box::new(nnf(expr_a, false)),
+                Box::new(nnf(expr_b, positive)),
+            )),
+        ),
+        // `ite(c, a, b)` is `(c and a) or (not c and b)`, and its negation
+        // `(c and not a) or (not c and not b)`.
+        IfThenElse(condition, then, otherwise) => Or(
+            Box::new(And(
+                Box::new(nnf(condition, true)),
+                Box::new(nnf(then, positive)),
+            )),
+            Box::new(And(
+                Box::new(nnf(condition, false)),
+                Box::new(nnf(otherwise, positive)),
+            )),
+        ),
+        Parenthesis(expr) => nnf(expr, positive),
+        Not(expr) => nnf(expr, !positive),
+        BooleanVariable(_) if positive => expr.clone(),
+        BooleanVariable(_) => Not(Box::new(expr.clone())),
+        BooleanValue(value) => constant((*value == self::BooleanValue::True) == positive),
+    }
+}
+
+/// `not expr`, or the operand of `expr` if it is a negation already.
+fn negated(expr: BooleanExpression) -> BooleanExpression {
+    match expr {
+        BooleanExpression::Not(expr) => *expr,
+        expr => BooleanExpression::Not(Box::new(expr)),
+    }
+}
+
+impl BooleanExpression {
+    /// Folds constants bottom up: `x and true` becomes `x`, `x and false`
+    /// `false`, `x or false` `x`, `x or true` `true`, `false implies x`
+    /// `true`, `not true` `false` and so on, so that an expression without
+    /// variables becomes a constant. Two equal operands of `and` or `or`
+    /// merge, those of `implies` and `equals` make them `true` and those
+    /// of `xor` `false`, and double negations cancel. An `ite` with a
+    /// constant condition becomes the chosen branch, and one with equal
+    /// branches that branch. `Parenthesis` nodes are dropped. Unlike
+    /// `simplify_in_context` nothing is assumed across operands, so this
+    /// is linear in the size of the expression, apart from comparing
+    /// operands. The result has the same truth function as `self`.
+    pub fn simplify(&self) -> BooleanExpression {
         use BooleanExpression::*;
-        use BooleanValueDomainExpression::Universe;
-        let mut free_variables: Vec<Variable> = Vec::new();
         match self {
-            BooleanVariable(symbol) => free_variables.push(Variable {
-                name: symbol.clone(),
-                domain: super::Domain::Boolean(Universe),
-            }),
-            Not(expr) => free_variables.extend(expr.get_free()),
-            Parenthesis(expr) => free_variables.extend(expr.get_free()),
             And(expr_a, expr_b) => {
-                free_variables.extend(expr_a.get_free());
-                free_variables.extend(expr_b.get_free());
+                let (expr_a, expr_b) = (expr_a.simplify(), expr_b.simplify());
+                match (constant_of(&expr_a), constant_of(&expr_b)) {
+                    (Some(false), _) | (_, Some(false)) => constant(false),
+                    (Some(true), _) => expr_b,
+                    (_, Some(true)) => expr_a,
+                    _ if expr_a == expr_b => expr_a,
+                    _ => And(Box::new(expr_a), Box::new(expr_b)),
+                }
             }
             Or(expr_a, expr_b) => {
-                free_variables.extend(expr_a.get_free());
-                free_variables.extend(expr_b.get_free());
+                let (expr_a, expr_b) = (expr_a.simplify(), expr_b.simplify());
+                match (constant_of(&expr_a), constant_of(&expr_b)) {
+                    (Some(true), _) | (_, Some(true)) => constant(true),
+                    (Some(false), _) => expr_b,
+                    (_, Some(false)) => expr_a,
+                    _ if expr_a == expr_b => expr_a,
+                    _ => Or(Box::new(expr_a), Box::new(expr_b)),
+                }
             }
             Implies(expr_a, expr_b) => {
-                free_variables.extend(expr_a.get_free());
-                free_variables.extend(expr_b.get_free());
+                let (expr_a, expr_b) = (expr_a.simplify(), expr_b.simplify());
+                match (constant_of(&expr_a), constant_of(&expr_b)) {
+                    (Some(false), _) | (_, Some(true)) => constant(true),
+                    (Some(true), _) => expr_b,
+                    (_, Some(false)) => negated(expr_a),
+                    _ if expr_a == expr_b => constant(true),
+                    _ => Implies(Box::new(expr_a), Box::new(expr_b)),
+                }
             }
             Equals(expr_a, expr_b) => {
-                free_variables.extend(expr_a.get_free());
-                free_variables.extend(expr_b.get_free());
+                let (expr_a, expr_b) = (expr_a.simplify(), expr_b.simplify());
+                match (constant_of(&expr_a), constant_of(&expr_b)) {
+                    (Some(a), Some(b)) => constant(a == b),
+                    (Some(true), _) => expr_b,
+                    (_, Some(true)) => expr_a,
+                    (Some(false), _) => negated(expr_b),
+                    (_, Some(false)) => negated(expr_a),
+                    _ if expr_a == expr_b => constant(true),
+                    _ => Equals(Box::new(expr_a), Box::new(expr_b)),
+                }
+            }
+            Xor(expr_a, expr_b) => {
+                let (expr_a, expr_b) = (expr_a.simplify(), expr_b.simplify());
+                match (constant_of(&expr_a), constant_of(&expr_b)) {
+                    (Some(a), Some(b)) => constant(a != b),
+                    (Some(false), _) => expr_b,
+                    (_, Some(false)) => expr_a,
+                    (Some(true), _) => negated(expr_b),
+                    (_, Some(true)) => negated(expr_a),
+                    _ if expr_a == expr_b => constant(false),
+                    _ => Xor(Box::new(expr_a), Box::new(expr_b)),
+                }
+            }
+            IfThenElse(condition, then, otherwise) => {
+                let condition = condition.simplify();
+                let (then, otherwise) = (then.simplify(), otherwise.simplify());
+                match (
+                    constant_of(&condition),
+                    constant_of(&then),
+                    constant_of(&otherwise),
+                ) {
+                    (Some(true), _, _) => then,
+                    (Some(false), _, _) => otherwise,
+                    (_, Some(true), Some(false)) => condition,
+                    (_, Some(false), Some(true)) => negated(condition),
+                    _ if then == otherwise => then,
+                    _ => IfThenElse(Box::new(condition), Box::new(then), Box::new(otherwise)),
+                }
+            }
+            Not(expr) => {
+                let expr = expr.simplify();
+                match constant_of(&expr) {
+                    Some(value) => constant(!value),
+                    None => negated(expr),
+                }
             }
-            BooleanValue(_) => (),
+            Parenthesis(expr) => expr.simplify(),
+            BooleanVariable(_) | BooleanValue(_) => self.clone(),
+        }
+    }
+
+    /// The negation normal form: `Not` is pushed inward until it applies
+    /// only to variables, negated constants are folded, `a implies b`
+    /// becomes `not a or b`, `a equals b` becomes
+    /// `(a and b) or (not a and not b)`, `a xor b`
+    /// `(a and not b) or (not a and b)` and `ite(c, a, b)`
+    /// `(c and a) or (not c and b)`, and `Parenthesis` nodes are dropped.
+    /// The result has the same truth function as `self`; each `equals`,
+    /// `xor` and `ite` copies an operand, so nested ones grow
+    /// exponentially.
+    pub fn to_nnf(&self) -> BooleanExpression {
+        nnf(self, true)
+    }
+}
+
+/// The expression inside any parentheses.
+fn bare(expr: &BooleanExpression) -> &BooleanExpression {
+    match expr {
+        BooleanExpression::Parenthesis(expr) => bare(expr),
+        expr => expr,
+    }
+}
+
+fn constant(value: bool) -> BooleanExpression {
+    BooleanExpression::BooleanValue(BooleanValue::from_bool(value))
+}
+
+fn constant_of(expr: &BooleanExpression) -> Option<bool> {
+    match bare(expr) {
+        BooleanExpression::BooleanValue(value) => Some(*value == BooleanValue::True),
+        _ => None,
+    }
+}
+
+/// Records that `expr` has `value`, along with what follows directly:
+/// the operand of a negation has the opposite value, the conjuncts of a
+/// true conjunction are true and the disjuncts of a false disjunction
+/// false.
+fn assume<'a>(
+    expr: &'a BooleanExpression,
+    value: bool,
+    facts: &mut Vec<(&'a BooleanExpression, bool)>,
+) {
+    use BooleanExpression::*;
+    match bare(expr) {
+        Not(expr) => assume(expr, !value, facts),
+        And(expr_a, expr_b) if value => {
+            assume(expr_a, value, facts);
+            assume(expr_b, value, facts);
+        }
+        Or(expr_a, expr_b) if !value => {
+            assume(expr_a, value, facts);
+            assume(expr_b, value, facts);
+        }
+        _ => (),
+    }
+    facts.push((bare(expr), value));
+}
+
+/// Simplifies the operands of a chain of `connective` left to right,
+/// each assuming the others do not decide the chain: true in a
+/// conjunction and false in a disjunction. Each operand is simplified
+/// under the current forms of the others, so two copies of the same
+/// operand cannot eliminate each other.
+fn simplify_chain(
+    connective: Connective,
+    expr: BooleanExpression,
+    facts: &[(&BooleanExpression, bool)],
+) -> BooleanExpression {
+    let neutral = matches!(connective, Connective::And);
+    let mut operands = connective.operands(expr);
+    for index in 0..operands.len() {
+        let mut local = facts.to_vec();
+        for (other, operand) in operands.iter().enumerate() {
+            if other != index {
+                assume(operand, neutral, &mut local);
+            }
+        }
+        let simplified = simplify_under(operands[index].clone(), &local);
+        operands[index] = simplified;
+    }
+    if operands
+        .iter()
+        .any(|operand| constant_of(operand) == Some(!neutral))
+    {
+        return constant(!neutral);
+    }
+    operands.retain(|operand| constant_of(operand) != Some(neutral));
+    if operands.is_empty() {
+        constant(neutral)
+    } else {
+        connective.join_all(operands)
+    }
+}
+
+fn simplify_under(
+    expr: BooleanExpression,
+    facts: &[(&BooleanExpression, bool)],
+) -> BooleanExpression {
+    use BooleanExpression::*;
+    if let Some((_, value)) = facts.iter().find(|(fact, _)| *fact == bare(&expr)) {
+        return constant(*value);
+    }
+    match expr {
+        And(_, _) => simplify_chain(Connective::And, expr, facts),
+        Or(_, _) => simplify_chain(Connective::Or, expr, facts),
+        Implies(expr_a, expr_b) => {
+            let mut local = facts.to_vec();
+            assume(&expr_a, true, &mut local);
+            let expr_b = simplify_under(*expr_b, &local);
+            let mut local = facts.to_vec();
+            assume(&expr_b, false, &mut local);
+            let expr_a = simplify_under(*expr_a, &local);
+            match (constant_of(&expr_a), constant_of(&expr_b)) {
+                (Some(false), _) | (_, Some(true)) => constant(true),
+                (Some(true), _) => expr_b,
+                (_, Some(false)) => Not(Box::new(expr_a)),
+                _ => Implies(Box::new(expr_a), Box::new(expr_b)),
+            }
+        }
+        Equals(expr_a, expr_b) => {
+            let (expr_a, expr_b) = (
+                simplify_under(*expr_a, facts),
+                simplify_under(*expr_b, facts),
+            );
+            match (constant_of(&expr_a), constant_of(&expr_b)) {
+                (Some(a), Some(b)) => constant(a == b),
+                (Some(true), _) => expr_b,
+                (_, Some(true)) => expr_a,
+                (Some(false), _) => Not(Box::new(expr_b)),
+                (_, Some(false)) => Not(Box::new(expr_a)),
+                _ => Equals(Box::new(expr_a), Box::new(expr_b)),
+            }
+        }
+        Xor(expr_a, expr_b) => {
+            let (expr_a, expr_b) = (
+                simplify_under(*expr_a, facts),
+                simplify_under(*expr_b, facts),
+            );
+            match (constant_of(&expr_a), constant_of(&expr_b)) {
+                (Some(a), Some(b)) => constant(a != b),
+                (Some(false), _) => expr_b,
+                (_, Some(false)) => expr_a,
+                (Some(true), _) => Not(Box::new(expr_b)),
+                (_, Some(true)) => Not(Box::new(expr_a)),
+                _ => Xor(Box::new(expr_a), Box::new(expr_b)),
+            }
+        }
+        IfThenElse(condition, then, otherwise) => {
+            let condition = simplify_under(*condition, facts);
+            match constant_of(&condition) {
+                Some(true) => simplify_under(*then, facts),
+                Some(false) => simplify_under(*otherwise, facts),
+                None => {
+                    let mut local = facts.to_vec();
+                    assume(&condition, true, &mut local);
+                    let then = simplify_under(*then, &local);
+                    let mut local = facts.to_vec();
+                    assume(&condition, false, &mut local);
+                    let otherwise = simplify_under(*otherwise, &local);
+                    match (constant_of(&then), constant_of(&otherwise)) {
+                        (Some(true), Some(false)) => condition,
+                        (Some(false), Some(true)) => Not(Box::new(condition)),
+                        _ if then == otherwise => then,
+                        _ => IfThenElse(Box::new(condition), Box::new(then), Box::new(otherwise)),
+                    }
+                }
+            }
+        }
+        Not(expr) => {
+            let expr = simplify_under(*expr, facts);
+            match constant_of(&expr) {
+                Some(value) => constant(!value),
+                None => Not(Box::new(expr)),
+            }
+        }
+        Parenthesis(expr) => {
+            let expr = simplify_under(*expr, facts);
+            match constant_of(&expr) {
+                Some(value) => constant(value),
+                None => Parenthesis(Box::new(expr)),
+            }
+        }
+        leaf => leaf,
+    }
+}
+
+/// Simplifies `expr` using what each part implies about the others:
+/// every conjunct of an `and` is assumed true while simplifying the
+/// rest, every disjunct of an `or` false, the premise of an `implies`
+/// true in its conclusion and the condition of an `ite` true in its
+/// first branch and false in its second. A subexpression equal to an
+/// assumed one, after parentheses, becomes a constant and the constants
+/// are folded away, so `x and (not x or y)` becomes `x and y` and
+/// `x and (x implies y)` also `x and y`. The result has the same truth
+/// function as `expr`.
+pub fn simplify_in_context(expr: BooleanExpression) -> BooleanExpression {
+    simplify_under(expr, &[])
+}
+
+/// `and`, `or` and `equals` chains are flattened and their operands
+/// sorted, as are the two operands of `xor`; `Parenthesis` nodes are
+/// dropped.
+impl super::Canonicalize for BooleanExpression {
+    fn canonicalize(self) -> Self {
+        use super::{chain_operands, sorted_chain};
+        use BooleanExpression::*;
+        let join_and = |a, b| And(Box::new(a), Box::new(b));
+        let join_or = |a, b| Or(Box::new(a), Box::new(b));
+        let join_equals = |a, b| Equals(Box::new(a), Box::new(b));
+        let join_xor = |a, b| Xor(Box::new(a), Box::new(b));
+        match self {
+            And(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_and(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        And(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_and,
+            ),
+            Or(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_or(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Or(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_or,
+            ),
+            Equals(expr_a, expr_b) => sorted_chain(
+                vec![expr_a.canonicalize(), expr_b.canonicalize()],
+                join_equals,
+            ),
+            Xor(expr_a, expr_b) => {
+                sorted_chain(vec![expr_a.canonicalize(), expr_b.canonicalize()], join_xor)
+            }
+            Implies(expr_a, expr_b) => Implies(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            IfThenElse(condition, then, otherwise) => IfThenElse(
+                Box::new(condition.canonicalize()),
+                Box::new(then.canonicalize()),
+                Box::new(otherwise.canonicalize()),
+            ),
+            Not(expr) => Not(Box::new(expr.canonicalize())),
+            Parenthesis(expr) => expr.canonicalize(),
+            leaf => leaf,
         }
-        free_variables
     }
 }
 
 impl super::Sample for BooleanValueDomainExpression {
-    fn sample(&self) -> Option<super::AssignedValue> {
+    fn try_sample(&self) -> Result<super::AssignedValue, super::SampleError> {
         use BooleanValueDomainExpression::*;
         match self {
-            Empty => None,
-            Single(val) => Some(super::AssignedValue::Boolean(val.clone())),
-            Universe => Some(super::AssignedValue::Boolean(BooleanValue::False)),
+            Empty => Err(super::SampleError::EmptyDomain),
+            Single(val) => Ok(super::AssignedValue::Boolean(val.clone())),
+            Universe => Ok(super::AssignedValue::Boolean(BooleanValue::False)),
         }
     }
 }
 
+impl super::ValueDomain for BooleanValueDomainExpression {
+    fn candidates(&self) -> Option<Vec<super::AssignedValue>> {
+        use BooleanValueDomainExpression::*;
+        let values = match self {
+            Empty => Vec::new(),
+            Single(val) => vec![val.clone()],
+            Universe => vec![BooleanValue::False, BooleanValue::True],
+        };
+        Some(
+            values
+                .into_iter()
+                .map(super::AssignedValue::Boolean)
+                .collect(),
+        )
+    }
+    fn contains(&self, value: &super::AssignedValue) -> bool {
+        use BooleanValueDomainExpression::*;
+        match (self, value) {
+            (Single(val), super::AssignedValue::Boolean(value)) => val == value,
+            (Universe, super::AssignedValue::Boolean(_)) => true,
+            _ => false,
+        }
+    }
+    fn cardinality(&self) -> Option<u128> {
+        use BooleanValueDomainExpression::*;
+        Some(match self {
+            Empty => 0,
+            Single(_) => 1,
+            Universe => 2,
+        })
+    }
+    fn clone_box(&self) -> Box<dyn super::ValueDomain> {
+        Box::new(self.clone())
+    }
+    fn identity(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{BooleanExpression, BooleanValue};
+    use crate::expressions::tests::{below, leaf, shrink_node, ArbitraryAt};
     use quickcheck::{Arbitrary, Gen};
 
     impl Arbitrary for BooleanValue {
@@ -88,20 +946,313 @@ mod tests {
                 BooleanValue::True
             }
         }
+        fn shrink(&self) -> Box<dyn Iterator<Item = BooleanValue>> {
+            match self {
+                BooleanValue::True => quickcheck::single_shrinker(BooleanValue::False),
+                BooleanValue::False => quickcheck::empty_shrinker(),
+            }
+        }
+    }
+
+    impl ArbitraryAt for BooleanExpression {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> BooleanExpression {
+            if leaf(g, depth) {
+                return match u32::arbitrary(g) % 16 {
+                    0 => BooleanExpression::BooleanValue(Arbitrary::arbitrary(g)),
+                    _ => BooleanExpression::BooleanVariable(Arbitrary::arbitrary(g)),
+                };
+            }
+            // Variables take the cases left over, which keeps the expected
+            // number of operands well below one and the trees shallow.
+            match u32::arbitrary(g) % 24 {
+                0 => BooleanExpression::And(below(g, depth), below(g, depth)),
+                1 => BooleanExpression::Or(below(g, depth), below(g, depth)),
+                2 => BooleanExpression::Implies(below(g, depth), below(g, depth)),
+                3 => BooleanExpression::Equals(below(g, depth), below(g, depth)),
+                4 => BooleanExpression::Xor(below(g, depth), below(g, depth)),
+                5 => BooleanExpression::Parenthesis(below(g, depth)),
+                6 => BooleanExpression::Not(below(g, depth)),
+                7 => BooleanExpression::BooleanValue(Arbitrary::arbitrary(g)),
+                8 => {
+                    BooleanExpression::IfThenElse(below(g, depth), below(g, depth), below(g, depth))
+                }
+                _ => BooleanExpression::BooleanVariable(Arbitrary::arbitrary(g)),
+            }
+        }
     }
 
     impl Arbitrary for BooleanExpression {
         fn arbitrary(g: &mut Gen) -> BooleanExpression {
-            match u32::arbitrary(g) % 16 {
-                0 => BooleanExpression::And(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g)),
-                1 => BooleanExpression::Or(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g)),
-                2 => BooleanExpression::Implies(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g)),
-                3 => BooleanExpression::Equals(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g)),
-                4 => BooleanExpression::Parenthesis(Arbitrary::arbitrary(g)),
-                5 => BooleanExpression::Not(Arbitrary::arbitrary(g)),
-                6 => BooleanExpression::BooleanValue(Arbitrary::arbitrary(g)),
-                _ => BooleanExpression::BooleanVariable(Arbitrary::arbitrary(g)),
+            BooleanExpression::arbitrary_at(g, 0)
+        }
+        fn shrink(&self) -> Box<dyn Iterator<Item = BooleanExpression>> {
+            use BooleanExpression::*;
+            let pair =
+                |expr_a: &BooleanExpression,
+                 expr_b: &BooleanExpression,
+                 rebuild: fn(Box<BooleanExpression>, Box<BooleanExpression>) -> _| {
+                    shrink_node(
+                        vec![expr_a.clone(), expr_b.clone()],
+                        (Box::new(expr_a.clone()), Box::new(expr_b.clone())),
+                        move |(expr_a, expr_b)| rebuild(expr_a, expr_b),
+                    )
+                };
+            match self {
+                BooleanVariable(_) => quickcheck::empty_shrinker(),
+                BooleanValue(value) => Box::new(value.shrink().map(BooleanValue)),
+                Not(expr) => shrink_node(vec![*expr.clone()], expr.clone(), Not),
+                Parenthesis(expr) => shrink_node(vec![*expr.clone()], expr.clone(), Parenthesis),
+                And(expr_a, expr_b) => pair(expr_a, expr_b, And),
+                Or(expr_a, expr_b) => pair(expr_a, expr_b, Or),
+                Implies(expr_a, expr_b) => pair(expr_a, expr_b, Implies),
+                Equals(expr_a, expr_b) => pair(expr_a, expr_b, Equals),
+                Xor(expr_a, expr_b) => pair(expr_a, expr_b, Xor),
+                IfThenElse(condition, then, otherwise) => shrink_node(
+                    vec![*condition.clone(), *then.clone(), *otherwise.clone()],
+                    (condition.clone(), then.clone(), otherwise.clone()),
+                    |(condition, then, otherwise)| IfThenElse(condition, then, otherwise),
+                ),
+            }
+        }
+    }
+
+    /// Whether two expressions agree under every assignment of their free
+    /// variables.
+    pub(crate) fn equivalent(a: &BooleanExpression, b: &BooleanExpression) -> bool {
+        use crate::expressions::{AssignedValue, Assignment, Evaluate, FreeVariable};
+        let mut names = Vec::new();
+        for variable in a.get_free().into_iter().chain(b.get_free()) {
+            if !names.contains(variable.name()) {
+                names.push(variable.name().clone());
+            }
+        }
+        (0..1u64 << names.len()).all(|row| {
+            let env: Vec<Assignment> = names
+                .iter()
+                .enumerate()
+                .map(|(bit, name)| {
+                    let value = if row >> bit & 1 == 1 {
+                        BooleanValue::True
+                    } else {
+                        BooleanValue::False
+                    };
+                    Assignment::new(name.clone(), AssignedValue::Boolean(value))
+                })
+                .collect();
+            a.evaluate(&env) == b.evaluate(&env)
+        })
+    }
+
+    fn var(name: &str) -> Box<BooleanExpression> {
+        Box::new(BooleanExpression::BooleanVariable(
+            crate::expressions::Symbol::new(name.to_string()),
+        ))
+    }
+
+    #[test]
+    fn factor_pulls_out_a_common_conjunct() {
+        use BooleanExpression::*;
+        let expr = Or(
+            Box::new(And(var("a"), var("b"))),
+            Box::new(And(var("a"), var("c"))),
+        );
+        assert_eq!(
+            super::factor(expr),
+            And(var("a"), Box::new(Or(var("b"), var("c"))))
+        );
+    }
+
+    #[test]
+    fn distribute_spreads_a_conjunct_over_a_disjunction() {
+        use BooleanExpression::*;
+        let expr = And(var("a"), Box::new(Or(var("b"), var("c"))));
+        assert_eq!(
+            super::distribute(expr),
+            Or(
+                Box::new(And(var("a"), var("b"))),
+                Box::new(And(var("a"), var("c")))
+            )
+        );
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn factor_and_distribute_preserve_the_truth_function(
+        expr: BooleanExpression,
+    ) -> quickcheck::TestResult {
+        use crate::expressions::FreeVariable;
+        if expr.get_free().len() > 10 {
+            return quickcheck::TestResult::discard();
+        }
+        quickcheck::TestResult::from_bool(
+            equivalent(&expr, &super::factor(expr.clone()))
+                && equivalent(&expr, &super::distribute(expr.clone())),
+        )
+    }
+
+    #[test]
+    fn conjuncts_simplify_each_other() {
+        use super::simplify_in_context;
+        use BooleanExpression::*;
+        let expr = And(var("x"), Box::new(Or(Box::new(Not(var("x"))), var("y"))));
+        assert_eq!(simplify_in_context(expr), And(var("x"), var("y")));
+        let expr = And(var("x"), Box::new(Implies(var("x"), var("y"))));
+        assert_eq!(simplify_in_context(expr), And(var("x"), var("y")));
+        let expr = Or(var("x"), Box::new(And(Box::new(Not(var("x"))), var("y"))));
+        assert_eq!(simplify_in_context(expr), Or(var("x"), var("y")));
+        assert_eq!(simplify_in_context(And(var("x"), var("x"))), *var("x"));
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn simplify_in_context_preserves_the_truth_function(
+        expr: BooleanExpression,
+    ) -> quickcheck::TestResult {
+        use crate::expressions::FreeVariable;
+        if expr.get_free().len() > 10 {
+            return quickcheck::TestResult::discard();
+        }
+        quickcheck::TestResult::from_bool(equivalent(
+            &expr,
+            &super::simplify_in_context(expr.clone()),
+        ))
+    }
+
+    #[test]
+    fn evaluation_reports_unbound_variables() {
+        use crate::expressions::{AssignedValue, Assignment, EvalError, Evaluate, Symbol};
+        let env = [Assignment::new(
+            Symbol::new("p".to_string()),
+            AssignedValue::Boolean(BooleanValue::True),
+        )];
+        let implies = BooleanExpression::Implies(var("p"), var("q"));
+        assert_eq!(
+            implies.evaluate(&env),
+            Err(EvalError::Unbound(Symbol::new("q".to_string())))
+        );
+        let or = BooleanExpression::Or(var("p"), var("q"));
+        assert_eq!(or.evaluate(&env), Ok(BooleanValue::True));
+        let equals = BooleanExpression::Equals(
+            var("p"),
+            Box::new(BooleanExpression::Parenthesis(Box::new(
+                BooleanExpression::Not(var("p")),
+            ))),
+        );
+        assert_eq!(equals.evaluate(&env), Ok(BooleanValue::False));
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn negation_normal_form_negates_only_variables(
+        expr: BooleanExpression,
+    ) -> quickcheck::TestResult {
+        use crate::expressions::FreeVariable;
+        use BooleanExpression::*;
+        fn normal(expr: &BooleanExpression) -> bool {
+            match expr {
+                And(expr_a, expr_b) | Or(expr_a, expr_b) => normal(expr_a) && normal(expr_b),
+                Not(expr) => matches!(**expr, BooleanVariable(_)),
+                BooleanVariable(_) | BooleanValue(_) => true,
+                Implies(_, _) | Equals(_, _) | Xor(_, _) | IfThenElse(_, _, _) | Parenthesis(_) => {
+                    false
+                }
             }
         }
+        if expr.get_free().len() > 10 {
+            return quickcheck::TestResult::discard();
+        }
+        let nnf = expr.to_nnf();
+        quickcheck::TestResult::from_bool(normal(&nnf) && equivalent(&expr, &nnf))
+    }
+
+    #[test]
+    fn negations_are_pushed_to_the_variables() {
+        use BooleanExpression::*;
+        let expr = Not(Box::new(Implies(
+            var("a"),
+            Box::new(Parenthesis(Box::new(Or(var("b"), Box::new(Not(var("c"))))))),
+        )));
+        assert_eq!(expr.to_nnf().to_string(), "a and (not b and c)");
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn simplify_preserves_the_truth_function(expr: BooleanExpression) -> quickcheck::TestResult {
+        use crate::expressions::FreeVariable;
+        if expr.get_free().len() > 10 {
+            return quickcheck::TestResult::discard();
+        }
+        quickcheck::TestResult::from_bool(equivalent(&expr, &expr.simplify()))
+    }
+
+    #[test]
+    fn constants_fold_away() {
+        use BooleanExpression::*;
+        let t = || Box::new(BooleanValue(super::BooleanValue::True));
+        let f = || Box::new(BooleanValue(super::BooleanValue::False));
+        assert_eq!(And(var("x"), t()).simplify(), *var("x"));
+        assert_eq!(And(var("x"), f()).simplify(), *f());
+        assert_eq!(Or(var("x"), f()).simplify(), *var("x"));
+        assert_eq!(Or(t(), var("x")).simplify(), *t());
+        assert_eq!(Implies(f(), var("x")).simplify(), *t());
+        assert_eq!(Not(Box::new(Parenthesis(t()))).simplify(), *f());
+        assert_eq!(Equals(Box::new(Not(var("x"))), f()).simplify(), *var("x"));
+        assert_eq!(
+            Or(Box::new(And(var("x"), var("x"))), var("x")).simplify(),
+            *var("x")
+        );
+    }
+
+    #[test]
+    fn xor_holds_for_exactly_one_operand() {
+        use crate::expressions::{AssignedValue, Assignment, Evaluate, Symbol};
+        use BooleanExpression::*;
+        let expr: BooleanExpression = "a or b xor c and d".parse().unwrap();
+        assert_eq!(
+            expr,
+            Or(
+                var("a"),
+                Box::new(Xor(var("b"), Box::new(And(var("c"), var("d")))))
+            )
+        );
+        assert_eq!(expr.to_string(), "a or b xor c and d");
+        let xor = Xor(var("p"), var("q"));
+        for (p, q) in [(false, false), (false, true), (true, false), (true, true)] {
+            let env = [("p", p), ("q", q)].map(|(name, value)| {
+                Assignment::new(
+                    Symbol::new(name.to_string()),
+                    AssignedValue::Boolean(super::BooleanValue::from_bool(value)),
+                )
+            });
+            assert_eq!(
+                xor.evaluate(&env),
+                Ok(super::BooleanValue::from_bool(p != q))
+            );
+        }
+        let t = || Box::new(BooleanValue(super::BooleanValue::True));
+        assert_eq!(Xor(var("x"), t()).simplify(), Not(var("x")));
+        assert_eq!(Xor(var("x"), var("x")).simplify().to_string(), "false");
+        assert_eq!(
+            Not(Box::new(xor)).to_nnf().to_string(),
+            "p and q or not p and not q"
+        );
+    }
+
+    #[test]
+    fn ite_chooses_between_its_branches() {
+        use super::simplify_in_context;
+        use BooleanExpression::*;
+        let expr: BooleanExpression = "not ite(c, a or b, false) and d".parse().unwrap();
+        assert_eq!(expr.to_string(), "not ite(c, a or b, false) and d");
+        let ite = |c, a, b| IfThenElse(c, a, b);
+        let t = || Box::new(BooleanValue(super::BooleanValue::True));
+        let f = || Box::new(BooleanValue(super::BooleanValue::False));
+        assert_eq!(ite(t(), var("a"), var("b")).simplify(), *var("a"));
+        assert_eq!(ite(var("c"), t(), f()).simplify(), *var("c"));
+        assert_eq!(ite(var("c"), var("a"), var("a")).simplify(), *var("a"));
+        assert_eq!(
+            simplify_in_context(ite(var("c"), var("c"), Box::new(Not(var("c"))))),
+            *t()
+        );
+        assert_eq!(
+            ite(var("c"), var("a"), var("b")).to_nnf().to_string(),
+            "c and a or not c and b"
+        );
     }
 }
@@ -0,0 +1,344 @@
+//! Combinators for building programs in code. Each returns exactly the
+//! tree that would be built by hand, without adding `Parenthesis` nodes,
+//! so
+//! ```
+//! use clp::expressions::dsl::*;
+//! let program = constrain(
+//!     is_in(int_var("x"), closed(0, 9)),
+//!     solve(satisfy(less(int(2) * int_var("x") + 1, int_var("y")))),
+//! );
+//! assert_eq!(program.to_string(), "x in [0, 9];\nsatisfy 2 * x + 1 < y;");
+//! ```
+//! Integer expressions are wrapped in `Int` so that the arithmetic
+//! operators build `Add`, `Minus`, `Times`, `Divide`, `Modulo` and
+//! `Negate` nodes.
+use std::ops;
+
+use super::boolean::{BooleanExpression, BooleanValue};
+use super::integer::{
+    BooleanIntegerNumberExpression, IntegerNumber, IntegerNumberDomainExpression,
+    IntegerNumberExpression,
+};
+use super::{
+    ConstraintLogicExpression, ConstraintProgramExpression, SatisfactionExpression, Symbol,
+};
+
+/// An integer expression under construction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Int(pub IntegerNumberExpression);
+
+impl From<i128> for Int {
+    fn from(n: i128) -> Int {
+        int(n)
+    }
+}
+
+impl From<Int> for Box<IntegerNumberExpression> {
+    fn from(expr: Int) -> Box<IntegerNumberExpression> {
+        Box::new(expr.0)
+    }
+}
+
+macro_rules! binary_operator {
+    ($trait:ident, $method:ident, $variant:ident) => {
+        impl<T: Into<Int>> ops::$trait<T> for Int {
+            type Output = Int;
+            fn $method(self, other: T) -> Int {
+                Int(IntegerNumberExpression::$variant(
+                    self.into(),
+                    other.into().into(),
+                ))
+            }
+        }
+    };
+}
+
+binary_operator!(Add, add, Add);
+binary_operator!(Sub, sub, Minus);
+binary_operator!(Mul, mul, Times);
+binary_operator!(Div, div, Divide);
+binary_operator!(Rem, rem, Modulo);
+
+impl ops::Neg for Int {
+    type Output = Int;
+    fn neg(self) -> Int {
+        Int(IntegerNumberExpression::Negate(self.into()))
+    }
+}
+
+fn symbol(name: &str) -> Symbol {
+    Symbol::new(name.to_string())
+}
+
+/// The boolean variable `name`.
+pub fn var(name: &str) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::BooleanVariable(symbol(name)))
+}
+
+/// The boolean constant `value`.
+pub fn boolean(value: bool) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::BooleanValue(BooleanValue::from_bool(
+        value,
+    )))
+}
+
+pub fn and(
+    expr_a: Box<BooleanExpression>,
+    expr_b: Box<BooleanExpression>,
+) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::And(expr_a, expr_b))
+}
+
+pub fn or(
+    expr_a: Box<BooleanExpression>,
+    expr_b: Box<BooleanExpression>,
+) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::Or(expr_a, expr_b))
+}
+
+pub fn implies(
+    expr_a: Box<BooleanExpression>,
+    expr_b: Box<BooleanExpression>,
+) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::Implies(expr_a, expr_b))
+}
+
+/// Boolean equivalence, written `equals`.
+pub fn equals(
+    expr_a: Box<BooleanExpression>,
+    expr_b: Box<BooleanExpression>,
+) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::Equals(expr_a, expr_b))
+}
+
+/// Exclusive or, written `xor`.
+pub fn xor(
+    expr_a: Box<BooleanExpression>,
+    expr_b: Box<BooleanExpression>,
+) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::Xor(expr_a, expr_b))
+}
+
+pub fn not(expr: Box<BooleanExpression>) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::Not(expr))
+}
+
+/// `then` when `condition` holds and `otherwise` when it does not,
+/// written `ite`.
+pub fn ite(
+    condition: Box<BooleanExpression>,
+    then: Box<BooleanExpression>,
+    otherwise: Box<BooleanExpression>,
+) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::IfThenElse(condition, then, otherwise))
+}
+
+/// Parentheses around a boolean expression.
+pub fn group(expr: Box<BooleanExpression>) -> Box<BooleanExpression> {
+    Box::new(BooleanExpression::Parenthesis(expr))
+}
+
+/// The integer variable `name`.
+pub fn int_var(name: &str) -> Int {
+    Int(IntegerNumberExpression::IntegerNumberVariable(symbol(name)))
+}
+
+/// The integer constant `n`.
+pub fn int(n: i128) -> Int {
+    Int(IntegerNumberExpression::IntegerNumberValue(
+        IntegerNumber::Value(n),
+    ))
+}
+
+/// Parentheses around an integer expression.
+pub fn parenthesised(expr: impl Into<Int>) -> Int {
+    Int(IntegerNumberExpression::Parenthesis(expr.into().into()))
+}
+
+/// The integer `then` when `condition` holds and `otherwise` when it
+/// does not, written `ite`.
+pub fn int_ite(
+    condition: Box<BooleanExpression>,
+    then: impl Into<Int>,
+    otherwise: impl Into<Int>,
+) -> Int {
+    Int(IntegerNumberExpression::IfThenElse(
+        condition,
+        then.into().into(),
+        otherwise.into().into(),
+    ))
+}
+
+/// The sum of `exprs`, written `sum`.
+pub fn sum<T: Into<Int>>(exprs: impl IntoIterator<Item = T>) -> Int {
+    Int(IntegerNumberExpression::Sum(
+        exprs.into_iter().map(|expr| expr.into().0).collect(),
+    ))
+}
+
+/// The product of `exprs`, written `product`.
+pub fn product<T: Into<Int>>(exprs: impl IntoIterator<Item = T>) -> Int {
+    Int(IntegerNumberExpression::Product(
+        exprs.into_iter().map(|expr| expr.into().0).collect(),
+    ))
+}
+
+/// Integer equality, written `=`.
+pub fn equal(
+    expr_a: impl Into<Int>,
+    expr_b: impl Into<Int>,
+) -> Box<BooleanIntegerNumberExpression> {
+    Box::new(BooleanIntegerNumberExpression::Equals(
+        expr_a.into().into(),
+        expr_b.into().into(),
+    ))
+}
+
+pub fn different(
+    expr_a: impl Into<Int>,
+    expr_b: impl Into<Int>,
+) -> Box<BooleanIntegerNumberExpression> {
+    Box::new(BooleanIntegerNumberExpression::Different(
+        expr_a.into().into(),
+        expr_b.into().into(),
+    ))
+}
+
+pub fn less(expr_a: impl Into<Int>, expr_b: impl Into<Int>) -> Box<BooleanIntegerNumberExpression> {
+    Box::new(BooleanIntegerNumberExpression::Less(
+        expr_a.into().into(),
+        expr_b.into().into(),
+    ))
+}
+
+pub fn greater(
+    expr_a: impl Into<Int>,
+    expr_b: impl Into<Int>,
+) -> Box<BooleanIntegerNumberExpression> {
+    Box::new(BooleanIntegerNumberExpression::Greater(
+        expr_a.into().into(),
+        expr_b.into().into(),
+    ))
+}
+
+/// Membership of `domain`, written `in`.
+pub fn is_in(
+    expr: impl Into<Int>,
+    domain: IntegerNumberDomainExpression,
+) -> Box<BooleanIntegerNumberExpression> {
+    Box::new(BooleanIntegerNumberExpression::In(
+        expr.into().into(),
+        Box::new(domain),
+    ))
+}
+
+/// The integers from `low` to `high`, both included.
+pub fn closed(low: impl Into<Int>, high: impl Into<Int>) -> IntegerNumberDomainExpression {
+    IntegerNumberDomainExpression::ClosedRange(low.into().into(), high.into().into())
+}
+
+/// The listed integers.
+pub fn set(elements: impl IntoIterator<Item = i128>) -> IntegerNumberDomainExpression {
+    IntegerNumberDomainExpression::ExplicitSet(elements.into_iter().map(|n| int(n).0).collect())
+}
+
+/// The constraint that no two of `exprs` are equal, written
+/// `alldifferent`.
+pub fn all_different<T: Into<Int>>(
+    exprs: impl IntoIterator<Item = T>,
+) -> ConstraintLogicExpression {
+    ConstraintLogicExpression::AllDifferent(exprs.into_iter().map(|expr| expr.into().0).collect())
+}
+
+/// A boolean expression or an integer comparison, usable as a constraint.
+pub trait Constraint {
+    fn into_constraint(self) -> ConstraintLogicExpression;
+}
+
+impl Constraint for Box<BooleanExpression> {
+    fn into_constraint(self) -> ConstraintLogicExpression {
+        ConstraintLogicExpression::Boolean(self)
+    }
+}
+
+impl Constraint for Box<BooleanIntegerNumberExpression> {
+    fn into_constraint(self) -> ConstraintLogicExpression {
+        ConstraintLogicExpression::OfIntegerNumber(self)
+    }
+}
+
+impl Constraint for ConstraintLogicExpression {
+    fn into_constraint(self) -> ConstraintLogicExpression {
+        self
+    }
+}
+
+pub fn satisfy(constraint: impl Constraint) -> Box<SatisfactionExpression> {
+    Box::new(SatisfactionExpression::Satisfy(Box::new(
+        constraint.into_constraint(),
+    )))
+}
+
+pub fn minimise(constraint: impl Constraint) -> Box<SatisfactionExpression> {
+    Box::new(SatisfactionExpression::Minimise(Box::new(
+        constraint.into_constraint(),
+    )))
+}
+
+pub fn maximise(constraint: impl Constraint) -> Box<SatisfactionExpression> {
+    Box::new(SatisfactionExpression::Maximise(Box::new(
+        constraint.into_constraint(),
+    )))
+}
+
+/// The program ending in `goal`.
+pub fn solve(goal: Box<SatisfactionExpression>) -> ConstraintProgramExpression {
+    ConstraintProgramExpression::Solve(goal)
+}
+
+/// The goal `goal` followed by the program `rest`.
+pub fn solve_and(
+    goal: Box<SatisfactionExpression>,
+    rest: ConstraintProgramExpression,
+) -> ConstraintProgramExpression {
+    ConstraintProgramExpression::SolveAnd(goal, Box::new(rest))
+}
+
+/// The constraint `constraint` followed by the program `rest`.
+pub fn constrain(
+    constraint: impl Constraint,
+    rest: ConstraintProgramExpression,
+) -> ConstraintProgramExpression {
+    ConstraintProgramExpression::ConstrainAnd(
+        Box::new(constraint.into_constraint()),
+        Box::new(rest),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expressions::parser::parse_program;
+
+    #[test]
+    fn combinators_build_the_parsed_tree() {
+        let program = constrain(
+            is_in(int_var("x"), closed(0, 9)),
+            constrain(
+                implies(and(var("p"), not(var("q"))), var("r")),
+                solve_and(
+                    satisfy(different(-int_var("y") % 3, int(2) - int_var("x"))),
+                    solve(maximise(less(
+                        int(2) * parenthesised(int_var("x") + int_var("y")) / 4,
+                        10,
+                    ))),
+                ),
+            ),
+        );
+        let source = "x in [0, 9];\n\
+                      p and not q implies r;\n\
+                      satisfy - y % 3 != 2 - x;\n\
+                      maximise 2 * (x + y) / 4 < 10;";
+        assert_eq!(program, parse_program(source).unwrap());
+    }
+}
@@ -1,24 +1,80 @@
-/// The possible values for integer numbers.
-#[derive(Debug, Clone, PartialEq, Eq)]
+use std::fmt;
+use std::ops::{ControlFlow, RangeInclusive};
+
+use super::visit::{FreeVariables, Visitor};
+
+/// The possible values for integer numbers. With the `serde` feature a
+/// value is written as a decimal string, since JSON readers commonly
+/// lose precision beyond 53 bits.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IntegerNumber {
     NaN,
-    Value(i128),
+    Value(#[cfg_attr(feature = "serde", serde(with = "decimal"))] i128),
+}
+
+/// Writes an `i128` as a decimal string and reads it back.
+#[cfg(feature = "serde")]
+pub(super) mod decimal {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i128, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IntegerNumberExpression {
     IntegerNumberVariable(super::Symbol),
     IntegerNumberValue(IntegerNumber),
     Parenthesis(Box<IntegerNumberExpression>),
     Negate(Box<IntegerNumberExpression>),
+    /// The absolute value.
+    Abs(Box<IntegerNumberExpression>),
     Add(Box<IntegerNumberExpression>, Box<IntegerNumberExpression>),
     Minus(Box<IntegerNumberExpression>, Box<IntegerNumberExpression>),
     Times(Box<IntegerNumberExpression>, Box<IntegerNumberExpression>),
     Divide(Box<IntegerNumberExpression>, Box<IntegerNumberExpression>),
     Modulo(Box<IntegerNumberExpression>, Box<IntegerNumberExpression>),
+    /// The sum of the expressions, `0` when there are none.
+    Sum(Vec<IntegerNumberExpression>),
+    /// The product of the expressions, `1` when there are none.
+    Product(Vec<IntegerNumberExpression>),
+    Pow(Box<IntegerNumberExpression>, Box<IntegerNumberExpression>),
+    PowMod(
+        Box<IntegerNumberExpression>,
+        Box<IntegerNumberExpression>,
+        Box<IntegerNumberExpression>,
+    ),
+    /// The integer component of a tuple at an index, counted from zero.
+    TupleGet(Box<super::tuple::TupleExpression>, usize),
+    /// `Let(symbol, value, body)` is `body` with `symbol` standing for the
+    /// value of `value`. The symbol is bound in `body` only.
+    Let(
+        super::Symbol,
+        Box<IntegerNumberExpression>,
+        Box<IntegerNumberExpression>,
+    ),
+    /// `1` when the boolean expression is true and `0` when it is false.
+    OfBoolean(Box<super::boolean::BooleanExpression>),
+    /// `IfThenElse(condition, then, otherwise)` is `then` when the
+    /// condition is true and `otherwise` when it is false.
+    IfThenElse(
+        Box<super::boolean::BooleanExpression>,
+        Box<IntegerNumberExpression>,
+        Box<IntegerNumberExpression>,
+    ),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IntegerNumberDomainExpression {
     Universe,
     Empty,
@@ -42,99 +98,537 @@ pub enum IntegerNumberDomainExpression {
     Complement(Box<IntegerNumberDomainExpression>),
 }
 
-impl super::FreeVariable for IntegerNumberExpression {
-    fn get_free(&self) -> Vec<super::Variable> {
+impl fmt::Display for IntegerNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntegerNumber::NaN => write!(f, "NaN"),
+            IntegerNumber::Value(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl IntegerNumberExpression {
+    /// Binding strength in the concrete syntax, from `+` and `-` (loosest)
+    /// through `*`, `/` and `%`, unary `-` and `^` to atoms.
+    fn precedence(&self) -> u8 {
         use IntegerNumberExpression::*;
-        let mut free: Vec<super::Variable> = Vec::new();
         match self {
-            IntegerNumberValue(_) => (),
-            IntegerNumberVariable(symbol) => free.push(super::Variable {
-                name: symbol.clone(),
-                domain: super::Domain::Integer(IntegerNumberDomainExpression::Universe),
-            }),
-            Parenthesis(expr) => free.extend(expr.get_free()),
-            Negate(expr) => free.extend(expr.get_free()),
-            Add(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            Add(_, _) | Minus(_, _) => 1,
+            Times(_, _) | Divide(_, _) | Modulo(_, _) => 2,
+            Negate(_) => 3,
+            Pow(_, _) => 4,
+            IntegerNumberVariable(_)
+            | IntegerNumberValue(_)
+            | Parenthesis(_)
+            | PowMod(_, _, _)
+            | Abs(_)
+            | TupleGet(_, _)
+            | Let(_, _, _)
+            | OfBoolean(_)
+            | IfThenElse(_, _, _)
+            | Sum(_)
+            | Product(_) => 5,
+        }
+    }
+
+    /// The expression inside any parentheses around it.
+    fn unparenthesised(&self) -> &IntegerNumberExpression {
+        match self {
+            IntegerNumberExpression::Parenthesis(expr) => expr.unparenthesised(),
+            expr => expr,
+        }
+    }
+
+    /// Rewrites the expression into a simpler one with the same value
+    /// under every assignment of numbers to its variables. Operators whose
+    /// operands are all constants are folded, with the remainder taking
+    /// the sign of the dividend as in the evaluator, and divisions and
+    /// remainders by a literal zero become `NaN` whatever the dividend. `x + 0`, `0 + x`, `x - 0`, `x * 1`, `1 * x`
+    /// and `x / 1` become `x`, while `x * 0`, `0 * x` and `x % 1` become
+    /// `0` only for a variable or number `x`, since any other operand may
+    /// be `NaN`. `0 % x` is left alone, since `0 % 0` is `NaN`, and so is
+    /// `- - x`, since negating the smallest `i128` is `NaN`. `Parenthesis`
+    /// nodes are dropped; printing adds the parentheses precedence needs.
+    /// Integer components are projected out of tuples written in full,
+    /// and an `ite` whose condition simplifies to a constant becomes the
+    /// chosen branch. A `sum` or `product` takes in the operands of those
+    /// of its own kind nested in it, folds its constants into one placed
+    /// last and drops it when it is the identity, so that an empty one
+    /// becomes `0` or `1` and one with a single operand that operand. A
+    /// constant `0` makes a product `0` only when every other operand is
+    /// a variable or a number.
+    pub fn simplify(&self) -> IntegerNumberExpression {
+        use super::Evaluate;
+        use IntegerNumberExpression::*;
+        let zero = IntegerNumberValue(IntegerNumber::Value(0));
+        let one = IntegerNumberValue(IntegerNumber::Value(1));
+        let nan = IntegerNumberValue(IntegerNumber::NaN);
+        let fold = |expr: IntegerNumberExpression| {
+            let constant = expr
+                .operands()
+                .iter()
+                .all(|operand| matches!(operand, IntegerNumberValue(_)));
+            match expr.evaluate(&[]) {
+                Ok(value) if constant => IntegerNumberValue(value),
+                _ => expr,
             }
-            Minus(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+        };
+        let number = |expr: &IntegerNumberExpression| {
+            matches!(
+                expr,
+                IntegerNumberVariable(_) | IntegerNumberValue(IntegerNumber::Value(_))
+            )
+        };
+        let simplified = |expr: &IntegerNumberExpression| Box::new(expr.simplify());
+        match self {
+            IntegerNumberValue(_) | IntegerNumberVariable(_) => self.clone(),
+            TupleGet(tuple, index) => match tuple.as_ref() {
+                super::tuple::TupleExpression::Tuple(components) => match components.get(*index) {
+                    Some(super::tuple::TupleComponent::Integer(expr)) => expr.simplify(),
+                    _ => self.clone(),
+                },
+                _ => self.clone(),
+            },
+            Let(symbol, value, body) => Let(symbol.clone(), simplified(value), simplified(body)),
+            OfBoolean(expr) => fold(OfBoolean(Box::new(super::boolean::simplify_in_context(
+                expr.as_ref().clone(),
+            )))),
+            IfThenElse(condition, then, otherwise) => {
+                let condition = super::boolean::simplify_in_context(condition.as_ref().clone());
+                match condition.evaluate(&[]) {
+                    Ok(super::boolean::BooleanValue::True) => then.simplify(),
+                    Ok(super::boolean::BooleanValue::False) => otherwise.simplify(),
+                    Err(_) => match (then.simplify(), otherwise.simplify()) {
+                        (then, otherwise) if then == otherwise => then,
+                        (then, otherwise) => {
+                            IfThenElse(Box::new(condition), Box::new(then), Box::new(otherwise))
+                        }
+                    },
+                }
             }
-            Times(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            Parenthesis(expr) => expr.simplify(),
+            Negate(expr) => fold(Negate(simplified(expr))),
+            Abs(expr) => fold(Abs(simplified(expr))),
+            Add(expr_a, expr_b) => match (expr_a.simplify(), expr_b.simplify()) {
+                (expr, other) | (other, expr) if other == zero => expr,
+                (expr_a, expr_b) => fold(Add(Box::new(expr_a), Box::new(expr_b))),
+            },
+            Minus(expr_a, expr_b) => match (expr_a.simplify(), expr_b.simplify()) {
+                (expr_a, expr_b) if expr_b == zero => expr_a,
+                (expr_a, expr_b) => fold(Minus(Box::new(expr_a), Box::new(expr_b))),
+            },
+            Times(expr_a, expr_b) => match (expr_a.simplify(), expr_b.simplify()) {
+                (expr, other) | (other, expr) if other == one => expr,
+                (expr, other) | (other, expr) if other == zero && number(&expr) => zero,
+                (expr_a, expr_b) => fold(Times(Box::new(expr_a), Box::new(expr_b))),
+            },
+            Pow(expr_a, expr_b) => fold(Pow(simplified(expr_a), simplified(expr_b))),
+            PowMod(expr_a, expr_b, expr_c) => fold(PowMod(
+                simplified(expr_a),
+                simplified(expr_b),
+                simplified(expr_c),
+            )),
+            Divide(expr_a, expr_b) => match (expr_a.simplify(), expr_b.simplify()) {
+                (_, expr_b) if expr_b == zero => nan,
+                (expr_a, expr_b) if expr_b == one => expr_a,
+                (expr_a, expr_b) => fold(Divide(Box::new(expr_a), Box::new(expr_b))),
+            },
+            Modulo(expr_a, expr_b) => match (expr_a.simplify(), expr_b.simplify()) {
+                (_, expr_b) if expr_b == zero => nan,
+                (expr_a, expr_b) if expr_b == one && number(&expr_a) => zero,
+                (expr_a, expr_b) => fold(Modulo(Box::new(expr_a), Box::new(expr_b))),
+            },
+            Sum(exprs) | Product(exprs) => {
+                let sum = matches!(self, Sum(_));
+                let (identity, join): (_, fn(Vec<IntegerNumberExpression>) -> _) = if sum {
+                    (zero.clone(), Sum)
+                } else {
+                    (one.clone(), Product)
+                };
+                let mut operands = Vec::new();
+                let mut constants = Vec::new();
+                for expr in exprs.iter().map(Self::simplify) {
+                    let nested = match expr {
+                        Sum(nested) if sum => nested,
+                        Product(nested) if !sum => nested,
+                        expr => vec![expr],
+                    };
+                    for expr in nested {
+                        match expr {
+                            IntegerNumberValue(IntegerNumber::NaN) => return nan,
+                            IntegerNumberValue(_) => constants.push(expr),
+                            expr => operands.push(expr),
+                        }
+                    }
+                }
+                // Evaluation is exact, so the constants fold into one
+                // whenever their result fits, whatever else is summed.
+                match join(constants.clone()).evaluate(&[]) {
+                    Ok(IntegerNumber::Value(0)) if !sum && operands.iter().all(number) => {
+                        return zero
+                    }
+                    Ok(value) if IntegerNumberValue(value.clone()) == identity => (),
+                    Ok(IntegerNumber::Value(n)) => {
+                        operands.push(IntegerNumberValue(IntegerNumber::Value(n)))
+                    }
+                    _ => operands.extend(constants),
+                }
+                match operands.len() {
+                    0 => identity,
+                    1 => operands.remove(0),
+                    _ => join(operands),
+                }
             }
-            Divide(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+        }
+    }
+
+    /// The operands of an operator. Leaves, tuple projections, `Let` and
+    /// conditionals have none, so searches do not look inside them.
+    fn operands(&self) -> Vec<&IntegerNumberExpression> {
+        use IntegerNumberExpression::*;
+        match self {
+            Parenthesis(expr) | Negate(expr) | Abs(expr) => vec![expr],
+            Sum(exprs) | Product(exprs) => exprs.iter().collect(),
+            Add(expr_a, expr_b)
+            | Minus(expr_a, expr_b)
+            | Times(expr_a, expr_b)
+            | Divide(expr_a, expr_b)
+            | Modulo(expr_a, expr_b)
+            | Pow(expr_a, expr_b) => vec![expr_a, expr_b],
+            PowMod(expr_a, expr_b, expr_c) => vec![expr_a, expr_b, expr_c],
+            IntegerNumberVariable(_)
+            | IntegerNumberValue(_)
+            | TupleGet(_, _)
+            | Let(_, _, _)
+            | OfBoolean(_)
+            | IfThenElse(_, _, _) => vec![],
+        }
+    }
+
+    fn size(&self) -> usize {
+        1 + self.operands().into_iter().map(Self::size).sum::<usize>()
+    }
+
+    /// Whether the expression is `target`, possibly in parentheses.
+    fn is(&self, target: &IntegerNumberExpression) -> bool {
+        match self {
+            IntegerNumberExpression::Parenthesis(expr) => expr.as_ref() == target,
+            expr => expr == target,
+        }
+    }
+
+    fn occurrences(&self, target: &IntegerNumberExpression) -> usize {
+        if self.is(target) {
+            1
+        } else {
+            self.operands()
+                .into_iter()
+                .map(|expr| expr.occurrences(target))
+                .sum()
+        }
+    }
+
+    /// The expression with every occurrence of `target` replaced by a
+    /// reference to `symbol`.
+    fn replaced(self, target: &IntegerNumberExpression, symbol: &super::Symbol) -> Self {
+        use IntegerNumberExpression::*;
+        if self.is(target) {
+            return IntegerNumberVariable(symbol.clone());
+        }
+        let replaced = |expr: Box<IntegerNumberExpression>| Box::new(expr.replaced(target, symbol));
+        match self {
+            Parenthesis(expr) => Parenthesis(replaced(expr)),
+            Negate(expr) => Negate(replaced(expr)),
+            Abs(expr) => Abs(replaced(expr)),
+            Add(expr_a, expr_b) => Add(replaced(expr_a), replaced(expr_b)),
+            Minus(expr_a, expr_b) => Minus(replaced(expr_a), replaced(expr_b)),
+            Times(expr_a, expr_b) => Times(replaced(expr_a), replaced(expr_b)),
+            Divide(expr_a, expr_b) => Divide(replaced(expr_a), replaced(expr_b)),
+            Modulo(expr_a, expr_b) => Modulo(replaced(expr_a), replaced(expr_b)),
+            Pow(expr_a, expr_b) => Pow(replaced(expr_a), replaced(expr_b)),
+            PowMod(expr_a, expr_b, expr_c) => {
+                PowMod(replaced(expr_a), replaced(expr_b), replaced(expr_c))
             }
-            Modulo(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            Sum(exprs) => Sum(exprs
+                .into_iter()
+                .map(|expr| expr.replaced(target, symbol))
+                .collect()),
+            Product(exprs) => Product(
+                exprs
+                    .into_iter()
+                    .map(|expr| expr.replaced(target, symbol))
+                    .collect(),
+            ),
+            leaf => leaf,
+        }
+    }
+
+    /// Binds every operator subexpression occurring more than once to a
+    /// fresh symbol from `symbols` and refers to it instead. Larger
+    /// subexpressions are bound first, each by a `Let` inside those of the
+    /// smaller ones, so a binding may refer to the bindings around it.
+    /// Subexpressions are compared structurally, after their
+    /// parentheses; tuples and existing `Let`s are left alone.
+    pub(crate) fn hoisted(self, symbols: &mut super::SymbolGenerator) -> IntegerNumberExpression {
+        let mut counts: std::collections::HashMap<&IntegerNumberExpression, usize> =
+            std::collections::HashMap::new();
+        let mut pending = vec![&self];
+        while let Some(expr) = pending.pop() {
+            let operands = expr.operands();
+            if !operands.is_empty() && !matches!(expr, IntegerNumberExpression::Parenthesis(_)) {
+                *counts.entry(expr).or_insert(0) += 1;
             }
+            pending.extend(operands);
         }
+        let mut repeated: Vec<IntegerNumberExpression> = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(expr, _)| expr.clone())
+            .collect();
+        repeated.sort_by(|expr_a, expr_b| {
+            expr_b
+                .size()
+                .cmp(&expr_a.size())
+                .then_with(|| expr_a.cmp(expr_b))
+        });
+        let mut body = self;
+        let mut bindings: Vec<(super::Symbol, IntegerNumberExpression)> = Vec::new();
+        for expr in repeated {
+            let count = body.occurrences(&expr)
+                + bindings
+                    .iter()
+                    .map(|(_, value)| value.occurrences(&expr))
+                    .sum::<usize>();
+            if count < 2 {
+                continue;
+            }
+            let symbol = symbols.fresh();
+            body = body.replaced(&expr, &symbol);
+            bindings = bindings
+                .into_iter()
+                .map(|(bound, value)| (bound, value.replaced(&expr, &symbol)))
+                .collect();
+            bindings.push((symbol, expr));
+        }
+        bindings.into_iter().fold(body, |body, (symbol, value)| {
+            IntegerNumberExpression::Let(symbol, Box::new(value), Box::new(body))
+        })
+    }
 
-        free
+    /// Writes the expression, in parentheses if it binds looser than `min`.
+    fn fmt_at(&self, f: &mut fmt::Formatter, min: u8) -> fmt::Result {
+        if self.precedence() < min {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
     }
 }
 
-impl super::FreeVariable for IntegerNumberDomainExpression {
-    fn get_free(&self) -> Vec<super::Variable> {
+/// Infix syntax with parentheses only where precedence requires them.
+/// Binary operators group to the left except `^`, which groups to the
+/// right. A negative literal is written `-5` while the negation of a
+/// literal is written `- 5`, so the two read back apart.
+impl fmt::Display for IntegerNumberExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use IntegerNumberExpression::*;
+        let binary = |f: &mut fmt::Formatter,
+                      a: &IntegerNumberExpression,
+                      op,
+                      b: &IntegerNumberExpression| {
+            let precedence = self.precedence();
+            let (left, right) = match self {
+                Pow(_, _) => (precedence + 1, precedence - 1),
+                _ => (precedence, precedence + 1),
+            };
+            a.fmt_at(f, left)?;
+            write!(f, " {} ", op)?;
+            b.fmt_at(f, right)
+        };
+        match self {
+            IntegerNumberVariable(symbol) => write!(f, "{}", symbol),
+            IntegerNumberValue(value) => write!(f, "{}", value),
+            Parenthesis(expr) => write!(f, "({})", expr),
+            Negate(expr) => {
+                let operand = if expr.precedence() < self.precedence() {
+                    format!("({})", expr)
+                } else {
+                    expr.to_string()
+                };
+                if operand.starts_with(|c: char| c.is_ascii_digit()) {
+                    write!(f, "- {}", operand)
+                } else {
+                    write!(f, "-{}", operand)
+                }
+            }
+            Add(expr_a, expr_b) => binary(f, expr_a, "+", expr_b),
+            Minus(expr_a, expr_b) => binary(f, expr_a, "-", expr_b),
+            Times(expr_a, expr_b) => binary(f, expr_a, "*", expr_b),
+            Divide(expr_a, expr_b) => binary(f, expr_a, "/", expr_b),
+            Modulo(expr_a, expr_b) => binary(f, expr_a, "%", expr_b),
+            Pow(expr_a, expr_b) => binary(f, expr_a, "^", expr_b),
+            PowMod(expr_a, expr_b, expr_c) => {
+                write!(f, "powmod({}, {}, {})", expr_a, expr_b, expr_c)
+            }
+            Abs(expr) => write!(f, "abs({})", expr),
+            Sum(exprs) | Product(exprs) => {
+                write!(
+                    f,
+                    "{}(",
+                    if matches!(self, Sum(_)) {
+                        "sum"
+                    } else {
+                        "product"
+                    }
+                )?;
+                for (index, expr) in exprs.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", expr)?;
+                }
+                write!(f, ")")
+            }
+            TupleGet(tuple, index) => write!(f, "{}.{}", tuple, index),
+            Let(symbol, value, body) => write!(f, "(let {} = {} in {})", symbol, value, body),
+            OfBoolean(expr) => write!(f, "int({})", expr),
+            IfThenElse(condition, then, otherwise) => {
+                write!(f, "ite({}, {}, {})", condition, then, otherwise)
+            }
+        }
+    }
+}
+
+/// Ranges are written with brackets for closed and parentheses for open
+/// endpoints, `[a, b)`, explicit sets in braces, and the set operations
+/// as functions such as `union([0, 3], {7})`.
+impl fmt::Display for IntegerNumberDomainExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use IntegerNumberDomainExpression::*;
-        let mut free: Vec<super::Variable> = Vec::new();
+        match self {
+            Universe => write!(f, "universe"),
+            Empty => write!(f, "empty"),
+            ClosedRange(expr_a, expr_b) => write!(f, "[{}, {}]", expr_a, expr_b),
+            OpenRange(expr_a, expr_b) => write!(f, "({}, {})", expr_a, expr_b),
+            OpenLeftClosedRightRange(expr_a, expr_b) => write!(f, "({}, {}]", expr_a, expr_b),
+            ClosedLeftOpenRightRange(expr_a, expr_b) => write!(f, "[{}, {})", expr_a, expr_b),
+            ExplicitSet(elements) => {
+                write!(f, "{{")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "}}")
+            }
+            Union(expr_a, expr_b) => write!(f, "union({}, {})", expr_a, expr_b),
+            Intersection(expr_a, expr_b) => write!(f, "intersection({}, {})", expr_a, expr_b),
+            Difference(expr_a, expr_b) => write!(f, "difference({}, {})", expr_a, expr_b),
+            Complement(expr) => write!(f, "complement({})", expr),
+        }
+    }
+}
 
+impl super::FreeVariable for IntegerNumberExpression {
+    fn get_free(&self) -> Vec<super::Variable> {
+        FreeVariables::of(|free| free.visit_integer(self))
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        use IntegerNumberExpression::*;
         match self {
-            Universe => (),
-            Empty => (),
-            ClosedRange(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            IntegerNumberValue(_) => ControlFlow::Continue(()),
+            IntegerNumberVariable(symbol) => visit(symbol),
+            Parenthesis(expr) | Negate(expr) | Abs(expr) => expr.each_free(visit),
+            Add(expr_a, expr_b)
+            | Minus(expr_a, expr_b)
+            | Times(expr_a, expr_b)
+            | Divide(expr_a, expr_b)
+            | Modulo(expr_a, expr_b)
+            | Pow(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
             }
-            OpenRange(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            PowMod(expr_a, expr_b, expr_c) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)?;
+                expr_c.each_free(visit)
             }
-            OpenLeftClosedRightRange(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            Sum(exprs) | Product(exprs) => {
+                for expr in exprs {
+                    expr.each_free(visit)?;
+                }
+                ControlFlow::Continue(())
             }
-            ClosedLeftOpenRightRange(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            TupleGet(tuple, _) => tuple.each_free(visit),
+            Let(symbol, value, body) => {
+                value.each_free(visit)?;
+                body.each_free(&mut |name| {
+                    if name == symbol {
+                        ControlFlow::Continue(())
+                    } else {
+                        visit(name)
+                    }
+                })
             }
-            ExplicitSet(expr) => free.extend(expr.get_free()),
-            Union(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            OfBoolean(expr) => expr.each_free(visit),
+            IfThenElse(condition, then, otherwise) => {
+                condition.each_free(visit)?;
+                then.each_free(visit)?;
+                otherwise.each_free(visit)
             }
-            Intersection(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+        }
+    }
+}
+
+impl super::FreeVariable for IntegerNumberDomainExpression {
+    fn get_free(&self) -> Vec<super::Variable> {
+        FreeVariables::of(|free| free.visit_integer_domain(self))
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        use IntegerNumberDomainExpression::*;
+        match self {
+            Universe | Empty => ControlFlow::Continue(()),
+            ClosedRange(expr_a, expr_b)
+            | OpenRange(expr_a, expr_b)
+            | OpenLeftClosedRightRange(expr_a, expr_b)
+            | ClosedLeftOpenRightRange(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
             }
-            Difference(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            ExplicitSet(expr) => expr.each_free(visit),
+            Union(expr_a, expr_b) | Intersection(expr_a, expr_b) | Difference(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
             }
-            Complement(expr) => free.extend(expr.get_free()),
+            Complement(expr) => expr.each_free(visit),
         }
-
-        free
     }
 }
 impl super::FreeVariable for Vec<IntegerNumberExpression> {
     fn get_free(&self) -> Vec<super::Variable> {
-        let mut free: Vec<super::Variable> = Vec::new();
-        for elt in self {
-            free.extend(elt.get_free());
-        }
-
-        free
+        FreeVariables::of(|free| {
+            for elt in self {
+                free.visit_integer(elt);
+            }
+        })
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.iter().try_for_each(|elt| elt.each_free(visit))
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BooleanIntegerNumberExpression {
     Equals(Box<IntegerNumberExpression>, Box<IntegerNumberExpression>),
     Different(Box<IntegerNumberExpression>, Box<IntegerNumberExpression>),
@@ -145,53 +639,1371 @@ pub enum BooleanIntegerNumberExpression {
         Box<IntegerNumberDomainExpression>,
     ),
 }
+impl BooleanIntegerNumberExpression {
+    /// The comparison with `IntegerNumberExpression::hoisted` applied to
+    /// each integer operand; domains are kept as they are.
+    pub(crate) fn hoisted(self, symbols: &mut super::SymbolGenerator) -> Self {
+        use BooleanIntegerNumberExpression::*;
+        let mut hoisted = |expr: Box<IntegerNumberExpression>| Box::new(expr.hoisted(symbols));
+        match self {
+            Equals(expr_a, expr_b) => Equals(hoisted(expr_a), hoisted(expr_b)),
+            Different(expr_a, expr_b) => Different(hoisted(expr_a), hoisted(expr_b)),
+            Greater(expr_a, expr_b) => Greater(hoisted(expr_a), hoisted(expr_b)),
+            Less(expr_a, expr_b) => Less(hoisted(expr_a), hoisted(expr_b)),
+            In(expr, domain) => In(hoisted(expr), domain),
+        }
+    }
+}
+
+impl fmt::Display for BooleanIntegerNumberExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use BooleanIntegerNumberExpression::*;
+        match self {
+            Equals(expr_a, expr_b) => write!(f, "{} = {}", expr_a, expr_b),
+            Different(expr_a, expr_b) => write!(f, "{} != {}", expr_a, expr_b),
+            Greater(expr_a, expr_b) => write!(f, "{} > {}", expr_a, expr_b),
+            Less(expr_a, expr_b) => write!(f, "{} < {}", expr_a, expr_b),
+            In(expr_a, expr_b) => write!(f, "{} in {}", expr_a, expr_b),
+        }
+    }
+}
+
 impl super::FreeVariable for BooleanIntegerNumberExpression {
     fn get_free(&self) -> Vec<super::Variable> {
+        FreeVariables::of(|free| free.visit_integer_comparison(self))
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
         use BooleanIntegerNumberExpression::*;
-        let mut free: Vec<super::Variable> = Vec::new();
         match self {
-            Equals(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            Equals(expr_a, expr_b)
+            | Different(expr_a, expr_b)
+            | Greater(expr_a, expr_b)
+            | Less(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
+            }
+            In(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
+            }
+        }
+    }
+}
+
+/// Combines two integers with a checked operation. `NaN` operands, and
+/// operations the checked arithmetic rejects (overflow, division by
+/// zero), all result in `NaN`.
+fn checked(
+    a: IntegerNumber,
+    b: IntegerNumber,
+    op: impl Fn(i128, i128) -> Option<i128>,
+) -> IntegerNumber {
+    match (a, b) {
+        (IntegerNumber::Value(a), IntegerNumber::Value(b)) => {
+            op(a, b).map_or(IntegerNumber::NaN, IntegerNumber::Value)
+        }
+        _ => IntegerNumber::NaN,
+    }
+}
+
+/// What a `Sum` has added up so far: the `i128` total, how many times
+/// it has wrapped past either end, and whether an operand was `NaN`.
+struct SumSoFar {
+    total: i128,
+    wraps: i128,
+    nan: bool,
+}
+
+impl SumSoFar {
+    /// Adds the values of `exprs`, and of the operands of any `Sum`
+    /// among them, under `env`.
+    fn add(
+        &mut self,
+        exprs: &[IntegerNumberExpression],
+        env: &super::Environment,
+    ) -> Result<(), super::EvalError> {
+        for expr in exprs {
+            match expr.unparenthesised() {
+                IntegerNumberExpression::Sum(nested) => self.add(nested, env)?,
+                expr => match expr.evaluate_ref(env)? {
+                    IntegerNumber::Value(n) => {
+                        let (total, wrapped) = self.total.overflowing_add(n);
+                        self.total = total;
+                        if wrapped {
+                            self.wraps += n.signum();
+                        }
+                    }
+                    IntegerNumber::NaN => self.nan = true,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&self) -> IntegerNumber {
+        if self.nan || self.wraps != 0 {
+            IntegerNumber::NaN
+        } else {
+            IntegerNumber::Value(self.total)
+        }
+    }
+}
+
+/// What a `Product` has multiplied so far: the magnitude, `None` once it
+/// exceeds a `u128`, its sign, and whether an operand was `0` or `NaN`.
+struct ProductSoFar {
+    magnitude: Option<u128>,
+    negative: bool,
+    zero: bool,
+    nan: bool,
+}
+
+impl ProductSoFar {
+    /// Multiplies in the values of `exprs`, and of the operands of any
+    /// `Product` among them, under `env`.
+    fn multiply(
+        &mut self,
+        exprs: &[IntegerNumberExpression],
+        env: &super::Environment,
+    ) -> Result<(), super::EvalError> {
+        for expr in exprs {
+            match expr.unparenthesised() {
+                IntegerNumberExpression::Product(nested) => self.multiply(nested, env)?,
+                expr => match expr.evaluate_ref(env)? {
+                    IntegerNumber::Value(0) => self.zero = true,
+                    IntegerNumber::Value(n) => {
+                        self.magnitude = self
+                            .magnitude
+                            .and_then(|magnitude| magnitude.checked_mul(n.unsigned_abs()));
+                        self.negative ^= n < 0;
+                    }
+                    IntegerNumber::NaN => self.nan = true,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&self) -> IntegerNumber {
+        let value = match (self.nan, self.zero, self.magnitude) {
+            (true, _, _) | (false, false, None) => None,
+            (false, true, _) => Some(0),
+            (false, false, Some(magnitude)) if self.negative => {
+                (magnitude <= i128::MIN.unsigned_abs()).then(|| (magnitude as i128).wrapping_neg())
+            }
+            (false, false, Some(magnitude)) => i128::try_from(magnitude).ok(),
+        };
+        value.map_or(IntegerNumber::NaN, IntegerNumber::Value)
+    }
+}
+
+/// How `Divide` and `Modulo` round when an operand is negative. In every
+/// mode the quotient `q` and remainder `r` of `a` by `b` satisfy
+/// `a = q * b + r` with `|r| < |b|`; the modes differ in the sign of `r`.
+/// Division by zero is `NaN` in every mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DivisionMode {
+    /// Rust's `/` and `%`: the quotient is rounded toward zero and the
+    /// remainder has the sign of the dividend, so `-7 / 2` is `-3` and
+    /// `-7 % 2` is `-1`.
+    #[default]
+    TruncateTowardZero,
+    /// The quotient is rounded toward negative infinity and the remainder
+    /// has the sign of the divisor, so `-7 / 2` is `-4`, `-7 % 2` is `1`
+    /// and `-7 % -2` is `-1`.
+    FloorDivision,
+    /// The remainder is never negative and the quotient is whatever makes
+    /// up the difference, as in `i128::div_euclid`, so `-7 / 2` is `-4`,
+    /// `-7 % 2` is `1`, `-7 / -2` is `4` and `-7 % -2` is `1`.
+    EuclideanRemainder,
+}
+
+impl DivisionMode {
+    /// The quotient of `a` by `b`, or `None` on division by zero or
+    /// overflow.
+    fn divide(self, a: i128, b: i128) -> Option<i128> {
+        match self {
+            DivisionMode::TruncateTowardZero => a.checked_div(b),
+            DivisionMode::FloorDivision => {
+                let quotient = a.checked_div(b)?;
+                if a % b != 0 && (a < 0) != (b < 0) {
+                    quotient.checked_sub(1)
+                } else {
+                    Some(quotient)
+                }
+            }
+            DivisionMode::EuclideanRemainder => a.checked_div_euclid(b),
+        }
+    }
+
+    /// The remainder of `a` by `b`, or `None` on division by zero or
+    /// overflow.
+    fn remainder(self, a: i128, b: i128) -> Option<i128> {
+        match self {
+            DivisionMode::TruncateTowardZero => a.checked_rem(b),
+            DivisionMode::FloorDivision => {
+                let remainder = a.checked_rem(b)?;
+                if remainder != 0 && (remainder < 0) != (b < 0) {
+                    Some(remainder + b)
+                } else {
+                    Some(remainder)
+                }
+            }
+            DivisionMode::EuclideanRemainder => a.checked_rem_euclid(b),
+        }
+    }
+}
+
+/// `base` raised to `exponent` by repeated squaring, or `None` when the
+/// exponent is negative or the result overflows `i128`.
+fn checked_pow(base: i128, exponent: i128) -> Option<i128> {
+    if exponent < 0 {
+        return None;
+    }
+    let mut result: i128 = 1;
+    let mut base = base;
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.checked_mul(base)?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = base.checked_mul(base)?;
+        }
+    }
+    Some(result)
+}
+
+/// `a * b mod modulus` for `0 <= a, b < modulus`, by doubling and adding
+/// so that no intermediate value exceeds twice the modulus.
+fn mul_mod(a: u128, b: u128, modulus: u128) -> u128 {
+    let mut result = 0;
+    let mut a = a;
+    let mut b = b;
+    while b > 0 {
+        if b & 1 == 1 {
+            result = (result + a) % modulus;
+        }
+        a = (a + a) % modulus;
+        b >>= 1;
+    }
+    result
+}
+
+/// `base` raised to `exponent` modulo `modulus` by repeated squaring.
+/// The result lies in `[0, modulus)`; a negative exponent or a modulus
+/// that is not positive gives `None`.
+fn checked_pow_mod(base: i128, exponent: i128, modulus: i128) -> Option<i128> {
+    if exponent < 0 || modulus <= 0 {
+        return None;
+    }
+    let modulus = modulus as u128;
+    let mut base = base.rem_euclid(modulus as i128) as u128;
+    let mut exponent = exponent;
+    let mut result = 1 % modulus;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, modulus);
+        }
+        base = mul_mod(base, base, modulus);
+        exponent >>= 1;
+    }
+    Some(result as i128)
+}
+
+/// Integer arithmetic never panics: division or modulo by zero and any
+/// `i128` overflow evaluate to `NaN`, and `NaN` propagates through every
+/// operation it takes part in. `Pow` and `PowMod` with a negative
+/// exponent are `NaN`, as is `PowMod` with a modulus that is not positive.
+/// A `TupleGet` with an index out of range or at a component that is not
+/// an integer is `NaN` too. The value of a `Let` is bound in front of
+/// `env`, shadowing any binding of the same symbol. `Divide` and `Modulo`
+/// round as the environment says. A `Sum` or `Product` is exact: it is
+/// `NaN` only when an operand is or the whole result does not fit, not
+/// when a partial result would overflow, and one nested in another of
+/// its kind counts its operands among the outer one's, so regrouping
+/// them never changes the value. A `0` factor makes a product `0` even
+/// when the other factors would overflow.
+impl IntegerNumberExpression {
+    /// The value under `env`, without cloning any part of the expression.
+    /// Apart from the error naming an unbound variable it allocates
+    /// nothing, so it is cheap enough to run at every search node.
+    pub fn evaluate_ref(
+        &self,
+        env: &super::Environment,
+    ) -> Result<IntegerNumber, super::EvalError> {
+        use IntegerNumberExpression::*;
+        let division = env.division();
+        match self {
+            IntegerNumberVariable(symbol) => env
+                .integer(symbol)
+                .cloned()
+                .ok_or_else(|| super::EvalError::Unbound(symbol.clone())),
+            IntegerNumberValue(value) => Ok(value.clone()),
+            Parenthesis(expr) => expr.evaluate_ref(env),
+            Abs(expr) => Ok(match expr.evaluate_ref(env)? {
+                IntegerNumber::Value(n) => n
+                    .checked_abs()
+                    .map_or(IntegerNumber::NaN, IntegerNumber::Value),
+                IntegerNumber::NaN => IntegerNumber::NaN,
+            }),
+            Negate(expr) => Ok(checked(
+                IntegerNumber::Value(0),
+                expr.evaluate_ref(env)?,
+                i128::checked_sub,
+            )),
+            Add(expr_a, expr_b) => Ok(checked(
+                expr_a.evaluate_ref(env)?,
+                expr_b.evaluate_ref(env)?,
+                i128::checked_add,
+            )),
+            Minus(expr_a, expr_b) => Ok(checked(
+                expr_a.evaluate_ref(env)?,
+                expr_b.evaluate_ref(env)?,
+                i128::checked_sub,
+            )),
+            Times(expr_a, expr_b) => Ok(checked(
+                expr_a.evaluate_ref(env)?,
+                expr_b.evaluate_ref(env)?,
+                i128::checked_mul,
+            )),
+            Divide(expr_a, expr_b) => Ok(checked(
+                expr_a.evaluate_ref(env)?,
+                expr_b.evaluate_ref(env)?,
+                |a, b| division.divide(a, b),
+            )),
+            Modulo(expr_a, expr_b) => Ok(checked(
+                expr_a.evaluate_ref(env)?,
+                expr_b.evaluate_ref(env)?,
+                |a, b| division.remainder(a, b),
+            )),
+            Sum(exprs) => {
+                let mut sum = SumSoFar {
+                    total: 0,
+                    wraps: 0,
+                    nan: false,
+                };
+                sum.add(exprs, env)?;
+                Ok(sum.value())
+            }
+            Product(exprs) => {
+                let mut product = ProductSoFar {
+                    magnitude: Some(1),
+                    negative: false,
+                    zero: false,
+                    nan: false,
+                };
+                product.multiply(exprs, env)?;
+                Ok(product.value())
+            }
+            Pow(expr_a, expr_b) => Ok(checked(
+                expr_a.evaluate_ref(env)?,
+                expr_b.evaluate_ref(env)?,
+                checked_pow,
+            )),
+            PowMod(expr_a, expr_b, expr_c) => {
+                match (
+                    expr_a.evaluate_ref(env)?,
+                    expr_b.evaluate_ref(env)?,
+                    expr_c.evaluate_ref(env)?,
+                ) {
+                    (
+                        IntegerNumber::Value(base),
+                        IntegerNumber::Value(exponent),
+                        IntegerNumber::Value(modulus),
+                    ) => Ok(checked_pow_mod(base, exponent, modulus)
+                        .map_or(IntegerNumber::NaN, IntegerNumber::Value)),
+                    _ => Ok(IntegerNumber::NaN),
+                }
             }
+            TupleGet(tuple, index) => Ok(tuple
+                .integer_component(*index, env)?
+                .unwrap_or(IntegerNumber::NaN)),
+            Let(symbol, value, body) => {
+                let value = value.evaluate_ref(env)?;
+                body.evaluate_ref(&env.bind(symbol, &value))
+            }
+            OfBoolean(expr) => match expr.evaluate_ref(env)? {
+                super::boolean::BooleanValue::True => Ok(IntegerNumber::Value(1)),
+                super::boolean::BooleanValue::False => Ok(IntegerNumber::Value(0)),
+            },
+            IfThenElse(condition, then, otherwise) => match condition.evaluate_ref(env)? {
+                super::boolean::BooleanValue::True => then.evaluate_ref(env),
+                super::boolean::BooleanValue::False => otherwise.evaluate_ref(env),
+            },
+        }
+    }
+}
+
+impl super::Evaluate for IntegerNumberExpression {
+    type Value = IntegerNumber;
+    fn evaluate_with(
+        &self,
+        env: &[super::Assignment],
+        division: DivisionMode,
+    ) -> Result<IntegerNumber, super::EvalError> {
+        self.evaluate_ref(&super::Environment::new(env).with_division(division))
+    }
+}
+
+/// The positions of the first two of `exprs` whose values under `env`
+/// are known and equal, ignoring those that cannot be evaluated yet and
+/// `NaN`, which equals nothing. A clash refutes `alldifferent` however
+/// the remaining variables are assigned.
+pub fn clash(
+    exprs: &[IntegerNumberExpression],
+    env: &super::Environment,
+) -> Option<(usize, usize)> {
+    let mut seen: std::collections::HashMap<i128, usize> = std::collections::HashMap::new();
+    for (position, expr) in exprs.iter().enumerate() {
+        if let Ok(IntegerNumber::Value(value)) = expr.evaluate_ref(env) {
+            if let Some(first) = seen.insert(value, position) {
+                return Some((first, position));
+            }
+        }
+    }
+    None
+}
+
+/// `expr` as an integer that is `1` when it is true and `0` when it is
+/// false, so that booleans can be counted or weighted in arithmetic. A
+/// ground expression is folded to its value; otherwise the result is an
+/// `OfBoolean` over it, whose boolean variables the search assigns like
+/// any others, so no integer variable needs to be tied to it.
+pub fn bool_as_int(expr: super::boolean::BooleanExpression) -> IntegerNumberExpression {
+    use super::{Evaluate, FreeVariable};
+    let indicator = IntegerNumberExpression::OfBoolean(Box::new(expr));
+    if indicator.get_free().is_empty() {
+        if let Ok(value) = indicator.evaluate(&[]) {
+            return IntegerNumberExpression::IntegerNumberValue(value);
+        }
+    }
+    indicator
+}
+
+/// Whether `expr` is a sum of variables scaled by constants, plus a
+/// constant. A product is linear when one side has no variables, and a
+/// `product` when at most one operand does, while a `sum` is linear when
+/// all its operands are. A division is linear when only its dividend
+/// has variables; `%`, `^`, `powmod`, `int` and `ite` are linear only
+/// without variables, except for `x ^ 1` and `x ^ 0`. A component of a
+/// tuple variable is linear, as is a component of a tuple written out
+/// when it is a linear integer expression. This classifies
+/// rather than extracts: `x / 2` counts as linear although
+/// `export::to_linear_system` cannot express its truncation.
+pub fn is_linear(expr: &IntegerNumberExpression) -> bool {
+    use super::{Evaluate, FreeVariable};
+    use IntegerNumberExpression::*;
+    let ground = |expr: &IntegerNumberExpression| expr.get_free().is_empty();
+    match expr {
+        IntegerNumberVariable(_) | IntegerNumberValue(_) => true,
+        TupleGet(tuple, index) => match tuple.as_ref() {
+            super::tuple::TupleExpression::TupleVariable(_, _) => true,
+            super::tuple::TupleExpression::Tuple(components) => matches!(
+                components.get(*index),
+                Some(super::tuple::TupleComponent::Integer(expr)) if is_linear(expr)
+            ),
+        },
+        Add(expr_a, expr_b) | Minus(expr_a, expr_b) => is_linear(expr_a) && is_linear(expr_b),
+        Negate(expr) | Parenthesis(expr) => is_linear(expr),
+        Abs(_) => ground(expr),
+        Let(_, value, body) => is_linear(value) && is_linear(body),
+        Times(expr_a, expr_b) => {
+            (ground(expr_a) && is_linear(expr_b)) || (ground(expr_b) && is_linear(expr_a))
+        }
+        Divide(expr_a, expr_b) => ground(expr_b) && is_linear(expr_a),
+        Sum(exprs) => exprs.iter().all(is_linear),
+        Product(exprs) => {
+            exprs.iter().filter(|expr| !ground(expr)).count() <= 1 && exprs.iter().all(is_linear)
+        }
+        Pow(expr_a, expr_b) => {
+            ground(expr)
+                || ground(expr_b)
+                    && matches!(
+                        expr_b.evaluate(&[]),
+                        Ok(IntegerNumber::Value(0)) | Ok(IntegerNumber::Value(1))
+                    )
+                    && is_linear(expr_a)
+        }
+        Modulo(_, _) | PowMod(_, _, _) | OfBoolean(_) | IfThenElse(_, _, _) => ground(expr),
+    }
+}
+
+impl super::Apply for IntegerNumberExpression {
+    fn apply_noting(&self, state: &[super::Assignment], substituted: &mut bool) -> Self {
+        use IntegerNumberExpression::*;
+        match self {
+            IntegerNumberVariable(symbol) => match super::integer_binding(state, symbol) {
+                Some(value) => {
+                    *substituted = true;
+                    IntegerNumberValue(value.clone())
+                }
+                None => IntegerNumberVariable(symbol.clone()),
+            },
+            IntegerNumberValue(value) => IntegerNumberValue(value.clone()),
+            Parenthesis(expr) => Parenthesis(Box::new(expr.apply_noting(state, substituted))),
+            Negate(expr) => Negate(Box::new(expr.apply_noting(state, substituted))),
+            Abs(expr) => Abs(Box::new(expr.apply_noting(state, substituted))),
+            Add(expr_a, expr_b) => Add(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Minus(expr_a, expr_b) => Minus(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Times(expr_a, expr_b) => Times(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Divide(expr_a, expr_b) => Divide(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Modulo(expr_a, expr_b) => Modulo(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Pow(expr_a, expr_b) => Pow(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            PowMod(expr_a, expr_b, expr_c) => PowMod(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+                Box::new(expr_c.apply_noting(state, substituted)),
+            ),
+            Sum(exprs) => Sum(exprs
+                .iter()
+                .map(|expr| expr.apply_noting(state, substituted))
+                .collect()),
+            Product(exprs) => Product(
+                exprs
+                    .iter()
+                    .map(|expr| expr.apply_noting(state, substituted))
+                    .collect(),
+            ),
+            TupleGet(tuple, index) => match tuple.apply_noting(state, substituted) {
+                super::tuple::TupleExpression::Tuple(components) => match components.get(*index) {
+                    Some(super::tuple::TupleComponent::Integer(expr)) => expr.clone(),
+                    _ => TupleGet(
+                        Box::new(super::tuple::TupleExpression::Tuple(components)),
+                        *index,
+                    ),
+                },
+                tuple => TupleGet(Box::new(tuple), *index),
+            },
+            Let(symbol, value, body) => {
+                let shadowed: Vec<super::Assignment> = state
+                    .iter()
+                    .filter(|assignment| assignment.name() != symbol)
+                    .cloned()
+                    .collect();
+                Let(
+                    symbol.clone(),
+                    Box::new(value.apply_noting(state, substituted)),
+                    Box::new(body.apply_noting(&shadowed, substituted)),
+                )
+            }
+            OfBoolean(expr) => OfBoolean(Box::new(expr.apply_noting(state, substituted))),
+            IfThenElse(condition, then, otherwise) => IfThenElse(
+                Box::new(condition.apply_noting(state, substituted)),
+                Box::new(then.apply_noting(state, substituted)),
+                Box::new(otherwise.apply_noting(state, substituted)),
+            ),
+        }
+    }
+}
+
+impl super::Substitute for IntegerNumberExpression {
+    fn substitute_with(&self, substitution: &super::Substitution) -> Self {
+        use IntegerNumberExpression::*;
+        let substituted =
+            |expr: &IntegerNumberExpression| Box::new(expr.substitute_with(substitution));
+        match self {
+            IntegerNumberVariable(symbol) => match substitution {
+                super::Substitution::Integer(name, replacement) if *name == symbol => {
+                    (*replacement).clone()
+                }
+                _ => self.clone(),
+            },
+            IntegerNumberValue(_) => self.clone(),
+            Parenthesis(expr) => Parenthesis(substituted(expr)),
+            Negate(expr) => Negate(substituted(expr)),
+            Abs(expr) => Abs(substituted(expr)),
+            Add(expr_a, expr_b) => Add(substituted(expr_a), substituted(expr_b)),
+            Minus(expr_a, expr_b) => Minus(substituted(expr_a), substituted(expr_b)),
+            Times(expr_a, expr_b) => Times(substituted(expr_a), substituted(expr_b)),
+            Divide(expr_a, expr_b) => Divide(substituted(expr_a), substituted(expr_b)),
+            Modulo(expr_a, expr_b) => Modulo(substituted(expr_a), substituted(expr_b)),
+            Pow(expr_a, expr_b) => Pow(substituted(expr_a), substituted(expr_b)),
+            PowMod(expr_a, expr_b, expr_c) => PowMod(
+                substituted(expr_a),
+                substituted(expr_b),
+                substituted(expr_c),
+            ),
+            Sum(exprs) => Sum(exprs
+                .iter()
+                .map(|expr| expr.substitute_with(substitution))
+                .collect()),
+            Product(exprs) => Product(
+                exprs
+                    .iter()
+                    .map(|expr| expr.substitute_with(substitution))
+                    .collect(),
+            ),
+            TupleGet(tuple, index) => {
+                TupleGet(Box::new(tuple.substitute_with(substitution)), *index)
+            }
+            Let(symbol, value, body) => {
+                use super::FreeVariable;
+                let (name, free) = match substitution {
+                    super::Substitution::Integer(name, replacement) => {
+                        (name, replacement.get_free())
+                    }
+                    super::Substitution::Boolean(name, replacement) => {
+                        (name, replacement.get_free())
+                    }
+                };
+                if *name == symbol {
+                    return Let(symbol.clone(), substituted(value), body.clone());
+                }
+                if !free.iter().any(|variable| variable.name() == symbol) {
+                    return Let(symbol.clone(), substituted(value), substituted(body));
+                }
+                // The replacement mentions the bound symbol, so the binding
+                // is renamed first, lest it capture the replacement's.
+                let taken = body
+                    .get_free()
+                    .into_iter()
+                    .chain(free)
+                    .map(|variable| variable.name().clone())
+                    .chain([(*name).clone()]);
+                let fresh = super::SymbolGenerator::new(symbol.name(), taken).fresh();
+                let renamed = body.substitute(symbol, &IntegerNumberVariable(fresh.clone()));
+                Let(fresh, substituted(value), substituted(&renamed))
+            }
+            OfBoolean(expr) => OfBoolean(Box::new(expr.substitute_with(substitution))),
+            IfThenElse(condition, then, otherwise) => IfThenElse(
+                Box::new(condition.substitute_with(substitution)),
+                substituted(then),
+                substituted(otherwise),
+            ),
+        }
+    }
+}
+
+/// The smallest interval known to hold every member of a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hull {
+    Empty,
+    Interval(i128, i128),
+    Unbounded,
+}
+
+impl Hull {
+    fn interval(low: Option<i128>, high: Option<i128>) -> Hull {
+        match (low, high) {
+            (Some(low), Some(high)) if low <= high => Hull::Interval(low, high),
+            _ => Hull::Empty,
+        }
+    }
+
+    fn union(self, other: Hull) -> Hull {
+        match (self, other) {
+            (Hull::Empty, hull) | (hull, Hull::Empty) => hull,
+            (Hull::Interval(a, b), Hull::Interval(c, d)) => Hull::Interval(a.min(c), b.max(d)),
+            _ => Hull::Unbounded,
+        }
+    }
+
+    fn intersection(self, other: Hull) -> Hull {
+        match (self, other) {
+            (Hull::Unbounded, hull) | (hull, Hull::Unbounded) => hull,
+            (Hull::Interval(a, b), Hull::Interval(c, d)) => {
+                Hull::interval(Some(a.max(c)), Some(b.min(d)))
+            }
+            _ => Hull::Empty,
+        }
+    }
+}
+
+impl IntegerNumberDomainExpression {
+    /// An interval holding every member of the domain, with the bounds
+    /// evaluated under `env` and divisions in them rounding as `division`
+    /// says. It is exact for ranges and explicit sets and may be wider
+    /// for set operations; `Complement` is always unbounded.
+    pub(crate) fn hull(
+        &self,
+        env: &[super::Assignment],
+        division: DivisionMode,
+    ) -> Result<Hull, super::EvalError> {
+        use super::Evaluate;
+        use IntegerNumberDomainExpression::*;
+        let bounds = |expr_a: &IntegerNumberExpression,
+                      expr_b: &IntegerNumberExpression,
+                      low_shift: i128,
+                      high_shift: i128| {
+            match (
+                expr_a.evaluate_with(env, division)?,
+                expr_b.evaluate_with(env, division)?,
+            ) {
+                (IntegerNumber::Value(a), IntegerNumber::Value(b)) => Ok(Hull::interval(
+                    a.checked_add(low_shift),
+                    b.checked_sub(high_shift),
+                )),
+                _ => Ok(Hull::Empty),
+            }
+        };
+        match self {
+            Universe | Complement(_) => Ok(Hull::Unbounded),
+            Empty => Ok(Hull::Empty),
+            ClosedRange(expr_a, expr_b) => bounds(expr_a, expr_b, 0, 0),
+            OpenRange(expr_a, expr_b) => bounds(expr_a, expr_b, 1, 1),
+            OpenLeftClosedRightRange(expr_a, expr_b) => bounds(expr_a, expr_b, 1, 0),
+            ClosedLeftOpenRightRange(expr_a, expr_b) => bounds(expr_a, expr_b, 0, 1),
+            ExplicitSet(elements) => {
+                let mut hull = Hull::Empty;
+                for element in elements {
+                    if let IntegerNumber::Value(n) = element.evaluate_with(env, division)? {
+                        hull = hull.union(Hull::Interval(n, n));
+                    }
+                }
+                Ok(hull)
+            }
+            Union(expr_a, expr_b) => Ok(expr_a
+                .hull(env, division)?
+                .union(expr_b.hull(env, division)?)),
+            Intersection(expr_a, expr_b) => Ok(expr_a
+                .hull(env, division)?
+                .intersection(expr_b.hull(env, division)?)),
+            Difference(expr_a, _) => expr_a.hull(env, division),
+        }
+    }
+
+    /// The members of a ground domain in ascending order, with divisions
+    /// in its bounds rounding as `division` says, or `None` when the
+    /// domain is unbounded, not ground, or may hold more than `limit`
+    /// members.
+    pub(crate) fn finite_values(&self, limit: u128, division: DivisionMode) -> Option<Vec<i128>> {
+        let env = super::Environment::new(&[]).with_division(division);
+        match self.hull(&[], division).ok()? {
+            Hull::Empty => Some(Vec::new()),
+            Hull::Interval(low, high) if high.abs_diff(low) < limit => Some(
+                (low..=high)
+                    .filter(|n| self.includes_ref(*n, &env) == Ok(true))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// The members of the domain within `window`, in ascending order,
+    /// tested one at a time as the iterator advances. The window is first
+    /// narrowed to the domain's hull, so that iterating an unbounded
+    /// window ends after a bounded domain's last member. Values whose
+    /// membership cannot be decided, because the domain is not ground,
+    /// are skipped.
+    pub fn iter(&self, window: RangeInclusive<i128>) -> impl Iterator<Item = i128> + '_ {
+        let (low, high) = match self.hull(&[], DivisionMode::default()) {
+            Ok(Hull::Empty) => (1, 0),
+            Ok(Hull::Interval(low, high)) => (low.max(*window.start()), high.min(*window.end())),
+            _ => window.into_inner(),
+        };
+        (low..=high).filter(move |n| self.includes(*n, &[]) == Ok(true))
+    }
+
+    /// Whether `n` is a member of the domain, with the range bounds and
+    /// set elements evaluated under `env`. Ranges with a `NaN` bound are
+    /// empty, and `Complement` is taken relative to `Universe`.
+    pub fn includes(&self, n: i128, env: &[super::Assignment]) -> Result<bool, super::EvalError> {
+        self.includes_ref(n, &super::Environment::new(env))
+    }
+
+    /// Like `includes`, under `env` and without cloning any part of the
+    /// domain.
+    pub fn includes_ref(
+        &self,
+        n: i128,
+        env: &super::Environment,
+    ) -> Result<bool, super::EvalError> {
+        use IntegerNumberDomainExpression::*;
+        fn within(
+            expr_a: &IntegerNumberExpression,
+            expr_b: &IntegerNumberExpression,
+            env: &super::Environment,
+            test: impl Fn(i128, i128) -> bool,
+        ) -> Result<bool, super::EvalError> {
+            match (expr_a.evaluate_ref(env)?, expr_b.evaluate_ref(env)?) {
+                (IntegerNumber::Value(a), IntegerNumber::Value(b)) => Ok(test(a, b)),
+                _ => Ok(false),
+            }
+        }
+        fn either(
+            a: Result<bool, super::EvalError>,
+            b: Result<bool, super::EvalError>,
+        ) -> Result<bool, super::EvalError> {
+            match (a, b) {
+                (Ok(true), _) | (_, Ok(true)) => Ok(true),
+                (Err(error), _) | (_, Err(error)) => Err(error),
+                _ => Ok(false),
+            }
+        }
+        fn both(
+            a: Result<bool, super::EvalError>,
+            b: Result<bool, super::EvalError>,
+        ) -> Result<bool, super::EvalError> {
+            match (a, b) {
+                (Ok(false), _) | (_, Ok(false)) => Ok(false),
+                (Err(error), _) | (_, Err(error)) => Err(error),
+                _ => Ok(true),
+            }
+        }
+        match self {
+            Universe => Ok(true),
+            Empty => Ok(false),
+            ClosedRange(expr_a, expr_b) => within(expr_a, expr_b, env, |a, b| a <= n && n <= b),
+            OpenRange(expr_a, expr_b) => within(expr_a, expr_b, env, |a, b| a < n && n < b),
+            OpenLeftClosedRightRange(expr_a, expr_b) => {
+                within(expr_a, expr_b, env, |a, b| a < n && n <= b)
+            }
+            ClosedLeftOpenRightRange(expr_a, expr_b) => {
+                within(expr_a, expr_b, env, |a, b| a <= n && n < b)
+            }
+            ExplicitSet(elements) => {
+                let mut found = Ok(false);
+                for element in elements {
+                    match element.evaluate_ref(env) {
+                        Ok(IntegerNumber::Value(m)) if m == n => return Ok(true),
+                        Ok(_) => (),
+                        Err(error) => found = Err(error),
+                    }
+                }
+                found
+            }
+            Union(expr_a, expr_b) => {
+                either(expr_a.includes_ref(n, env), expr_b.includes_ref(n, env))
+            }
+            Intersection(expr_a, expr_b) => {
+                both(expr_a.includes_ref(n, env), expr_b.includes_ref(n, env))
+            }
+            Difference(expr_a, expr_b) => both(
+                expr_a.includes_ref(n, env),
+                expr_b.includes_ref(n, env).map(|included| !included),
+            ),
+            Complement(expr) => expr.includes_ref(n, env).map(|included| !included),
+        }
+    }
+}
+
+/// The members of `dom` within `window` as an `ExplicitSet`, so that a
+/// domain built from set operations is evaluated once and can then be
+/// substituted wherever it is used. Values whose membership cannot be
+/// decided, because the domain is not ground, are left out.
+pub fn materialize(
+    dom: &IntegerNumberDomainExpression,
+    window: RangeInclusive<i128>,
+) -> IntegerNumberDomainExpression {
+    IntegerNumberDomainExpression::ExplicitSet(
+        dom.iter(window)
+            .map(|n| IntegerNumberExpression::IntegerNumberValue(IntegerNumber::Value(n)))
+            .collect(),
+    )
+}
+
+/// Removes `Universe` and `Empty` from the set operations of `dom`.
+/// `Universe` absorbs a union and `Empty` an intersection, while `Empty`
+/// in a union, `Universe` in an intersection and `Empty` subtracted from
+/// a set leave the other operand. Subtracting anything from `Empty`, or
+/// `Universe` from anything, leaves `Empty`, and each of the two is the
+/// complement of the other. Operands are simplified first, so no rule is
+/// left to apply to the result.
+pub fn simplify_domain(dom: IntegerNumberDomainExpression) -> IntegerNumberDomainExpression {
+    use IntegerNumberDomainExpression::*;
+    match dom {
+        Union(expr_a, expr_b) => match (simplify_domain(*expr_a), simplify_domain(*expr_b)) {
+            (Universe, _) | (_, Universe) => Universe,
+            (Empty, expr) | (expr, Empty) => expr,
+            (expr_a, expr_b) => Union(Box::new(expr_a), Box::new(expr_b)),
+        },
+        Intersection(expr_a, expr_b) => {
+            match (simplify_domain(*expr_a), simplify_domain(*expr_b)) {
+                (Empty, _) | (_, Empty) => Empty,
+                (Universe, expr) | (expr, Universe) => expr,
+                (expr_a, expr_b) => Intersection(Box::new(expr_a), Box::new(expr_b)),
+            }
+        }
+        Difference(expr_a, expr_b) => match (simplify_domain(*expr_a), simplify_domain(*expr_b)) {
+            (expr, Empty) => expr,
+            (Empty, _) | (_, Universe) => Empty,
+            (expr_a, expr_b) => Difference(Box::new(expr_a), Box::new(expr_b)),
+        },
+        Complement(expr) => match simplify_domain(*expr) {
+            Universe => Empty,
+            Empty => Universe,
+            expr => Complement(Box::new(expr)),
+        },
+        leaf => leaf,
+    }
+}
+
+impl super::Apply for IntegerNumberDomainExpression {
+    fn apply_noting(&self, state: &[super::Assignment], substituted: &mut bool) -> Self {
+        use IntegerNumberDomainExpression::*;
+        match self {
+            Universe => Universe,
+            Empty => Empty,
+            ClosedRange(expr_a, expr_b) => ClosedRange(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            OpenRange(expr_a, expr_b) => OpenRange(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            OpenLeftClosedRightRange(expr_a, expr_b) => OpenLeftClosedRightRange(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            ClosedLeftOpenRightRange(expr_a, expr_b) => ClosedLeftOpenRightRange(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            ExplicitSet(elements) => ExplicitSet(
+                elements
+                    .iter()
+                    .map(|element| element.apply_noting(state, substituted))
+                    .collect(),
+            ),
+            Union(expr_a, expr_b) => Union(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Intersection(expr_a, expr_b) => Intersection(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Difference(expr_a, expr_b) => Difference(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Complement(expr) => Complement(Box::new(expr.apply_noting(state, substituted))),
+        }
+    }
+}
+
+impl super::Substitute for IntegerNumberDomainExpression {
+    fn substitute_with(&self, substitution: &super::Substitution) -> Self {
+        use IntegerNumberDomainExpression::*;
+        match self {
+            Universe => Universe,
+            Empty => Empty,
+            ClosedRange(expr_a, expr_b) => ClosedRange(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            OpenRange(expr_a, expr_b) => OpenRange(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            OpenLeftClosedRightRange(expr_a, expr_b) => OpenLeftClosedRightRange(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            ClosedLeftOpenRightRange(expr_a, expr_b) => ClosedLeftOpenRightRange(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            ExplicitSet(elements) => ExplicitSet(
+                elements
+                    .iter()
+                    .map(|element| element.substitute_with(substitution))
+                    .collect(),
+            ),
+            Union(expr_a, expr_b) => Union(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            Intersection(expr_a, expr_b) => Intersection(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            Difference(expr_a, expr_b) => Difference(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            Complement(expr) => Complement(Box::new(expr.substitute_with(substitution))),
+        }
+    }
+}
+
+/// Comparisons follow IEEE-like semantics for `NaN`: it is never equal
+/// to, greater than, less than or in the domain of anything, so only
+/// `Different` holds when a `NaN` is involved.
+impl BooleanIntegerNumberExpression {
+    /// The value under `env`, without cloning any part of the expression.
+    pub fn evaluate_ref(
+        &self,
+        env: &super::Environment,
+    ) -> Result<super::boolean::BooleanValue, super::EvalError> {
+        use super::boolean::BooleanValue;
+        use BooleanIntegerNumberExpression::*;
+        fn compare(
+            expr_a: &IntegerNumberExpression,
+            expr_b: &IntegerNumberExpression,
+            env: &super::Environment,
+            test: fn(&i128, &i128) -> bool,
+        ) -> Result<BooleanValue, super::EvalError> {
+            match (expr_a.evaluate_ref(env)?, expr_b.evaluate_ref(env)?) {
+                (IntegerNumber::Value(a), IntegerNumber::Value(b)) => {
+                    Ok(BooleanValue::from_bool(test(&a, &b)))
+                }
+                _ => Ok(BooleanValue::False),
+            }
+        }
+        match self {
+            Equals(expr_a, expr_b) => compare(expr_a, expr_b, env, i128::eq),
             Different(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+                super::boolean::not(compare(expr_a, expr_b, env, i128::eq))
             }
-            Greater(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            Greater(expr_a, expr_b) => compare(expr_a, expr_b, env, i128::gt),
+            Less(expr_a, expr_b) => compare(expr_a, expr_b, env, i128::lt),
+            In(expr_a, expr_b) => match expr_a.evaluate_ref(env)? {
+                IntegerNumber::Value(n) => expr_b.includes_ref(n, env).map(BooleanValue::from_bool),
+                IntegerNumber::NaN => Ok(BooleanValue::False),
+            },
+        }
+    }
+}
+
+impl super::Evaluate for BooleanIntegerNumberExpression {
+    type Value = super::boolean::BooleanValue;
+    fn evaluate_with(
+        &self,
+        env: &[super::Assignment],
+        division: DivisionMode,
+    ) -> Result<super::boolean::BooleanValue, super::EvalError> {
+        self.evaluate_ref(&super::Environment::new(env).with_division(division))
+    }
+}
+
+impl super::Apply for BooleanIntegerNumberExpression {
+    fn apply_noting(&self, state: &[super::Assignment], substituted: &mut bool) -> Self {
+        use BooleanIntegerNumberExpression::*;
+        match self {
+            Equals(expr_a, expr_b) => Equals(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Different(expr_a, expr_b) => Different(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Greater(expr_a, expr_b) => Greater(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            Less(expr_a, expr_b) => Less(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            In(expr_a, expr_b) => In(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+        }
+    }
+}
+
+impl super::Substitute for BooleanIntegerNumberExpression {
+    fn substitute_with(&self, substitution: &super::Substitution) -> Self {
+        use BooleanIntegerNumberExpression::*;
+        match self {
+            Equals(expr_a, expr_b) => Equals(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            Different(expr_a, expr_b) => Different(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            Greater(expr_a, expr_b) => Greater(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            Less(expr_a, expr_b) => Less(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            In(expr_a, expr_b) => In(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+        }
+    }
+}
+
+/// `+` and `*` chains are flattened and their operands sorted, as are
+/// the operands of `sum` and `product`; `Parenthesis` nodes are dropped.
+impl super::Canonicalize for IntegerNumberExpression {
+    fn canonicalize(self) -> Self {
+        use super::{chain_operands, sorted_chain};
+        use IntegerNumberExpression::*;
+        let join_add = |a, b| Add(Box::new(a), Box::new(b));
+        let join_times = |a, b| Times(Box::new(a), Box::new(b));
+        match self {
+            Add(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_add(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Add(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_add,
+            ),
+            Times(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_times(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Times(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_times,
+            ),
+            Minus(expr_a, expr_b) => Minus(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            Divide(expr_a, expr_b) => Divide(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            Modulo(expr_a, expr_b) => Modulo(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            Pow(expr_a, expr_b) => Pow(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            PowMod(expr_a, expr_b, expr_c) => PowMod(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+                Box::new(expr_c.canonicalize()),
+            ),
+            Sum(exprs) => {
+                let mut exprs: Vec<_> = exprs.into_iter().map(Self::canonicalize).collect();
+                exprs.sort();
+                Sum(exprs)
             }
-            Less(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            Product(exprs) => {
+                let mut exprs: Vec<_> = exprs.into_iter().map(Self::canonicalize).collect();
+                exprs.sort();
+                Product(exprs)
             }
-            In(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.get_free());
+            Negate(expr) => Negate(Box::new(expr.canonicalize())),
+            Abs(expr) => Abs(Box::new(expr.canonicalize())),
+            Parenthesis(expr) => expr.canonicalize(),
+            Let(symbol, value, body) => Let(
+                symbol,
+                Box::new(value.canonicalize()),
+                Box::new(body.canonicalize()),
+            ),
+            OfBoolean(expr) => OfBoolean(Box::new(expr.canonicalize())),
+            IfThenElse(condition, then, otherwise) => IfThenElse(
+                Box::new(condition.canonicalize()),
+                Box::new(then.canonicalize()),
+                Box::new(otherwise.canonicalize()),
+            ),
+            leaf => leaf,
+        }
+    }
+}
+
+/// `union` and `intersection` chains are flattened and their operands
+/// sorted, and explicit sets are sorted without duplicates.
+impl super::Canonicalize for IntegerNumberDomainExpression {
+    fn canonicalize(self) -> Self {
+        use super::{chain_operands, sorted_chain};
+        use IntegerNumberDomainExpression::*;
+        let join_union = |a, b| Union(Box::new(a), Box::new(b));
+        let join_intersection = |a, b| Intersection(Box::new(a), Box::new(b));
+        let range = |expr_a: Box<IntegerNumberExpression>, expr_b: Box<IntegerNumberExpression>| {
+            (
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            )
+        };
+        match self {
+            Union(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_union(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Union(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_union,
+            ),
+            Intersection(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_intersection(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Intersection(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_intersection,
+            ),
+            Difference(expr_a, expr_b) => Difference(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            Complement(expr) => Complement(Box::new(expr.canonicalize())),
+            ExplicitSet(elements) => {
+                let mut elements: Vec<IntegerNumberExpression> = elements
+                    .into_iter()
+                    .map(|element| element.canonicalize())
+                    .collect();
+                elements.sort();
+                elements.dedup();
+                ExplicitSet(elements)
+            }
+            ClosedRange(expr_a, expr_b) => {
+                let (expr_a, expr_b) = range(expr_a, expr_b);
+                ClosedRange(expr_a, expr_b)
+            }
+            OpenRange(expr_a, expr_b) => {
+                let (expr_a, expr_b) = range(expr_a, expr_b);
+                OpenRange(expr_a, expr_b)
+            }
+            OpenLeftClosedRightRange(expr_a, expr_b) => {
+                let (expr_a, expr_b) = range(expr_a, expr_b);
+                OpenLeftClosedRightRange(expr_a, expr_b)
+            }
+            ClosedLeftOpenRightRange(expr_a, expr_b) => {
+                let (expr_a, expr_b) = range(expr_a, expr_b);
+                ClosedLeftOpenRightRange(expr_a, expr_b)
+            }
+            leaf => leaf,
+        }
+    }
+}
+
+/// `=` and `!=` have their operands sorted and `a > b` becomes `b < a`.
+impl super::Canonicalize for BooleanIntegerNumberExpression {
+    fn canonicalize(self) -> Self {
+        use BooleanIntegerNumberExpression::*;
+        let sorted = |expr_a: Box<IntegerNumberExpression>,
+                      expr_b: Box<IntegerNumberExpression>| {
+            let (expr_a, expr_b) = (expr_a.canonicalize(), expr_b.canonicalize());
+            if expr_b < expr_a {
+                (Box::new(expr_b), Box::new(expr_a))
+            } else {
+                (Box::new(expr_a), Box::new(expr_b))
+            }
+        };
+        match self {
+            Equals(expr_a, expr_b) => {
+                let (expr_a, expr_b) = sorted(expr_a, expr_b);
+                Equals(expr_a, expr_b)
+            }
+            Different(expr_a, expr_b) => {
+                let (expr_a, expr_b) = sorted(expr_a, expr_b);
+                Different(expr_a, expr_b)
             }
+            Greater(expr_a, expr_b) => Less(
+                Box::new(expr_b.canonicalize()),
+                Box::new(expr_a.canonicalize()),
+            ),
+            Less(expr_a, expr_b) => Less(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            In(expr_a, expr_b) => In(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
         }
-        free
     }
 }
 
+/// The sample of a ground domain is its smallest member, so that it is
+/// the same every time: the lower bound of a range closed on the left,
+/// or one above it for a range open on the left. A range with a `NaN`
+/// bound or with its bounds the wrong way round is empty.
 impl super::Sample for IntegerNumberDomainExpression {
-    fn sample(&self) -> Option<super::AssignedValue> {
+    fn try_sample(&self) -> Result<super::AssignedValue, super::SampleError> {
+        use super::FreeVariable;
         use IntegerNumberDomainExpression::*;
         match self {
-            Empty => None,
-            Universe => Some(super::AssignedValue::Integer(IntegerNumber::Value(0))),
-            _ => unimplemented!(),
+            Empty => Err(super::SampleError::EmptyDomain),
+            Universe => Err(super::SampleError::Unbounded),
+            _ if !self.get_free().is_empty() => Err(super::SampleError::RequiresEnvironment),
+            _ => match self.hull(&[], DivisionMode::default()) {
+                Ok(Hull::Interval(low, high)) => self
+                    .iter(low..=high)
+                    .next()
+                    .map(|n| super::AssignedValue::Integer(IntegerNumber::Value(n)))
+                    .ok_or(super::SampleError::EmptyDomain),
+                Ok(Hull::Unbounded) => Err(super::SampleError::Unbounded),
+                _ => Err(super::SampleError::EmptyDomain),
+            },
+        }
+    }
+}
+
+/// Only ground domains have candidates or contain values.
+impl super::ValueDomain for IntegerNumberDomainExpression {
+    fn candidates(&self) -> Option<Vec<super::AssignedValue>> {
+        self.candidates_with(DivisionMode::default())
+    }
+    fn contains(&self, value: &super::AssignedValue) -> bool {
+        self.contains_with(value, DivisionMode::default())
+    }
+    fn candidates_with(&self, division: DivisionMode) -> Option<Vec<super::AssignedValue>> {
+        let values = self.finite_values(super::MAX_CANDIDATES, division)?;
+        Some(
+            values
+                .into_iter()
+                .map(|n| super::AssignedValue::Integer(IntegerNumber::Value(n)))
+                .collect(),
+        )
+    }
+    fn contains_with(&self, value: &super::AssignedValue, division: DivisionMode) -> bool {
+        match value {
+            super::AssignedValue::Integer(IntegerNumber::Value(n)) => {
+                let env = super::Environment::new(&[]).with_division(division);
+                self.includes_ref(*n, &env) == Ok(true)
+            }
+            _ => false,
         }
     }
+    fn cardinality(&self) -> Option<u128> {
+        self.finite_values(super::MAX_CANDIDATES, DivisionMode::default())
+            .map(|values| values.len() as u128)
+    }
+    fn clone_box(&self) -> Box<dyn super::ValueDomain> {
+        Box::new(self.clone())
+    }
+    fn identity(&self) -> String {
+        format!("{:?}", self)
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::{
         BooleanIntegerNumberExpression, IntegerNumber, IntegerNumberDomainExpression,
         IntegerNumberExpression,
     };
+    use crate::expressions::tests::{below, leaf, shrink_node, ArbitraryAt};
     use quickcheck::{Arbitrary, Gen};
 
     impl Arbitrary for IntegerNumber {
@@ -201,98 +2013,909 @@ mod tests {
                 _ => IntegerNumber::Value(Arbitrary::arbitrary(g)),
             }
         }
+        /// Values shrink toward zero, and `NaN` not at all.
+        fn shrink(&self) -> Box<dyn Iterator<Item = IntegerNumber>> {
+            match self {
+                IntegerNumber::NaN => quickcheck::empty_shrinker(),
+                IntegerNumber::Value(n) => Box::new(n.shrink().map(IntegerNumber::Value)),
+            }
+        }
+    }
+
+    type Operand = Box<IntegerNumberExpression>;
+
+    /// The shrinks of a node with two integer operands: either operand on
+    /// its own, then the node with one of them shrunk.
+    fn shrink_pair(
+        expr_a: &IntegerNumberExpression,
+        expr_b: &IntegerNumberExpression,
+        rebuild: fn(Operand, Operand) -> IntegerNumberExpression,
+    ) -> Box<dyn Iterator<Item = IntegerNumberExpression>> {
+        shrink_node(
+            vec![expr_a.clone(), expr_b.clone()],
+            (Box::new(expr_a.clone()), Box::new(expr_b.clone())),
+            move |(expr_a, expr_b)| rebuild(expr_a, expr_b),
+        )
+    }
+
+    impl ArbitraryAt for IntegerNumberExpression {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> IntegerNumberExpression {
+            if leaf(g, depth) {
+                return match u32::arbitrary(g) % 10 {
+                    0 => IntegerNumberExpression::IntegerNumberValue(Arbitrary::arbitrary(g)),
+                    _ => IntegerNumberExpression::IntegerNumberVariable(Arbitrary::arbitrary(g)),
+                };
+            }
+            match u32::arbitrary(g) % 20 {
+                0 => IntegerNumberExpression::IntegerNumberValue(Arbitrary::arbitrary(g)),
+                1 => IntegerNumberExpression::Parenthesis(below(g, depth)),
+                2 => IntegerNumberExpression::Negate(below(g, depth)),
+                3 => IntegerNumberExpression::Add(below(g, depth), below(g, depth)),
+                4 => IntegerNumberExpression::Minus(below(g, depth), below(g, depth)),
+                5 => IntegerNumberExpression::Times(below(g, depth), below(g, depth)),
+                6 => IntegerNumberExpression::Divide(below(g, depth), below(g, depth)),
+                7 => IntegerNumberExpression::Modulo(below(g, depth), below(g, depth)),
+                8 => IntegerNumberExpression::Pow(below(g, depth), below(g, depth)),
+                9 => IntegerNumberExpression::PowMod(
+                    below(g, depth),
+                    below(g, depth),
+                    below(g, depth),
+                ),
+                10 => IntegerNumberExpression::Abs(below(g, depth)),
+                _ => IntegerNumberExpression::IntegerNumberVariable(Arbitrary::arbitrary(g)),
+            }
+        }
     }
 
     impl Arbitrary for IntegerNumberExpression {
         fn arbitrary(g: &mut Gen) -> IntegerNumberExpression {
-            match u32::arbitrary(g) % 16 {
-                0 => IntegerNumberExpression::IntegerNumberValue(Arbitrary::arbitrary(g)),
-                1 => IntegerNumberExpression::Parenthesis(Arbitrary::arbitrary(g)),
-                2 => IntegerNumberExpression::Negate(Arbitrary::arbitrary(g)),
-                3 => IntegerNumberExpression::Add(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g)),
-                4 => {
-                    IntegerNumberExpression::Minus(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g))
-                }
-                5 => {
-                    IntegerNumberExpression::Times(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g))
-                }
-                6 => IntegerNumberExpression::Divide(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+            IntegerNumberExpression::arbitrary_at(g, 0)
+        }
+        fn shrink(&self) -> Box<dyn Iterator<Item = IntegerNumberExpression>> {
+            use IntegerNumberExpression::*;
+            match self {
+                IntegerNumberVariable(_) | TupleGet(_, _) => quickcheck::empty_shrinker(),
+                IntegerNumberValue(value) => Box::new(value.shrink().map(IntegerNumberValue)),
+                Parenthesis(expr) => shrink_node(vec![*expr.clone()], expr.clone(), Parenthesis),
+                Negate(expr) => shrink_node(vec![*expr.clone()], expr.clone(), Negate),
+                Abs(expr) => shrink_node(vec![*expr.clone()], expr.clone(), Abs),
+                Add(expr_a, expr_b) => shrink_pair(expr_a, expr_b, Add),
+                Minus(expr_a, expr_b) => shrink_pair(expr_a, expr_b, Minus),
+                Times(expr_a, expr_b) => shrink_pair(expr_a, expr_b, Times),
+                Divide(expr_a, expr_b) => shrink_pair(expr_a, expr_b, Divide),
+                Modulo(expr_a, expr_b) => shrink_pair(expr_a, expr_b, Modulo),
+                Pow(expr_a, expr_b) => shrink_pair(expr_a, expr_b, Pow),
+                PowMod(expr_a, expr_b, expr_c) => shrink_node(
+                    vec![*expr_a.clone(), *expr_b.clone(), *expr_c.clone()],
+                    (expr_a.clone(), expr_b.clone(), expr_c.clone()),
+                    |(expr_a, expr_b, expr_c)| PowMod(expr_a, expr_b, expr_c),
                 ),
-                7 => IntegerNumberExpression::Modulo(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+                Sum(exprs) => shrink_node(exprs.clone(), exprs.clone(), Sum),
+                Product(exprs) => shrink_node(exprs.clone(), exprs.clone(), Product),
+                Let(symbol, value, body) => {
+                    let symbol = symbol.clone();
+                    shrink_node(
+                        vec![*value.clone(), *body.clone()],
+                        (value.clone(), body.clone()),
+                        move |(value, body)| Let(symbol.clone(), value, body),
+                    )
+                }
+                OfBoolean(expr) => Box::new(expr.shrink().map(OfBoolean)),
+                IfThenElse(condition, then, otherwise) => shrink_node(
+                    vec![*then.clone(), *otherwise.clone()],
+                    (condition.clone(), then.clone(), otherwise.clone()),
+                    |(condition, then, otherwise)| IfThenElse(condition, then, otherwise),
                 ),
-                _ => IntegerNumberExpression::IntegerNumberVariable(Arbitrary::arbitrary(g)),
             }
         }
     }
 
-    impl Arbitrary for IntegerNumberDomainExpression {
-        fn arbitrary(g: &mut Gen) -> IntegerNumberDomainExpression {
+    impl ArbitraryAt for IntegerNumberDomainExpression {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> IntegerNumberDomainExpression {
+            if leaf(g, depth) {
+                return match u32::arbitrary(g) % 23 {
+                    0 => IntegerNumberDomainExpression::Empty,
+                    _ => IntegerNumberDomainExpression::Universe,
+                };
+            }
             match u32::arbitrary(g) % 32 {
                 0 => IntegerNumberDomainExpression::Empty,
-                1 => IntegerNumberDomainExpression::ClosedRange(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
-                ),
-                2 => IntegerNumberDomainExpression::OpenRange(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
-                ),
+                1 => IntegerNumberDomainExpression::ClosedRange(below(g, depth), below(g, depth)),
+                2 => IntegerNumberDomainExpression::OpenRange(below(g, depth), below(g, depth)),
                 3 => IntegerNumberDomainExpression::OpenLeftClosedRightRange(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+                    below(g, depth),
+                    below(g, depth),
                 ),
                 4 => IntegerNumberDomainExpression::ClosedLeftOpenRightRange(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
-                ),
-                5 => IntegerNumberDomainExpression::ExplicitSet(Arbitrary::arbitrary(g)),
-                6 => IntegerNumberDomainExpression::Union(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
-                ),
-                7 => IntegerNumberDomainExpression::Intersection(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
-                ),
-                8 => IntegerNumberDomainExpression::Difference(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+                    below(g, depth),
+                    below(g, depth),
                 ),
-                9 => IntegerNumberDomainExpression::Complement(Arbitrary::arbitrary(g)),
+                5 => IntegerNumberDomainExpression::ExplicitSet(below(g, depth)),
+                6 => IntegerNumberDomainExpression::Union(below(g, depth), below(g, depth)),
+                7 => IntegerNumberDomainExpression::Intersection(below(g, depth), below(g, depth)),
+                8 => IntegerNumberDomainExpression::Difference(below(g, depth), below(g, depth)),
+                9 => IntegerNumberDomainExpression::Complement(below(g, depth)),
                 _ => IntegerNumberDomainExpression::Universe,
             }
         }
     }
 
+    impl Arbitrary for IntegerNumberDomainExpression {
+        fn arbitrary(g: &mut Gen) -> IntegerNumberDomainExpression {
+            IntegerNumberDomainExpression::arbitrary_at(g, 0)
+        }
+        /// Domains of integers shrink to `Universe` before their bounds or
+        /// elements do.
+        fn shrink(&self) -> Box<dyn Iterator<Item = IntegerNumberDomainExpression>> {
+            use IntegerNumberDomainExpression::*;
+            let bounds = |low: &Operand, high: &Operand, rebuild: fn(Operand, Operand) -> _| {
+                shrink_node(
+                    vec![Universe],
+                    (low.clone(), high.clone()),
+                    move |(low, high)| rebuild(low, high),
+                )
+            };
+            let pair = |expr_a: &IntegerNumberDomainExpression,
+                        expr_b: &IntegerNumberDomainExpression,
+                        rebuild: fn(Box<_>, Box<_>) -> _| {
+                shrink_node(
+                    vec![expr_a.clone(), expr_b.clone()],
+                    (Box::new(expr_a.clone()), Box::new(expr_b.clone())),
+                    move |(expr_a, expr_b)| rebuild(expr_a, expr_b),
+                )
+            };
+            match self {
+                Universe => quickcheck::empty_shrinker(),
+                Empty => quickcheck::single_shrinker(Universe),
+                ClosedRange(low, high) => bounds(low, high, ClosedRange),
+                OpenRange(low, high) => bounds(low, high, OpenRange),
+                OpenLeftClosedRightRange(low, high) => bounds(low, high, OpenLeftClosedRightRange),
+                ClosedLeftOpenRightRange(low, high) => bounds(low, high, ClosedLeftOpenRightRange),
+                ExplicitSet(elements) => shrink_node(vec![Universe], elements.clone(), ExplicitSet),
+                Union(expr_a, expr_b) => pair(expr_a, expr_b, Union),
+                Intersection(expr_a, expr_b) => pair(expr_a, expr_b, Intersection),
+                Difference(expr_a, expr_b) => pair(expr_a, expr_b, Difference),
+                Complement(expr) => shrink_node(vec![*expr.clone()], expr.clone(), Complement),
+            }
+        }
+    }
+
+    impl ArbitraryAt for BooleanIntegerNumberExpression {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> BooleanIntegerNumberExpression {
+            match u32::arbitrary(g) % 5 {
+                0 => BooleanIntegerNumberExpression::Equals(below(g, depth), below(g, depth)),
+                1 => BooleanIntegerNumberExpression::Different(below(g, depth), below(g, depth)),
+                2 => BooleanIntegerNumberExpression::Greater(below(g, depth), below(g, depth)),
+                3 => BooleanIntegerNumberExpression::Less(below(g, depth), below(g, depth)),
+                4 => BooleanIntegerNumberExpression::In(below(g, depth), below(g, depth)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
     impl Arbitrary for BooleanIntegerNumberExpression {
         fn arbitrary(g: &mut Gen) -> BooleanIntegerNumberExpression {
-            match u32::arbitrary(g) % 5 {
-                0 => BooleanIntegerNumberExpression::Equals(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
-                ),
-                1 => BooleanIntegerNumberExpression::Different(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
-                ),
-                2 => BooleanIntegerNumberExpression::Greater(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+            BooleanIntegerNumberExpression::arbitrary_at(g, 0)
+        }
+        fn shrink(&self) -> Box<dyn Iterator<Item = BooleanIntegerNumberExpression>> {
+            use BooleanIntegerNumberExpression::*;
+            let pair = |expr_a: &Operand, expr_b: &Operand, rebuild: fn(Operand, Operand) -> _| {
+                shrink_node(
+                    vec![],
+                    (expr_a.clone(), expr_b.clone()),
+                    move |(expr_a, expr_b)| rebuild(expr_a, expr_b),
+                )
+            };
+            match self {
+                Equals(expr_a, expr_b) => pair(expr_a, expr_b, Equals),
+                Different(expr_a, expr_b) => pair(expr_a, expr_b, Different),
+                Greater(expr_a, expr_b) => pair(expr_a, expr_b, Greater),
+                Less(expr_a, expr_b) => pair(expr_a, expr_b, Less),
+                In(expr, domain) => {
+                    shrink_node(vec![], (expr.clone(), domain.clone()), |(expr, domain)| {
+                        In(expr, domain)
+                    })
+                }
+            }
+        }
+    }
+
+    /// A non-empty domain with literal bounds inside `-20..=20`, for tests
+    /// that need domains the solver can enumerate. The plain `Arbitrary`
+    /// impl builds domains of any shape, most of them empty or huge.
+    #[derive(Debug, Clone)]
+    pub(crate) struct SmallDomain(pub(crate) IntegerNumberDomainExpression);
+
+    impl Arbitrary for SmallDomain {
+        fn arbitrary(g: &mut Gen) -> SmallDomain {
+            use IntegerNumberDomainExpression::*;
+            let bound = |g: &mut Gen| i128::from(i8::arbitrary(g) % 20);
+            let (a, b) = (bound(g), bound(g));
+            let (low, high) = (a.min(b), a.max(b));
+            SmallDomain(match u32::arbitrary(g) % 8 {
+                0 => ClosedRange(value(low), value(high)),
+                1 => OpenRange(value(low - 1), value(high + 1)),
+                2 => OpenLeftClosedRightRange(value(low - 1), value(high)),
+                3 => ClosedLeftOpenRightRange(value(low), value(high + 1)),
+                4 => ExplicitSet(vec![*value(a), *value(b)]),
+                5 => Union(
+                    Box::new(SmallDomain::arbitrary(g).0),
+                    Box::new(SmallDomain::arbitrary(g).0),
                 ),
-                3 => BooleanIntegerNumberExpression::Less(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+                6 => Difference(
+                    Box::new(ClosedRange(value(low), value(high + 1))),
+                    Box::new(ExplicitSet(vec![*value(b)])),
                 ),
-                4 => BooleanIntegerNumberExpression::In(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+                _ => Intersection(
+                    Box::new(ClosedRange(value(low), value(high))),
+                    Box::new(ExplicitSet(vec![*value(a), *value(high + 1)])),
                 ),
-                _ => unreachable!(),
+            })
+        }
+    }
+
+    fn value(n: i128) -> Box<IntegerNumberExpression> {
+        Box::new(IntegerNumberExpression::IntegerNumberValue(
+            IntegerNumber::Value(n),
+        ))
+    }
+
+    #[test]
+    fn pow_mod_stays_below_the_modulus_where_pow_overflows() {
+        use crate::expressions::Evaluate;
+        let modulus = 1_000_000_000_000_000_000_000_000_000_057;
+        let pow = IntegerNumberExpression::Pow(value(7), value(1_000_000_000_000_000_000));
+        let pow_mod = IntegerNumberExpression::PowMod(
+            value(7),
+            value(1_000_000_000_000_000_000),
+            value(modulus),
+        );
+        assert_eq!(pow.evaluate(&[]), Ok(IntegerNumber::NaN));
+        assert_eq!(
+            pow_mod.evaluate(&[]),
+            Ok(IntegerNumber::Value(
+                745_549_563_994_374_998_005_913_758_982
+            ))
+        );
+        assert_eq!(
+            IntegerNumberExpression::Pow(value(-3), value(5)).evaluate(&[]),
+            Ok(IntegerNumber::Value(-243))
+        );
+    }
+
+    #[test]
+    fn negative_exponents_are_nan() {
+        use crate::expressions::Evaluate;
+        assert_eq!(
+            IntegerNumberExpression::Pow(value(2), value(-1)).evaluate(&[]),
+            Ok(IntegerNumber::NaN)
+        );
+        assert_eq!(
+            IntegerNumberExpression::PowMod(value(2), value(-1), value(7)).evaluate(&[]),
+            Ok(IntegerNumber::NaN)
+        );
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn small_domains_are_non_empty_and_bounded(domain: SmallDomain, n: i128) -> bool {
+        let includes = |n| domain.0.includes(n, &[]) == Ok(true);
+        (-20..=20).any(includes) && ((-20..=20).contains(&n) || !includes(n))
+    }
+
+    #[test]
+    fn linear_and_nonlinear_expressions_are_told_apart() {
+        use super::is_linear;
+        use crate::expressions::tuple::{ComponentType, TupleComponent, TupleExpression};
+        use IntegerNumberExpression::*;
+        let var = |name: &str| {
+            Box::new(IntegerNumberVariable(crate::expressions::Symbol::new(
+                name.to_string(),
+            )))
+        };
+        let scaled = Add(
+            Box::new(Times(value(2), var("x"))),
+            Box::new(Times(value(3), var("y"))),
+        );
+        assert!(is_linear(&scaled));
+        assert!(!is_linear(&Times(var("x"), var("y"))));
+        assert!(is_linear(&Divide(var("x"), value(2))));
+        assert!(!is_linear(&Divide(value(2), var("x"))));
+        assert!(!is_linear(&Modulo(var("x"), value(2))));
+        assert!(is_linear(&Pow(var("x"), value(1))));
+        assert!(!is_linear(&Pow(var("x"), value(2))));
+        assert!(!is_linear(&Pow(value(2), var("x"))));
+        assert!(!is_linear(&Pow(value(0), var("x"))));
+        assert!(is_linear(&Pow(value(2), value(3))));
+        let pair = |component: IntegerNumberExpression| {
+            Box::new(TupleExpression::Tuple(vec![
+                TupleComponent::Integer(*value(1)),
+                TupleComponent::Integer(component),
+            ]))
+        };
+        assert!(is_linear(&TupleGet(pair(scaled.clone()), 1)));
+        assert!(!is_linear(&TupleGet(pair(Times(var("x"), var("y"))), 1)));
+        assert!(!is_linear(&TupleGet(pair(Pow(var("x"), value(2))), 1)));
+        let point = TupleExpression::TupleVariable(
+            crate::expressions::Symbol::new("p".to_string()),
+            vec![ComponentType::Integer, ComponentType::Integer],
+        );
+        assert!(is_linear(&TupleGet(Box::new(point), 0)));
+    }
+
+    #[test]
+    fn finite_values_enumerates_set_operations_in_order() {
+        use super::DivisionMode::TruncateTowardZero;
+        use IntegerNumberDomainExpression::*;
+        let domain = Difference(
+            Box::new(Union(
+                Box::new(OpenRange(value(5), value(8))),
+                Box::new(ClosedRange(value(-2), value(1))),
+            )),
+            Box::new(ExplicitSet(vec![*value(0), *value(6)])),
+        );
+        assert_eq!(
+            domain.finite_values(100, TruncateTowardZero),
+            Some(vec![-2, -1, 1, 7])
+        );
+        assert_eq!(domain.finite_values(5, TruncateTowardZero), None);
+        assert_eq!(
+            Complement(Box::new(Empty)).finite_values(100, TruncateTowardZero),
+            None
+        );
+        assert_eq!(
+            ClosedRange(value(3), value(1)).finite_values(100, TruncateTowardZero),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn simplify_folds_divisions_and_remainders() {
+        use IntegerNumberExpression::*;
+        let x = || {
+            Box::new(IntegerNumberVariable(crate::expressions::Symbol::new(
+                "x".to_string(),
+            )))
+        };
+        assert_eq!(Divide(x(), value(1)).simplify(), *x());
+        assert_eq!(Modulo(x(), value(1)).simplify(), *value(0));
+        assert_eq!(Modulo(value(0), x()).simplify(), Modulo(value(0), x()));
+        assert_eq!(Modulo(value(0), value(5)).simplify(), *value(0));
+        assert_eq!(Divide(value(-7), value(2)).simplify(), *value(-3));
+        assert_eq!(Modulo(value(-7), value(2)).simplify(), *value(-1));
+        assert_eq!(
+            Divide(value(4), value(0)).simplify(),
+            IntegerNumberValue(IntegerNumber::NaN)
+        );
+    }
+
+    #[test]
+    fn materialized_domains_keep_their_members() {
+        use super::materialize;
+        use IntegerNumberDomainExpression::*;
+        let domain = Union(
+            Box::new(ClosedRange(value(-3), value(1))),
+            Box::new(OpenRange(value(4), value(9))),
+        );
+        let frozen = materialize(&domain, -10..=10);
+        assert!(matches!(&frozen, ExplicitSet(elements) if elements.len() == 9));
+        for n in -10..=10 {
+            assert_eq!(frozen.includes(n, &[]), domain.includes(n, &[]));
+        }
+        assert_eq!(
+            materialize(&Complement(Box::new(domain)), 0..=3),
+            ExplicitSet(vec![*value(2), *value(3)])
+        );
+    }
+
+    #[test]
+    fn sampling_reports_why_there_is_no_value() {
+        use crate::expressions::{Sample, SampleError};
+        use IntegerNumberDomainExpression::*;
+        assert_eq!(Empty.try_sample(), Err(SampleError::EmptyDomain));
+        assert_eq!(Universe.try_sample(), Err(SampleError::Unbounded));
+        assert_eq!(Universe.sample(), None);
+        let bounded_by_n = ClosedRange(
+            value(0),
+            Box::new(IntegerNumberExpression::IntegerNumberVariable(
+                crate::expressions::Symbol::new("n".to_string()),
+            )),
+        );
+        assert_eq!(
+            bounded_by_n.try_sample(),
+            Err(SampleError::RequiresEnvironment)
+        );
+    }
+
+    #[test]
+    fn division_rounds_as_the_mode_says() {
+        use super::DivisionMode::*;
+        use crate::expressions::Evaluate;
+        use IntegerNumberExpression::*;
+        let result = |expr: IntegerNumberExpression, mode| match expr.evaluate_with(&[], mode) {
+            Ok(IntegerNumber::Value(n)) => n,
+            other => panic!("expected a number, got {:?}", other),
+        };
+        for (mode, quotient, remainder) in [
+            (TruncateTowardZero, -3, -1),
+            (FloorDivision, -4, 1),
+            (EuclideanRemainder, -4, 1),
+        ] {
+            assert_eq!(result(Divide(value(-7), value(2)), mode), quotient);
+            assert_eq!(result(Modulo(value(-7), value(2)), mode), remainder);
+        }
+        for (mode, quotient, remainder) in [
+            (TruncateTowardZero, 3, -1),
+            (FloorDivision, 3, -1),
+            (EuclideanRemainder, 4, 1),
+        ] {
+            assert_eq!(result(Divide(value(-7), value(-2)), mode), quotient);
+            assert_eq!(result(Modulo(value(-7), value(-2)), mode), remainder);
+        }
+        assert_eq!(
+            Divide(value(-7), value(2)).evaluate(&[]),
+            Divide(value(-7), value(2)).evaluate_with(&[], TruncateTowardZero)
+        );
+        assert_eq!(
+            Modulo(value(7), value(0)).evaluate_with(&[], FloorDivision),
+            Ok(IntegerNumber::NaN)
+        );
+    }
+
+    #[test]
+    fn booleans_as_integers_can_be_counted() {
+        use super::bool_as_int;
+        use crate::expressions::boolean::{BooleanExpression, BooleanValue};
+        use crate::expressions::{ConstraintLogicExpression, Symbol};
+        use crate::solver::{count_models, program_of};
+        let flag = |name: &str| {
+            bool_as_int(BooleanExpression::BooleanVariable(Symbol::new(
+                name.to_string(),
+            )))
+        };
+        let sum = IntegerNumberExpression::Add(
+            Box::new(IntegerNumberExpression::Add(
+                Box::new(flag("p")),
+                Box::new(flag("q")),
+            )),
+            Box::new(flag("r")),
+        );
+        assert_eq!(sum.to_string(), "int(p) + int(q) + int(r)");
+        let exactly_two = program_of(vec![ConstraintLogicExpression::OfIntegerNumber(Box::new(
+            BooleanIntegerNumberExpression::Equals(Box::new(sum), value(2)),
+        ))]);
+        assert_eq!(count_models(&exactly_two), Ok(3));
+        assert_eq!(
+            bool_as_int(BooleanExpression::BooleanValue(BooleanValue::True)),
+            *value(1)
+        );
+    }
+
+    #[test]
+    fn universe_and_empty_leave_set_operations() {
+        use super::simplify_domain;
+        use IntegerNumberDomainExpression::*;
+        let range = || ClosedRange(value(0), value(3));
+        let union = |a, b| Union(Box::new(a), Box::new(b));
+        let intersection = |a, b| Intersection(Box::new(a), Box::new(b));
+        let difference = |a, b| Difference(Box::new(a), Box::new(b));
+        let complement = |a| Complement(Box::new(a));
+        assert_eq!(simplify_domain(union(Universe, range())), Universe);
+        assert_eq!(simplify_domain(union(range(), Universe)), Universe);
+        assert_eq!(simplify_domain(intersection(Empty, range())), Empty);
+        assert_eq!(simplify_domain(intersection(range(), Empty)), Empty);
+        assert_eq!(simplify_domain(union(Empty, range())), range());
+        assert_eq!(simplify_domain(intersection(Universe, range())), range());
+        assert_eq!(simplify_domain(difference(range(), Empty)), range());
+        assert_eq!(simplify_domain(difference(Empty, range())), Empty);
+        assert_eq!(simplify_domain(difference(range(), Universe)), Empty);
+        assert_eq!(simplify_domain(complement(Universe)), Empty);
+        assert_eq!(simplify_domain(complement(Empty)), Universe);
+        assert_eq!(simplify_domain(complement(range())), complement(range()));
+        let nested = intersection(
+            union(Empty, complement(complement(Universe))),
+            difference(union(range(), Empty), intersection(Universe, Empty)),
+        );
+        assert_eq!(simplify_domain(nested), range());
+    }
+
+    #[test]
+    fn domains_iterate_their_members_in_order() {
+        use IntegerNumberDomainExpression::*;
+        let domain = Union(
+            Box::new(ClosedRange(value(10), value(12))),
+            Box::new(OpenRange(value(-3), value(1))),
+        );
+        assert_eq!(
+            domain.iter(i128::MIN..=i128::MAX).collect::<Vec<i128>>(),
+            vec![-2, -1, 0, 10, 11, 12]
+        );
+        assert_eq!(domain.iter(0..=10).collect::<Vec<i128>>(), vec![0, 10]);
+        // Unbounded, so only as many members are tested as are taken.
+        let outside = Complement(Box::new(ClosedRange(value(0), value(2))));
+        assert_eq!(
+            outside
+                .iter(-2..=i128::MAX)
+                .filter(|n| n % 2 == 1)
+                .take(3)
+                .collect::<Vec<i128>>(),
+            vec![3, 5, 7]
+        );
+    }
+
+    /// `let t = x * y + 3 in abs(t - z) % 5 + t ^ 2 - (x, p).0 / 2`, with
+    /// the tuple component read through `TupleGet`.
+    fn let_expression() -> IntegerNumberExpression {
+        use crate::expressions::boolean::BooleanExpression;
+        use crate::expressions::tuple::{TupleComponent, TupleExpression};
+        use crate::expressions::Symbol;
+        use IntegerNumberExpression::*;
+        let var = |name: &str| Box::new(IntegerNumberVariable(Symbol::new(name.to_string())));
+        let pair = TupleExpression::Tuple(vec![
+            TupleComponent::Integer(*var("x")),
+            TupleComponent::Boolean(BooleanExpression::BooleanVariable(Symbol::new(
+                "p".to_string(),
+            ))),
+        ]);
+        Let(
+            Symbol::new("t".to_string()),
+            Box::new(Add(Box::new(Times(var("x"), var("y"))), value(3))),
+            Box::new(Minus(
+                Box::new(Add(
+                    Box::new(Modulo(
+                        Box::new(Abs(Box::new(Minus(var("t"), var("z"))))),
+                        value(5),
+                    )),
+                    Box::new(Pow(var("t"), value(2))),
+                )),
+                Box::new(Divide(Box::new(TupleGet(Box::new(pair), 0)), value(2))),
+            )),
+        )
+    }
+
+    #[test]
+    fn borrowed_evaluation_matches_the_owning_one() {
+        use super::DivisionMode;
+        use crate::expressions::boolean::BooleanValue;
+        use crate::expressions::{AssignedValue, Assignment, Environment, Evaluate, Symbol};
+        let expr = let_expression();
+        let env = |x: i128, y: i128, z: i128, t: i128| {
+            let mut env: Vec<Assignment> = [("x", x), ("y", y), ("z", z), ("t", t)]
+                .iter()
+                .map(|(name, n)| {
+                    Assignment::new(
+                        Symbol::new(name.to_string()),
+                        AssignedValue::Integer(IntegerNumber::Value(*n)),
+                    )
+                })
+                .collect();
+            env.push(Assignment::new(
+                Symbol::new("p".to_string()),
+                AssignedValue::Boolean(BooleanValue::True),
+            ));
+            env
+        };
+        for (x, y, z) in [(1, 2, 3), (-3, 4, 20), (0, 0, 0), (-7, -1, 1)] {
+            let env = env(x, y, z, 100);
+            let t = x * y + 3;
+            let expected = (t - z).abs() % 5 + t * t - x / 2;
+            assert_eq!(
+                expr.evaluate_ref(&Environment::new(&env)),
+                Ok(IntegerNumber::Value(expected))
+            );
+            for mode in [
+                DivisionMode::TruncateTowardZero,
+                DivisionMode::FloorDivision,
+                DivisionMode::EuclideanRemainder,
+            ] {
+                assert_eq!(
+                    expr.evaluate_ref(&Environment::new(&env).with_division(mode)),
+                    expr.evaluate_with(&env, mode)
+                );
             }
         }
+        // The tuple is evaluated whole, so its unbound boolean is an error
+        // even though only the integer is read.
+        let unbound = &env(1, 2, 3, 4)[..4];
+        assert_eq!(
+            expr.evaluate_ref(&Environment::new(unbound)),
+            Err(crate::expressions::EvalError::Unbound(Symbol::new(
+                "p".to_string()
+            )))
+        );
+        assert_eq!(
+            expr.evaluate_ref(&Environment::new(unbound)),
+            expr.evaluate(unbound)
+        );
+        let domain = IntegerNumberDomainExpression::ClosedRange(value(0), Box::new(expr.clone()));
+        let member = BooleanIntegerNumberExpression::In(value(5), Box::new(domain));
+        let env = env(1, 2, 3, 4);
+        assert_eq!(
+            member.evaluate_ref(&Environment::new(&env)),
+            Ok(BooleanValue::True)
+        );
+        assert_eq!(
+            member.evaluate_ref(&Environment::new(&env)),
+            member.evaluate(&env)
+        );
+    }
+
+    #[test]
+    fn ranges_sample_their_smallest_member() {
+        use crate::expressions::{AssignedValue, Sample, SampleError};
+        use IntegerNumberDomainExpression::*;
+        let sample = |domain: IntegerNumberDomainExpression| match domain.try_sample() {
+            Ok(AssignedValue::Integer(IntegerNumber::Value(n))) => Ok(n),
+            Ok(other) => panic!("expected an integer, got {:?}", other),
+            Err(error) => Err(error),
+        };
+        assert_eq!(sample(ClosedRange(value(-3), value(4))), Ok(-3));
+        assert_eq!(
+            sample(ClosedLeftOpenRightRange(value(-3), value(4))),
+            Ok(-3)
+        );
+        assert_eq!(sample(OpenRange(value(-3), value(4))), Ok(-2));
+        assert_eq!(
+            sample(OpenLeftClosedRightRange(value(-3), value(4))),
+            Ok(-2)
+        );
+        assert_eq!(sample(ClosedRange(value(7), value(7))), Ok(7));
+        assert_eq!(
+            sample(ClosedRange(value(5), value(4))),
+            Err(SampleError::EmptyDomain)
+        );
+        assert_eq!(
+            sample(OpenRange(value(4), value(5))),
+            Err(SampleError::EmptyDomain)
+        );
+        let nan = Box::new(IntegerNumberExpression::IntegerNumberValue(
+            IntegerNumber::NaN,
+        ));
+        assert_eq!(ClosedRange(nan, value(4)).sample(), None);
+        assert_eq!(
+            sample(Difference(
+                Box::new(ClosedRange(value(0), value(9))),
+                Box::new(ExplicitSet(vec![*value(0), *value(1)]))
+            )),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn overflow_and_division_by_zero_are_nan() {
+        use crate::expressions::{EvalError, Evaluate, Symbol};
+        use IntegerNumberExpression::*;
+        let nan = Ok(IntegerNumber::NaN);
+        assert_eq!(Times(value(i128::MAX), value(2)).evaluate(&[]), nan);
+        assert_eq!(Negate(value(i128::MIN)).evaluate(&[]), nan);
+        assert_eq!(Modulo(value(7), value(0)).evaluate(&[]), nan);
+        let quotient = Box::new(Divide(value(7), value(0)));
+        assert_eq!(
+            Parenthesis(Box::new(Add(quotient, value(1)))).evaluate(&[]),
+            nan
+        );
+        assert_eq!(
+            Minus(value(9), value(4)).evaluate(&[]),
+            Ok(IntegerNumber::Value(5))
+        );
+        let x = Symbol::new("x".to_string());
+        assert_eq!(
+            Add(value(1), Box::new(IntegerNumberVariable(x.clone()))).evaluate(&[]),
+            Err(EvalError::Unbound(x))
+        );
+    }
+
+    #[test]
+    fn membership_respects_open_and_closed_endpoints() {
+        use IntegerNumberDomainExpression::*;
+        let members = |domain: IntegerNumberDomainExpression| {
+            (-1..=5)
+                .filter(|&n| domain.includes(n, &[]) == Ok(true))
+                .collect::<Vec<i128>>()
+        };
+        assert_eq!(members(ClosedRange(value(0), value(3))), [0, 1, 2, 3]);
+        assert_eq!(members(OpenRange(value(0), value(3))), [1, 2]);
+        assert_eq!(
+            members(OpenLeftClosedRightRange(value(0), value(3))),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            members(ClosedLeftOpenRightRange(value(0), value(3))),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            members(Complement(Box::new(ClosedRange(value(0), value(3))))),
+            [-1, 4, 5]
+        );
+        assert_eq!(
+            members(Difference(
+                Box::new(Union(
+                    Box::new(ExplicitSet(vec![*value(-1)])),
+                    Box::new(ClosedRange(value(2), value(5))),
+                )),
+                Box::new(Intersection(
+                    Box::new(ClosedRange(value(0), value(3))),
+                    Box::new(ExplicitSet(vec![*value(3), *value(4)])),
+                )),
+            )),
+            [-1, 2, 4, 5]
+        );
+    }
+
+    #[test]
+    fn only_different_holds_for_nan() {
+        use crate::expressions::boolean::BooleanValue;
+        use crate::expressions::Evaluate;
+        use BooleanIntegerNumberExpression::*;
+        let nan = || {
+            Box::new(IntegerNumberExpression::IntegerNumberValue(
+                IntegerNumber::NaN,
+            ))
+        };
+        let holds = |expr: BooleanIntegerNumberExpression| expr.evaluate(&[]).unwrap();
+        assert_eq!(holds(Equals(nan(), nan())), BooleanValue::False);
+        assert_eq!(holds(Greater(nan(), value(1))), BooleanValue::False);
+        assert_eq!(holds(Less(value(1), nan())), BooleanValue::False);
+        assert_eq!(holds(Different(nan(), value(1))), BooleanValue::True);
+        assert_eq!(
+            holds(In(nan(), Box::new(IntegerNumberDomainExpression::Universe))),
+            BooleanValue::False
+        );
+        assert_eq!(holds(Less(value(1), value(2))), BooleanValue::True);
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn simplify_keeps_the_value(expr: IntegerNumberExpression, values: Vec<i128>) -> bool {
+        use crate::expressions::{AssignedValue, Assignment, Evaluate, FreeVariable};
+        let env: Vec<Assignment> = expr
+            .get_free()
+            .iter()
+            .enumerate()
+            .map(|(i, variable)| {
+                let value = values.get(i).copied().unwrap_or(0);
+                Assignment::new(
+                    variable.name().clone(),
+                    AssignedValue::Integer(IntegerNumber::Value(value)),
+                )
+            })
+            .collect();
+        expr.evaluate(&env) == expr.simplify().evaluate(&env)
+    }
+
+    #[test]
+    fn simplify_folds_constants_and_identities() {
+        use IntegerNumberExpression::*;
+        let x = || {
+            Box::new(IntegerNumberVariable(crate::expressions::Symbol::new(
+                "x".to_string(),
+            )))
+        };
+        let sum = Add(value(2), Box::new(Times(value(3), value(4))));
+        assert_eq!(sum.simplify(), *value(14));
+        let expr = Times(
+            Box::new(Parenthesis(Box::new(Add(x(), value(0))))),
+            Box::new(Minus(value(3), value(2))),
+        );
+        assert_eq!(expr.simplify(), *x());
+        assert_eq!(Times(value(0), x()).simplify(), *value(0));
+        let quotient = Box::new(Divide(value(1), x()));
+        assert_eq!(
+            Times(quotient.clone(), value(0)).simplify(),
+            Times(quotient, value(0))
+        );
+        assert_eq!(
+            Negate(Box::new(Negate(x()))).simplify(),
+            Negate(Box::new(Negate(x())))
+        );
+        assert_eq!(
+            Modulo(x(), Box::new(Minus(value(1), value(1)))).simplify(),
+            IntegerNumberValue(IntegerNumber::NaN)
+        );
+    }
+
+    #[test]
+    fn conditionals_pick_the_branch_their_condition_selects() {
+        use crate::expressions::boolean::BooleanValue;
+        use crate::expressions::{
+            AssignedValue, Assignment, EvalError, Evaluate, FreeVariable, Symbol,
+        };
+        let expr: IntegerNumberExpression = "ite(p and q, x + 1, y) * 2".parse().unwrap();
+        assert_eq!(expr.to_string(), "ite(p and q, x + 1, y) * 2");
+        let names: Vec<String> = expr
+            .get_free()
+            .iter()
+            .map(|variable| variable.name().to_string())
+            .collect();
+        assert_eq!(names, ["p", "q", "x", "y"]);
+        let mut env = vec![
+            Assignment::new(
+                Symbol::new("p".to_string()),
+                AssignedValue::Boolean(BooleanValue::True),
+            ),
+            Assignment::new(
+                Symbol::new("x".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(4)),
+            ),
+            Assignment::new(
+                Symbol::new("y".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(-3)),
+            ),
+        ];
+        assert_eq!(
+            expr.evaluate(&env),
+            Err(EvalError::Unbound(Symbol::new("q".to_string())))
+        );
+        env.push(Assignment::new(
+            Symbol::new("q".to_string()),
+            AssignedValue::Boolean(BooleanValue::False),
+        ));
+        assert_eq!(expr.evaluate(&env), Ok(IntegerNumber::Value(-6)));
+        let ground: IntegerNumberExpression = "ite(true or p, 1 + 2, y)".parse().unwrap();
+        assert_eq!(ground.simplify(), *value(3));
+    }
+
+    #[test]
+    fn sums_and_products_are_exact_and_fold_their_constants() {
+        use crate::expressions::{AssignedValue, Assignment, Evaluate, Symbol};
+        let parse = |source: &str| source.parse::<IntegerNumberExpression>().unwrap();
+        assert_eq!(parse("sum()").evaluate(&[]), Ok(IntegerNumber::Value(0)));
+        assert_eq!(
+            parse("product()").evaluate(&[]),
+            Ok(IntegerNumber::Value(1))
+        );
+        let max = i128::MAX;
+        let overflowing = parse(&format!("sum({}, 1, -2)", max));
+        assert_eq!(overflowing.evaluate(&[]), Ok(IntegerNumber::Value(max - 1)));
+        assert_eq!(
+            parse(&format!("sum({}, 1)", max)).evaluate(&[]),
+            Ok(IntegerNumber::NaN)
+        );
+        assert_eq!(
+            parse(&format!("product({}, {}, 0)", max, max)).evaluate(&[]),
+            Ok(IntegerNumber::Value(0))
+        );
+        assert_eq!(
+            parse("product(-2, 85070591730234615865843651857942052864)").evaluate(&[]),
+            Ok(IntegerNumber::Value(i128::MIN))
+        );
+        assert_eq!(
+            parse("sum(1, NaN, 2)").evaluate(&[]),
+            Ok(IntegerNumber::NaN)
+        );
+
+        let expr = parse("sum(x, 2, (sum(3, y)), product(z))");
+        assert_eq!(expr.to_string(), "sum(x, 2, (sum(3, y)), product(z))");
+        let env = [("x", 1), ("y", 10), ("z", 100)].map(|(name, value)| {
+            Assignment::new(
+                Symbol::new(name.to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(value)),
+            )
+        });
+        assert_eq!(expr.evaluate(&env), Ok(IntegerNumber::Value(116)));
+        assert_eq!(expr.simplify(), parse("sum(x, y, z, 5)"));
+        assert_eq!(parse("sum(x, 1, -1)").simplify(), parse("x"));
+        assert_eq!(
+            parse("product(2, product(x, 3))").simplify(),
+            parse("product(x, 6)")
+        );
+        assert_eq!(parse("product(x, 0, y)").simplify(), *value(0));
+        assert_eq!(
+            parse("product(x / y, 0)").simplify(),
+            parse("product(x / y, 0)")
+        );
+        assert_eq!(parse("sum(2, 3)").simplify(), *value(5));
+    }
+
+    #[test]
+    fn expressions_shrink_to_the_part_that_fails() {
+        use crate::expressions::parser::parse_integer;
+        use crate::expressions::tests::minimal;
+        let expr = parse_integer("(x + 3) * (y / (2 - z)) - 7").unwrap();
+        let shrunk = minimal(expr, |expr| expr.to_string().contains('/'));
+        assert_eq!(shrunk.to_string(), "y / 0");
+        let domain = IntegerNumberDomainExpression::Complement(Box::new(
+            IntegerNumberDomainExpression::ClosedRange(value(-5), value(12)),
+        ));
+        let shrunk = minimal(domain, |domain| domain.to_string().contains('['));
+        assert_eq!(shrunk.to_string(), "[0, 0]");
     }
 }
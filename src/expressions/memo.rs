@@ -0,0 +1,208 @@
+//! Memoised evaluation, for searches that evaluate the same expressions
+//! under the same values of their variables over and over. The search
+//! of `solve_all` does not use it: `evaluate_ref` allocates nothing and
+//! is cheaper than building a key.
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::discriminant;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{Assignment, Canonicalize, EvalError, Evaluate, FreeVariable, Symbol};
+
+/// The number of caches made so far, from which each takes its id.
+static CACHES: AtomicUsize = AtomicUsize::new(0);
+
+/// An expression prepared by `MemoCache::prepare`: the expression with
+/// the index of its canonical form in the cache and the names of its
+/// free variables, found once rather than at every evaluation. The index
+/// means something only to the cache that prepared it, whose id is kept
+/// alongside.
+#[derive(Debug, Clone)]
+pub struct Memoised<'e, E> {
+    expr: &'e E,
+    cache: usize,
+    index: usize,
+    free: Vec<Symbol>,
+}
+
+/// The index of an expression's canonical form with the bindings its
+/// free variables take, sorted. Expressions that canonicalize alike,
+/// evaluated under environments that agree on their variables, get
+/// equal keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MemoKey {
+    index: usize,
+    bindings: Vec<Assignment>,
+}
+
+impl MemoKey {
+    /// The key of evaluating `expr` under `env`. Of the bindings in `env`,
+    /// only those evaluation would read count: the first of each type for
+    /// every free variable of `expr`.
+    pub fn new<E>(expr: &Memoised<E>, env: &[Assignment]) -> MemoKey {
+        let mut bindings: Vec<Assignment> = Vec::new();
+        for assignment in env {
+            let read = expr.free.contains(assignment.name())
+                && !bindings.iter().any(|binding| {
+                    binding.name() == assignment.name()
+                        && discriminant(binding.value()) == discriminant(assignment.value())
+                });
+            if read {
+                bindings.push(assignment.clone());
+            }
+        }
+        bindings.sort();
+        MemoKey {
+            index: expr.index,
+            bindings,
+        }
+    }
+}
+
+impl<E> Memoised<'_, E> {
+    /// The names of the free variables of the expression.
+    pub fn free(&self) -> &[Symbol] {
+        &self.free
+    }
+}
+
+/// Results of evaluation keyed by `MemoKey`, with counters of how often
+/// a result was reused.
+#[derive(Debug, Clone)]
+pub struct MemoCache<E: Evaluate> {
+    id: usize,
+    canonical: HashMap<E, usize>,
+    results: HashMap<MemoKey, Result<E::Value, EvalError>>,
+    hits: usize,
+    misses: usize,
+}
+
+impl<E> MemoCache<E>
+where
+    E: Evaluate + Canonicalize + FreeVariable + Clone + Hash + Eq,
+    E::Value: Clone,
+{
+    pub fn new() -> MemoCache<E> {
+        MemoCache {
+            id: CACHES.fetch_add(1, Ordering::Relaxed),
+            canonical: HashMap::new(),
+            results: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Prepares `expr` for `evaluate`. Expressions that canonicalize alike
+    /// share their results.
+    pub fn prepare<'e>(&mut self, expr: &'e E) -> Memoised<'e, E> {
+        let next = self.canonical.len();
+        let index = *self
+            .canonical
+            .entry(expr.clone().canonicalize())
+            .or_insert(next);
+        Memoised {
+            expr,
+            cache: self.id,
+            index,
+            free: expr
+                .get_free()
+                .into_iter()
+                .map(|variable| variable.name().clone())
+                .collect(),
+        }
+    }
+
+    /// The value of the expression under `env`, evaluated only if no
+    /// expression with the same key was evaluated before. The expression
+    /// must have been prepared by this cache, or a clone of it; debug
+    /// builds panic otherwise.
+    pub fn evaluate(
+        &mut self,
+        expr: &Memoised<E>,
+        env: &[Assignment],
+    ) -> Result<E::Value, EvalError> {
+        debug_assert_eq!(
+            expr.cache, self.id,
+            "the expression was prepared by another cache"
+        );
+        let key = MemoKey::new(expr, env);
+        if let Some(result) = self.results.get(&key) {
+            self.hits += 1;
+            return result.clone();
+        }
+        self.misses += 1;
+        let result = expr.expr.evaluate(env);
+        self.results.insert(key, result.clone());
+        result
+    }
+
+    /// Evaluations answered from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Evaluations that had to be carried out.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+impl<E> Default for MemoCache<E>
+where
+    E: Evaluate + Canonicalize + FreeVariable + Clone + Hash + Eq,
+    E::Value: Clone,
+{
+    fn default() -> MemoCache<E> {
+        MemoCache::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoCache, MemoKey};
+    use crate::expressions::boolean::BooleanValue;
+    use crate::expressions::integer::IntegerNumber;
+    use crate::expressions::parser::parse_program;
+    use crate::expressions::{AssignedValue, Assignment, ConstraintLogicExpression, Symbol};
+    use crate::solver::constraints;
+
+    #[test]
+    fn the_same_bindings_hit_the_cache() {
+        let program = parse_program("x + y > 3;\nsatisfy 3 < y + x;").unwrap();
+        let exprs: Vec<&ConstraintLogicExpression> = constraints(&program);
+        let int = |name: &str, n| {
+            Assignment::new(
+                Symbol::new(name.to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(n)),
+            )
+        };
+        let env = [int("x", 1), int("y", 5)];
+        let mut cache = MemoCache::new();
+        let first = cache.prepare(exprs[0]);
+        let second = cache.prepare(exprs[1]);
+        assert_eq!(cache.evaluate(&first, &env), Ok(BooleanValue::True));
+        assert_eq!(cache.evaluate(&first, &env), Ok(BooleanValue::True));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        // Bindings of other variables and their order do not matter.
+        let reordered = [int("z", 0), int("y", 5), int("x", 1)];
+        assert_eq!(MemoKey::new(&first, &env), MemoKey::new(&first, &reordered));
+        assert_eq!(cache.evaluate(&second, &reordered), Ok(BooleanValue::True));
+        assert_eq!((cache.hits(), cache.misses()), (2, 1));
+        assert_eq!(
+            cache.evaluate(&first, &[int("x", 1), int("y", 2)]),
+            Ok(BooleanValue::False)
+        );
+        assert_eq!((cache.hits(), cache.misses()), (2, 2));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "prepared by another cache")]
+    fn expressions_prepared_by_another_cache_are_refused() {
+        let program = parse_program("satisfy x > 3;").unwrap();
+        let expr = constraints(&program)[0];
+        let mut other = MemoCache::new();
+        let prepared = other.prepare(expr);
+        let _ = MemoCache::new().evaluate(&prepared, &[]);
+    }
+}
@@ -5,11 +5,24 @@
 //! A CLP program is constructed as a type tree from the expression enums described in this file.
 //! To be interesting a program should have at least one free variable and no self contradictions.
 
+use std::fmt;
+use std::ops::ControlFlow;
+
+use visit::{FreeVariables, Visitor};
+
 pub mod boolean;
+pub mod dsl;
 pub mod integer;
+pub mod memo;
+pub mod parser;
+pub mod rational;
+pub mod tuple;
+pub mod visit;
 
 /// The name of a symbol (variable or constant of some type).
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Symbol {
     name: String,
 }
@@ -18,81 +31,668 @@ impl Symbol {
     pub fn new(s: String) -> Symbol {
         Symbol { name: s }
     }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Makes symbols named by a prefix and a counter, `t0`, `t1` and so on,
+/// skipping the names already taken.
+#[derive(Debug, Clone)]
+pub struct SymbolGenerator {
+    prefix: String,
+    next: usize,
+    taken: std::collections::HashSet<String>,
+}
+
+impl SymbolGenerator {
+    pub fn new(prefix: &str, taken: impl IntoIterator<Item = Symbol>) -> SymbolGenerator {
+        SymbolGenerator {
+            prefix: prefix.to_string(),
+            next: 0,
+            taken: taken.into_iter().map(|symbol| symbol.name).collect(),
+        }
+    }
+
+    /// A symbol not taken and not made before.
+    pub fn fresh(&mut self) -> Symbol {
+        loop {
+            let name = format!("{}{}", self.prefix, self.next);
+            self.next += 1;
+            if self.taken.insert(name.clone()) {
+                return Symbol::new(name);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Domain {
     Boolean(boolean::BooleanValueDomainExpression),
     Integer(integer::IntegerNumberDomainExpression),
+    Rational(rational::RationalDomainExpression),
+    /// The tuples whose components are members of the given domains.
+    Tuple(Vec<Domain>),
+    /// A domain defined outside the crate. It cannot be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(Box<dyn ValueDomain>),
+}
+
+impl Domain {
+    pub fn as_value_domain(&self) -> &dyn ValueDomain {
+        match self {
+            Domain::Boolean(dom) => dom,
+            Domain::Integer(dom) => dom,
+            Domain::Rational(dom) => dom,
+            Domain::Tuple(dom) => dom,
+            Domain::Custom(dom) => dom.as_ref(),
+        }
+    }
+}
+
+impl Clone for Domain {
+    fn clone(&self) -> Domain {
+        match self {
+            Domain::Boolean(dom) => Domain::Boolean(dom.clone()),
+            Domain::Integer(dom) => Domain::Integer(dom.clone()),
+            Domain::Rational(dom) => Domain::Rational(dom.clone()),
+            Domain::Tuple(dom) => Domain::Tuple(dom.clone()),
+            Domain::Custom(dom) => Domain::Custom(dom.clone_box()),
+        }
+    }
+}
+
+/// Custom domains are equal when their identities are.
+impl PartialEq for Domain {
+    fn eq(&self, other: &Domain) -> bool {
+        match (self, other) {
+            (Domain::Boolean(dom_a), Domain::Boolean(dom_b)) => dom_a == dom_b,
+            (Domain::Integer(dom_a), Domain::Integer(dom_b)) => dom_a == dom_b,
+            (Domain::Rational(dom_a), Domain::Rational(dom_b)) => dom_a == dom_b,
+            (Domain::Tuple(dom_a), Domain::Tuple(dom_b)) => dom_a == dom_b,
+            (Domain::Custom(dom_a), Domain::Custom(dom_b)) => dom_a.identity() == dom_b.identity(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Domain {}
+
+/// Custom domains hash their identities, as they are compared.
+impl std::hash::Hash for Domain {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Domain::Boolean(dom) => dom.hash(state),
+            Domain::Integer(dom) => dom.hash(state),
+            Domain::Rational(dom) => dom.hash(state),
+            Domain::Tuple(dom) => dom.hash(state),
+            Domain::Custom(dom) => dom.identity().hash(state),
+        }
+    }
+}
+
+/// Integer domains as the parser reads them, rational domains in the
+/// same way, boolean domains as the set of their values and tuple
+/// domains as their components in parentheses.
+/// Custom domains have no syntax and are shown by their debug
+/// representation.
+impl fmt::Display for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use boolean::BooleanValueDomainExpression;
+        match self {
+            Domain::Boolean(BooleanValueDomainExpression::Universe) => write!(f, "{{false, true}}"),
+            Domain::Boolean(BooleanValueDomainExpression::Empty) => write!(f, "empty"),
+            Domain::Boolean(BooleanValueDomainExpression::Single(value)) => {
+                write!(f, "{{{}}}", value)
+            }
+            Domain::Integer(dom) => write!(f, "{}", dom),
+            Domain::Rational(dom) => write!(f, "{}", dom),
+            Domain::Tuple(domains) => {
+                write!(f, "(")?;
+                for (i, domain) in domains.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", domain)?;
+                }
+                write!(f, ")")
+            }
+            Domain::Custom(dom) => write!(f, "{:?}", dom),
+        }
+    }
+}
+
+/// Reasons a domain has no value to sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleError {
+    EmptyDomain,
+    /// The domain has no bound to pick a value from.
+    Unbounded,
+    /// The domain depends on free variables.
+    RequiresEnvironment,
 }
+
 pub trait Sample {
-    fn sample(&self) -> Option<AssignedValue>;
+    fn try_sample(&self) -> Result<AssignedValue, SampleError>;
+    /// `try_sample` without the reason it failed.
+    fn sample(&self) -> Option<AssignedValue> {
+        self.try_sample().ok()
+    }
 }
 
 impl Sample for Domain {
-    fn sample(&self) -> Option<AssignedValue> {
+    fn try_sample(&self) -> Result<AssignedValue, SampleError> {
         match self {
-            Domain::Boolean(dom) => dom.sample(),
-            Domain::Integer(dom) => dom.sample(),
+            Domain::Boolean(dom) => dom.try_sample(),
+            Domain::Integer(dom) => dom.try_sample(),
+            Domain::Rational(dom) => dom.try_sample(),
+            Domain::Tuple(dom) => dom.try_sample(),
+            Domain::Custom(dom) => dom.try_sample(),
         }
     }
 }
-trait Reduce {
-    fn reduce(&self, value: AssignedValue) -> Vec<Box<Self>>;
+
+/// The most values a domain enumerates as candidates.
+pub(crate) const MAX_CANDIDATES: u128 = 1 << 20;
+
+/// A set of values a variable can range over. Implementing it lets a
+/// new kind of domain take part in solving through `Domain::Custom`
+/// without changes to the core enums; its values are drawn from the
+/// existing `AssignedValue`s.
+pub trait ValueDomain: Sample + fmt::Debug {
+    /// Every value of the domain in the order a search tries them, or
+    /// `None` when there are too many to enumerate.
+    fn candidates(&self) -> Option<Vec<AssignedValue>>;
+    fn contains(&self, value: &AssignedValue) -> bool;
+    /// The candidates with integer divisions in the domain's bounds
+    /// rounding as `division` says. Domains without such divisions can
+    /// keep the default, which ignores it.
+    fn candidates_with(&self, division: integer::DivisionMode) -> Option<Vec<AssignedValue>> {
+        let _ = division;
+        self.candidates()
+    }
+    /// Whether the domain holds `value`, with integer divisions in its
+    /// bounds rounding as `division` says.
+    fn contains_with(&self, value: &AssignedValue, division: integer::DivisionMode) -> bool {
+        let _ = division;
+        self.contains(value)
+    }
+    /// The number of values in the domain, `None` when it is unbounded
+    /// or too large to count.
+    fn cardinality(&self) -> Option<u128>;
+    fn clone_box(&self) -> Box<dyn ValueDomain>;
+    /// A key that is the same for two domains exactly when they hold the
+    /// same values, by which `Domain` compares and hashes custom domains.
+    /// It should name the kind of domain as well as its parameters, so
+    /// that domains of different kinds do not share keys. The domains of
+    /// the crate use their derived debug representations, which spell
+    /// out the whole expression.
+    fn identity(&self) -> String;
 }
 
 /// The set of values currently supported in CLP.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AssignedValue {
     Boolean(boolean::BooleanValue),
     Integer(integer::IntegerNumber),
+    Rational(rational::Rational),
+    Tuple(Vec<AssignedValue>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Values are written as the literals that denote them, tuples as
+/// `TupleExpression` writes them.
+impl fmt::Display for AssignedValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssignedValue::Boolean(value) => write!(f, "{}", value),
+            AssignedValue::Integer(value) => write!(f, "{}", value),
+            AssignedValue::Rational(value) => write!(f, "{}", value),
+            AssignedValue::Tuple(values) => {
+                write!(f, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                if values.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Variable {
     name: Symbol,
     domain: Domain,
 }
 
 impl Variable {
+    pub fn new(name: Symbol, domain: Domain) -> Variable {
+        Variable { name, domain }
+    }
+    pub fn name(&self) -> &Symbol {
+        &self.name
+    }
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
     pub fn assignment(&self) -> Option<Assignment> {
-        match self.domain.sample() {
-            None => None,
-            Some(value) => Some(Assignment {
-                name: self.name.clone(),
-                value,
-            }),
-        }
+        self.domain.sample().map(|value| Assignment {
+            name: self.name.clone(),
+            value,
+        })
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Assignment {
     name: Symbol,
     value: AssignedValue,
 }
 
+impl Assignment {
+    pub fn new(name: Symbol, value: AssignedValue) -> Assignment {
+        Assignment { name, value }
+    }
+    pub fn name(&self) -> &Symbol {
+        &self.name
+    }
+    pub fn value(&self) -> &AssignedValue {
+        &self.value
+    }
+}
+
+/// The boolean value bound to `symbol` in `env`, if any.
+/// Bindings of another type under the same name are skipped.
+fn boolean_binding<'a>(
+    env: &'a [Assignment],
+    symbol: &Symbol,
+) -> Option<&'a boolean::BooleanValue> {
+    env.iter().find_map(|assignment| match &assignment.value {
+        AssignedValue::Boolean(value) if assignment.name == *symbol => Some(value),
+        _ => None,
+    })
+}
+
+/// The integer value bound to `symbol` in `env`, if any.
+/// Bindings of another type under the same name are skipped.
+fn integer_binding<'a>(
+    env: &'a [Assignment],
+    symbol: &Symbol,
+) -> Option<&'a integer::IntegerNumber> {
+    env.iter().find_map(|assignment| match &assignment.value {
+        AssignedValue::Integer(value) if assignment.name == *symbol => Some(value),
+        _ => None,
+    })
+}
+
+/// The rational value bound to `symbol` in `env`, if any.
+/// Bindings of another type under the same name are skipped.
+fn rational_binding<'a>(env: &'a [Assignment], symbol: &Symbol) -> Option<&'a rational::Rational> {
+    env.iter().find_map(|assignment| match &assignment.value {
+        AssignedValue::Rational(value) if assignment.name == *symbol => Some(value),
+        _ => None,
+    })
+}
+
+/// The tuple bound to `symbol` in `env`, if any.
+/// Bindings of another type under the same name are skipped.
+fn tuple_binding<'a>(env: &'a [Assignment], symbol: &Symbol) -> Option<&'a Vec<AssignedValue>> {
+    env.iter().find_map(|assignment| match &assignment.value {
+        AssignedValue::Tuple(values) if assignment.name == *symbol => Some(values),
+        _ => None,
+    })
+}
+
+/// The bindings an expression is evaluated under: a slice of
+/// assignments, extended by the values bound by the enclosing `Let`s,
+/// and the way integer division rounds. Extending it borrows the outer
+/// environment instead of copying it, so evaluating allocates nothing.
+#[derive(Debug, Clone, Copy)]
+pub struct Environment<'a> {
+    assignments: &'a [Assignment],
+    division: integer::DivisionMode,
+    /// The innermost `Let` binding and the environment it extends.
+    bound: Option<(&'a Symbol, &'a integer::IntegerNumber, &'a Environment<'a>)>,
+}
+
+impl<'a> Environment<'a> {
+    /// The environment of `assignments`, with integer division truncating
+    /// toward zero.
+    pub fn new(assignments: &'a [Assignment]) -> Self {
+        Environment {
+            assignments,
+            division: integer::DivisionMode::default(),
+            bound: None,
+        }
+    }
+
+    /// The same bindings, with integer division rounding as `division`
+    /// says.
+    pub fn with_division(self, division: integer::DivisionMode) -> Self {
+        Environment { division, ..self }
+    }
+
+    pub fn division(&self) -> integer::DivisionMode {
+        self.division
+    }
+
+    /// This environment with `symbol` bound to `value` in front,
+    /// shadowing any integer binding of the same symbol.
+    fn bind<'b>(
+        &'b self,
+        symbol: &'b Symbol,
+        value: &'b integer::IntegerNumber,
+    ) -> Environment<'b> {
+        Environment {
+            assignments: self.assignments,
+            division: self.division,
+            bound: Some((symbol, value, self)),
+        }
+    }
+
+    fn integer(&self, symbol: &Symbol) -> Option<&integer::IntegerNumber> {
+        match self.bound {
+            Some((bound, value, _)) if bound == symbol => Some(value),
+            Some((_, _, outer)) => outer.integer(symbol),
+            None => integer_binding(self.assignments, symbol),
+        }
+    }
+
+    /// `Let` binds integers only, so the other types are looked up in
+    /// the assignments alone.
+    fn boolean(&self, symbol: &Symbol) -> Option<&boolean::BooleanValue> {
+        boolean_binding(self.assignments, symbol)
+    }
+
+    fn rational(&self, symbol: &Symbol) -> Option<&rational::Rational> {
+        rational_binding(self.assignments, symbol)
+    }
+
+    fn tuple(&self, symbol: &Symbol) -> Option<&Vec<AssignedValue>> {
+        tuple_binding(self.assignments, symbol)
+    }
+}
+
+/// Reasons an expression could not be evaluated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    Unbound(Symbol),
+}
+
+/// Evaluation of an expression under an environment of assignments.
+pub trait Evaluate {
+    type Value;
+    /// The value under `env`, with integer division rounding as
+    /// `division` says.
+    fn evaluate_with(
+        &self,
+        env: &[Assignment],
+        division: integer::DivisionMode,
+    ) -> Result<Self::Value, EvalError>;
+    /// The value under `env`, with integer division truncating toward
+    /// zero.
+    fn evaluate(&self, env: &[Assignment]) -> Result<Self::Value, EvalError> {
+        self.evaluate_with(env, integer::DivisionMode::default())
+    }
+}
+
+/// Substitution of assigned values for the variables they name.
+/// Variables without a matching assignment are left in place.
+pub trait Apply: Sized {
+    fn apply(&self, state: &[Assignment]) -> Self {
+        self.apply_noting(state, &mut false)
+    }
+    /// As `apply`, also setting `substituted` when some variable is
+    /// replaced by its value. It is left as it was otherwise.
+    fn apply_noting(&self, state: &[Assignment], substituted: &mut bool) -> Self;
+}
+
+/// A variable and the expression of its type to put in its place.
+#[derive(Debug, Clone, Copy)]
+pub enum Substitution<'a> {
+    Integer(&'a Symbol, &'a integer::IntegerNumberExpression),
+    Boolean(&'a Symbol, &'a boolean::BooleanExpression),
+}
+
+/// Substitution of whole expressions for the variables they replace,
+/// where `Apply` only puts values in their place. Only variables of the
+/// replacement's type are replaced, and a `Let` binding the name hides it
+/// in its body. A `Let` binding a name the replacement mentions is
+/// renamed to a fresh symbol first, so that the replacement's variables
+/// keep referring to what they did.
+pub trait Substitute: Sized {
+    fn substitute_with(&self, substitution: &Substitution) -> Self;
+    /// Replaces every integer variable `symbol` by `replacement`.
+    fn substitute(&self, symbol: &Symbol, replacement: &integer::IntegerNumberExpression) -> Self {
+        self.substitute_with(&Substitution::Integer(symbol, replacement))
+    }
+    /// Replaces every boolean variable `symbol` by `replacement`.
+    fn substitute_boolean(
+        &self,
+        symbol: &Symbol,
+        replacement: &boolean::BooleanExpression,
+    ) -> Self {
+        self.substitute_with(&Substitution::Boolean(symbol, replacement))
+    }
+}
+
+/// Rewriting into a canonical form in which expressions that differ
+/// only by the order of commutative operands, by the grouping of
+/// associative ones or by `Parenthesis` nodes become identical.
+pub trait Canonicalize {
+    fn canonicalize(self) -> Self;
+}
+
+/// The operands of a chain of one associative operator, left to right.
+/// `split` takes apart a node of the operator and gives back anything else.
+pub(crate) fn chain_operands<T>(expr: T, split: impl Fn(T) -> Result<(T, T), T>) -> Vec<T> {
+    let mut operands = Vec::new();
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        match split(expr) {
+            Ok((expr_a, expr_b)) => {
+                pending.push(expr_b);
+                pending.push(expr_a);
+            }
+            Err(operand) => operands.push(operand),
+        }
+    }
+    operands
+}
+
+/// Sorts the operands of an associative and commutative operator and
+/// joins them back up to the left.
+pub(crate) fn sorted_chain<T: Ord>(mut operands: Vec<T>, join: impl Fn(T, T) -> T) -> T {
+    operands.sort();
+    let mut operands = operands.into_iter();
+    let first = operands.next().expect("a chain has operands");
+    operands.fold(first, join)
+}
+
 pub trait FreeVariable {
     fn get_free(&self) -> Vec<Variable>;
+    /// Calls `visit` with the name of each variable `get_free` lists, in
+    /// the same order, until `visit` breaks. The default collects
+    /// `get_free`; the expressions of the crate walk their trees instead,
+    /// so that nothing is allocated.
+    fn each_free(&self, visit: &mut dyn FnMut(&Symbol) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.get_free()
+            .iter()
+            .try_for_each(|variable| visit(variable.name()))
+    }
+    /// The length of `get_free`, which counts a variable once for each
+    /// occurrence.
+    fn free_variable_count(&self) -> usize {
+        let mut count = 0;
+        let _ = self.each_free(&mut |_| {
+            count += 1;
+            ControlFlow::Continue(())
+        });
+        count
+    }
+    /// Whether `get_free` is non-empty, stopping at the first variable.
+    fn has_free(&self) -> bool {
+        self.each_free(&mut |_| ControlFlow::Break(())).is_break()
+    }
 }
 
-#[derive(Debug, Clone)]
+/// The extent of an expression tree, in nodes of the expression enums:
+/// every statement of a program, goal, constraint, operator, variable and
+/// literal counts as one, while symbols and the components of a tuple
+/// are part of the node holding them.
+pub trait Measure {
+    /// The number of nodes in the tree.
+    fn node_count(&self) -> usize;
+    /// The number of nodes on the longest path from the root down, so
+    /// `1` for a variable or a literal. Each statement of a program holds
+    /// the rest, so the depth of a program grows with its length.
+    fn depth(&self) -> usize;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ConstraintLogicExpression {
     Boolean(Box<boolean::BooleanExpression>),
     OfIntegerNumber(Box<integer::BooleanIntegerNumberExpression>),
+    OfRational(Box<rational::BooleanRationalExpression>),
+    /// Holds when no two of the integer expressions have the same value.
+    /// As with `!=`, `NaN` differs from everything.
+    AllDifferent(Vec<integer::IntegerNumberExpression>),
 }
 impl FreeVariable for ConstraintLogicExpression {
     fn get_free(&self) -> Vec<Variable> {
+        FreeVariables::of(|free| free.visit_constraint(self))
+    }
+    fn each_free(&self, visit: &mut dyn FnMut(&Symbol) -> ControlFlow<()>) -> ControlFlow<()> {
         use ConstraintLogicExpression::*;
-        let mut free: Vec<Variable> = Vec::new();
         match self {
-            Boolean(expr) => free.extend(expr.get_free()),
-            OfIntegerNumber(expr) => free.extend(expr.get_free()),
+            Boolean(expr) => expr.each_free(visit),
+            OfIntegerNumber(expr) => expr.each_free(visit),
+            OfRational(expr) => expr.each_free(visit),
+            AllDifferent(exprs) => exprs.iter().try_for_each(|expr| expr.each_free(visit)),
         }
-        free
     }
 }
-#[derive(Debug, Clone)]
+impl ConstraintLogicExpression {
+    /// The value under `env`, without cloning any part of the expression.
+    /// `AllDifferent` is `False` as soon as two of its expressions are
+    /// known to clash, even when others cannot be evaluated yet.
+    pub fn evaluate_ref(&self, env: &Environment) -> Result<boolean::BooleanValue, EvalError> {
+        use ConstraintLogicExpression::*;
+        match self {
+            Boolean(expr) => expr.evaluate_ref(env),
+            OfIntegerNumber(expr) => expr.evaluate_ref(env),
+            OfRational(expr) => expr.evaluate_ref(env),
+            AllDifferent(exprs) => {
+                if integer::clash(exprs, env).is_some() {
+                    return Ok(boolean::BooleanValue::False);
+                }
+                for expr in exprs {
+                    expr.evaluate_ref(env)?;
+                }
+                Ok(boolean::BooleanValue::True)
+            }
+        }
+    }
+}
+impl Evaluate for ConstraintLogicExpression {
+    type Value = boolean::BooleanValue;
+    fn evaluate_with(
+        &self,
+        env: &[Assignment],
+        division: integer::DivisionMode,
+    ) -> Result<boolean::BooleanValue, EvalError> {
+        self.evaluate_ref(&Environment::new(env).with_division(division))
+    }
+}
+impl fmt::Display for ConstraintLogicExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ConstraintLogicExpression::*;
+        match self {
+            Boolean(expr) => write!(f, "{}", expr),
+            OfIntegerNumber(expr) => write!(f, "{}", expr),
+            OfRational(expr) => write!(f, "{}", expr),
+            AllDifferent(exprs) => {
+                write!(f, "alldifferent(")?;
+                for (position, expr) in exprs.iter().enumerate() {
+                    if position > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", expr)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+impl Canonicalize for ConstraintLogicExpression {
+    fn canonicalize(self) -> Self {
+        use ConstraintLogicExpression::*;
+        match self {
+            Boolean(expr) => Boolean(Box::new(expr.canonicalize())),
+            OfIntegerNumber(expr) => OfIntegerNumber(Box::new(expr.canonicalize())),
+            OfRational(expr) => OfRational(Box::new(expr.canonicalize())),
+            AllDifferent(exprs) => {
+                let mut exprs: Vec<integer::IntegerNumberExpression> =
+                    exprs.into_iter().map(Canonicalize::canonicalize).collect();
+                exprs.sort();
+                AllDifferent(exprs)
+            }
+        }
+    }
+}
+impl Apply for ConstraintLogicExpression {
+    fn apply_noting(&self, state: &[Assignment], substituted: &mut bool) -> Self {
+        use ConstraintLogicExpression::*;
+        match self {
+            Boolean(expr) => Boolean(Box::new(expr.apply_noting(state, substituted))),
+            OfIntegerNumber(expr) => {
+                OfIntegerNumber(Box::new(expr.apply_noting(state, substituted)))
+            }
+            OfRational(expr) => OfRational(Box::new(expr.apply_noting(state, substituted))),
+            AllDifferent(exprs) => AllDifferent(
+                exprs
+                    .iter()
+                    .map(|expr| expr.apply_noting(state, substituted))
+                    .collect(),
+            ),
+        }
+    }
+}
+impl Substitute for ConstraintLogicExpression {
+    fn substitute_with(&self, substitution: &Substitution) -> Self {
+        use ConstraintLogicExpression::*;
+        match self {
+            Boolean(expr) => Boolean(Box::new(expr.substitute_with(substitution))),
+            OfIntegerNumber(expr) => OfIntegerNumber(Box::new(expr.substitute_with(substitution))),
+            OfRational(expr) => OfRational(Box::new(expr.substitute_with(substitution))),
+            AllDifferent(exprs) => AllDifferent(
+                exprs
+                    .iter()
+                    .map(|expr| expr.substitute_with(substitution))
+                    .collect(),
+            ),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SatisfactionExpression {
     Satisfy(Box<ConstraintLogicExpression>),
     Minimise(Box<ConstraintLogicExpression>),
@@ -100,17 +700,57 @@ pub enum SatisfactionExpression {
 }
 impl FreeVariable for SatisfactionExpression {
     fn get_free(&self) -> Vec<Variable> {
+        FreeVariables::of(|free| free.visit_satisfaction(self))
+    }
+    fn each_free(&self, visit: &mut dyn FnMut(&Symbol) -> ControlFlow<()>) -> ControlFlow<()> {
         use SatisfactionExpression::*;
-        let mut free: Vec<Variable> = Vec::new();
         match self {
-            Satisfy(expr) => free.extend(expr.get_free()),
-            Minimise(expr) => free.extend(expr.get_free()),
-            Maximise(expr) => free.extend(expr.get_free()),
+            Satisfy(expr) | Minimise(expr) | Maximise(expr) => expr.each_free(visit),
         }
-        free
     }
 }
-#[derive(Debug, Clone)]
+impl fmt::Display for SatisfactionExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use SatisfactionExpression::*;
+        match self {
+            Satisfy(expr) => write!(f, "satisfy {}", expr),
+            Minimise(expr) => write!(f, "minimise {}", expr),
+            Maximise(expr) => write!(f, "maximise {}", expr),
+        }
+    }
+}
+impl Canonicalize for SatisfactionExpression {
+    fn canonicalize(self) -> Self {
+        use SatisfactionExpression::*;
+        match self {
+            Satisfy(expr) => Satisfy(Box::new(expr.canonicalize())),
+            Minimise(expr) => Minimise(Box::new(expr.canonicalize())),
+            Maximise(expr) => Maximise(Box::new(expr.canonicalize())),
+        }
+    }
+}
+impl Apply for SatisfactionExpression {
+    fn apply_noting(&self, state: &[Assignment], substituted: &mut bool) -> Self {
+        use SatisfactionExpression::*;
+        match self {
+            Satisfy(expr) => Satisfy(Box::new(expr.apply_noting(state, substituted))),
+            Minimise(expr) => Minimise(Box::new(expr.apply_noting(state, substituted))),
+            Maximise(expr) => Maximise(Box::new(expr.apply_noting(state, substituted))),
+        }
+    }
+}
+impl Substitute for SatisfactionExpression {
+    fn substitute_with(&self, substitution: &Substitution) -> Self {
+        use SatisfactionExpression::*;
+        match self {
+            Satisfy(expr) => Satisfy(Box::new(expr.substitute_with(substitution))),
+            Minimise(expr) => Minimise(Box::new(expr.substitute_with(substitution))),
+            Maximise(expr) => Maximise(Box::new(expr.substitute_with(substitution))),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ConstraintProgramExpression {
     Solve(Box<SatisfactionExpression>),
     SolveAnd(
@@ -124,31 +764,158 @@ pub enum ConstraintProgramExpression {
 }
 impl FreeVariable for &ConstraintProgramExpression {
     fn get_free(&self) -> Vec<Variable> {
+        FreeVariables::of(|free| free.visit_program(self))
+    }
+    fn each_free(&self, visit: &mut dyn FnMut(&Symbol) -> ControlFlow<()>) -> ControlFlow<()> {
         use ConstraintProgramExpression::*;
-        let mut free: Vec<Variable> = Vec::new();
-        match self {
-            Solve(expr) => free.extend(expr.get_free()),
-            SolveAnd(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.as_ref().get_free());
-            }
-            ConstrainAnd(expr_a, expr_b) => {
-                free.extend(expr_a.get_free());
-                free.extend(expr_b.as_ref().get_free());
+        let mut rest = *self;
+        loop {
+            rest = match rest {
+                Solve(expr) => return expr.each_free(visit),
+                SolveAnd(expr_a, expr_b) => {
+                    expr_a.each_free(visit)?;
+                    expr_b
+                }
+                ConstrainAnd(expr_a, expr_b) => {
+                    expr_a.each_free(visit)?;
+                    expr_b
+                }
+            };
+        }
+    }
+}
+/// Programs are written one statement per line, each ended by `;`.
+/// The statements are written one per line, walking the chain of them
+/// in a loop, so that long programs do not exhaust the stack.
+impl fmt::Display for ConstraintProgramExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ConstraintProgramExpression::*;
+        let mut program = self;
+        loop {
+            match program {
+                Solve(expr) => return write!(f, "{};", expr),
+                SolveAnd(expr, rest) => {
+                    writeln!(f, "{};", expr)?;
+                    program = rest;
+                }
+                ConstrainAnd(expr, rest) => {
+                    writeln!(f, "{};", expr)?;
+                    program = rest;
+                }
             }
         }
-        free
+    }
+}
+impl Canonicalize for ConstraintProgramExpression {
+    fn canonicalize(self) -> Self {
+        use ConstraintProgramExpression::*;
+        match self {
+            Solve(expr) => Solve(Box::new(expr.canonicalize())),
+            SolveAnd(expr_a, expr_b) => SolveAnd(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            ConstrainAnd(expr_a, expr_b) => ConstrainAnd(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+        }
+    }
+}
+impl Apply for ConstraintProgramExpression {
+    fn apply_noting(&self, state: &[Assignment], substituted: &mut bool) -> Self {
+        use ConstraintProgramExpression::*;
+        match self {
+            Solve(expr) => Solve(Box::new(expr.apply_noting(state, substituted))),
+            SolveAnd(expr_a, expr_b) => SolveAnd(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+            ConstrainAnd(expr_a, expr_b) => ConstrainAnd(
+                Box::new(expr_a.apply_noting(state, substituted)),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+        }
+    }
+}
+impl Substitute for ConstraintProgramExpression {
+    fn substitute_with(&self, substitution: &Substitution) -> Self {
+        use ConstraintProgramExpression::*;
+        match self {
+            Solve(expr) => Solve(Box::new(expr.substitute_with(substitution))),
+            SolveAnd(expr_a, expr_b) => SolveAnd(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+            ConstrainAnd(expr_a, expr_b) => ConstrainAnd(
+                Box::new(expr_a.substitute_with(substitution)),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+        }
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
 
     use super::{
         ConstraintLogicExpression, ConstraintProgramExpression, SatisfactionExpression, Symbol,
     };
     use quickcheck::{Arbitrary, Gen};
 
+    /// Generation of expression trees at a depth below the root, so that
+    /// the trees stay finite however the variants recurse.
+    pub(crate) trait ArbitraryAt: Sized {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> Self;
+    }
+
+    impl<T: ArbitraryAt> ArbitraryAt for Box<T> {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> Box<T> {
+            Box::new(T::arbitrary_at(g, depth))
+        }
+    }
+
+    /// Fewer elements the deeper the list, down to none at `g.size()`.
+    impl<T: ArbitraryAt> ArbitraryAt for Vec<T> {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> Vec<T> {
+            let length = usize::arbitrary(g) % (g.size().saturating_sub(depth) + 1);
+            (0..length).map(|_| T::arbitrary_at(g, depth)).collect()
+        }
+    }
+
+    /// A subtree of a node at `depth`.
+    pub(crate) fn below<T: ArbitraryAt>(g: &mut Gen, depth: usize) -> T {
+        T::arbitrary_at(g, depth + 1)
+    }
+
+    /// Whether a node at `depth` should be a leaf: never at the root,
+    /// always from `g.size()` down, and more likely the deeper it is in
+    /// between.
+    pub(crate) fn leaf(g: &mut Gen, depth: usize) -> bool {
+        depth >= g.size() || usize::arbitrary(g) % g.size() < depth
+    }
+
+    /// The shrinks of a node: first `subtrees`, the parts of it of its
+    /// own type, then the node itself with `operands` shrunk one at a
+    /// time and put back by `rebuild`.
+    pub(crate) fn shrink_node<T: 'static, O: Arbitrary>(
+        subtrees: Vec<T>,
+        operands: O,
+        rebuild: impl Fn(O) -> T + 'static,
+    ) -> Box<dyn Iterator<Item = T>> {
+        Box::new(subtrees.into_iter().chain(operands.shrink().map(rebuild)))
+    }
+
+    /// What quickcheck would report of a failing `value`: it shrinks to
+    /// the first of its shrinks that still fails, for as long as there
+    /// is one.
+    pub(crate) fn minimal<T: Arbitrary>(mut value: T, fails: impl Fn(&T) -> bool) -> T {
+        while let Some(smaller) = value.shrink().find(&fails) {
+            value = smaller;
+        }
+        value
+    }
+
     impl Arbitrary for Symbol {
         fn arbitrary(g: &mut Gen) -> Symbol {
             fn some_name(g: &mut Gen) -> String {
@@ -193,11 +960,37 @@ mod tests {
         }
     }
 
+    impl ArbitraryAt for ConstraintLogicExpression {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> ConstraintLogicExpression {
+            match u32::arbitrary(g) % 2 {
+                0 => ConstraintLogicExpression::Boolean(below(g, depth)),
+                1 => ConstraintLogicExpression::OfIntegerNumber(below(g, depth)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
     impl Arbitrary for ConstraintLogicExpression {
         fn arbitrary(g: &mut Gen) -> ConstraintLogicExpression {
-            match u32::arbitrary(g) % 2 {
-                0 => ConstraintLogicExpression::Boolean(Arbitrary::arbitrary(g)),
-                1 => ConstraintLogicExpression::OfIntegerNumber(Arbitrary::arbitrary(g)),
+            ConstraintLogicExpression::arbitrary_at(g, 0)
+        }
+        fn shrink(&self) -> Box<dyn Iterator<Item = ConstraintLogicExpression>> {
+            use ConstraintLogicExpression::*;
+            match self {
+                Boolean(expr) => Box::new(expr.shrink().map(Boolean)),
+                OfIntegerNumber(expr) => Box::new(expr.shrink().map(OfIntegerNumber)),
+                AllDifferent(exprs) => Box::new(exprs.shrink().map(AllDifferent)),
+                OfRational(_) => quickcheck::empty_shrinker(),
+            }
+        }
+    }
+
+    impl ArbitraryAt for SatisfactionExpression {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> SatisfactionExpression {
+            match u32::arbitrary(g) % 3 {
+                0 => SatisfactionExpression::Satisfy(below(g, depth)),
+                1 => SatisfactionExpression::Maximise(below(g, depth)),
+                2 => SatisfactionExpression::Minimise(below(g, depth)),
                 _ => unreachable!(),
             }
         }
@@ -205,27 +998,208 @@ mod tests {
 
     impl Arbitrary for SatisfactionExpression {
         fn arbitrary(g: &mut Gen) -> SatisfactionExpression {
-            match u32::arbitrary(g) % 3 {
-                0 => SatisfactionExpression::Satisfy(Arbitrary::arbitrary(g)),
-                1 => SatisfactionExpression::Maximise(Arbitrary::arbitrary(g)),
-                2 => SatisfactionExpression::Minimise(Arbitrary::arbitrary(g)),
-                _ => unreachable!(),
+            SatisfactionExpression::arbitrary_at(g, 0)
+        }
+        /// An objective shrinks to satisfying its constraint.
+        fn shrink(&self) -> Box<dyn Iterator<Item = SatisfactionExpression>> {
+            use SatisfactionExpression::*;
+            match self {
+                Satisfy(expr) => Box::new(expr.shrink().map(Satisfy)),
+                Minimise(expr) => shrink_node(vec![Satisfy(expr.clone())], expr.clone(), Minimise),
+                Maximise(expr) => shrink_node(vec![Satisfy(expr.clone())], expr.clone(), Maximise),
+            }
+        }
+    }
+
+    impl ArbitraryAt for ConstraintProgramExpression {
+        fn arbitrary_at(g: &mut Gen, depth: usize) -> ConstraintProgramExpression {
+            if leaf(g, depth) {
+                return ConstraintProgramExpression::Solve(below(g, depth));
+            }
+            match u32::arbitrary(g) % 5 {
+                0 => ConstraintProgramExpression::Solve(below(g, depth)),
+                1 => ConstraintProgramExpression::SolveAnd(below(g, depth), below(g, depth)),
+                _ => ConstraintProgramExpression::ConstrainAnd(below(g, depth), below(g, depth)),
             }
         }
     }
+
     impl Arbitrary for ConstraintProgramExpression {
         fn arbitrary(g: &mut Gen) -> ConstraintProgramExpression {
-            match u32::arbitrary(g) % 5 {
-                0 => ConstraintProgramExpression::Solve(Arbitrary::arbitrary(g)),
-                1 => ConstraintProgramExpression::SolveAnd(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+            ConstraintProgramExpression::arbitrary_at(g, 0)
+        }
+        /// A program shrinks to the rest of it without its first
+        /// statement, or without all but its first goal.
+        fn shrink(&self) -> Box<dyn Iterator<Item = ConstraintProgramExpression>> {
+            use ConstraintProgramExpression::*;
+            match self {
+                Solve(goal) => Box::new(goal.shrink().map(Solve)),
+                SolveAnd(goal, rest) => shrink_node(
+                    vec![*rest.clone(), Solve(goal.clone())],
+                    (goal.clone(), rest.clone()),
+                    |(goal, rest)| SolveAnd(goal, rest),
                 ),
-                _ => ConstraintProgramExpression::ConstrainAnd(
-                    Arbitrary::arbitrary(g),
-                    Arbitrary::arbitrary(g),
+                ConstrainAnd(constraint, rest) => shrink_node(
+                    vec![*rest.clone()],
+                    (constraint.clone(), rest.clone()),
+                    |(constraint, rest)| ConstrainAnd(constraint, rest),
                 ),
             }
         }
     }
+
+    #[cfg(feature = "serde")]
+    #[quickcheck_macros::quickcheck]
+    fn programs_round_trip_through_json(program: ConstraintProgramExpression) -> bool {
+        let json = serde_json::to_string(&program).unwrap();
+        serde_json::from_str::<ConstraintProgramExpression>(&json).unwrap() == program
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn integers_are_written_as_strings() {
+        use super::integer::IntegerNumber;
+        use super::{AssignedValue, Assignment};
+        let assignment = Assignment::new(
+            Symbol::new("x".to_string()),
+            AssignedValue::Integer(IntegerNumber::Value(i128::MIN)),
+        );
+        let json = serde_json::to_string(&assignment).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"x","value":{"Integer":{"Value":"-170141183460469231731687303715884105728"}}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Assignment>(&json).unwrap(),
+            assignment
+        );
+    }
+
+    #[test]
+    fn substitution_replaces_only_free_variables_of_its_type() {
+        use super::dsl::{int, int_var};
+        use super::integer::IntegerNumberExpression;
+        use super::parser::{parse_boolean, parse_integer, parse_program};
+        use super::Substitute;
+        let x = Symbol::new("x".to_string());
+        let program = parse_program("x in [0, 9];\np or x;\nsatisfy 2 * x < 5;").unwrap();
+        let replacement = parse_integer("y - 1").unwrap();
+        assert_eq!(
+            program.substitute(&x, &replacement).to_string(),
+            "y - 1 in [0, 9];\np or x;\nsatisfy 2 * (y - 1) < 5;"
+        );
+        let negation = parse_boolean("not q").unwrap();
+        assert_eq!(
+            program.substitute_boolean(&x, &negation).to_string(),
+            "x in [0, 9];\np or not q;\nsatisfy 2 * x < 5;"
+        );
+        let z = Symbol::new("z".to_string());
+        assert_eq!(program.substitute(&z, &replacement), program);
+
+        let shadowed = IntegerNumberExpression::Let(
+            x.clone(),
+            (int_var("x") + 1).into(),
+            (int(2) * int_var("x")).into(),
+        );
+        assert_eq!(
+            shadowed.substitute(&x, &replacement).to_string(),
+            "(let x = y - 1 + 1 in 2 * x)"
+        );
+
+        let y = Symbol::new("y".to_string());
+        let capturing = IntegerNumberExpression::Let(
+            y.clone(),
+            int(1).into(),
+            (int_var("x") + int_var("y")).into(),
+        );
+        assert_eq!(
+            capturing
+                .substitute(&x, &parse_integer("y").unwrap())
+                .to_string(),
+            "(let y0 = 1 in y + y0)"
+        );
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn free_variables_are_counted_as_listed(program: ConstraintProgramExpression) -> bool {
+        use super::FreeVariable;
+        let program = &program;
+        let free = program.get_free();
+        let mut names = Vec::new();
+        let _ = program.each_free(&mut |name| {
+            names.push(name.clone());
+            std::ops::ControlFlow::Continue(())
+        });
+        names.iter().eq(free.iter().map(|variable| variable.name()))
+            && program.free_variable_count() == free.len()
+            && program.has_free() != free.is_empty()
+    }
+
+    #[test]
+    fn expressions_and_domains_hash_as_they_compare() {
+        use super::dsl::closed;
+        use super::integer::{IntegerNumber, IntegerNumberExpression};
+        use super::parser::parse_integer;
+        use super::{Domain, Evaluate};
+        use std::collections::{HashMap, HashSet};
+        let expr = parse_integer("(2 + 3) * (2 + 3) - 2 + 3").unwrap();
+        let mut values: HashMap<&IntegerNumberExpression, IntegerNumber> = HashMap::new();
+        let mut pending = vec![&expr];
+        while let Some(expr) = pending.pop() {
+            if let IntegerNumberExpression::Times(expr_a, expr_b)
+            | IntegerNumberExpression::Minus(expr_a, expr_b)
+            | IntegerNumberExpression::Add(expr_a, expr_b) = expr
+            {
+                pending.extend([expr_a.as_ref(), expr_b.as_ref()]);
+            }
+            values.insert(expr, expr.evaluate(&[]).unwrap());
+        }
+        let shared = parse_integer("(2 + 3)").unwrap();
+        assert_eq!(values.get(&shared), Some(&IntegerNumber::Value(5)));
+        assert_eq!(values.get(&expr), Some(&IntegerNumber::Value(26)));
+
+        let range = closed(0, 9);
+        let domains: HashSet<Domain> = [
+            Domain::Integer(range.clone()),
+            Domain::Integer(range.clone()),
+            Domain::Custom(Box::new(range.clone())),
+            Domain::Custom(Box::new(range)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(domains.len(), 2);
+    }
+
+    #[test]
+    fn arbitrary_programs_are_no_deeper_than_the_generator_is_large() {
+        use super::Measure;
+        let mut g = Gen::new(100);
+        for _ in 0..10_000 {
+            // Below the last statement come a goal, a constraint and a
+            // comparison before anything that can be a leaf.
+            assert!(ConstraintProgramExpression::arbitrary(&mut g).depth() <= g.size() + 5);
+        }
+    }
+
+    #[test]
+    fn programs_shrink_to_their_essential_statements() {
+        use super::parser::parse_program;
+        use super::FreeVariable;
+        let program = parse_program("x in [0, 9];\np or q;\nmaximise x + y > 2;").unwrap();
+        let shrunk = minimal(program, |program| program.get_free().len() >= 2);
+        assert_eq!(shrunk.to_string(), "satisfy x + y > 0;");
+    }
+
+    #[test]
+    fn long_programs_print_without_recursing() {
+        use crate::solver::program_of;
+        let statements = 10_000;
+        let p = ConstraintLogicExpression::Boolean(Box::new(
+            super::boolean::BooleanExpression::BooleanVariable(Symbol::new("p".to_string())),
+        ));
+        let program = program_of(vec![p; statements]);
+        let text = program.to_string();
+        assert_eq!(text.lines().count(), statements);
+        assert!(text.ends_with("p;\nsatisfy p;"));
+    }
 }
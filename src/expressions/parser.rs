@@ -0,0 +1,1002 @@
+//! # Parser
+//! Reads constraint programs from the concrete syntax written by the
+//! `Display` implementations of the expression enums, e.g.
+//! ```text
+//! x in [1, 3];
+//! a or not b;
+//! satisfy x * 5 > 9;
+//! ```
+//! A program is a sequence of statements each ended by `;`. The last
+//! statement is a goal (`satisfy`, `minimise` or `maximise` followed by a
+//! constraint), earlier ones are either goals or plain constraints.
+//! Single expressions are read with `parse_boolean`, `parse_integer` and
+//! `parse_comparison`, or through their `FromStr` impls.
+//!
+//! Every pair of parentheses in the source becomes a `Parenthesis` node,
+//! while printing only adds parentheses where precedence requires them,
+//! so a tree that relies on grouping without a `Parenthesis` node reads
+//! back with one.
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+use super::boolean::{BooleanExpression, BooleanValue};
+use super::integer::{
+    BooleanIntegerNumberExpression, IntegerNumber, IntegerNumberDomainExpression,
+    IntegerNumberExpression,
+};
+use super::{
+    ConstraintLogicExpression, ConstraintProgramExpression, SatisfactionExpression, Symbol,
+};
+
+/// Words with a meaning of their own, which cannot name variables.
+const KEYWORDS: [&str; 25] = [
+    "and",
+    "or",
+    "xor",
+    "not",
+    "implies",
+    "equals",
+    "true",
+    "false",
+    "in",
+    "NaN",
+    "satisfy",
+    "minimise",
+    "maximise",
+    "universe",
+    "empty",
+    "union",
+    "intersection",
+    "difference",
+    "complement",
+    "powmod",
+    "abs",
+    "ite",
+    "alldifferent",
+    "sum",
+    "product",
+];
+
+/// Punctuation, longest first so that `!=` is not read as `!`.
+const PUNCTUATION: [&str; 18] = [
+    "!=", "(", ")", "[", "]", "{", "}", ",", ";", "+", "-", "*", "/", "%", "^", "=", "<", ">",
+];
+
+/// How deeply expressions may nest by default, counting every pair of
+/// parentheses and every operand of a prefix or right-grouping operator
+/// such as `not`, unary `-`, `^` and `implies`. Well beyond what written
+/// programs need, and low enough for the 2 MiB stack of a spawned thread
+/// even without optimisations.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Errors found while parsing. Offsets are in bytes from the start of
+/// the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// A token that cannot appear where it was found.
+    Unexpected {
+        offset: usize,
+        found: String,
+        expected: String,
+    },
+    /// The input ended while `expected` was still missing.
+    UnexpectedEnd { expected: String },
+    /// A character that starts no token.
+    InvalidCharacter { offset: usize, found: char },
+    /// A number literal outside the range of `i128`.
+    InvalidNumber { offset: usize, literal: String },
+    /// Expressions nested deeper than the limit `depth`.
+    TooDeep { depth: usize },
+}
+
+impl ParseError {
+    /// How far into the input the error was found, used to report the
+    /// more informative of two failed alternatives.
+    fn reach(&self) -> usize {
+        match self {
+            ParseError::Unexpected { offset, .. }
+            | ParseError::InvalidCharacter { offset, .. }
+            | ParseError::InvalidNumber { offset, .. } => *offset,
+            ParseError::UnexpectedEnd { .. } | ParseError::TooDeep { .. } => usize::MAX,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Unexpected {
+                offset,
+                found,
+                expected,
+            } => write!(
+                f,
+                "expected {} at byte {}, found `{}`",
+                expected, offset, found
+            ),
+            ParseError::UnexpectedEnd { expected } => {
+                write!(f, "expected {} at the end of the input", expected)
+            }
+            ParseError::InvalidCharacter { offset, found } => {
+                write!(f, "unexpected character `{}` at byte {}", found, offset)
+            }
+            ParseError::InvalidNumber { offset, literal } => {
+                write!(f, "number `{}` at byte {} is out of range", literal, offset)
+            }
+            ParseError::TooDeep { depth } => {
+                write!(f, "expressions are nested deeper than {} levels", depth)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Number(String),
+    Punctuation(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) | Token::Number(word) => write!(f, "{}", word),
+            Token::Punctuation(punctuation) => write!(f, "{}", punctuation),
+        }
+    }
+}
+
+struct Lexeme {
+    offset: usize,
+    token: Token,
+}
+
+/// Splits `source` into tokens, numbering their offsets from `base`.
+fn tokenize(source: &str, base: usize) -> Result<Vec<Lexeme>, ParseError> {
+    let mut lexemes = Vec::new();
+    let mut rest = source.char_indices().peekable();
+    while let Some(&(start, c)) = rest.peek() {
+        let offset = base + start;
+        if c.is_whitespace() {
+            rest.next();
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(&(_, c)) = rest.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                word.push(c);
+                rest.next();
+            }
+            lexemes.push(Lexeme {
+                offset,
+                token: Token::Word(word),
+            });
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(&(_, c)) = rest.peek() {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+                number.push(c);
+                rest.next();
+            }
+            lexemes.push(Lexeme {
+                offset,
+                token: Token::Number(number),
+            });
+        } else {
+            let punctuation = PUNCTUATION
+                .iter()
+                .find(|punctuation| source[start..].starts_with(*punctuation))
+                .ok_or(ParseError::InvalidCharacter { offset, found: c })?;
+            for _ in 0..punctuation.len() {
+                rest.next();
+            }
+            lexemes.push(Lexeme {
+                offset,
+                token: Token::Punctuation(punctuation),
+            });
+        }
+    }
+    Ok(lexemes)
+}
+
+/// A statement of a program: a goal or a plain constraint.
+enum Statement {
+    Goal(SatisfactionExpression),
+    Constraint(ConstraintLogicExpression),
+}
+
+/// Recursive descent over a token list.
+struct Parser {
+    lexemes: Vec<Lexeme>,
+    position: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Parser {
+    fn new(source: &str, base: usize) -> Result<Parser, ParseError> {
+        Ok(Parser {
+            lexemes: tokenize(source, base)?,
+            position: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        })
+    }
+
+    /// Parses with `parse` one level deeper, failing instead once that
+    /// is beyond the limit, so that hostile input cannot exhaust the
+    /// stack.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Parser) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == self.max_depth {
+            return Err(ParseError::TooDeep {
+                depth: self.max_depth,
+            });
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.lexemes.get(self.position).map(|lexeme| &lexeme.token)
+    }
+
+    fn at_end(&self) -> bool {
+        self.position == self.lexemes.len()
+    }
+
+    fn is(&self, punctuation: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punctuation(found)) if *found == punctuation)
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(found)) if found == word)
+    }
+
+    fn eat(&mut self, punctuation: &str) -> bool {
+        let found = self.is(punctuation);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = self.is_word(word);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    /// An error for the current token, which is not what was `expected`.
+    fn error(&self, expected: &str) -> ParseError {
+        match self.lexemes.get(self.position) {
+            Some(lexeme) => ParseError::Unexpected {
+                offset: lexeme.offset,
+                found: lexeme.token.to_string(),
+                expected: expected.to_string(),
+            },
+            None => ParseError::UnexpectedEnd {
+                expected: expected.to_string(),
+            },
+        }
+    }
+
+    fn expect(&mut self, punctuation: &str) -> Result<(), ParseError> {
+        if self.eat(punctuation) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("`{}`", punctuation)))
+        }
+    }
+
+    /// The offset of the current token, or `usize::MAX` at the end.
+    fn reach(&self) -> usize {
+        self.lexemes
+            .get(self.position)
+            .map_or(usize::MAX, |lexeme| lexeme.offset)
+    }
+
+    fn expect_end(&self) -> Result<(), ParseError> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(self.error("the end of the statement"))
+        }
+    }
+
+    fn identifier(&mut self) -> Option<Symbol> {
+        match self.peek() {
+            Some(Token::Word(word)) if !KEYWORDS.contains(&word.as_str()) => {
+                let symbol = Symbol::new(word.clone());
+                self.position += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        if self.eat_word("satisfy") {
+            Ok(Statement::Goal(SatisfactionExpression::Satisfy(Box::new(
+                self.constraint()?,
+            ))))
+        } else if self.eat_word("minimise") {
+            Ok(Statement::Goal(SatisfactionExpression::Minimise(Box::new(
+                self.constraint()?,
+            ))))
+        } else if self.eat_word("maximise") {
+            Ok(Statement::Goal(SatisfactionExpression::Maximise(Box::new(
+                self.constraint()?,
+            ))))
+        } else {
+            Ok(Statement::Constraint(self.constraint()?))
+        }
+    }
+
+    /// An integer comparison if one parses, otherwise a boolean expression.
+    /// When neither fits, the error of the alternative that got further
+    /// is reported.
+    fn constraint(&mut self) -> Result<ConstraintLogicExpression, ParseError> {
+        if self.eat_word("alldifferent") {
+            return Ok(ConstraintLogicExpression::AllDifferent(
+                self.integer_list()?,
+            ));
+        }
+        let start = self.position;
+        let integer_error = match self.comparison() {
+            Ok(expr) => return Ok(ConstraintLogicExpression::OfIntegerNumber(Box::new(expr))),
+            Err(error @ ParseError::TooDeep { .. }) => return Err(error),
+            Err(error) => error,
+        };
+        self.position = start;
+        match self.boolean() {
+            Ok(expr) if self.at_end() || self.is(";") || self.reach() > integer_error.reach() => {
+                Ok(ConstraintLogicExpression::Boolean(Box::new(expr)))
+            }
+            Ok(_) => Err(integer_error),
+            Err(boolean_error) if boolean_error.reach() >= integer_error.reach() => {
+                Err(boolean_error)
+            }
+            Err(_) => Err(integer_error),
+        }
+    }
+
+    /// A parenthesised list of integer expressions separated by commas,
+    /// possibly empty.
+    fn integer_list(&mut self) -> Result<Vec<IntegerNumberExpression>, ParseError> {
+        self.expect("(")?;
+        let mut exprs = Vec::new();
+        if self.eat(")") {
+            return Ok(exprs);
+        }
+        loop {
+            exprs.push(self.nested(Parser::integer)?);
+            if !self.eat(",") {
+                self.expect(")")?;
+                return Ok(exprs);
+            }
+        }
+    }
+
+    fn boolean(&mut self) -> Result<BooleanExpression, ParseError> {
+        let mut expr = self.implication()?;
+        while self.eat_word("equals") {
+            expr = BooleanExpression::Equals(Box::new(expr), Box::new(self.implication()?));
+        }
+        Ok(expr)
+    }
+
+    fn implication(&mut self) -> Result<BooleanExpression, ParseError> {
+        let expr = self.disjunction()?;
+        if self.eat_word("implies") {
+            return Ok(BooleanExpression::Implies(
+                Box::new(expr),
+                Box::new(self.nested(Parser::implication)?),
+            ));
+        }
+        Ok(expr)
+    }
+
+    fn disjunction(&mut self) -> Result<BooleanExpression, ParseError> {
+        let mut expr = self.exclusion()?;
+        while self.eat_word("or") {
+            expr = BooleanExpression::Or(Box::new(expr), Box::new(self.exclusion()?));
+        }
+        Ok(expr)
+    }
+
+    fn exclusion(&mut self) -> Result<BooleanExpression, ParseError> {
+        let mut expr = self.conjunction()?;
+        while self.eat_word("xor") {
+            expr = BooleanExpression::Xor(Box::new(expr), Box::new(self.conjunction()?));
+        }
+        Ok(expr)
+    }
+
+    fn conjunction(&mut self) -> Result<BooleanExpression, ParseError> {
+        let mut expr = self.negation()?;
+        while self.eat_word("and") {
+            expr = BooleanExpression::And(Box::new(expr), Box::new(self.negation()?));
+        }
+        Ok(expr)
+    }
+
+    fn negation(&mut self) -> Result<BooleanExpression, ParseError> {
+        if self.eat_word("not") {
+            return Ok(BooleanExpression::Not(Box::new(
+                self.nested(Parser::negation)?,
+            )));
+        }
+        self.boolean_atom()
+    }
+
+    fn boolean_atom(&mut self) -> Result<BooleanExpression, ParseError> {
+        if self.eat_word("true") {
+            Ok(BooleanExpression::BooleanValue(BooleanValue::True))
+        } else if self.eat_word("false") {
+            Ok(BooleanExpression::BooleanValue(BooleanValue::False))
+        } else if self.eat_word("ite") {
+            self.expect("(")?;
+            let condition = self.nested(Parser::boolean)?;
+            self.expect(",")?;
+            let then = self.nested(Parser::boolean)?;
+            self.expect(",")?;
+            let otherwise = self.nested(Parser::boolean)?;
+            self.expect(")")?;
+            Ok(BooleanExpression::IfThenElse(
+                Box::new(condition),
+                Box::new(then),
+                Box::new(otherwise),
+            ))
+        } else if self.eat("(") {
+            let expr = self.nested(Parser::boolean)?;
+            self.expect(")")?;
+            Ok(BooleanExpression::Parenthesis(Box::new(expr)))
+        } else if let Some(symbol) = self.identifier() {
+            Ok(BooleanExpression::BooleanVariable(symbol))
+        } else {
+            Err(self.error("a boolean expression"))
+        }
+    }
+
+    fn comparison(&mut self) -> Result<BooleanIntegerNumberExpression, ParseError> {
+        use BooleanIntegerNumberExpression::*;
+        let expr = Box::new(self.integer()?);
+        if self.eat("=") {
+            Ok(Equals(expr, Box::new(self.integer()?)))
+        } else if self.eat("!=") {
+            Ok(Different(expr, Box::new(self.integer()?)))
+        } else if self.eat(">") {
+            Ok(Greater(expr, Box::new(self.integer()?)))
+        } else if self.eat("<") {
+            Ok(Less(expr, Box::new(self.integer()?)))
+        } else if self.eat_word("in") {
+            Ok(In(expr, Box::new(self.domain()?)))
+        } else {
+            Err(self.error("a comparison"))
+        }
+    }
+
+    fn integer(&mut self) -> Result<IntegerNumberExpression, ParseError> {
+        use IntegerNumberExpression::*;
+        let mut expr = self.product()?;
+        loop {
+            if self.eat("+") {
+                expr = Add(Box::new(expr), Box::new(self.product()?));
+            } else if self.eat("-") {
+                expr = Minus(Box::new(expr), Box::new(self.product()?));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<IntegerNumberExpression, ParseError> {
+        use IntegerNumberExpression::*;
+        let mut expr = self.unary()?;
+        loop {
+            if self.eat("*") {
+                expr = Times(Box::new(expr), Box::new(self.unary()?));
+            } else if self.eat("/") {
+                expr = Divide(Box::new(expr), Box::new(self.unary()?));
+            } else if self.eat("%") {
+                expr = Modulo(Box::new(expr), Box::new(self.unary()?));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Whether the current token is a `-` written directly against a
+    /// number, which makes the two a negative literal.
+    fn at_negative_literal(&self) -> bool {
+        match (
+            self.lexemes.get(self.position),
+            self.lexemes.get(self.position + 1),
+        ) {
+            (Some(minus), Some(number)) => {
+                minus.token == Token::Punctuation("-")
+                    && matches!(number.token, Token::Number(_))
+                    && number.offset == minus.offset + 1
+            }
+            _ => false,
+        }
+    }
+
+    fn unary(&mut self) -> Result<IntegerNumberExpression, ParseError> {
+        if self.is("-") && !self.at_negative_literal() {
+            self.position += 1;
+            return Ok(IntegerNumberExpression::Negate(Box::new(
+                self.nested(Parser::unary)?,
+            )));
+        }
+        let base = self.integer_atom()?;
+        if self.eat("^") {
+            return Ok(IntegerNumberExpression::Pow(
+                Box::new(base),
+                Box::new(self.nested(Parser::unary)?),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn number(&mut self, negative: bool) -> Result<IntegerNumber, ParseError> {
+        let lexeme = &self.lexemes[self.position];
+        let literal = match &lexeme.token {
+            Token::Number(digits) if negative => format!("-{}", digits),
+            Token::Number(digits) => digits.clone(),
+            _ => return Err(self.error("a number")),
+        };
+        let value = literal
+            .parse::<i128>()
+            .map_err(|_| ParseError::InvalidNumber {
+                offset: lexeme.offset,
+                literal: literal.clone(),
+            })?;
+        self.position += 1;
+        Ok(IntegerNumber::Value(value))
+    }
+
+    fn integer_atom(&mut self) -> Result<IntegerNumberExpression, ParseError> {
+        use IntegerNumberExpression::*;
+        if self.at_negative_literal() {
+            self.position += 1;
+            Ok(IntegerNumberValue(self.number(true)?))
+        } else if matches!(self.peek(), Some(Token::Number(_))) {
+            Ok(IntegerNumberValue(self.number(false)?))
+        } else if self.eat_word("NaN") {
+            Ok(IntegerNumberValue(IntegerNumber::NaN))
+        } else if self.eat_word("powmod") {
+            self.expect("(")?;
+            let base = self.nested(Parser::integer)?;
+            self.expect(",")?;
+            let exponent = self.nested(Parser::integer)?;
+            self.expect(",")?;
+            let modulus = self.nested(Parser::integer)?;
+            self.expect(")")?;
+            Ok(PowMod(
+                Box::new(base),
+                Box::new(exponent),
+                Box::new(modulus),
+            ))
+        } else if self.eat_word("abs") {
+            self.expect("(")?;
+            let expr = self.nested(Parser::integer)?;
+            self.expect(")")?;
+            Ok(Abs(Box::new(expr)))
+        } else if self.eat_word("sum") {
+            Ok(Sum(self.integer_list()?))
+        } else if self.eat_word("product") {
+            Ok(Product(self.integer_list()?))
+        } else if self.eat_word("ite") {
+            self.expect("(")?;
+            let condition = self.nested(Parser::boolean)?;
+            self.expect(",")?;
+            let then = self.nested(Parser::integer)?;
+            self.expect(",")?;
+            let otherwise = self.nested(Parser::integer)?;
+            self.expect(")")?;
+            Ok(IfThenElse(
+                Box::new(condition),
+                Box::new(then),
+                Box::new(otherwise),
+            ))
+        } else if self.eat("(") {
+            let expr = self.nested(Parser::integer)?;
+            self.expect(")")?;
+            Ok(Parenthesis(Box::new(expr)))
+        } else if let Some(symbol) = self.identifier() {
+            Ok(IntegerNumberVariable(symbol))
+        } else {
+            Err(self.error("an integer expression"))
+        }
+    }
+
+    /// The two domain arguments of a set operation, `(a, b)`.
+    fn domain_pair(
+        &mut self,
+    ) -> Result<
+        (
+            Box<IntegerNumberDomainExpression>,
+            Box<IntegerNumberDomainExpression>,
+        ),
+        ParseError,
+    > {
+        self.expect("(")?;
+        let expr_a = self.nested(Parser::domain)?;
+        self.expect(",")?;
+        let expr_b = self.nested(Parser::domain)?;
+        self.expect(")")?;
+        Ok((Box::new(expr_a), Box::new(expr_b)))
+    }
+
+    fn domain(&mut self) -> Result<IntegerNumberDomainExpression, ParseError> {
+        use IntegerNumberDomainExpression::*;
+        if self.eat_word("universe") {
+            Ok(Universe)
+        } else if self.eat_word("empty") {
+            Ok(Empty)
+        } else if self.eat_word("union") {
+            let (expr_a, expr_b) = self.domain_pair()?;
+            Ok(Union(expr_a, expr_b))
+        } else if self.eat_word("intersection") {
+            let (expr_a, expr_b) = self.domain_pair()?;
+            Ok(Intersection(expr_a, expr_b))
+        } else if self.eat_word("difference") {
+            let (expr_a, expr_b) = self.domain_pair()?;
+            Ok(Difference(expr_a, expr_b))
+        } else if self.eat_word("complement") {
+            self.expect("(")?;
+            let expr = self.nested(Parser::domain)?;
+            self.expect(")")?;
+            Ok(Complement(Box::new(expr)))
+        } else if self.eat("{") {
+            let mut elements = Vec::new();
+            if !self.eat("}") {
+                loop {
+                    elements.push(self.integer()?);
+                    if self.eat("}") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            Ok(ExplicitSet(elements))
+        } else if self.is("[") || self.is("(") {
+            let closed_left = self.eat("[");
+            if !closed_left {
+                self.position += 1;
+            }
+            let lower = Box::new(self.integer()?);
+            self.expect(",")?;
+            let upper = Box::new(self.integer()?);
+            let closed_right = if self.eat("]") {
+                true
+            } else if self.eat(")") {
+                false
+            } else {
+                return Err(self.error("`]` or `)`"));
+            };
+            Ok(match (closed_left, closed_right) {
+                (true, true) => ClosedRange(lower, upper),
+                (false, false) => OpenRange(lower, upper),
+                (false, true) => OpenLeftClosedRightRange(lower, upper),
+                (true, false) => ClosedLeftOpenRightRange(lower, upper),
+            })
+        } else {
+            Err(self.error("a domain"))
+        }
+    }
+}
+
+/// Chains statements into a program; the last one must be a goal.
+fn program(statements: Vec<Statement>) -> Result<ConstraintProgramExpression, ParseError> {
+    let mut statements = statements.into_iter().rev();
+    let mut program = match statements.next() {
+        Some(Statement::Goal(goal)) => ConstraintProgramExpression::Solve(Box::new(goal)),
+        _ => {
+            return Err(ParseError::UnexpectedEnd {
+                expected: "a goal (`satisfy`, `minimise` or `maximise`)".to_string(),
+            })
+        }
+    };
+    for statement in statements {
+        program = match statement {
+            Statement::Goal(goal) => {
+                ConstraintProgramExpression::SolveAnd(Box::new(goal), Box::new(program))
+            }
+            Statement::Constraint(constraint) => {
+                ConstraintProgramExpression::ConstrainAnd(Box::new(constraint), Box::new(program))
+            }
+        };
+    }
+    Ok(program)
+}
+
+/// Parses a whole program, with expressions nested at most
+/// `DEFAULT_MAX_DEPTH` deep.
+pub fn parse_program(source: &str) -> Result<ConstraintProgramExpression, ParseError> {
+    parse_program_with_max_depth(source, DEFAULT_MAX_DEPTH)
+}
+
+/// Parses a whole program, failing with `ParseError::TooDeep` where
+/// expressions nest more than `max_depth` deep.
+pub fn parse_program_with_max_depth(
+    source: &str,
+    max_depth: usize,
+) -> Result<ConstraintProgramExpression, ParseError> {
+    let mut parser = Parser::new(source, 0)?;
+    parser.max_depth = max_depth;
+    let mut statements = Vec::new();
+    while !parser.at_end() {
+        statements.push(parser.statement()?);
+        if !parser.at_end() {
+            parser.expect(";")?;
+        }
+    }
+    program(statements)
+}
+
+/// Parses a single boolean expression, such as `a and (b or not c)`,
+/// with no trailing `;`.
+pub fn parse_boolean(source: &str) -> Result<BooleanExpression, ParseError> {
+    let mut parser = Parser::new(source, 0)?;
+    let expr = parser.boolean()?;
+    parser.expect_end()?;
+    Ok(expr)
+}
+
+impl std::str::FromStr for BooleanExpression {
+    type Err = ParseError;
+    fn from_str(source: &str) -> Result<BooleanExpression, ParseError> {
+        parse_boolean(source)
+    }
+}
+
+/// Parses a single integer expression, such as `2 * x + 1`.
+pub fn parse_integer(source: &str) -> Result<IntegerNumberExpression, ParseError> {
+    let mut parser = Parser::new(source, 0)?;
+    let expr = parser.integer()?;
+    parser.expect_end()?;
+    Ok(expr)
+}
+
+impl std::str::FromStr for IntegerNumberExpression {
+    type Err = ParseError;
+    fn from_str(source: &str) -> Result<IntegerNumberExpression, ParseError> {
+        parse_integer(source)
+    }
+}
+
+/// Parses a single integer comparison, such as `2 * x + 1 < y` or
+/// `z in [0, 100)`.
+pub fn parse_comparison(source: &str) -> Result<BooleanIntegerNumberExpression, ParseError> {
+    let mut parser = Parser::new(source, 0)?;
+    let expr = parser.comparison()?;
+    parser.expect_end()?;
+    Ok(expr)
+}
+
+impl std::str::FromStr for BooleanIntegerNumberExpression {
+    type Err = ParseError;
+    fn from_str(source: &str) -> Result<BooleanIntegerNumberExpression, ParseError> {
+        parse_comparison(source)
+    }
+}
+
+/// Writes a program in the syntax `parse_program` reads.
+pub fn to_source(program: &ConstraintProgramExpression) -> String {
+    program.to_string()
+}
+
+/// Errors reading a program from a stream.
+#[derive(Debug)]
+pub enum ProgramIoError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ProgramIoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgramIoError::Io(error) => write!(f, "{}", error),
+            ProgramIoError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ProgramIoError {}
+
+impl From<io::Error> for ProgramIoError {
+    fn from(error: io::Error) -> ProgramIoError {
+        ProgramIoError::Io(error)
+    }
+}
+
+impl From<ParseError> for ProgramIoError {
+    fn from(error: ParseError) -> ProgramIoError {
+        ProgramIoError::Parse(error)
+    }
+}
+
+/// Writes a program to `w` statement by statement, without building its
+/// source text in memory.
+pub fn write_program<W: Write>(program: &ConstraintProgramExpression, w: &mut W) -> io::Result<()> {
+    writeln!(w, "{}", program)
+}
+
+/// Reads a program from `r` one statement at a time, so only the text of
+/// the statement being parsed is held in memory.
+pub fn read_program<R: Read>(r: &mut R) -> Result<ConstraintProgramExpression, ProgramIoError> {
+    let mut reader = io::BufReader::new(r);
+    let mut statements = Vec::new();
+    let mut chunk = Vec::new();
+    let mut offset = 0;
+    loop {
+        chunk.clear();
+        let read = reader.read_until(b';', &mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        let text = std::str::from_utf8(&chunk)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let text = text.strip_suffix(';').unwrap_or(text);
+        let mut parser = Parser::new(text, offset)?;
+        if !parser.at_end() {
+            statements.push(parser.statement()?);
+            parser.expect_end()?;
+        } else if chunk.ends_with(b";") {
+            return Err(parser.error("a statement").into());
+        }
+        offset += read;
+    }
+    Ok(program(statements)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_program, read_program, to_source, write_program, ParseError};
+    use std::io::Cursor;
+
+    const SOURCE: &str = "x in [1, 3];
+y in union((0, 2], {7, -1});
+a or not b and (c implies d);
+x - (y - 2) * - 5 != -3;
+maximise powmod(x, 2 ^ y, 7) = abs(x) % 3;
+satisfy a equals b implies c;";
+
+    #[test]
+    fn source_reads_back_to_the_same_text() {
+        let program = parse_program(SOURCE).unwrap();
+        assert_eq!(to_source(&program), SOURCE);
+    }
+
+    #[test]
+    fn programs_round_trip_through_a_stream() {
+        let program = parse_program(SOURCE).unwrap();
+        let mut buffer = Cursor::new(Vec::new());
+        write_program(&program, &mut buffer).unwrap();
+        buffer.set_position(0);
+        let read = read_program(&mut buffer).unwrap();
+        assert_eq!(to_source(&read), to_source(&program));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(
+            parse_program("satisfy x < ;").err(),
+            Some(ParseError::Unexpected {
+                offset: 12,
+                found: ";".to_string(),
+                expected: "an integer expression".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_program("a and b);\nsatisfy a;").err(),
+            Some(ParseError::Unexpected {
+                offset: 7,
+                found: ")".to_string(),
+                expected: "`;`".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error_not_a_crash() {
+        use super::{parse_program_with_max_depth, DEFAULT_MAX_DEPTH};
+        let nested = |depth: usize| format!("satisfy {}a{};", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_program(&nested(DEFAULT_MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse_program(&nested(DEFAULT_MAX_DEPTH + 1)).err(),
+            Some(ParseError::TooDeep {
+                depth: DEFAULT_MAX_DEPTH
+            })
+        );
+        assert_eq!(
+            parse_program(&nested(1_000_000)).err(),
+            Some(ParseError::TooDeep {
+                depth: DEFAULT_MAX_DEPTH
+            })
+        );
+        assert!(parse_program(&format!("satisfy {}x > 0;", "- ".repeat(100_000))).is_err());
+        assert!(parse_program_with_max_depth(&nested(3), 3).is_ok());
+        assert_eq!(
+            parse_program_with_max_depth(&nested(4), 3).err(),
+            Some(ParseError::TooDeep { depth: 3 })
+        );
+    }
+
+    #[test]
+    fn boolean_expressions_parse_on_their_own() {
+        use super::parse_boolean;
+        use crate::expressions::boolean::BooleanExpression;
+        let expr: BooleanExpression = "a and (b or not c) implies d".parse().unwrap();
+        assert_eq!(expr.to_string(), "a and (b or not c) implies d");
+        let BooleanExpression::Implies(premise, _) = expr else {
+            panic!("expected `implies` to bind loosest");
+        };
+        assert!(matches!(*premise, BooleanExpression::And(_, _)));
+        assert_eq!(
+            parse_boolean("a or b;").err(),
+            Some(ParseError::Unexpected {
+                offset: 6,
+                found: ";".to_string(),
+                expected: "the end of the statement".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_boolean("not"),
+            Err(ParseError::UnexpectedEnd {
+                expected: "a boolean expression".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn comparisons_parse_on_their_own() {
+        use super::{parse_comparison, parse_integer};
+        use crate::expressions::integer::{
+            BooleanIntegerNumberExpression, IntegerNumberDomainExpression,
+        };
+        for source in [
+            "2 * x + 1 < y",
+            "x != -y % 3",
+            "z in [0, 100]",
+            "z in (0, 100)",
+            "z in (0, 100]",
+            "z in [0, 100)",
+        ] {
+            assert_eq!(parse_comparison(source).unwrap().to_string(), source);
+        }
+        let BooleanIntegerNumberExpression::In(_, domain) = "z in (0, 100]".parse().unwrap() else {
+            panic!("expected a domain constraint");
+        };
+        assert!(matches!(
+            *domain,
+            IntegerNumberDomainExpression::OpenLeftClosedRightRange(_, _)
+        ));
+        assert_eq!(
+            parse_integer("(x - 1) / 2").unwrap().to_string(),
+            "(x - 1) / 2"
+        );
+        assert_eq!(
+            parse_comparison("x + * 2 = y").err(),
+            Some(ParseError::Unexpected {
+                offset: 4,
+                found: "*".to_string(),
+                expected: "an integer expression".to_string(),
+            })
+        );
+    }
+}
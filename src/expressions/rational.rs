@@ -0,0 +1,1187 @@
+//! Rational numbers: fractions of two `i128`s kept in lowest terms, with
+//! expressions, domains and comparisons of their own. They mirror the
+//! integer ones without the operators that only make sense for integers
+//! (`%`, `^`, `powmod`); an integer expression is used as a rational
+//! through `RationalExpression::OfInteger`. There is no syntax for them
+//! in the parser yet.
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{self, ControlFlow};
+
+use super::integer::{IntegerNumber, IntegerNumberExpression};
+use super::visit::{FreeVariables, Visitor};
+
+/// The possible values for rational numbers. A value is a numerator and
+/// a positive denominator without a common factor, so that equal numbers
+/// are equal values; the parts are private and only `Rational::new`
+/// builds them. Arithmetic whose result has no such representation,
+/// because it divides by zero or a part overflows, gives `NaN`. The
+/// derived order compares the parts; `Rational::compare` orders by value.
+/// With the `serde` feature the parts are written as decimal strings, as
+/// integers are, and parts that are not in lowest terms are rejected.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Parts", into = "Parts")
+)]
+pub struct Rational(Parts);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Parts {
+    NaN,
+    Value(
+        #[cfg_attr(feature = "serde", serde(with = "super::integer::decimal"))] i128,
+        #[cfg_attr(feature = "serde", serde(with = "super::integer::decimal"))] i128,
+    ),
+}
+
+impl fmt::Debug for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Parts> for Rational {
+    type Error = String;
+    fn try_from(parts: Parts) -> Result<Rational, String> {
+        let rational = match parts {
+            Parts::NaN => Rational::NAN,
+            Parts::Value(a, b) => Rational::new(a, b),
+        };
+        if rational.0 == parts {
+            Ok(rational)
+        } else {
+            Err(format!("{:?} is not a fraction in lowest terms", parts))
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Rational> for Parts {
+    fn from(rational: Rational) -> Parts {
+        rational.0
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// The order of `a / b` and `c / d` for positive `b` and `d`, found by
+/// comparing the integer parts and then the reciprocals of the
+/// fractional parts, so that nothing is multiplied and nothing overflows.
+fn compare_fractions(a: i128, b: i128, c: i128, d: i128) -> Ordering {
+    let (whole_a, rest_a) = (a.div_euclid(b), a.rem_euclid(b));
+    let (whole_c, rest_c) = (c.div_euclid(d), c.rem_euclid(d));
+    match (whole_a.cmp(&whole_c), rest_a, rest_c) {
+        (Ordering::Equal, 0, 0) => Ordering::Equal,
+        (Ordering::Equal, 0, _) => Ordering::Less,
+        (Ordering::Equal, _, 0) => Ordering::Greater,
+        (Ordering::Equal, _, _) => compare_fractions(d, rest_c, b, rest_a),
+        (order, _, _) => order,
+    }
+}
+
+impl Rational {
+    /// The result of arithmetic that has no value.
+    pub const NAN: Rational = Rational(Parts::NaN);
+
+    /// `numerator / denominator` in lowest terms, `NaN` when the
+    /// denominator is zero.
+    pub fn new(numerator: i128, denominator: i128) -> Rational {
+        if denominator == 0 {
+            return Rational::NAN;
+        }
+        let divisor = gcd(numerator.unsigned_abs(), denominator.unsigned_abs());
+        let magnitude = numerator.unsigned_abs() / divisor;
+        let numerator = if (numerator < 0) != (denominator < 0) {
+            0i128.checked_sub_unsigned(magnitude)
+        } else {
+            i128::try_from(magnitude).ok()
+        };
+        match (
+            numerator,
+            i128::try_from(denominator.unsigned_abs() / divisor),
+        ) {
+            (Some(numerator), Ok(denominator)) => Rational(Parts::Value(numerator, denominator)),
+            _ => Rational::NAN,
+        }
+    }
+
+    /// The integer `n` as a rational.
+    pub fn integer(n: i128) -> Rational {
+        Rational(Parts::Value(n, 1))
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.0 == Parts::NaN
+    }
+
+    /// The numerator in lowest terms, `None` for `NaN`.
+    pub fn numerator(&self) -> Option<i128> {
+        self.parts().map(|(numerator, _)| numerator)
+    }
+
+    /// The denominator in lowest terms, always positive, `None` for
+    /// `NaN`.
+    pub fn denominator(&self) -> Option<i128> {
+        self.parts().map(|(_, denominator)| denominator)
+    }
+
+    fn parts(&self) -> Option<(i128, i128)> {
+        match self.0 {
+            Parts::Value(a, b) => Some((a, b)),
+            Parts::NaN => None,
+        }
+    }
+
+    /// The order of the two numbers by value, `None` if either is `NaN`.
+    pub fn compare(&self, other: &Rational) -> Option<Ordering> {
+        let ((a, b), (c, d)) = self.parts().zip(other.parts())?;
+        Some(compare_fractions(a, b, c, d))
+    }
+
+    pub fn abs(self) -> Rational {
+        match self.parts() {
+            Some((a, b)) => a
+                .checked_abs()
+                .map_or(Rational::NAN, |a| Rational(Parts::Value(a, b))),
+            None => Rational::NAN,
+        }
+    }
+
+    /// One divided by the number, `NaN` for zero.
+    pub fn recip(self) -> Rational {
+        match self.parts() {
+            Some((a, b)) => Rational::new(b, a),
+            None => Rational::NAN,
+        }
+    }
+}
+
+impl ops::Neg for Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        match self.parts() {
+            Some((a, b)) => a
+                .checked_neg()
+                .map_or(Rational::NAN, |a| Rational(Parts::Value(a, b))),
+            None => Rational::NAN,
+        }
+    }
+}
+
+impl ops::Add for Rational {
+    type Output = Rational;
+    fn add(self, other: Rational) -> Rational {
+        let Some(((a, b), (c, d))) = self.parts().zip(other.parts()) else {
+            return Rational::NAN;
+        };
+        // Both denominators are positive, so their divisor fits.
+        let divisor = gcd(b as u128, d as u128) as i128;
+        let (b, d_reduced) = (b / divisor, d / divisor);
+        let numerator = a
+            .checked_mul(d_reduced)
+            .zip(c.checked_mul(b))
+            .and_then(|(a, c)| a.checked_add(c));
+        match (numerator, b.checked_mul(d)) {
+            (Some(numerator), Some(denominator)) => Rational::new(numerator, denominator),
+            _ => Rational::NAN,
+        }
+    }
+}
+
+impl ops::Sub for Rational {
+    type Output = Rational;
+    fn sub(self, other: Rational) -> Rational {
+        ops::Add::add(self, -other)
+    }
+}
+
+impl ops::Mul for Rational {
+    type Output = Rational;
+    fn mul(self, other: Rational) -> Rational {
+        let Some(((a, b), (c, d))) = self.parts().zip(other.parts()) else {
+            return Rational::NAN;
+        };
+        // Cancelling across first keeps the products as small as they
+        // can be; each divisor is at most a positive denominator.
+        let divisor_ad = gcd(a.unsigned_abs(), d as u128) as i128;
+        let divisor_cb = gcd(c.unsigned_abs(), b as u128) as i128;
+        let numerator = (a / divisor_ad).checked_mul(c / divisor_cb);
+        let denominator = (b / divisor_cb).checked_mul(d / divisor_ad);
+        match (numerator, denominator) {
+            (Some(numerator), Some(denominator)) => Rational::new(numerator, denominator),
+            _ => Rational::NAN,
+        }
+    }
+}
+
+impl ops::Div for Rational {
+    type Output = Rational;
+    fn div(self, other: Rational) -> Rational {
+        ops::Mul::mul(self, other.recip())
+    }
+}
+
+/// Integers are written as integers, other values as `n/d`.
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.parts() {
+            None => write!(f, "NaN"),
+            Some((a, 1)) => write!(f, "{}", a),
+            Some((a, b)) => write!(f, "{}/{}", a, b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RationalExpression {
+    RationalVariable(super::Symbol),
+    RationalValue(Rational),
+    Parenthesis(Box<RationalExpression>),
+    Negate(Box<RationalExpression>),
+    /// The absolute value.
+    Abs(Box<RationalExpression>),
+    Add(Box<RationalExpression>, Box<RationalExpression>),
+    Minus(Box<RationalExpression>, Box<RationalExpression>),
+    Times(Box<RationalExpression>, Box<RationalExpression>),
+    Divide(Box<RationalExpression>, Box<RationalExpression>),
+    /// The value of an integer expression, `NaN` when it is `NaN`.
+    OfInteger(Box<IntegerNumberExpression>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RationalDomainExpression {
+    Universe,
+    Empty,
+    ClosedRange(Box<RationalExpression>, Box<RationalExpression>),
+    OpenRange(Box<RationalExpression>, Box<RationalExpression>),
+    OpenLeftClosedRightRange(Box<RationalExpression>, Box<RationalExpression>),
+    ClosedLeftOpenRightRange(Box<RationalExpression>, Box<RationalExpression>),
+    ExplicitSet(Vec<RationalExpression>),
+    Union(Box<RationalDomainExpression>, Box<RationalDomainExpression>),
+    Intersection(Box<RationalDomainExpression>, Box<RationalDomainExpression>),
+    Difference(Box<RationalDomainExpression>, Box<RationalDomainExpression>),
+    Complement(Box<RationalDomainExpression>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BooleanRationalExpression {
+    Equals(Box<RationalExpression>, Box<RationalExpression>),
+    Different(Box<RationalExpression>, Box<RationalExpression>),
+    Greater(Box<RationalExpression>, Box<RationalExpression>),
+    Less(Box<RationalExpression>, Box<RationalExpression>),
+    In(Box<RationalExpression>, Box<RationalDomainExpression>),
+}
+
+impl RationalExpression {
+    /// Binding strength as for integer expressions. A literal written
+    /// `n/d` binds like a division, so that it is parenthesised where a
+    /// division would be.
+    fn precedence(&self) -> u8 {
+        use RationalExpression::*;
+        match self {
+            Add(_, _) | Minus(_, _) => 1,
+            Times(_, _) | Divide(_, _) => 2,
+            RationalValue(value) if value.denominator().is_some_and(|d| d != 1) => 2,
+            Negate(_) => 3,
+            RationalVariable(_) | RationalValue(_) | Parenthesis(_) | Abs(_) | OfInteger(_) => 5,
+        }
+    }
+
+    fn fmt_at(&self, f: &mut fmt::Formatter, min: u8) -> fmt::Result {
+        if self.precedence() < min {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+
+    /// The value under `env`, without cloning any part of the expression.
+    pub fn evaluate_ref(&self, env: &super::Environment) -> Result<Rational, super::EvalError> {
+        use RationalExpression::*;
+        match self {
+            RationalVariable(symbol) => env
+                .rational(symbol)
+                .cloned()
+                .ok_or_else(|| super::EvalError::Unbound(symbol.clone())),
+            RationalValue(value) => Ok(value.clone()),
+            Parenthesis(expr) => expr.evaluate_ref(env),
+            Negate(expr) => Ok(-expr.evaluate_ref(env)?),
+            Abs(expr) => Ok(expr.evaluate_ref(env)?.abs()),
+            Add(expr_a, expr_b) => Ok(expr_a.evaluate_ref(env)? + expr_b.evaluate_ref(env)?),
+            Minus(expr_a, expr_b) => Ok(expr_a.evaluate_ref(env)? - expr_b.evaluate_ref(env)?),
+            Times(expr_a, expr_b) => Ok(expr_a.evaluate_ref(env)? * expr_b.evaluate_ref(env)?),
+            Divide(expr_a, expr_b) => Ok(expr_a.evaluate_ref(env)? / expr_b.evaluate_ref(env)?),
+            OfInteger(expr) => match expr.evaluate_ref(env)? {
+                IntegerNumber::Value(n) => Ok(Rational::integer(n)),
+                IntegerNumber::NaN => Ok(Rational::NAN),
+            },
+        }
+    }
+}
+
+/// Infix syntax with parentheses only where precedence requires them, as
+/// for integer expressions. Integer expressions used as rationals are
+/// written `rational(...)`.
+impl fmt::Display for RationalExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RationalExpression::*;
+        let binary =
+            |f: &mut fmt::Formatter, a: &RationalExpression, op, b: &RationalExpression| {
+                let precedence = self.precedence();
+                a.fmt_at(f, precedence)?;
+                write!(f, " {} ", op)?;
+                b.fmt_at(f, precedence + 1)
+            };
+        match self {
+            RationalVariable(symbol) => write!(f, "{}", symbol),
+            RationalValue(value) => write!(f, "{}", value),
+            Parenthesis(expr) => write!(f, "({})", expr),
+            Negate(expr) => {
+                let operand = if expr.precedence() < self.precedence() {
+                    format!("({})", expr)
+                } else {
+                    expr.to_string()
+                };
+                if operand.starts_with(|c: char| c.is_ascii_digit()) {
+                    write!(f, "- {}", operand)
+                } else {
+                    write!(f, "-{}", operand)
+                }
+            }
+            Abs(expr) => write!(f, "abs({})", expr),
+            Add(expr_a, expr_b) => binary(f, expr_a, "+", expr_b),
+            Minus(expr_a, expr_b) => binary(f, expr_a, "-", expr_b),
+            Times(expr_a, expr_b) => binary(f, expr_a, "*", expr_b),
+            Divide(expr_a, expr_b) => binary(f, expr_a, "/", expr_b),
+            OfInteger(expr) => write!(f, "rational({})", expr),
+        }
+    }
+}
+
+/// Domains are written as integer domains are.
+impl fmt::Display for RationalDomainExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RationalDomainExpression::*;
+        match self {
+            Universe => write!(f, "universe"),
+            Empty => write!(f, "empty"),
+            ClosedRange(expr_a, expr_b) => write!(f, "[{}, {}]", expr_a, expr_b),
+            OpenRange(expr_a, expr_b) => write!(f, "({}, {})", expr_a, expr_b),
+            OpenLeftClosedRightRange(expr_a, expr_b) => write!(f, "({}, {}]", expr_a, expr_b),
+            ClosedLeftOpenRightRange(expr_a, expr_b) => write!(f, "[{}, {})", expr_a, expr_b),
+            ExplicitSet(elements) => {
+                write!(f, "{{")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "}}")
+            }
+            Union(expr_a, expr_b) => write!(f, "union({}, {})", expr_a, expr_b),
+            Intersection(expr_a, expr_b) => write!(f, "intersection({}, {})", expr_a, expr_b),
+            Difference(expr_a, expr_b) => write!(f, "difference({}, {})", expr_a, expr_b),
+            Complement(expr) => write!(f, "complement({})", expr),
+        }
+    }
+}
+
+impl fmt::Display for BooleanRationalExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use BooleanRationalExpression::*;
+        match self {
+            Equals(expr_a, expr_b) => write!(f, "{} = {}", expr_a, expr_b),
+            Different(expr_a, expr_b) => write!(f, "{} != {}", expr_a, expr_b),
+            Greater(expr_a, expr_b) => write!(f, "{} > {}", expr_a, expr_b),
+            Less(expr_a, expr_b) => write!(f, "{} < {}", expr_a, expr_b),
+            In(expr_a, expr_b) => write!(f, "{} in {}", expr_a, expr_b),
+        }
+    }
+}
+
+impl super::FreeVariable for RationalExpression {
+    fn get_free(&self) -> Vec<super::Variable> {
+        FreeVariables::of(|free| free.visit_rational(self))
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        use RationalExpression::*;
+        match self {
+            RationalValue(_) => ControlFlow::Continue(()),
+            RationalVariable(symbol) => visit(symbol),
+            Parenthesis(expr) | Negate(expr) | Abs(expr) => expr.each_free(visit),
+            Add(expr_a, expr_b)
+            | Minus(expr_a, expr_b)
+            | Times(expr_a, expr_b)
+            | Divide(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
+            }
+            OfInteger(expr) => expr.each_free(visit),
+        }
+    }
+}
+
+impl super::FreeVariable for RationalDomainExpression {
+    fn get_free(&self) -> Vec<super::Variable> {
+        FreeVariables::of(|free| free.visit_rational_domain(self))
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        use RationalDomainExpression::*;
+        match self {
+            Universe | Empty => ControlFlow::Continue(()),
+            ClosedRange(expr_a, expr_b)
+            | OpenRange(expr_a, expr_b)
+            | OpenLeftClosedRightRange(expr_a, expr_b)
+            | ClosedLeftOpenRightRange(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
+            }
+            ExplicitSet(elements) => elements
+                .iter()
+                .try_for_each(|element| element.each_free(visit)),
+            Union(expr_a, expr_b) | Intersection(expr_a, expr_b) | Difference(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
+            }
+            Complement(expr) => expr.each_free(visit),
+        }
+    }
+}
+
+impl super::FreeVariable for BooleanRationalExpression {
+    fn get_free(&self) -> Vec<super::Variable> {
+        FreeVariables::of(|free| free.visit_rational_comparison(self))
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        use BooleanRationalExpression::*;
+        match self {
+            Equals(expr_a, expr_b)
+            | Different(expr_a, expr_b)
+            | Greater(expr_a, expr_b)
+            | Less(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
+            }
+            In(expr_a, expr_b) => {
+                expr_a.each_free(visit)?;
+                expr_b.each_free(visit)
+            }
+        }
+    }
+}
+
+impl super::Evaluate for RationalExpression {
+    type Value = Rational;
+    fn evaluate_with(
+        &self,
+        env: &[super::Assignment],
+        division: super::integer::DivisionMode,
+    ) -> Result<Rational, super::EvalError> {
+        self.evaluate_ref(&super::Environment::new(env).with_division(division))
+    }
+}
+
+impl RationalDomainExpression {
+    /// Whether `q` is a member of the domain, with range bounds and set
+    /// elements evaluated under `env`. `NaN` is a member of no domain but
+    /// the complement of one, and ranges with a `NaN` bound are empty.
+    pub fn includes_ref(
+        &self,
+        q: &Rational,
+        env: &super::Environment,
+    ) -> Result<bool, super::EvalError> {
+        use RationalDomainExpression::*;
+        let within = |expr_a: &RationalExpression,
+                      expr_b: &RationalExpression,
+                      low: fn(Ordering) -> bool,
+                      high: fn(Ordering) -> bool| {
+            let (a, b) = (expr_a.evaluate_ref(env)?, expr_b.evaluate_ref(env)?);
+            Ok(match (a.compare(q), q.compare(&b)) {
+                (Some(below), Some(above)) => low(below) && high(above),
+                _ => false,
+            })
+        };
+        match self {
+            Universe => Ok(!q.is_nan()),
+            Empty => Ok(false),
+            ClosedRange(expr_a, expr_b) => within(expr_a, expr_b, Ordering::is_le, Ordering::is_le),
+            OpenRange(expr_a, expr_b) => within(expr_a, expr_b, Ordering::is_lt, Ordering::is_lt),
+            OpenLeftClosedRightRange(expr_a, expr_b) => {
+                within(expr_a, expr_b, Ordering::is_lt, Ordering::is_le)
+            }
+            ClosedLeftOpenRightRange(expr_a, expr_b) => {
+                within(expr_a, expr_b, Ordering::is_le, Ordering::is_lt)
+            }
+            ExplicitSet(elements) => {
+                for element in elements {
+                    if element.evaluate_ref(env)?.compare(q) == Some(Ordering::Equal) {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Union(expr_a, expr_b) => {
+                Ok(expr_a.includes_ref(q, env)? || expr_b.includes_ref(q, env)?)
+            }
+            Intersection(expr_a, expr_b) => {
+                Ok(expr_a.includes_ref(q, env)? && expr_b.includes_ref(q, env)?)
+            }
+            Difference(expr_a, expr_b) => {
+                Ok(expr_a.includes_ref(q, env)? && !expr_b.includes_ref(q, env)?)
+            }
+            Complement(expr) => Ok(!expr.includes_ref(q, env)?),
+        }
+    }
+
+    /// Like `includes_ref`, under the assignments `env`.
+    pub fn includes(
+        &self,
+        q: &Rational,
+        env: &[super::Assignment],
+    ) -> Result<bool, super::EvalError> {
+        self.includes_ref(q, &super::Environment::new(env))
+    }
+
+    /// The bounds of a ground range, `None` for anything else or for a
+    /// range with a `NaN` bound.
+    fn bounds(&self) -> Option<(Rational, Rational, Ordering)> {
+        use super::Evaluate;
+        use RationalDomainExpression::*;
+        let (expr_a, expr_b) = match self {
+            ClosedRange(expr_a, expr_b)
+            | OpenRange(expr_a, expr_b)
+            | OpenLeftClosedRightRange(expr_a, expr_b)
+            | ClosedLeftOpenRightRange(expr_a, expr_b) => (expr_a, expr_b),
+            _ => return None,
+        };
+        let (a, b) = (expr_a.evaluate(&[]).ok()?, expr_b.evaluate(&[]).ok()?);
+        let order = a.compare(&b)?;
+        Some((a, b, order))
+    }
+
+    /// The members of a ground domain in increasing order, when there are
+    /// finitely many: sets, empty ranges and closed ranges of one point,
+    /// and the set operations that keep them finite. `None` for other
+    /// domains, including any with free variables.
+    pub(crate) fn finite_values(&self) -> Option<Vec<Rational>> {
+        use super::{Evaluate, FreeVariable};
+        use RationalDomainExpression::*;
+        let mut values = match self {
+            Universe | Complement(_) => return None,
+            Empty => Vec::new(),
+            ExplicitSet(elements) => elements
+                .iter()
+                .map(|element| element.evaluate(&[]).ok())
+                .collect::<Option<Vec<Rational>>>()?
+                .into_iter()
+                .filter(|value| !value.is_nan())
+                .collect(),
+            ClosedRange(_, _)
+            | OpenRange(_, _)
+            | OpenLeftClosedRightRange(_, _)
+            | ClosedLeftOpenRightRange(_, _) => match self.bounds() {
+                Some((a, _, Ordering::Equal)) if matches!(self, ClosedRange(_, _)) => vec![a],
+                Some((_, _, Ordering::Less)) => return None,
+                Some(_) => Vec::new(),
+                None if self.has_free() => return None,
+                None => Vec::new(),
+            },
+            Union(expr_a, expr_b) => {
+                let mut values = expr_a.finite_values()?;
+                values.extend(expr_b.finite_values()?);
+                values
+            }
+            Intersection(expr_a, expr_b) => {
+                match (expr_a.finite_values(), expr_b.finite_values()) {
+                    (Some(values), _) => values
+                        .into_iter()
+                        .filter(|value| expr_b.includes(value, &[]) == Ok(true))
+                        .collect(),
+                    (None, Some(values)) => values
+                        .into_iter()
+                        .filter(|value| expr_a.includes(value, &[]) == Ok(true))
+                        .collect(),
+                    (None, None) => return None,
+                }
+            }
+            Difference(expr_a, expr_b) => expr_a
+                .finite_values()?
+                .into_iter()
+                .filter(|value| expr_b.includes(value, &[]) == Ok(false))
+                .collect(),
+        };
+        values.sort_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal));
+        values.dedup();
+        Some(values)
+    }
+}
+
+impl super::Apply for RationalExpression {
+    fn apply_noting(&self, state: &[super::Assignment], substituted: &mut bool) -> Self {
+        use RationalExpression::*;
+        let applied = |expr: &RationalExpression, substituted: &mut bool| {
+            Box::new(expr.apply_noting(state, substituted))
+        };
+        match self {
+            RationalVariable(symbol) => match super::rational_binding(state, symbol) {
+                Some(value) => {
+                    *substituted = true;
+                    RationalValue(value.clone())
+                }
+                None => RationalVariable(symbol.clone()),
+            },
+            RationalValue(value) => RationalValue(value.clone()),
+            Parenthesis(expr) => Parenthesis(applied(expr, substituted)),
+            Negate(expr) => Negate(applied(expr, substituted)),
+            Abs(expr) => Abs(applied(expr, substituted)),
+            Add(expr_a, expr_b) => Add(applied(expr_a, substituted), applied(expr_b, substituted)),
+            Minus(expr_a, expr_b) => {
+                Minus(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            Times(expr_a, expr_b) => {
+                Times(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            Divide(expr_a, expr_b) => {
+                Divide(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            OfInteger(expr) => OfInteger(Box::new(expr.apply_noting(state, substituted))),
+        }
+    }
+}
+
+impl super::Apply for RationalDomainExpression {
+    fn apply_noting(&self, state: &[super::Assignment], substituted: &mut bool) -> Self {
+        use RationalDomainExpression::*;
+        let applied = |expr: &RationalExpression, substituted: &mut bool| {
+            Box::new(expr.apply_noting(state, substituted))
+        };
+        let applied_domain = |expr: &RationalDomainExpression, substituted: &mut bool| {
+            Box::new(expr.apply_noting(state, substituted))
+        };
+        match self {
+            Universe => Universe,
+            Empty => Empty,
+            ClosedRange(expr_a, expr_b) => {
+                ClosedRange(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            OpenRange(expr_a, expr_b) => {
+                OpenRange(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            OpenLeftClosedRightRange(expr_a, expr_b) => {
+                OpenLeftClosedRightRange(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            ClosedLeftOpenRightRange(expr_a, expr_b) => {
+                ClosedLeftOpenRightRange(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            ExplicitSet(elements) => ExplicitSet(
+                elements
+                    .iter()
+                    .map(|element| element.apply_noting(state, substituted))
+                    .collect(),
+            ),
+            Union(expr_a, expr_b) => Union(
+                applied_domain(expr_a, substituted),
+                applied_domain(expr_b, substituted),
+            ),
+            Intersection(expr_a, expr_b) => Intersection(
+                applied_domain(expr_a, substituted),
+                applied_domain(expr_b, substituted),
+            ),
+            Difference(expr_a, expr_b) => Difference(
+                applied_domain(expr_a, substituted),
+                applied_domain(expr_b, substituted),
+            ),
+            Complement(expr) => Complement(applied_domain(expr, substituted)),
+        }
+    }
+}
+
+impl super::Apply for BooleanRationalExpression {
+    fn apply_noting(&self, state: &[super::Assignment], substituted: &mut bool) -> Self {
+        use BooleanRationalExpression::*;
+        let applied = |expr: &RationalExpression, substituted: &mut bool| {
+            Box::new(expr.apply_noting(state, substituted))
+        };
+        match self {
+            Equals(expr_a, expr_b) => {
+                Equals(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            Different(expr_a, expr_b) => {
+                Different(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            Greater(expr_a, expr_b) => {
+                Greater(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            Less(expr_a, expr_b) => {
+                Less(applied(expr_a, substituted), applied(expr_b, substituted))
+            }
+            In(expr_a, expr_b) => In(
+                applied(expr_a, substituted),
+                Box::new(expr_b.apply_noting(state, substituted)),
+            ),
+        }
+    }
+}
+
+/// Rational variables are never replaced; integer and boolean ones are
+/// replaced inside `OfInteger`.
+impl super::Substitute for RationalExpression {
+    fn substitute_with(&self, substitution: &super::Substitution) -> Self {
+        use RationalExpression::*;
+        let substituted = |expr: &RationalExpression| Box::new(expr.substitute_with(substitution));
+        match self {
+            RationalVariable(_) | RationalValue(_) => self.clone(),
+            Parenthesis(expr) => Parenthesis(substituted(expr)),
+            Negate(expr) => Negate(substituted(expr)),
+            Abs(expr) => Abs(substituted(expr)),
+            Add(expr_a, expr_b) => Add(substituted(expr_a), substituted(expr_b)),
+            Minus(expr_a, expr_b) => Minus(substituted(expr_a), substituted(expr_b)),
+            Times(expr_a, expr_b) => Times(substituted(expr_a), substituted(expr_b)),
+            Divide(expr_a, expr_b) => Divide(substituted(expr_a), substituted(expr_b)),
+            OfInteger(expr) => OfInteger(Box::new(expr.substitute_with(substitution))),
+        }
+    }
+}
+
+impl super::Substitute for RationalDomainExpression {
+    fn substitute_with(&self, substitution: &super::Substitution) -> Self {
+        use RationalDomainExpression::*;
+        let substituted = |expr: &RationalExpression| Box::new(expr.substitute_with(substitution));
+        let substituted_domain =
+            |expr: &RationalDomainExpression| Box::new(expr.substitute_with(substitution));
+        match self {
+            Universe => Universe,
+            Empty => Empty,
+            ClosedRange(expr_a, expr_b) => ClosedRange(substituted(expr_a), substituted(expr_b)),
+            OpenRange(expr_a, expr_b) => OpenRange(substituted(expr_a), substituted(expr_b)),
+            OpenLeftClosedRightRange(expr_a, expr_b) => {
+                OpenLeftClosedRightRange(substituted(expr_a), substituted(expr_b))
+            }
+            ClosedLeftOpenRightRange(expr_a, expr_b) => {
+                ClosedLeftOpenRightRange(substituted(expr_a), substituted(expr_b))
+            }
+            ExplicitSet(elements) => ExplicitSet(
+                elements
+                    .iter()
+                    .map(|element| element.substitute_with(substitution))
+                    .collect(),
+            ),
+            Union(expr_a, expr_b) => Union(substituted_domain(expr_a), substituted_domain(expr_b)),
+            Intersection(expr_a, expr_b) => {
+                Intersection(substituted_domain(expr_a), substituted_domain(expr_b))
+            }
+            Difference(expr_a, expr_b) => {
+                Difference(substituted_domain(expr_a), substituted_domain(expr_b))
+            }
+            Complement(expr) => Complement(substituted_domain(expr)),
+        }
+    }
+}
+
+impl super::Substitute for BooleanRationalExpression {
+    fn substitute_with(&self, substitution: &super::Substitution) -> Self {
+        use BooleanRationalExpression::*;
+        let substituted = |expr: &RationalExpression| Box::new(expr.substitute_with(substitution));
+        match self {
+            Equals(expr_a, expr_b) => Equals(substituted(expr_a), substituted(expr_b)),
+            Different(expr_a, expr_b) => Different(substituted(expr_a), substituted(expr_b)),
+            Greater(expr_a, expr_b) => Greater(substituted(expr_a), substituted(expr_b)),
+            Less(expr_a, expr_b) => Less(substituted(expr_a), substituted(expr_b)),
+            In(expr_a, expr_b) => In(
+                substituted(expr_a),
+                Box::new(expr_b.substitute_with(substitution)),
+            ),
+        }
+    }
+}
+
+impl BooleanRationalExpression {
+    /// The value under `env`, without cloning any part of the expression.
+    /// Comparisons with `NaN` are false, except `!=`.
+    pub fn evaluate_ref(
+        &self,
+        env: &super::Environment,
+    ) -> Result<super::boolean::BooleanValue, super::EvalError> {
+        use super::boolean::BooleanValue;
+        use BooleanRationalExpression::*;
+        let compare = |expr_a: &RationalExpression,
+                       expr_b: &RationalExpression,
+                       test: fn(Ordering) -> bool| {
+            let (a, b) = (expr_a.evaluate_ref(env)?, expr_b.evaluate_ref(env)?);
+            Ok(BooleanValue::from_bool(a.compare(&b).is_some_and(test)))
+        };
+        match self {
+            Equals(expr_a, expr_b) => compare(expr_a, expr_b, Ordering::is_eq),
+            Different(expr_a, expr_b) => {
+                super::boolean::not(compare(expr_a, expr_b, Ordering::is_eq))
+            }
+            Greater(expr_a, expr_b) => compare(expr_a, expr_b, Ordering::is_gt),
+            Less(expr_a, expr_b) => compare(expr_a, expr_b, Ordering::is_lt),
+            In(expr_a, expr_b) => match expr_a.evaluate_ref(env)? {
+                q if q.is_nan() => Ok(BooleanValue::False),
+                q => expr_b.includes_ref(&q, env).map(BooleanValue::from_bool),
+            },
+        }
+    }
+}
+
+impl super::Evaluate for BooleanRationalExpression {
+    type Value = super::boolean::BooleanValue;
+    fn evaluate_with(
+        &self,
+        env: &[super::Assignment],
+        division: super::integer::DivisionMode,
+    ) -> Result<super::boolean::BooleanValue, super::EvalError> {
+        self.evaluate_ref(&super::Environment::new(env).with_division(division))
+    }
+}
+
+/// `+` and `*` chains are flattened and their operands sorted;
+/// `Parenthesis` nodes are dropped.
+impl super::Canonicalize for RationalExpression {
+    fn canonicalize(self) -> Self {
+        use super::{chain_operands, sorted_chain};
+        use RationalExpression::*;
+        let join_add = |a, b| Add(Box::new(a), Box::new(b));
+        let join_times = |a, b| Times(Box::new(a), Box::new(b));
+        let canonical = |expr: Box<RationalExpression>| Box::new(expr.canonicalize());
+        match self {
+            Add(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_add(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Add(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_add,
+            ),
+            Times(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_times(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Times(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_times,
+            ),
+            Minus(expr_a, expr_b) => Minus(canonical(expr_a), canonical(expr_b)),
+            Divide(expr_a, expr_b) => Divide(canonical(expr_a), canonical(expr_b)),
+            Negate(expr) => Negate(canonical(expr)),
+            Abs(expr) => Abs(canonical(expr)),
+            Parenthesis(expr) => expr.canonicalize(),
+            OfInteger(expr) => OfInteger(Box::new(expr.canonicalize())),
+            leaf => leaf,
+        }
+    }
+}
+
+/// `union` and `intersection` chains are flattened and their operands
+/// sorted, and explicit sets are sorted without duplicates.
+impl super::Canonicalize for RationalDomainExpression {
+    fn canonicalize(self) -> Self {
+        use super::{chain_operands, sorted_chain};
+        use RationalDomainExpression::*;
+        let join_union = |a, b| Union(Box::new(a), Box::new(b));
+        let join_intersection = |a, b| Intersection(Box::new(a), Box::new(b));
+        let canonical = |expr: Box<RationalExpression>| Box::new(expr.canonicalize());
+        match self {
+            Union(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_union(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Union(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_union,
+            ),
+            Intersection(expr_a, expr_b) => sorted_chain(
+                chain_operands(
+                    join_intersection(expr_a.canonicalize(), expr_b.canonicalize()),
+                    |expr| match expr {
+                        Intersection(expr_a, expr_b) => Ok((*expr_a, *expr_b)),
+                        expr => Err(expr),
+                    },
+                ),
+                join_intersection,
+            ),
+            Difference(expr_a, expr_b) => Difference(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            Complement(expr) => Complement(Box::new(expr.canonicalize())),
+            ExplicitSet(elements) => {
+                let mut elements: Vec<RationalExpression> = elements
+                    .into_iter()
+                    .map(|element| element.canonicalize())
+                    .collect();
+                elements.sort();
+                elements.dedup();
+                ExplicitSet(elements)
+            }
+            ClosedRange(expr_a, expr_b) => ClosedRange(canonical(expr_a), canonical(expr_b)),
+            OpenRange(expr_a, expr_b) => OpenRange(canonical(expr_a), canonical(expr_b)),
+            OpenLeftClosedRightRange(expr_a, expr_b) => {
+                OpenLeftClosedRightRange(canonical(expr_a), canonical(expr_b))
+            }
+            ClosedLeftOpenRightRange(expr_a, expr_b) => {
+                ClosedLeftOpenRightRange(canonical(expr_a), canonical(expr_b))
+            }
+            leaf => leaf,
+        }
+    }
+}
+
+/// `=` and `!=` have their operands sorted and `a > b` becomes `b < a`.
+impl super::Canonicalize for BooleanRationalExpression {
+    fn canonicalize(self) -> Self {
+        use BooleanRationalExpression::*;
+        let sorted = |expr_a: Box<RationalExpression>, expr_b: Box<RationalExpression>| {
+            let (expr_a, expr_b) = (expr_a.canonicalize(), expr_b.canonicalize());
+            if expr_b < expr_a {
+                (Box::new(expr_b), Box::new(expr_a))
+            } else {
+                (Box::new(expr_a), Box::new(expr_b))
+            }
+        };
+        match self {
+            Equals(expr_a, expr_b) => {
+                let (expr_a, expr_b) = sorted(expr_a, expr_b);
+                Equals(expr_a, expr_b)
+            }
+            Different(expr_a, expr_b) => {
+                let (expr_a, expr_b) = sorted(expr_a, expr_b);
+                Different(expr_a, expr_b)
+            }
+            Greater(expr_a, expr_b) => Less(
+                Box::new(expr_b.canonicalize()),
+                Box::new(expr_a.canonicalize()),
+            ),
+            Less(expr_a, expr_b) => Less(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+            In(expr_a, expr_b) => In(
+                Box::new(expr_a.canonicalize()),
+                Box::new(expr_b.canonicalize()),
+            ),
+        }
+    }
+}
+
+/// A finite domain samples its smallest member, as integer domains do.
+/// Otherwise a range samples its lower bound when it is closed on the
+/// left, its upper bound when only that is closed and the midpoint when
+/// both ends are open, and a union samples either side. Other domains
+/// with infinitely many members are not sampled.
+impl super::Sample for RationalDomainExpression {
+    fn try_sample(&self) -> Result<super::AssignedValue, super::SampleError> {
+        use super::FreeVariable;
+        use RationalDomainExpression::*;
+        let found = |value: Rational| Ok(super::AssignedValue::Rational(value));
+        match self {
+            Empty => Err(super::SampleError::EmptyDomain),
+            Universe => Err(super::SampleError::Unbounded),
+            _ if self.has_free() => Err(super::SampleError::RequiresEnvironment),
+            _ => match (self.finite_values(), self.bounds(), self) {
+                (Some(values), _, _) => values
+                    .into_iter()
+                    .next()
+                    .map_or(Err(super::SampleError::EmptyDomain), found),
+                (None, Some((low, _, _)), ClosedRange(_, _) | ClosedLeftOpenRightRange(_, _)) => {
+                    found(low)
+                }
+                (None, Some((_, high, _)), OpenLeftClosedRightRange(_, _)) => found(high),
+                (None, Some((low, high, _)), _) => found((low + high) / Rational::integer(2)),
+                (None, None, Union(expr_a, expr_b)) => {
+                    expr_a.try_sample().or_else(|_| expr_b.try_sample())
+                }
+                (None, None, _) => Err(super::SampleError::Unbounded),
+            },
+        }
+    }
+}
+
+/// Only ground domains have candidates or contain values, and only
+/// finite ones can be enumerated or counted.
+impl super::ValueDomain for RationalDomainExpression {
+    fn candidates(&self) -> Option<Vec<super::AssignedValue>> {
+        Some(
+            self.finite_values()?
+                .into_iter()
+                .map(super::AssignedValue::Rational)
+                .collect(),
+        )
+    }
+    fn contains(&self, value: &super::AssignedValue) -> bool {
+        match value {
+            super::AssignedValue::Rational(q) => self.includes(q, &[]) == Ok(true),
+            _ => false,
+        }
+    }
+    fn cardinality(&self) -> Option<u128> {
+        self.finite_values().map(|values| values.len() as u128)
+    }
+    fn clone_box(&self) -> Box<dyn super::ValueDomain> {
+        Box::new(self.clone())
+    }
+    fn identity(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BooleanRationalExpression, Rational, RationalDomainExpression, RationalExpression,
+    };
+    use crate::expressions::{
+        AssignedValue, Assignment, ConstraintLogicExpression, ConstraintProgramExpression,
+        Evaluate, SatisfactionExpression, Symbol,
+    };
+    use std::cmp::Ordering;
+
+    fn value(numerator: i128, denominator: i128) -> Box<RationalExpression> {
+        Box::new(RationalExpression::RationalValue(Rational::new(
+            numerator,
+            denominator,
+        )))
+    }
+
+    fn variable(name: &str) -> Box<RationalExpression> {
+        Box::new(RationalExpression::RationalVariable(Symbol::new(
+            name.to_string(),
+        )))
+    }
+
+    #[test]
+    fn arithmetic_stays_in_lowest_terms() {
+        assert_eq!(Rational::new(6, -4).numerator(), Some(-3));
+        assert_eq!(Rational::new(6, -4).denominator(), Some(2));
+        assert_eq!(Rational::new(0, -7), Rational::integer(0));
+        assert_eq!(Rational::new(2, 4), Rational::new(1, 2));
+        assert_eq!(Rational::new(1, 0), Rational::NAN);
+        assert_eq!(Rational::new(1, 0).compare(&Rational::integer(1)), None);
+        assert_eq!(
+            Rational::new(1, 2) + Rational::new(1, 3),
+            Rational::new(5, 6)
+        );
+        assert_eq!(
+            Rational::new(1, 2) - Rational::new(1, 2),
+            Rational::integer(0)
+        );
+        assert_eq!(
+            Rational::new(2, 3) * Rational::new(3, 4),
+            Rational::new(1, 2)
+        );
+        assert_eq!(
+            Rational::new(2, 3) / Rational::new(4, 9),
+            Rational::new(3, 2)
+        );
+        assert_eq!(Rational::new(2, 3) / Rational::integer(0), Rational::NAN);
+        assert_eq!(
+            Rational::integer(i128::MAX) + Rational::integer(1),
+            Rational::NAN
+        );
+        assert_eq!(-Rational::integer(i128::MIN), Rational::NAN);
+        assert_eq!(Rational::new(i128::MIN, i128::MIN), Rational::integer(1));
+        assert_eq!(
+            Rational::new(i128::MAX, i128::MAX - 1)
+                .compare(&Rational::new(i128::MAX - 1, i128::MAX - 2)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Rational::new(-1, 3).compare(&Rational::new(-1, 2)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(Rational::NAN.compare(&Rational::NAN), None);
+        let sum = RationalExpression::Divide(
+            Box::new(RationalExpression::Add(variable("q"), value(1, 4))),
+            value(-1, 2),
+        );
+        let q = Assignment::new(
+            Symbol::new("q".to_string()),
+            AssignedValue::Rational(Rational::new(1, 4)),
+        );
+        assert_eq!(sum.to_string(), "(q + 1/4) / (-1/2)");
+        assert_eq!(sum.evaluate(&[q]), Ok(Rational::integer(-1)));
+    }
+
+    #[test]
+    fn rational_variables_are_solved_over_finite_domains() {
+        let member = BooleanRationalExpression::In(
+            variable("q"),
+            Box::new(RationalDomainExpression::ExplicitSet(vec![
+                *value(5, 4),
+                *value(1, 2),
+                *value(3, 4),
+            ])),
+        );
+        let above = BooleanRationalExpression::Greater(variable("q"), value(2, 3));
+        let program = ConstraintProgramExpression::ConstrainAnd(
+            Box::new(ConstraintLogicExpression::OfRational(Box::new(member))),
+            Box::new(ConstraintProgramExpression::Solve(Box::new(
+                SatisfactionExpression::Satisfy(Box::new(ConstraintLogicExpression::OfRational(
+                    Box::new(above),
+                ))),
+            ))),
+        );
+        let solutions: Vec<String> = crate::solver::solve_all(&program)
+            .unwrap()
+            .iter()
+            .flatten()
+            .map(|solution| solution.to_string())
+            .collect();
+        assert_eq!(solutions, vec!["q = 3/4", "q = 5/4"]);
+        let open = RationalDomainExpression::OpenRange(value(1, 3), value(1, 2));
+        assert_eq!(open.includes(&Rational::new(2, 5), &[]), Ok(true));
+        assert_eq!(open.includes(&Rational::new(1, 2), &[]), Ok(false));
+        use crate::expressions::Sample;
+        assert_eq!(
+            open.sample(),
+            Some(AssignedValue::Rational(Rational::new(5, 12)))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fractions_not_in_lowest_terms_are_rejected() {
+        let half = serde_json::to_string(&Rational::new(-1, 2)).unwrap();
+        assert_eq!(half, r#"{"Value":["-1","2"]}"#);
+        assert_eq!(
+            serde_json::from_str::<Rational>(&half).unwrap(),
+            Rational::new(-1, 2)
+        );
+        for json in [
+            r#"{"Value":["2","4"]}"#,
+            r#"{"Value":["1","-2"]}"#,
+            r#"{"Value":["1","0"]}"#,
+        ] {
+            assert!(serde_json::from_str::<Rational>(json).is_err());
+        }
+    }
+}
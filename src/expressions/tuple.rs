@@ -0,0 +1,392 @@
+//! Tuples: values made of a fixed number of components, each of which
+//! may be of a different type. A component is taken out of a tuple with
+//! `IntegerNumberExpression::TupleGet`.
+use std::fmt;
+use std::ops::ControlFlow;
+
+use super::boolean::{BooleanExpression, BooleanValueDomainExpression};
+use super::integer::{
+    DivisionMode, IntegerNumber, IntegerNumberDomainExpression, IntegerNumberExpression,
+};
+use super::rational::{RationalDomainExpression, RationalExpression};
+use super::visit::{FreeVariables, Visitor};
+use super::{AssignedValue, Domain};
+
+/// The type of a tuple component.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ComponentType {
+    Boolean,
+    Integer,
+    Rational,
+    Tuple(Vec<ComponentType>),
+}
+
+impl ComponentType {
+    /// The domain of all values of the type.
+    pub fn universe(&self) -> Domain {
+        match self {
+            ComponentType::Boolean => Domain::Boolean(BooleanValueDomainExpression::Universe),
+            ComponentType::Integer => Domain::Integer(IntegerNumberDomainExpression::Universe),
+            ComponentType::Rational => Domain::Rational(RationalDomainExpression::Universe),
+            ComponentType::Tuple(types) => {
+                Domain::Tuple(types.iter().map(ComponentType::universe).collect())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TupleExpression {
+    /// A variable ranging over the tuples with components of the given
+    /// types.
+    TupleVariable(super::Symbol, Vec<ComponentType>),
+    Tuple(Vec<TupleComponent>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TupleComponent {
+    Boolean(BooleanExpression),
+    Integer(IntegerNumberExpression),
+    Rational(RationalExpression),
+    Tuple(TupleExpression),
+}
+
+impl TupleComponent {
+    /// The component with the value of `value`.
+    fn literal(value: &AssignedValue) -> TupleComponent {
+        match value {
+            AssignedValue::Boolean(value) => {
+                TupleComponent::Boolean(BooleanExpression::BooleanValue(value.clone()))
+            }
+            AssignedValue::Integer(value) => {
+                TupleComponent::Integer(IntegerNumberExpression::IntegerNumberValue(value.clone()))
+            }
+            AssignedValue::Rational(value) => {
+                TupleComponent::Rational(RationalExpression::RationalValue(value.clone()))
+            }
+            AssignedValue::Tuple(values) => TupleComponent::Tuple(TupleExpression::Tuple(
+                values.iter().map(TupleComponent::literal).collect(),
+            )),
+        }
+    }
+}
+
+/// Tuples are written in parentheses, with a trailing comma after a
+/// single component; tuple variables by their name. There is no syntax
+/// for them in the parser yet.
+impl fmt::Display for TupleExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TupleExpression::TupleVariable(symbol, _) => write!(f, "{}", symbol),
+            TupleExpression::Tuple(components) => {
+                write!(f, "(")?;
+                for (i, component) in components.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", component)?;
+                }
+                if components.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for TupleComponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TupleComponent::Boolean(expr) => write!(f, "{}", expr),
+            TupleComponent::Integer(expr) => write!(f, "{}", expr),
+            TupleComponent::Rational(expr) => write!(f, "{}", expr),
+            TupleComponent::Tuple(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+impl super::FreeVariable for TupleExpression {
+    fn get_free(&self) -> Vec<super::Variable> {
+        FreeVariables::of(|free| free.visit_tuple(self))
+    }
+    fn each_free(
+        &self,
+        visit: &mut dyn FnMut(&super::Symbol) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        match self {
+            TupleExpression::TupleVariable(symbol, _) => visit(symbol),
+            TupleExpression::Tuple(components) => {
+                components.iter().try_for_each(|component| match component {
+                    TupleComponent::Boolean(expr) => expr.each_free(visit),
+                    TupleComponent::Integer(expr) => expr.each_free(visit),
+                    TupleComponent::Rational(expr) => expr.each_free(visit),
+                    TupleComponent::Tuple(expr) => expr.each_free(visit),
+                })
+            }
+        }
+    }
+}
+
+impl TupleExpression {
+    /// The integer at `index` under `env`, or `None` if the index is out
+    /// of range or the component is not an integer. Every component is
+    /// evaluated, so an unbound variable anywhere in the tuple is an error
+    /// as it is for `evaluate`, but nothing is cloned or collected.
+    pub(crate) fn integer_component(
+        &self,
+        index: usize,
+        env: &super::Environment,
+    ) -> Result<Option<IntegerNumber>, super::EvalError> {
+        match self {
+            TupleExpression::TupleVariable(symbol, _) => match env.tuple(symbol) {
+                Some(values) => Ok(match values.get(index) {
+                    Some(AssignedValue::Integer(value)) => Some(value.clone()),
+                    _ => None,
+                }),
+                None => Err(super::EvalError::Unbound(symbol.clone())),
+            },
+            TupleExpression::Tuple(components) => {
+                let mut found = None;
+                for (i, component) in components.iter().enumerate() {
+                    match component {
+                        TupleComponent::Boolean(expr) => {
+                            expr.evaluate_ref(env)?;
+                        }
+                        TupleComponent::Integer(expr) => {
+                            let value = expr.evaluate_ref(env)?;
+                            if i == index {
+                                found = Some(value);
+                            }
+                        }
+                        TupleComponent::Rational(expr) => {
+                            expr.evaluate_ref(env)?;
+                        }
+                        TupleComponent::Tuple(expr) => {
+                            expr.integer_component(0, env)?;
+                        }
+                    }
+                }
+                Ok(found)
+            }
+        }
+    }
+}
+
+impl super::Evaluate for TupleExpression {
+    type Value = Vec<AssignedValue>;
+    fn evaluate_with(
+        &self,
+        env: &[super::Assignment],
+        division: DivisionMode,
+    ) -> Result<Vec<AssignedValue>, super::EvalError> {
+        match self {
+            TupleExpression::TupleVariable(symbol, _) => super::tuple_binding(env, symbol)
+                .cloned()
+                .ok_or_else(|| super::EvalError::Unbound(symbol.clone())),
+            TupleExpression::Tuple(components) => components
+                .iter()
+                .map(|component| match component {
+                    TupleComponent::Boolean(expr) => expr
+                        .evaluate_with(env, division)
+                        .map(AssignedValue::Boolean),
+                    TupleComponent::Integer(expr) => expr
+                        .evaluate_with(env, division)
+                        .map(AssignedValue::Integer),
+                    TupleComponent::Rational(expr) => expr
+                        .evaluate_with(env, division)
+                        .map(AssignedValue::Rational),
+                    TupleComponent::Tuple(expr) => {
+                        expr.evaluate_with(env, division).map(AssignedValue::Tuple)
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl super::Apply for TupleExpression {
+    fn apply_noting(&self, state: &[super::Assignment], substituted: &mut bool) -> Self {
+        match self {
+            TupleExpression::TupleVariable(symbol, _) => {
+                match super::tuple_binding(state, symbol) {
+                    Some(values) => {
+                        *substituted = true;
+                        TupleExpression::Tuple(values.iter().map(TupleComponent::literal).collect())
+                    }
+                    None => self.clone(),
+                }
+            }
+            TupleExpression::Tuple(components) => TupleExpression::Tuple(
+                components
+                    .iter()
+                    .map(|component| match component {
+                        TupleComponent::Boolean(expr) => {
+                            TupleComponent::Boolean(expr.apply_noting(state, substituted))
+                        }
+                        TupleComponent::Integer(expr) => {
+                            TupleComponent::Integer(expr.apply_noting(state, substituted))
+                        }
+                        TupleComponent::Rational(expr) => {
+                            TupleComponent::Rational(expr.apply_noting(state, substituted))
+                        }
+                        TupleComponent::Tuple(expr) => {
+                            TupleComponent::Tuple(expr.apply_noting(state, substituted))
+                        }
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Tuple variables are never replaced; the components of a tuple written
+/// in full are substituted into.
+impl super::Substitute for TupleExpression {
+    fn substitute_with(&self, substitution: &super::Substitution) -> Self {
+        match self {
+            TupleExpression::TupleVariable(_, _) => self.clone(),
+            TupleExpression::Tuple(components) => TupleExpression::Tuple(
+                components
+                    .iter()
+                    .map(|component| match component {
+                        TupleComponent::Boolean(expr) => {
+                            TupleComponent::Boolean(expr.substitute_with(substitution))
+                        }
+                        TupleComponent::Integer(expr) => {
+                            TupleComponent::Integer(expr.substitute_with(substitution))
+                        }
+                        TupleComponent::Rational(expr) => {
+                            TupleComponent::Rational(expr.substitute_with(substitution))
+                        }
+                        TupleComponent::Tuple(expr) => {
+                            TupleComponent::Tuple(expr.substitute_with(substitution))
+                        }
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// The product of the component domains.
+impl super::Sample for Vec<Domain> {
+    fn try_sample(&self) -> Result<AssignedValue, super::SampleError> {
+        self.iter()
+            .map(|domain| domain.try_sample())
+            .collect::<Result<Vec<AssignedValue>, super::SampleError>>()
+            .map(AssignedValue::Tuple)
+    }
+}
+
+/// The product of the component domains. Its candidates are ordered
+/// lexicographically by component.
+impl super::ValueDomain for Vec<Domain> {
+    fn candidates(&self) -> Option<Vec<AssignedValue>> {
+        self.candidates_with(DivisionMode::default())
+    }
+    fn contains(&self, value: &AssignedValue) -> bool {
+        self.contains_with(value, DivisionMode::default())
+    }
+    fn candidates_with(&self, division: DivisionMode) -> Option<Vec<AssignedValue>> {
+        if self.cardinality()? > super::MAX_CANDIDATES {
+            return None;
+        }
+        let mut tuples: Vec<Vec<AssignedValue>> = vec![Vec::new()];
+        for domain in self {
+            let values = domain.as_value_domain().candidates_with(division)?;
+            tuples = tuples
+                .into_iter()
+                .flat_map(|tuple| {
+                    values.iter().map(move |value| {
+                        let mut tuple = tuple.clone();
+                        tuple.push(value.clone());
+                        tuple
+                    })
+                })
+                .collect();
+        }
+        Some(tuples.into_iter().map(AssignedValue::Tuple).collect())
+    }
+    fn contains_with(&self, value: &AssignedValue, division: DivisionMode) -> bool {
+        match value {
+            AssignedValue::Tuple(values) => {
+                values.len() == self.len()
+                    && self.iter().zip(values).all(|(domain, value)| {
+                        domain.as_value_domain().contains_with(value, division)
+                    })
+            }
+            _ => false,
+        }
+    }
+    fn cardinality(&self) -> Option<u128> {
+        self.iter().try_fold(1u128, |product, domain| {
+            product.checked_mul(domain.as_value_domain().cardinality()?)
+        })
+    }
+    fn clone_box(&self) -> Box<dyn super::ValueDomain> {
+        Box::new(self.clone())
+    }
+    fn identity(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ComponentType, TupleComponent, TupleExpression};
+    use crate::expressions::boolean::{BooleanValue, BooleanValueDomainExpression};
+    use crate::expressions::integer::{IntegerNumberDomainExpression, IntegerNumberExpression};
+    use crate::expressions::{
+        AssignedValue, Domain, FreeVariable, Sample, SampleError, Symbol, ValueDomain,
+    };
+
+    #[test]
+    fn tuple_domains_sample_their_components() {
+        let pair = vec![
+            Domain::Boolean(BooleanValueDomainExpression::Single(BooleanValue::True)),
+            Domain::Boolean(BooleanValueDomainExpression::Universe),
+        ];
+        assert_eq!(
+            Domain::Tuple(pair.clone()).sample(),
+            Some(AssignedValue::Tuple(vec![
+                AssignedValue::Boolean(BooleanValue::True),
+                AssignedValue::Boolean(BooleanValue::False),
+            ]))
+        );
+        assert_eq!(pair.cardinality(), Some(2));
+        assert_eq!(pair.candidates().map(|tuples| tuples.len()), Some(2));
+        let point = Domain::Tuple(vec![
+            Domain::Boolean(BooleanValueDomainExpression::Universe),
+            Domain::Integer(IntegerNumberDomainExpression::Universe),
+        ]);
+        assert_eq!(point.try_sample(), Err(SampleError::Unbounded));
+        assert_eq!(point.as_value_domain().candidates(), None);
+    }
+
+    #[test]
+    fn free_variables_are_collected_through_components() {
+        let symbol = |name: &str| Symbol::new(name.to_string());
+        let tuple = TupleExpression::Tuple(vec![
+            TupleComponent::Integer(IntegerNumberExpression::IntegerNumberVariable(symbol("x"))),
+            TupleComponent::Tuple(TupleExpression::TupleVariable(
+                symbol("p"),
+                vec![ComponentType::Integer, ComponentType::Boolean],
+            )),
+        ]);
+        let free = IntegerNumberExpression::TupleGet(Box::new(tuple), 0).get_free();
+        assert_eq!(free.len(), 2);
+        assert_eq!(free[0].name(), &symbol("x"));
+        assert_eq!(free[1].name(), &symbol("p"));
+        assert_eq!(
+            free[1].domain(),
+            &Domain::Tuple(vec![
+                Domain::Integer(IntegerNumberDomainExpression::Universe),
+                Domain::Boolean(BooleanValueDomainExpression::Universe),
+            ])
+        );
+    }
+}
@@ -0,0 +1,912 @@
+//! Generic traversal of expression trees.
+//!
+//! A `Visitor` borrows a tree and a `Fold` takes one apart and builds it
+//! back up. Both have a method for each kind of expression, which by
+//! default passes on to the children through the function of the same
+//! kind below (`walk_integer` for `Visitor::visit_integer`, `fold_integer`
+//! for `Fold::fold_integer`, and so on), so an implementation overrides
+//! only the cases it cares about and calls the function to carry on
+//! below them:
+//! ```
+//! use clp::expressions::integer::IntegerNumberExpression;
+//! use clp::expressions::parser::parse_program;
+//! use clp::expressions::visit::{walk_integer, Visitor};
+//!
+//! struct Products(usize);
+//! impl<'a> Visitor<'a> for Products {
+//!     fn visit_integer(&mut self, expr: &'a IntegerNumberExpression) {
+//!         if let IntegerNumberExpression::Times(_, _) = expr {
+//!             self.0 += 1;
+//!         }
+//!         walk_integer(self, expr);
+//!     }
+//! }
+//!
+//! let program = parse_program("x * y < 2 * (x + 1);\nsatisfy x > 0;").unwrap();
+//! let mut products = Products(0);
+//! products.visit_program(&program);
+//! assert_eq!(products.0, 2);
+//! ```
+//! Children are visited in the order they are written, so a visitor sees
+//! variables in the order `get_free` lists them.
+use super::boolean::BooleanExpression;
+use super::integer::{
+    BooleanIntegerNumberExpression, IntegerNumberDomainExpression, IntegerNumberExpression,
+};
+use super::rational::{BooleanRationalExpression, RationalDomainExpression, RationalExpression};
+use super::tuple::{TupleComponent, TupleExpression};
+use super::{
+    ConstraintLogicExpression, ConstraintProgramExpression, Measure, SatisfactionExpression, Symbol,
+};
+
+/// Walks an expression tree by reference.
+pub trait Visitor<'a> {
+    fn visit_program(&mut self, expr: &'a ConstraintProgramExpression) {
+        walk_program(self, expr)
+    }
+    fn visit_satisfaction(&mut self, expr: &'a SatisfactionExpression) {
+        walk_satisfaction(self, expr)
+    }
+    fn visit_constraint(&mut self, expr: &'a ConstraintLogicExpression) {
+        walk_constraint(self, expr)
+    }
+    fn visit_boolean(&mut self, expr: &'a BooleanExpression) {
+        walk_boolean(self, expr)
+    }
+    fn visit_integer(&mut self, expr: &'a IntegerNumberExpression) {
+        walk_integer(self, expr)
+    }
+    fn visit_integer_domain(&mut self, expr: &'a IntegerNumberDomainExpression) {
+        walk_integer_domain(self, expr)
+    }
+    fn visit_integer_comparison(&mut self, expr: &'a BooleanIntegerNumberExpression) {
+        walk_integer_comparison(self, expr)
+    }
+    fn visit_rational(&mut self, expr: &'a RationalExpression) {
+        walk_rational(self, expr)
+    }
+    fn visit_rational_domain(&mut self, expr: &'a RationalDomainExpression) {
+        walk_rational_domain(self, expr)
+    }
+    fn visit_rational_comparison(&mut self, expr: &'a BooleanRationalExpression) {
+        walk_rational_comparison(self, expr)
+    }
+    fn visit_tuple(&mut self, expr: &'a TupleExpression) {
+        walk_tuple(self, expr)
+    }
+    /// Each symbol of the tree: the name of every variable, and the symbol
+    /// a `Let` binds, before its value. Does nothing by default.
+    fn visit_symbol(&mut self, _symbol: &'a Symbol) {}
+}
+
+pub fn walk_program<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    expr: &'a ConstraintProgramExpression,
+) {
+    use ConstraintProgramExpression::*;
+    match expr {
+        Solve(goal) => visitor.visit_satisfaction(goal),
+        SolveAnd(goal, rest) => {
+            visitor.visit_satisfaction(goal);
+            visitor.visit_program(rest);
+        }
+        ConstrainAnd(constraint, rest) => {
+            visitor.visit_constraint(constraint);
+            visitor.visit_program(rest);
+        }
+    }
+}
+
+pub fn walk_satisfaction<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    expr: &'a SatisfactionExpression,
+) {
+    use SatisfactionExpression::*;
+    match expr {
+        Satisfy(expr) | Minimise(expr) | Maximise(expr) => visitor.visit_constraint(expr),
+    }
+}
+
+pub fn walk_constraint<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    expr: &'a ConstraintLogicExpression,
+) {
+    use ConstraintLogicExpression::*;
+    match expr {
+        Boolean(expr) => visitor.visit_boolean(expr),
+        OfIntegerNumber(expr) => visitor.visit_integer_comparison(expr),
+        OfRational(expr) => visitor.visit_rational_comparison(expr),
+        AllDifferent(exprs) => {
+            for expr in exprs {
+                visitor.visit_integer(expr);
+            }
+        }
+    }
+}
+
+pub fn walk_boolean<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &'a BooleanExpression) {
+    use BooleanExpression::*;
+    match expr {
+        BooleanVariable(symbol) => visitor.visit_symbol(symbol),
+        BooleanValue(_) => (),
+        Not(expr) | Parenthesis(expr) => visitor.visit_boolean(expr),
+        And(expr_a, expr_b)
+        | Or(expr_a, expr_b)
+        | Implies(expr_a, expr_b)
+        | Equals(expr_a, expr_b)
+        | Xor(expr_a, expr_b) => {
+            visitor.visit_boolean(expr_a);
+            visitor.visit_boolean(expr_b);
+        }
+        IfThenElse(condition, then, otherwise) => {
+            visitor.visit_boolean(condition);
+            visitor.visit_boolean(then);
+            visitor.visit_boolean(otherwise);
+        }
+    }
+}
+
+pub fn walk_integer<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    expr: &'a IntegerNumberExpression,
+) {
+    use IntegerNumberExpression::*;
+    match expr {
+        IntegerNumberVariable(symbol) => visitor.visit_symbol(symbol),
+        IntegerNumberValue(_) => (),
+        Parenthesis(expr) | Negate(expr) | Abs(expr) => visitor.visit_integer(expr),
+        Add(expr_a, expr_b)
+        | Minus(expr_a, expr_b)
+        | Times(expr_a, expr_b)
+        | Divide(expr_a, expr_b)
+        | Modulo(expr_a, expr_b)
+        | Pow(expr_a, expr_b) => {
+            visitor.visit_integer(expr_a);
+            visitor.visit_integer(expr_b);
+        }
+        PowMod(expr_a, expr_b, expr_c) => {
+            visitor.visit_integer(expr_a);
+            visitor.visit_integer(expr_b);
+            visitor.visit_integer(expr_c);
+        }
+        Sum(exprs) | Product(exprs) => {
+            for expr in exprs {
+                visitor.visit_integer(expr);
+            }
+        }
+        TupleGet(tuple, _) => visitor.visit_tuple(tuple),
+        Let(symbol, value, body) => {
+            visitor.visit_symbol(symbol);
+            visitor.visit_integer(value);
+            visitor.visit_integer(body);
+        }
+        OfBoolean(expr) => visitor.visit_boolean(expr),
+        IfThenElse(condition, then, otherwise) => {
+            visitor.visit_boolean(condition);
+            visitor.visit_integer(then);
+            visitor.visit_integer(otherwise);
+        }
+    }
+}
+
+pub fn walk_integer_domain<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    expr: &'a IntegerNumberDomainExpression,
+) {
+    use IntegerNumberDomainExpression::*;
+    match expr {
+        Universe | Empty => (),
+        ClosedRange(expr_a, expr_b)
+        | OpenRange(expr_a, expr_b)
+        | OpenLeftClosedRightRange(expr_a, expr_b)
+        | ClosedLeftOpenRightRange(expr_a, expr_b) => {
+            visitor.visit_integer(expr_a);
+            visitor.visit_integer(expr_b);
+        }
+        ExplicitSet(elements) => {
+            for element in elements {
+                visitor.visit_integer(element);
+            }
+        }
+        Union(expr_a, expr_b) | Intersection(expr_a, expr_b) | Difference(expr_a, expr_b) => {
+            visitor.visit_integer_domain(expr_a);
+            visitor.visit_integer_domain(expr_b);
+        }
+        Complement(expr) => visitor.visit_integer_domain(expr),
+    }
+}
+
+pub fn walk_integer_comparison<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    expr: &'a BooleanIntegerNumberExpression,
+) {
+    use BooleanIntegerNumberExpression::*;
+    match expr {
+        Equals(expr_a, expr_b)
+        | Different(expr_a, expr_b)
+        | Greater(expr_a, expr_b)
+        | Less(expr_a, expr_b) => {
+            visitor.visit_integer(expr_a);
+            visitor.visit_integer(expr_b);
+        }
+        In(expr, domain) => {
+            visitor.visit_integer(expr);
+            visitor.visit_integer_domain(domain);
+        }
+    }
+}
+
+pub fn walk_rational<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &'a RationalExpression) {
+    use RationalExpression::*;
+    match expr {
+        RationalVariable(symbol) => visitor.visit_symbol(symbol),
+        RationalValue(_) => (),
+        Parenthesis(expr) | Negate(expr) | Abs(expr) => visitor.visit_rational(expr),
+        Add(expr_a, expr_b)
+        | Minus(expr_a, expr_b)
+        | Times(expr_a, expr_b)
+        | Divide(expr_a, expr_b) => {
+            visitor.visit_rational(expr_a);
+            visitor.visit_rational(expr_b);
+        }
+        OfInteger(expr) => visitor.visit_integer(expr),
+    }
+}
+
+pub fn walk_rational_domain<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    expr: &'a RationalDomainExpression,
+) {
+    use RationalDomainExpression::*;
+    match expr {
+        Universe | Empty => (),
+        ClosedRange(expr_a, expr_b)
+        | OpenRange(expr_a, expr_b)
+        | OpenLeftClosedRightRange(expr_a, expr_b)
+        | ClosedLeftOpenRightRange(expr_a, expr_b) => {
+            visitor.visit_rational(expr_a);
+            visitor.visit_rational(expr_b);
+        }
+        ExplicitSet(elements) => {
+            for element in elements {
+                visitor.visit_rational(element);
+            }
+        }
+        Union(expr_a, expr_b) | Intersection(expr_a, expr_b) | Difference(expr_a, expr_b) => {
+            visitor.visit_rational_domain(expr_a);
+            visitor.visit_rational_domain(expr_b);
+        }
+        Complement(expr) => visitor.visit_rational_domain(expr),
+    }
+}
+
+pub fn walk_rational_comparison<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    expr: &'a BooleanRationalExpression,
+) {
+    use BooleanRationalExpression::*;
+    match expr {
+        Equals(expr_a, expr_b)
+        | Different(expr_a, expr_b)
+        | Greater(expr_a, expr_b)
+        | Less(expr_a, expr_b) => {
+            visitor.visit_rational(expr_a);
+            visitor.visit_rational(expr_b);
+        }
+        In(expr, domain) => {
+            visitor.visit_rational(expr);
+            visitor.visit_rational_domain(domain);
+        }
+    }
+}
+
+pub fn walk_tuple<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &'a TupleExpression) {
+    match expr {
+        TupleExpression::TupleVariable(symbol, _) => visitor.visit_symbol(symbol),
+        TupleExpression::Tuple(components) => {
+            for component in components {
+                match component {
+                    TupleComponent::Boolean(expr) => visitor.visit_boolean(expr),
+                    TupleComponent::Integer(expr) => visitor.visit_integer(expr),
+                    TupleComponent::Rational(expr) => visitor.visit_rational(expr),
+                    TupleComponent::Tuple(expr) => visitor.visit_tuple(expr),
+                }
+            }
+        }
+    }
+}
+
+/// Rebuilds an expression tree from its parts, bottom up.
+pub trait Fold {
+    fn fold_program(&mut self, expr: ConstraintProgramExpression) -> ConstraintProgramExpression {
+        fold_program(self, expr)
+    }
+    fn fold_satisfaction(&mut self, expr: SatisfactionExpression) -> SatisfactionExpression {
+        fold_satisfaction(self, expr)
+    }
+    fn fold_constraint(&mut self, expr: ConstraintLogicExpression) -> ConstraintLogicExpression {
+        fold_constraint(self, expr)
+    }
+    fn fold_boolean(&mut self, expr: BooleanExpression) -> BooleanExpression {
+        fold_boolean(self, expr)
+    }
+    fn fold_integer(&mut self, expr: IntegerNumberExpression) -> IntegerNumberExpression {
+        fold_integer(self, expr)
+    }
+    fn fold_integer_domain(
+        &mut self,
+        expr: IntegerNumberDomainExpression,
+    ) -> IntegerNumberDomainExpression {
+        fold_integer_domain(self, expr)
+    }
+    fn fold_integer_comparison(
+        &mut self,
+        expr: BooleanIntegerNumberExpression,
+    ) -> BooleanIntegerNumberExpression {
+        fold_integer_comparison(self, expr)
+    }
+    fn fold_rational(&mut self, expr: RationalExpression) -> RationalExpression {
+        fold_rational(self, expr)
+    }
+    fn fold_rational_domain(&mut self, expr: RationalDomainExpression) -> RationalDomainExpression {
+        fold_rational_domain(self, expr)
+    }
+    fn fold_rational_comparison(
+        &mut self,
+        expr: BooleanRationalExpression,
+    ) -> BooleanRationalExpression {
+        fold_rational_comparison(self, expr)
+    }
+    fn fold_tuple(&mut self, expr: TupleExpression) -> TupleExpression {
+        fold_tuple(self, expr)
+    }
+    /// Each symbol of the tree, as `Visitor::visit_symbol` sees them.
+    /// Renaming a variable here renames every occurrence of it, and a
+    /// `Let` together with the occurrences it binds. Keeps the symbol by
+    /// default.
+    fn fold_symbol(&mut self, symbol: Symbol) -> Symbol {
+        symbol
+    }
+}
+
+pub fn fold_program<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: ConstraintProgramExpression,
+) -> ConstraintProgramExpression {
+    use ConstraintProgramExpression::*;
+    match expr {
+        Solve(goal) => Solve(Box::new(folder.fold_satisfaction(*goal))),
+        SolveAnd(goal, rest) => SolveAnd(
+            Box::new(folder.fold_satisfaction(*goal)),
+            Box::new(folder.fold_program(*rest)),
+        ),
+        ConstrainAnd(constraint, rest) => ConstrainAnd(
+            Box::new(folder.fold_constraint(*constraint)),
+            Box::new(folder.fold_program(*rest)),
+        ),
+    }
+}
+
+pub fn fold_satisfaction<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: SatisfactionExpression,
+) -> SatisfactionExpression {
+    use SatisfactionExpression::*;
+    match expr {
+        Satisfy(expr) => Satisfy(Box::new(folder.fold_constraint(*expr))),
+        Minimise(expr) => Minimise(Box::new(folder.fold_constraint(*expr))),
+        Maximise(expr) => Maximise(Box::new(folder.fold_constraint(*expr))),
+    }
+}
+
+pub fn fold_constraint<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: ConstraintLogicExpression,
+) -> ConstraintLogicExpression {
+    use ConstraintLogicExpression::*;
+    match expr {
+        Boolean(expr) => Boolean(Box::new(folder.fold_boolean(*expr))),
+        OfIntegerNumber(expr) => OfIntegerNumber(Box::new(folder.fold_integer_comparison(*expr))),
+        OfRational(expr) => OfRational(Box::new(folder.fold_rational_comparison(*expr))),
+        AllDifferent(exprs) => AllDifferent(
+            exprs
+                .into_iter()
+                .map(|expr| folder.fold_integer(expr))
+                .collect(),
+        ),
+    }
+}
+
+pub fn fold_boolean<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: BooleanExpression,
+) -> BooleanExpression {
+    use BooleanExpression::*;
+    let mut fold = |expr: Box<BooleanExpression>| Box::new(folder.fold_boolean(*expr));
+    match expr {
+        BooleanVariable(symbol) => BooleanVariable(folder.fold_symbol(symbol)),
+        BooleanValue(value) => BooleanValue(value),
+        Not(expr) => Not(fold(expr)),
+        Parenthesis(expr) => Parenthesis(fold(expr)),
+        And(expr_a, expr_b) => And(fold(expr_a), fold(expr_b)),
+        Or(expr_a, expr_b) => Or(fold(expr_a), fold(expr_b)),
+        Implies(expr_a, expr_b) => Implies(fold(expr_a), fold(expr_b)),
+        Equals(expr_a, expr_b) => Equals(fold(expr_a), fold(expr_b)),
+        Xor(expr_a, expr_b) => Xor(fold(expr_a), fold(expr_b)),
+        IfThenElse(condition, then, otherwise) => {
+            IfThenElse(fold(condition), fold(then), fold(otherwise))
+        }
+    }
+}
+
+pub fn fold_integer<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: IntegerNumberExpression,
+) -> IntegerNumberExpression {
+    use IntegerNumberExpression::*;
+    let fold =
+        |folder: &mut F, expr: Box<IntegerNumberExpression>| Box::new(folder.fold_integer(*expr));
+    match expr {
+        IntegerNumberVariable(symbol) => IntegerNumberVariable(folder.fold_symbol(symbol)),
+        IntegerNumberValue(value) => IntegerNumberValue(value),
+        Parenthesis(expr) => Parenthesis(fold(folder, expr)),
+        Negate(expr) => Negate(fold(folder, expr)),
+        Abs(expr) => Abs(fold(folder, expr)),
+        Add(expr_a, expr_b) => Add(fold(folder, expr_a), fold(folder, expr_b)),
+        Minus(expr_a, expr_b) => Minus(fold(folder, expr_a), fold(folder, expr_b)),
+        Times(expr_a, expr_b) => Times(fold(folder, expr_a), fold(folder, expr_b)),
+        Divide(expr_a, expr_b) => Divide(fold(folder, expr_a), fold(folder, expr_b)),
+        Modulo(expr_a, expr_b) => Modulo(fold(folder, expr_a), fold(folder, expr_b)),
+        Pow(expr_a, expr_b) => Pow(fold(folder, expr_a), fold(folder, expr_b)),
+        PowMod(expr_a, expr_b, expr_c) => PowMod(
+            fold(folder, expr_a),
+            fold(folder, expr_b),
+            fold(folder, expr_c),
+        ),
+        Sum(exprs) => Sum(exprs
+            .into_iter()
+            .map(|expr| folder.fold_integer(expr))
+            .collect()),
+        Product(exprs) => Product(
+            exprs
+                .into_iter()
+                .map(|expr| folder.fold_integer(expr))
+                .collect(),
+        ),
+        TupleGet(tuple, index) => TupleGet(Box::new(folder.fold_tuple(*tuple)), index),
+        Let(symbol, value, body) => {
+            let symbol = folder.fold_symbol(symbol);
+            Let(symbol, fold(folder, value), fold(folder, body))
+        }
+        OfBoolean(expr) => OfBoolean(Box::new(folder.fold_boolean(*expr))),
+        IfThenElse(condition, then, otherwise) => IfThenElse(
+            Box::new(folder.fold_boolean(*condition)),
+            fold(folder, then),
+            fold(folder, otherwise),
+        ),
+    }
+}
+
+pub fn fold_integer_domain<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: IntegerNumberDomainExpression,
+) -> IntegerNumberDomainExpression {
+    use IntegerNumberDomainExpression::*;
+    let fold =
+        |folder: &mut F, expr: Box<IntegerNumberExpression>| Box::new(folder.fold_integer(*expr));
+    let fold_domain = |folder: &mut F, expr: Box<IntegerNumberDomainExpression>| {
+        Box::new(folder.fold_integer_domain(*expr))
+    };
+    match expr {
+        Universe => Universe,
+        Empty => Empty,
+        ClosedRange(low, high) => ClosedRange(fold(folder, low), fold(folder, high)),
+        OpenRange(low, high) => OpenRange(fold(folder, low), fold(folder, high)),
+        OpenLeftClosedRightRange(low, high) => {
+            OpenLeftClosedRightRange(fold(folder, low), fold(folder, high))
+        }
+        ClosedLeftOpenRightRange(low, high) => {
+            ClosedLeftOpenRightRange(fold(folder, low), fold(folder, high))
+        }
+        ExplicitSet(elements) => ExplicitSet(
+            elements
+                .into_iter()
+                .map(|element| folder.fold_integer(element))
+                .collect(),
+        ),
+        Union(expr_a, expr_b) => Union(fold_domain(folder, expr_a), fold_domain(folder, expr_b)),
+        Intersection(expr_a, expr_b) => {
+            Intersection(fold_domain(folder, expr_a), fold_domain(folder, expr_b))
+        }
+        Difference(expr_a, expr_b) => {
+            Difference(fold_domain(folder, expr_a), fold_domain(folder, expr_b))
+        }
+        Complement(expr) => Complement(fold_domain(folder, expr)),
+    }
+}
+
+pub fn fold_integer_comparison<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: BooleanIntegerNumberExpression,
+) -> BooleanIntegerNumberExpression {
+    use BooleanIntegerNumberExpression::*;
+    let fold =
+        |folder: &mut F, expr: Box<IntegerNumberExpression>| Box::new(folder.fold_integer(*expr));
+    match expr {
+        Equals(expr_a, expr_b) => Equals(fold(folder, expr_a), fold(folder, expr_b)),
+        Different(expr_a, expr_b) => Different(fold(folder, expr_a), fold(folder, expr_b)),
+        Greater(expr_a, expr_b) => Greater(fold(folder, expr_a), fold(folder, expr_b)),
+        Less(expr_a, expr_b) => Less(fold(folder, expr_a), fold(folder, expr_b)),
+        In(expr, domain) => In(
+            fold(folder, expr),
+            Box::new(folder.fold_integer_domain(*domain)),
+        ),
+    }
+}
+
+pub fn fold_rational<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: RationalExpression,
+) -> RationalExpression {
+    use RationalExpression::*;
+    let fold =
+        |folder: &mut F, expr: Box<RationalExpression>| Box::new(folder.fold_rational(*expr));
+    match expr {
+        RationalVariable(symbol) => RationalVariable(folder.fold_symbol(symbol)),
+        RationalValue(value) => RationalValue(value),
+        Parenthesis(expr) => Parenthesis(fold(folder, expr)),
+        Negate(expr) => Negate(fold(folder, expr)),
+        Abs(expr) => Abs(fold(folder, expr)),
+        Add(expr_a, expr_b) => Add(fold(folder, expr_a), fold(folder, expr_b)),
+        Minus(expr_a, expr_b) => Minus(fold(folder, expr_a), fold(folder, expr_b)),
+        Times(expr_a, expr_b) => Times(fold(folder, expr_a), fold(folder, expr_b)),
+        Divide(expr_a, expr_b) => Divide(fold(folder, expr_a), fold(folder, expr_b)),
+        OfInteger(expr) => OfInteger(Box::new(folder.fold_integer(*expr))),
+    }
+}
+
+pub fn fold_rational_domain<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: RationalDomainExpression,
+) -> RationalDomainExpression {
+    use RationalDomainExpression::*;
+    let fold =
+        |folder: &mut F, expr: Box<RationalExpression>| Box::new(folder.fold_rational(*expr));
+    let fold_domain = |folder: &mut F, expr: Box<RationalDomainExpression>| {
+        Box::new(folder.fold_rational_domain(*expr))
+    };
+    match expr {
+        Universe => Universe,
+        Empty => Empty,
+        ClosedRange(low, high) => ClosedRange(fold(folder, low), fold(folder, high)),
+        OpenRange(low, high) => OpenRange(fold(folder, low), fold(folder, high)),
+        OpenLeftClosedRightRange(low, high) => {
+            OpenLeftClosedRightRange(fold(folder, low), fold(folder, high))
+        }
+        ClosedLeftOpenRightRange(low, high) => {
+            ClosedLeftOpenRightRange(fold(folder, low), fold(folder, high))
+        }
+        ExplicitSet(elements) => ExplicitSet(
+            elements
+                .into_iter()
+                .map(|element| folder.fold_rational(element))
+                .collect(),
+        ),
+        Union(expr_a, expr_b) => Union(fold_domain(folder, expr_a), fold_domain(folder, expr_b)),
+        Intersection(expr_a, expr_b) => {
+            Intersection(fold_domain(folder, expr_a), fold_domain(folder, expr_b))
+        }
+        Difference(expr_a, expr_b) => {
+            Difference(fold_domain(folder, expr_a), fold_domain(folder, expr_b))
+        }
+        Complement(expr) => Complement(fold_domain(folder, expr)),
+    }
+}
+
+pub fn fold_rational_comparison<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: BooleanRationalExpression,
+) -> BooleanRationalExpression {
+    use BooleanRationalExpression::*;
+    let fold =
+        |folder: &mut F, expr: Box<RationalExpression>| Box::new(folder.fold_rational(*expr));
+    match expr {
+        Equals(expr_a, expr_b) => Equals(fold(folder, expr_a), fold(folder, expr_b)),
+        Different(expr_a, expr_b) => Different(fold(folder, expr_a), fold(folder, expr_b)),
+        Greater(expr_a, expr_b) => Greater(fold(folder, expr_a), fold(folder, expr_b)),
+        Less(expr_a, expr_b) => Less(fold(folder, expr_a), fold(folder, expr_b)),
+        In(expr, domain) => In(
+            fold(folder, expr),
+            Box::new(folder.fold_rational_domain(*domain)),
+        ),
+    }
+}
+
+pub fn fold_tuple<F: Fold + ?Sized>(folder: &mut F, expr: TupleExpression) -> TupleExpression {
+    match expr {
+        TupleExpression::TupleVariable(symbol, types) => {
+            TupleExpression::TupleVariable(folder.fold_symbol(symbol), types)
+        }
+        TupleExpression::Tuple(components) => TupleExpression::Tuple(
+            components
+                .into_iter()
+                .map(|component| match component {
+                    TupleComponent::Boolean(expr) => {
+                        TupleComponent::Boolean(folder.fold_boolean(expr))
+                    }
+                    TupleComponent::Integer(expr) => {
+                        TupleComponent::Integer(folder.fold_integer(expr))
+                    }
+                    TupleComponent::Rational(expr) => {
+                        TupleComponent::Rational(folder.fold_rational(expr))
+                    }
+                    TupleComponent::Tuple(expr) => TupleComponent::Tuple(folder.fold_tuple(expr)),
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// Collects the free variables of a tree, as `get_free` lists them: each
+/// occurrence of a variable outside the `Let`s binding its name, with the
+/// universe of its type as its domain.
+#[derive(Default)]
+pub(crate) struct FreeVariables<'a> {
+    free: Vec<super::Variable>,
+    bound: Vec<&'a Symbol>,
+}
+
+impl<'a> FreeVariables<'a> {
+    /// The free variables of whatever `visit` walks.
+    pub(crate) fn of(visit: impl FnOnce(&mut FreeVariables<'a>)) -> Vec<super::Variable> {
+        let mut free = FreeVariables::default();
+        visit(&mut free);
+        free.free
+    }
+
+    fn push(&mut self, symbol: &Symbol, domain: super::Domain) {
+        if !self.bound.contains(&symbol) {
+            self.free.push(super::Variable {
+                name: symbol.clone(),
+                domain,
+            });
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for FreeVariables<'a> {
+    fn visit_boolean(&mut self, expr: &'a BooleanExpression) {
+        match expr {
+            BooleanExpression::BooleanVariable(symbol) => self.push(
+                symbol,
+                super::Domain::Boolean(super::boolean::BooleanValueDomainExpression::Universe),
+            ),
+            _ => walk_boolean(self, expr),
+        }
+    }
+    fn visit_integer(&mut self, expr: &'a IntegerNumberExpression) {
+        match expr {
+            IntegerNumberExpression::IntegerNumberVariable(symbol) => self.push(
+                symbol,
+                super::Domain::Integer(IntegerNumberDomainExpression::Universe),
+            ),
+            IntegerNumberExpression::Let(symbol, value, body) => {
+                self.visit_integer(value);
+                self.bound.push(symbol);
+                self.visit_integer(body);
+                self.bound.pop();
+            }
+            _ => walk_integer(self, expr),
+        }
+    }
+    fn visit_rational(&mut self, expr: &'a RationalExpression) {
+        match expr {
+            RationalExpression::RationalVariable(symbol) => self.push(
+                symbol,
+                super::Domain::Rational(RationalDomainExpression::Universe),
+            ),
+            _ => walk_rational(self, expr),
+        }
+    }
+    fn visit_tuple(&mut self, expr: &'a TupleExpression) {
+        match expr {
+            TupleExpression::TupleVariable(symbol, types) => self.push(
+                symbol,
+                super::Domain::Tuple(
+                    types
+                        .iter()
+                        .map(super::tuple::ComponentType::universe)
+                        .collect(),
+                ),
+            ),
+            _ => walk_tuple(self, expr),
+        }
+    }
+}
+
+/// Counts the nodes of a tree and the depth of the deepest.
+#[derive(Default)]
+struct Extent {
+    nodes: usize,
+    /// The depth of the node being visited.
+    level: usize,
+    deepest: usize,
+}
+
+impl Extent {
+    fn of(visit: impl FnOnce(&mut Extent)) -> Extent {
+        let mut extent = Extent::default();
+        visit(&mut extent);
+        extent
+    }
+
+    /// Counts a node, with `walk` going through its children.
+    fn node(&mut self, walk: impl FnOnce(&mut Extent)) {
+        self.nodes += 1;
+        self.level += 1;
+        self.deepest = self.deepest.max(self.level);
+        walk(self);
+        self.level -= 1;
+    }
+}
+
+impl<'a> Visitor<'a> for Extent {
+    fn visit_program(&mut self, expr: &'a ConstraintProgramExpression) {
+        self.node(|extent| walk_program(extent, expr))
+    }
+    fn visit_satisfaction(&mut self, expr: &'a SatisfactionExpression) {
+        self.node(|extent| walk_satisfaction(extent, expr))
+    }
+    fn visit_constraint(&mut self, expr: &'a ConstraintLogicExpression) {
+        self.node(|extent| walk_constraint(extent, expr))
+    }
+    fn visit_boolean(&mut self, expr: &'a BooleanExpression) {
+        self.node(|extent| walk_boolean(extent, expr))
+    }
+    fn visit_integer(&mut self, expr: &'a IntegerNumberExpression) {
+        self.node(|extent| walk_integer(extent, expr))
+    }
+    fn visit_integer_domain(&mut self, expr: &'a IntegerNumberDomainExpression) {
+        self.node(|extent| walk_integer_domain(extent, expr))
+    }
+    fn visit_integer_comparison(&mut self, expr: &'a BooleanIntegerNumberExpression) {
+        self.node(|extent| walk_integer_comparison(extent, expr))
+    }
+    fn visit_rational(&mut self, expr: &'a RationalExpression) {
+        self.node(|extent| walk_rational(extent, expr))
+    }
+    fn visit_rational_domain(&mut self, expr: &'a RationalDomainExpression) {
+        self.node(|extent| walk_rational_domain(extent, expr))
+    }
+    fn visit_rational_comparison(&mut self, expr: &'a BooleanRationalExpression) {
+        self.node(|extent| walk_rational_comparison(extent, expr))
+    }
+    fn visit_tuple(&mut self, expr: &'a TupleExpression) {
+        self.node(|extent| walk_tuple(extent, expr))
+    }
+}
+
+macro_rules! measure {
+    ($type:ty, $visit:ident) => {
+        impl Measure for $type {
+            fn node_count(&self) -> usize {
+                Extent::of(|extent| extent.$visit(self)).nodes
+            }
+            fn depth(&self) -> usize {
+                Extent::of(|extent| extent.$visit(self)).deepest
+            }
+        }
+    };
+}
+
+measure!(ConstraintProgramExpression, visit_program);
+measure!(SatisfactionExpression, visit_satisfaction);
+measure!(ConstraintLogicExpression, visit_constraint);
+measure!(BooleanExpression, visit_boolean);
+measure!(IntegerNumberExpression, visit_integer);
+measure!(IntegerNumberDomainExpression, visit_integer_domain);
+measure!(BooleanIntegerNumberExpression, visit_integer_comparison);
+measure!(RationalExpression, visit_rational);
+measure!(RationalDomainExpression, visit_rational_domain);
+measure!(BooleanRationalExpression, visit_rational_comparison);
+measure!(TupleExpression, visit_tuple);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expressions::dsl::*;
+    use crate::expressions::FreeVariable;
+
+    /// Counts the nodes of each kind.
+    #[derive(Default)]
+    struct Census {
+        booleans: usize,
+        integers: usize,
+        symbols: Vec<String>,
+    }
+
+    impl<'a> Visitor<'a> for Census {
+        fn visit_boolean(&mut self, expr: &'a BooleanExpression) {
+            self.booleans += 1;
+            walk_boolean(self, expr);
+        }
+        fn visit_integer(&mut self, expr: &'a IntegerNumberExpression) {
+            self.integers += 1;
+            walk_integer(self, expr);
+        }
+        fn visit_symbol(&mut self, symbol: &'a Symbol) {
+            self.symbols.push(symbol.to_string());
+        }
+    }
+
+    /// Renames `x` to `z` and doubles every integer literal.
+    struct Rewrite;
+
+    impl Fold for Rewrite {
+        fn fold_integer(&mut self, expr: IntegerNumberExpression) -> IntegerNumberExpression {
+            match expr {
+                IntegerNumberExpression::IntegerNumberValue(
+                    crate::expressions::integer::IntegerNumber::Value(n),
+                ) => IntegerNumberExpression::IntegerNumberValue(
+                    crate::expressions::integer::IntegerNumber::Value(2 * n),
+                ),
+                expr => fold_integer(self, expr),
+            }
+        }
+        fn fold_symbol(&mut self, symbol: Symbol) -> Symbol {
+            match symbol.name() {
+                "x" => Symbol::new("z".to_string()),
+                _ => symbol,
+            }
+        }
+    }
+
+    #[test]
+    fn visitors_and_folds_override_only_what_they_need() {
+        let binding = IntegerNumberExpression::Let(
+            Symbol::new("y".to_string()),
+            (int_var("x") + 1).into(),
+            (int_var("y") * int_ite(var("p"), 2, 3)).into(),
+        );
+        let program = constrain(
+            is_in(int_var("x"), closed(0, 9)),
+            constrain(
+                implies(var("p"), not(var("q"))),
+                solve(satisfy(greater(Int(binding), int_var("x")))),
+            ),
+        );
+        let mut census = Census::default();
+        census.visit_program(&program);
+        assert_eq!(census.booleans, 5);
+        assert_eq!(census.integers, 13);
+        assert_eq!(census.symbols, ["x", "p", "q", "y", "x", "y", "p", "x"]);
+
+        let program = Rewrite.fold_program(program);
+        assert_eq!(
+            program.to_string(),
+            "z in [0, 18];\n\
+             p implies not q;\n\
+             satisfy (let y = z + 2 in y * ite(p, 4, 6)) > z;"
+        );
+        let names: Vec<String> = (&program)
+            .get_free()
+            .iter()
+            .map(|variable| variable.name().to_string())
+            .collect();
+        assert_eq!(names, ["z", "p", "q", "z", "p", "z"]);
+    }
+
+    #[test]
+    fn measures_count_every_node_once() {
+        use crate::expressions::parser::{parse_integer, parse_program};
+        let leaf = parse_integer("x").unwrap();
+        assert_eq!((leaf.node_count(), leaf.depth()), (1, 1));
+        let expr = parse_integer("(1 + 2) * 3").unwrap();
+        assert_eq!((expr.node_count(), expr.depth()), (6, 4));
+        let program = parse_program("x in [0, 9];\nsatisfy x + 1 > 2 * y;").unwrap();
+        assert_eq!(program.node_count(), 17);
+        assert_eq!(program.depth(), 7);
+        let program = parse_program("satisfy p or (q and not r);").unwrap();
+        assert_eq!((program.node_count(), program.depth()), (10, 8));
+    }
+}
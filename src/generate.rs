@@ -0,0 +1,164 @@
+//! Random constraint programs for benchmarking. The same parameters and
+//! seed always give the same program.
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::{Rng, SeedableRng};
+
+use crate::expressions::boolean::{BooleanExpression, BooleanValue};
+use crate::expressions::integer::{
+    BooleanIntegerNumberExpression, IntegerNumber, IntegerNumberDomainExpression,
+    IntegerNumberExpression,
+};
+use crate::expressions::{ConstraintLogicExpression, ConstraintProgramExpression, Symbol};
+use crate::solver::program_of;
+
+/// A random k-SAT instance over the boolean variables `v1` to `vN`, with
+/// one constraint per clause. Each clause is the disjunction of `k`
+/// literals over distinct variables, or over any variables when `k`
+/// exceeds `vars`, each negated with probability one half. Without
+/// variables there are no clauses either, and the program is
+/// `satisfy true;`.
+pub fn random_ksat(
+    vars: usize,
+    clauses: usize,
+    k: usize,
+    seed: u64,
+) -> ConstraintProgramExpression {
+    use BooleanExpression::*;
+    if vars == 0 {
+        return program_of(Vec::new());
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let constraints = (0..clauses)
+        .map(|_| {
+            let variables: Vec<usize> = if k <= vars {
+                index::sample(&mut rng, vars, k).into_vec()
+            } else {
+                (0..k).map(|_| rng.gen_range(0..vars)).collect()
+            };
+            let clause = variables
+                .into_iter()
+                .map(|variable| {
+                    let atom = BooleanVariable(Symbol::new(format!("v{}", variable + 1)));
+                    if rng.gen_bool(0.5) {
+                        Not(Box::new(atom))
+                    } else {
+                        atom
+                    }
+                })
+                .reduce(|expr_a, expr_b| Or(Box::new(expr_a), Box::new(expr_b)))
+                .unwrap_or(BooleanValue(self::BooleanValue::False));
+            ConstraintLogicExpression::Boolean(Box::new(clause))
+        })
+        .collect();
+    program_of(constraints)
+}
+
+/// A random binary CSP over the integer variables `x1` to `xN`. Each
+/// variable first gets the domain `[0, domain_size - 1]`, then each of
+/// the `constraints` compares two distinct variables, picked at random,
+/// with `=`, `!=`, `<` or `>`. With a single variable it is compared with
+/// itself, and without variables the program is `satisfy true;`. A
+/// `domain_size` below one counts as one.
+pub fn random_csp(
+    vars: usize,
+    domain_size: i128,
+    constraints: usize,
+    seed: u64,
+) -> ConstraintProgramExpression {
+    use BooleanIntegerNumberExpression::*;
+    if vars == 0 {
+        return program_of(Vec::new());
+    }
+    let largest = domain_size.max(1) - 1;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let variable = |i: usize| {
+        Box::new(IntegerNumberExpression::IntegerNumberVariable(Symbol::new(
+            format!("x{}", i + 1),
+        )))
+    };
+    let value = |n| {
+        Box::new(IntegerNumberExpression::IntegerNumberValue(
+            IntegerNumber::Value(n),
+        ))
+    };
+    let domains = (0..vars).map(|i| {
+        In(
+            variable(i),
+            Box::new(IntegerNumberDomainExpression::ClosedRange(
+                value(0),
+                value(largest),
+            )),
+        )
+    });
+    let comparisons: Vec<BooleanIntegerNumberExpression> = (0..constraints)
+        .map(|_| {
+            let (a, b) = if vars >= 2 {
+                let pair = index::sample(&mut rng, vars, 2);
+                (pair.index(0), pair.index(1))
+            } else {
+                (0, 0)
+            };
+            match rng.gen_range(0..4) {
+                0 => Equals(variable(a), variable(b)),
+                1 => Different(variable(a), variable(b)),
+                2 => Less(variable(a), variable(b)),
+                _ => Greater(variable(a), variable(b)),
+            }
+        })
+        .collect();
+    program_of(
+        domains
+            .chain(comparisons)
+            .map(|expr| ConstraintLogicExpression::OfIntegerNumber(Box::new(expr)))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{random_csp, random_ksat};
+    use crate::expressions::{ConstraintLogicExpression, FreeVariable};
+    use crate::solver::{constraints, free_variables};
+
+    #[test]
+    fn the_same_seed_gives_the_same_program() {
+        assert_eq!(random_ksat(20, 85, 3, 7), random_ksat(20, 85, 3, 7));
+        assert_ne!(random_ksat(20, 85, 3, 7), random_ksat(20, 85, 3, 8));
+        assert_eq!(random_csp(8, 5, 12, 7), random_csp(8, 5, 12, 7));
+        assert_ne!(random_csp(8, 5, 12, 7), random_csp(8, 5, 12, 8));
+    }
+
+    #[test]
+    fn generated_programs_have_the_requested_shape() {
+        let names = |free: Vec<crate::expressions::Variable>| {
+            let mut names: Vec<String> = free.iter().map(|v| v.name().to_string()).collect();
+            names.sort();
+            names.dedup();
+            names
+        };
+        let ksat = random_ksat(20, 85, 3, 7);
+        let clauses = constraints(&ksat);
+        assert_eq!(clauses.len(), 85);
+        for clause in clauses {
+            assert!(matches!(clause, ConstraintLogicExpression::Boolean(_)));
+            assert_eq!(names(clause.get_free()).len(), 3);
+        }
+        assert!(names(free_variables(&ksat).unwrap()).len() <= 20);
+        let csp = random_csp(8, 5, 12, 7);
+        assert_eq!(constraints(&csp).len(), 8 + 12);
+        assert_eq!(names(free_variables(&csp).unwrap()).len(), 8);
+    }
+
+    #[test]
+    fn degenerate_parameters_give_well_formed_programs() {
+        use crate::expressions::parser::to_source;
+        use crate::solver::solve_all;
+        assert_eq!(to_source(&random_ksat(0, 5, 3, 7)), "satisfy true;");
+        assert_eq!(to_source(&random_csp(0, 5, 4, 7)), "satisfy true;");
+        let csp = random_csp(3, 0, 2, 7);
+        assert_eq!(free_variables(&csp).unwrap().len(), 3);
+        assert!(solve_all(&csp).is_ok());
+        assert_eq!(random_csp(3, -4, 2, 7), csp);
+    }
+}
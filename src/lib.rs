@@ -4,6 +4,8 @@
 //! that actually needs doing while dumping the core
 //! work to some random guy with a keyboard.
 
+pub mod export;
 pub mod expressions;
+pub mod generate;
 
 pub mod solver;
@@ -1,13 +1,92 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::ops::ControlFlow;
+
+use crate::expressions::boolean::{BooleanExpression, BooleanValue, BooleanValueDomainExpression};
+use crate::expressions::integer::{
+    BooleanIntegerNumberExpression, DivisionMode, IntegerNumber, IntegerNumberDomainExpression,
+    IntegerNumberExpression,
+};
+use crate::expressions::rational::{RationalDomainExpression, RationalExpression};
 use crate::expressions::{
-    AssignedValue, Assignment, ConstraintProgramExpression, Symbol, Variable,
+    AssignedValue, Assignment, ConstraintLogicExpression, ConstraintProgramExpression, Domain,
+    SatisfactionExpression, Symbol, SymbolGenerator, Variable,
+};
+
+mod analysis;
+mod incremental;
+mod local;
+mod reduction;
+mod registry;
+mod search;
+
+pub use analysis::{
+    find_trivial_contradictions, overflow_risk, required_features, unconstrained_variables,
+    FeatureSet, OverflowWarning,
 };
+pub use incremental::{Consistency, ConstraintId, Solver};
+pub use local::{solve_local, LocalSearchConfig};
+pub use reduction::{reduce, Reduction};
+pub use registry::{solve_named, Strategy, StrategyRegistry};
 
 /// Assigned value to a constant or variable in a solution.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Solution {
-    Unsatisfiable(Symbol, String),
+    Unsatisfiable(UnsatReason),
     Variable(Symbol, AssignedValue),
     Constant(Symbol, AssignedValue),
 }
+
+/// Why `solve` found no solution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnsatReason {
+    /// Narrowing the bounds of the variable, as `reduce` does, left it
+    /// without values.
+    EmptyDomain(Symbol),
+    /// No assignment satisfies every constraint. Holds the variables of
+    /// a set of constraints that have no solution together, in the order
+    /// they first occur.
+    ConflictingConstraints(Vec<Symbol>),
+    /// The variable has no finite domain to enumerate, so it could not be
+    /// searched.
+    UnboundedSearch(Symbol),
+    /// The search could not run for another reason.
+    Failed(SolveError),
+}
+
+/// Bindings are written `name = value` and an unsatisfiable entry as its
+/// reason.
+impl std::fmt::Display for Solution {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Solution::Unsatisfiable(reason) => write!(f, "{}", reason),
+            Solution::Variable(name, value) | Solution::Constant(name, value) => {
+                write!(f, "{} = {}", name, value)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for UnsatReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UnsatReason::EmptyDomain(symbol) => write!(f, "`{}` has no value left", symbol),
+            UnsatReason::ConflictingConstraints(symbols) if symbols.is_empty() => {
+                write!(f, "the constraints conflict")
+            }
+            UnsatReason::ConflictingConstraints(symbols) => {
+                let names: Vec<String> = symbols.iter().map(ToString::to_string).collect();
+                write!(f, "the constraints on {} conflict", names.join(", "))
+            }
+            UnsatReason::UnboundedSearch(symbol) => {
+                write!(f, "`{}` has no finite domain", symbol)
+            }
+            UnsatReason::Failed(error) => write!(f, "{}", unknown_reason(error.clone())),
+        }
+    }
+}
+
 pub fn generate_attempt(free: Vec<Variable>) -> Option<Vec<Assignment>> {
     let mut assigned = Vec::default();
     for x in free {
@@ -21,39 +100,2869 @@ pub fn generate_attempt(free: Vec<Variable>) -> Option<Vec<Assignment>> {
 }
 pub fn apply(
     program: ConstraintProgramExpression,
-    _state: Vec<Assignment>,
+    state: Vec<Assignment>,
 ) -> ConstraintProgramExpression {
-    program
+    use crate::expressions::Apply;
+    program.apply(&state)
+}
+/// Substitutes a single assignment into the program and reports the
+/// names that it bound: the assigned name if the program used it as a
+/// variable of the assigned type, and nothing otherwise. The substitution
+/// notes when it replaces a variable, so this lets a search keep its set
+/// of free variables up to date without walking the program again.
+pub fn apply_tracking(
+    program: ConstraintProgramExpression,
+    assignment: &Assignment,
+) -> (ConstraintProgramExpression, Vec<String>) {
+    use crate::expressions::Apply;
+    let mut substituted = false;
+    let applied = program.apply_noting(std::slice::from_ref(assignment), &mut substituted);
+    let bound = if substituted {
+        vec![assignment.name().name().to_string()]
+    } else {
+        Vec::new()
+    };
+    (applied, bound)
 }
-pub fn reduce(program: ConstraintProgramExpression) -> ConstraintProgramExpression {
-    program
+/// The free variables of the program, one for each name, in the order
+/// the names first occur. A name used with two types, such as a boolean
+/// `x` and an integer `x`, is a conflict.
+pub fn free_variables(
+    program: &ConstraintProgramExpression,
+) -> Result<Vec<Variable>, DomainConflict> {
+    use crate::expressions::FreeVariable;
+    let mut free = VariableSet::new();
+    for variable in program.get_free() {
+        free.insert(variable)?;
+    }
+    Ok(free.into_vec())
+}
+
+/// Variables with distinct names, kept in the order they were first
+/// inserted. Names are looked up by hash, so collecting the variables of
+/// a large expression takes linear rather than quadratic time.
+#[derive(Debug, Clone, Default)]
+pub struct VariableSet {
+    positions: HashMap<Symbol, usize>,
+    variables: Vec<Variable>,
+}
+
+impl VariableSet {
+    pub fn new() -> VariableSet {
+        VariableSet::default()
+    }
+    /// Adds the variable unless one of the same name is already in the
+    /// set, telling whether it was added. A variable of the same name
+    /// whose domain is of another type is a conflict, and leaves the set
+    /// as it was.
+    pub fn insert(&mut self, variable: Variable) -> Result<bool, DomainConflict> {
+        match self.positions.get(variable.name()) {
+            Some(&position) => {
+                let known = &self.variables[position];
+                if same_type(known.domain(), variable.domain()) {
+                    Ok(false)
+                } else {
+                    Err(DomainConflict {
+                        name: variable.name().to_string(),
+                        first: known.domain().clone(),
+                        second: variable.domain().clone(),
+                    })
+                }
+            }
+            None => {
+                self.positions
+                    .insert(variable.name().clone(), self.variables.len());
+                self.variables.push(variable);
+                Ok(true)
+            }
+        }
+    }
+    pub fn contains(&self, name: &Symbol) -> bool {
+        self.positions.contains_key(name)
+    }
+    /// The variables in the order they were first inserted.
+    pub fn into_vec(self) -> Vec<Variable> {
+        self.variables
+    }
+}
+
+/// A variable that occurs with two domains of different types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainConflict {
+    pub name: String,
+    pub first: Domain,
+    pub second: Domain,
+}
+
+/// Whether two domains hold values of the same type.
+fn same_type(domain_a: &Domain, domain_b: &Domain) -> bool {
+    match (domain_a, domain_b) {
+        (Domain::Boolean(_), Domain::Boolean(_))
+        | (Domain::Integer(_), Domain::Integer(_))
+        | (Domain::Rational(_), Domain::Rational(_)) => true,
+        (Domain::Tuple(domains_a), Domain::Tuple(domains_b)) => {
+            domains_a.len() == domains_b.len()
+                && domains_a
+                    .iter()
+                    .zip(domains_b)
+                    .all(|(domain_a, domain_b)| same_type(domain_a, domain_b))
+        }
+        (Domain::Custom(_), Domain::Custom(_)) => domain_a == domain_b,
+        _ => false,
+    }
+}
+
+/// The domain of each free variable of the program by name. An integer
+/// or rational variable restricted by constraints `x in D` with ground
+/// domains gets the intersection of those domains, or the single one,
+/// instead of the universe. A name used with two types, such as a
+/// boolean `x` and an integer `x`, is a conflict.
+pub fn variable_domains(
+    program: &ConstraintProgramExpression,
+) -> Result<HashMap<String, Domain>, DomainConflict> {
+    Ok(narrowed_domains(free_variables(program)?, program))
+}
+
+/// The domains of the variables narrowed by the program's `x in D`
+/// constraints, as `variable_domains` gives them.
+fn narrowed_domains(
+    free: Vec<Variable>,
+    program: &ConstraintProgramExpression,
+) -> HashMap<String, Domain> {
+    let mut domains: HashMap<String, Domain> = free
+        .into_iter()
+        .map(|variable| (variable.name().to_string(), variable.domain().clone()))
+        .collect();
+    for (symbol, restriction) in constraints(program)
+        .into_iter()
+        .filter_map(search::restriction)
+    {
+        if let Some(Domain::Integer(domain)) = domains.get_mut(symbol.name()) {
+            *domain = match &*domain {
+                IntegerNumberDomainExpression::Universe => restriction.clone(),
+                domain => IntegerNumberDomainExpression::Intersection(
+                    Box::new(domain.clone()),
+                    Box::new(restriction.clone()),
+                ),
+            };
+        }
+    }
+    for (symbol, restriction) in constraints(program)
+        .into_iter()
+        .filter_map(search::rational_restriction)
+    {
+        if let Some(Domain::Rational(domain)) = domains.get_mut(symbol.name()) {
+            *domain = match &*domain {
+                RationalDomainExpression::Universe => restriction.clone(),
+                domain => RationalDomainExpression::Intersection(
+                    Box::new(domain.clone()),
+                    Box::new(restriction.clone()),
+                ),
+            };
+        }
+    }
+    domains
+}
+
+/// A problem `validate` finds in a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The name is used for variables of two types, such as a boolean
+    /// `x` and an integer `x`.
+    ConflictingDomain(Symbol),
+    /// The variable's domain, narrowed as `variable_domains` narrows it,
+    /// has no values, so no assignment of the variable exists.
+    EmptyDomain(Symbol),
+}
+
+/// A constraint `validate` finds degenerate. It leaves the program as
+/// solvable as it was, so it is likely a modelling mistake rather than
+/// an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationWarning {
+    /// The constraint with the index is an `alldifferent` of fewer than
+    /// two terms, which always holds.
+    TrivialAllDifferent(usize),
+    /// The constraint with the index has a `sum` of no terms, which is
+    /// `0` and which `simplify` folds to `0`.
+    EmptySum(usize),
 }
 
-pub fn free_variables(program: &ConstraintProgramExpression) -> Vec<Variable> {
+/// Whether an integer expression in the constraint is a `sum` of no
+/// terms.
+fn has_empty_sum(constraint: &ConstraintLogicExpression) -> bool {
+    use crate::expressions::visit::{walk_integer, Visitor};
+    struct EmptySum(bool);
+    impl<'a> Visitor<'a> for EmptySum {
+        fn visit_integer(&mut self, expr: &'a IntegerNumberExpression) {
+            match expr {
+                IntegerNumberExpression::Sum(exprs) if exprs.is_empty() => self.0 = true,
+                expr => walk_integer(self, expr),
+            }
+        }
+    }
+    let mut found = EmptySum(false);
+    found.visit_constraint(constraint);
+    found.0
+}
+
+/// Checks the program before it is solved, reporting every conflicting
+/// name once, in the order the names first occur, followed by every
+/// variable with an empty domain. Unlike `free_variables`, which stops
+/// at the first conflict, all errors are collected. A program without
+/// errors is valid, and the warnings about its degenerate constraints
+/// are returned in the order of `constraints`.
+pub fn validate(
+    program: &ConstraintProgramExpression,
+) -> Result<Vec<ValidationWarning>, Vec<ValidationError>> {
+    use crate::expressions::FreeVariable;
+    let mut conflicting: HashSet<Symbol> = HashSet::new();
+    let mut free = VariableSet::new();
+    for variable in program.get_free() {
+        if let Err(conflict) = free.insert(variable) {
+            conflicting.insert(Symbol::new(conflict.name));
+        }
+    }
+    let free = free.into_vec();
+    let (conflicts, names): (Vec<Symbol>, Vec<Symbol>) = free
+        .iter()
+        .map(|variable| variable.name().clone())
+        .partition(|name| conflicting.contains(name));
+    let mut errors: Vec<ValidationError> = conflicts
+        .into_iter()
+        .map(ValidationError::ConflictingDomain)
+        .collect();
+    let domains = narrowed_domains(free, program);
+    for name in names {
+        if domains[name.name()].as_value_domain().cardinality() == Some(0) {
+            errors.push(ValidationError::EmptyDomain(name));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut warnings = Vec::new();
+    for (index, constraint) in constraints(program).into_iter().enumerate() {
+        if let ConstraintLogicExpression::AllDifferent(exprs) = constraint {
+            if exprs.len() < 2 {
+                warnings.push(ValidationWarning::TrivialAllDifferent(index));
+            }
+        }
+        if has_empty_sum(constraint) {
+            warnings.push(ValidationWarning::EmptySum(index));
+        }
+    }
+    Ok(warnings)
+}
+
+/// The constraints of a program in the order they appear, including
+/// the constraints of its satisfaction goals. Constraint indices
+/// reported by the solver refer to this order.
+pub fn constraints(program: &ConstraintProgramExpression) -> Vec<&ConstraintLogicExpression> {
+    use crate::expressions::SatisfactionExpression::*;
+    use ConstraintProgramExpression::*;
+    let mut found = Vec::new();
+    let mut rest = Some(program);
+    while let Some(program) = rest {
+        let goal = match program {
+            Solve(goal) => {
+                rest = None;
+                Some(goal)
+            }
+            SolveAnd(goal, next) => {
+                rest = Some(next);
+                Some(goal)
+            }
+            ConstrainAnd(constraint, next) => {
+                rest = Some(next);
+                found.push(constraint.as_ref());
+                None
+            }
+        };
+        match goal.map(|goal| goal.as_ref()) {
+            Some(Satisfy(constraint)) | Some(Minimise(constraint)) | Some(Maximise(constraint)) => {
+                found.push(constraint.as_ref())
+            }
+            None => (),
+        }
+    }
+    found
+}
+
+/// The constraints in order, the last one as the `satisfy` goal; `true`
+/// without constraints.
+pub(crate) fn program_of(
+    mut constraints: Vec<ConstraintLogicExpression>,
+) -> ConstraintProgramExpression {
+    let goal = constraints.pop().unwrap_or_else(|| {
+        ConstraintLogicExpression::Boolean(Box::new(BooleanExpression::BooleanValue(
+            BooleanValue::True,
+        )))
+    });
+    let goal = ConstraintProgramExpression::Solve(Box::new(SatisfactionExpression::Satisfy(
+        Box::new(goal),
+    )));
+    constraints
+        .into_iter()
+        .rev()
+        .fold(goal, |rest, constraint| {
+            ConstraintProgramExpression::ConstrainAnd(Box::new(constraint), Box::new(rest))
+        })
+}
+
+fn map_goal(
+    goal: SatisfactionExpression,
+    f: &mut impl FnMut(ConstraintLogicExpression) -> ConstraintLogicExpression,
+) -> SatisfactionExpression {
+    use SatisfactionExpression::*;
+    match goal {
+        Satisfy(constraint) => Satisfy(Box::new(f(*constraint))),
+        Minimise(constraint) => Minimise(Box::new(f(*constraint))),
+        Maximise(constraint) => Maximise(Box::new(f(*constraint))),
+    }
+}
+
+/// Rewrites every constraint of the program with `f`, in `constraints`
+/// order, keeping the structure of the program.
+pub(crate) fn map_constraints(
+    program: ConstraintProgramExpression,
+    f: &mut impl FnMut(ConstraintLogicExpression) -> ConstraintLogicExpression,
+) -> ConstraintProgramExpression {
+    use ConstraintProgramExpression::*;
+    match program {
+        Solve(goal) => Solve(Box::new(map_goal(*goal, f))),
+        SolveAnd(goal, next) => {
+            let goal = map_goal(*goal, f);
+            SolveAnd(Box::new(goal), Box::new(map_constraints(*next, f)))
+        }
+        ConstrainAnd(constraint, next) => {
+            let constraint = f(*constraint);
+            ConstrainAnd(Box::new(constraint), Box::new(map_constraints(*next, f)))
+        }
+    }
+}
+
+fn disjuncts(expr: &BooleanExpression, found: &mut Vec<BooleanExpression>) {
+    match expr {
+        BooleanExpression::Or(expr_a, expr_b) => {
+            disjuncts(expr_a, found);
+            disjuncts(expr_b, found);
+        }
+        BooleanExpression::Parenthesis(expr) => disjuncts(expr, found),
+        _ => found.push(expr.clone()),
+    }
+}
+
+/// Splits the program on its first constraint that is a disjunction,
+/// giving one program per disjunct with the disjunction replaced by it.
+/// Nested disjunctions are flattened, so `a or (b or c)` gives three
+/// programs. The program has a solution exactly when one of the parts
+/// has, so the parts can be solved independently. A program without a
+/// disjunctive constraint is returned whole.
+pub fn split_disjunctions(
+    program: ConstraintProgramExpression,
+) -> Vec<ConstraintProgramExpression> {
+    let split = constraints(&program)
+        .iter()
+        .enumerate()
+        .find_map(|(index, constraint)| {
+            let ConstraintLogicExpression::Boolean(expr) = constraint else {
+                return None;
+            };
+            let mut found = Vec::new();
+            disjuncts(expr, &mut found);
+            (found.len() > 1).then_some((index, found))
+        });
+    let Some((index, found)) = split else {
+        return vec![program];
+    };
+    found
+        .into_iter()
+        .map(|disjunct| {
+            let mut position = 0;
+            map_constraints(program.clone(), &mut |constraint| {
+                position += 1;
+                if position == index + 1 {
+                    ConstraintLogicExpression::Boolean(Box::new(disjunct.clone()))
+                } else {
+                    constraint
+                }
+            })
+        })
+        .collect()
+}
+
+/// Splits the program into independent parts: two constraints go to the
+/// same part when they share a variable, directly or through other
+/// constraints. The parts are in the order of their first constraints,
+/// each holding its constraints in program order, and a solution of the
+/// program is a solution of every part taken together. Goals become
+/// plain `satisfy` constraints, so an objective is not carried over.
+pub fn decompose(program: &ConstraintProgramExpression) -> Vec<ConstraintProgramExpression> {
     use crate::expressions::FreeVariable;
-    program.get_free()
+    let constraints = constraints(program);
+    let names: Vec<Vec<Symbol>> = constraints
+        .iter()
+        .map(|constraint| {
+            constraint
+                .get_free()
+                .iter()
+                .map(|variable| variable.name().clone())
+                .collect()
+        })
+        .collect();
+    // `part[i]` is the part of constraint `i`, merged into the lowest.
+    let mut part: Vec<usize> = (0..constraints.len()).collect();
+    for i in 0..constraints.len() {
+        for j in 0..i {
+            if part[i] != part[j] && names[i].iter().any(|name| names[j].contains(name)) {
+                let (from, to) = (part[i].max(part[j]), part[i].min(part[j]));
+                part.iter_mut()
+                    .filter(|other| **other == from)
+                    .for_each(|other| *other = to);
+            }
+        }
+    }
+    let mut firsts: Vec<usize> = part.clone();
+    firsts.sort_unstable();
+    firsts.dedup();
+    firsts
+        .into_iter()
+        .map(|first| {
+            program_of(
+                constraints
+                    .iter()
+                    .zip(&part)
+                    .filter(|(_, of)| **of == first)
+                    .map(|(constraint, _)| (*constraint).clone())
+                    .collect(),
+            )
+        })
+        .collect()
 }
-pub fn solve(_program: ConstraintProgramExpression) -> Vec<Solution> {
-    Vec::new()
+
+/// Binds the integer subexpressions that occur more than once to fresh
+/// symbols `t0`, `t1` and so on with `Let`, and refers to those instead.
+/// As a `Let` is itself an integer expression, occurrences are shared
+/// within one operand of a comparison, not across operands or
+/// constraints. The fresh symbols avoid the names of the free variables.
+pub fn hoist_common_subexpressions(
+    program: ConstraintProgramExpression,
+) -> ConstraintProgramExpression {
+    use crate::expressions::FreeVariable;
+    let mut symbols = SymbolGenerator::new(
+        "t",
+        (&program)
+            .get_free()
+            .into_iter()
+            .map(|variable| variable.name().clone()),
+    );
+    map_constraints(program, &mut |constraint| match constraint {
+        ConstraintLogicExpression::OfIntegerNumber(expr) => {
+            ConstraintLogicExpression::OfIntegerNumber(Box::new(expr.hoisted(&mut symbols)))
+        }
+        constraint => constraint,
+    })
+}
+
+fn violated(program: &ConstraintProgramExpression, env: &[Assignment]) -> bool {
+    use crate::expressions::Evaluate;
+    let ground = apply(program.clone(), env.to_vec());
+    constraints(&ground)
+        .iter()
+        .any(|constraint| constraint.evaluate(&[]) == Ok(BooleanValue::False))
+}
+
+/// Shrinks an assignment that violates the program down to a minimal
+/// subset of its bindings that still makes some constraint evaluate to
+/// `False`; dropping any one of the returned bindings leaves every
+/// constraint either satisfied or not yet evaluable. Returns `None` when
+/// `env` violates no constraint.
+pub fn minimal_violation(
+    program: &ConstraintProgramExpression,
+    env: &[Assignment],
+) -> Option<Vec<Assignment>> {
+    if !violated(program, env) {
+        return None;
+    }
+    let mut kept = env.to_vec();
+    let mut index = 0;
+    while index < kept.len() {
+        let mut candidate = kept.clone();
+        candidate.remove(index);
+        if violated(program, &candidate) {
+            kept = candidate;
+        } else {
+            index += 1;
+        }
+    }
+    Some(kept)
+}
+
+/// How many constraints `env` satisfies, out of how many it decides.
+/// A constraint that cannot be evaluated for want of a binding counts in
+/// neither; one decided by its bound variables alone, such as `p or q`
+/// with `p` true, counts even when others are unbound.
+pub fn satisfaction_degree(
+    program: &ConstraintProgramExpression,
+    env: &[Assignment],
+) -> (usize, usize) {
+    use crate::expressions::Evaluate;
+    constraints(program)
+        .iter()
+        .fold((0, 0), |(satisfied, total), constraint| {
+            match constraint.evaluate(env) {
+                Ok(BooleanValue::True) => (satisfied + 1, total + 1),
+                Ok(BooleanValue::False) => (satisfied, total + 1),
+                Err(_) => (satisfied, total),
+            }
+        })
+}
+
+/// The program with every variable `solution` assigns replaced by its
+/// value. Variables it leaves unassigned stay free, so the result is
+/// ground only when `solution` is complete; `is_satisfied_ground`
+/// rejects a program that is not.
+pub fn ground(
+    program: &ConstraintProgramExpression,
+    solution: &[Assignment],
+) -> ConstraintProgramExpression {
+    use crate::expressions::Apply;
+    program.apply(solution)
+}
+
+/// Whether `program` has no free variables and every constraint
+/// evaluates to `True`, which makes it an independent check of a
+/// solution once the solution has been substituted by `ground`.
+pub fn is_satisfied_ground(program: &ConstraintProgramExpression) -> bool {
+    use crate::expressions::{Evaluate, FreeVariable};
+    program.get_free().is_empty()
+        && constraints(program)
+            .iter()
+            .all(|constraint| constraint.evaluate(&[]) == Ok(BooleanValue::True))
+}
+
+/// The first satisfying assignment found by backtracking search, one
+/// `Solution::Variable` per free variable, or for a program with a
+/// `minimise` or `maximise` goal the best one, the first found among
+/// equally good ones, as `solve_with` optimises. Otherwise a single
+/// `Solution::Unsatisfiable` says why. When there is no solution the
+/// reason is the variable `reduce` leaves without values, if there is
+/// one, and otherwise the variables of a core of conflicting
+/// constraints, found as `check` finds it.
+pub fn solve(program: ConstraintProgramExpression) -> Vec<Solution> {
+    use crate::expressions::FreeVariable;
+    let config = SolveConfig {
+        goal: SolveGoal::First,
+        ..SolveConfig::default()
+    };
+    let reason = match solve_with(&program, &config) {
+        Ok(report) => match report.solutions.into_iter().next() {
+            Some(solution) => return solution,
+            None => match reduce(program.clone()) {
+                Reduction::Unsatisfiable(symbol) => UnsatReason::EmptyDomain(symbol),
+                Reduction::Reduced(_) => {
+                    let all = constraints(&program);
+                    let mut symbols: Vec<Symbol> = Vec::new();
+                    for index in unsat_core(&program, &config) {
+                        for variable in all[index].get_free() {
+                            if !symbols.contains(variable.name()) {
+                                symbols.push(variable.name().clone());
+                            }
+                        }
+                    }
+                    UnsatReason::ConflictingConstraints(symbols)
+                }
+            },
+        },
+        Err(SolveError::Unbounded(symbol)) => UnsatReason::UnboundedSearch(symbol),
+        Err(error) => UnsatReason::Failed(error),
+    };
+    vec![Solution::Unsatisfiable(reason)]
+}
+
+/// Rewrites the program so that programs differing only in the order of
+/// commutative operands, the grouping of associative ones or redundant
+/// parentheses become identical.
+pub fn canonicalize(program: ConstraintProgramExpression) -> ConstraintProgramExpression {
+    use crate::expressions::Canonicalize;
+    program.canonicalize()
+}
+
+/// A normal form of the program: its constraints canonicalized, without
+/// duplicates and in ascending order, all but the last as plain
+/// constraints and the last as the `satisfy` goal. The first `minimise`
+/// or `maximise` goal stays the goal instead, after all the others and as
+/// written, since canonicalizing may swap the sides of its comparison
+/// and with them the objective. A program with a constraint that
+/// `find_trivial_contradictions` reports becomes `satisfy false;`; that
+/// lint passes over terms that may be `NaN`, which can differ from
+/// themselves, so the program keeps its solutions.
+/// Programs with the same constraints in any order and grouping
+/// normalize to the same tree.
+pub fn normalize_program(program: ConstraintProgramExpression) -> ConstraintProgramExpression {
+    use crate::expressions::Canonicalize;
+    let falsity = || {
+        ConstraintLogicExpression::Boolean(Box::new(BooleanExpression::BooleanValue(
+            BooleanValue::False,
+        )))
+    };
+    if !find_trivial_contradictions(&program).is_empty() {
+        return program_of(vec![falsity()]);
+    }
+    let mut objective = None;
+    let mut rest = Some(&program);
+    while let Some(program) = rest {
+        let goal = match program {
+            ConstraintProgramExpression::Solve(goal) => {
+                rest = None;
+                goal
+            }
+            ConstraintProgramExpression::SolveAnd(goal, next) => {
+                rest = Some(next);
+                goal
+            }
+            ConstraintProgramExpression::ConstrainAnd(_, next) => {
+                rest = Some(next);
+                continue;
+            }
+        };
+        if !matches!(goal.as_ref(), SatisfactionExpression::Satisfy(_)) {
+            objective = Some(goal.as_ref().clone());
+            break;
+        }
+    }
+    let mut all: Vec<ConstraintLogicExpression> = constraints(&program)
+        .into_iter()
+        .map(|constraint| constraint.clone().canonicalize())
+        .collect();
+    all.sort();
+    all.dedup();
+    if let Some(
+        SatisfactionExpression::Minimise(constraint) | SatisfactionExpression::Maximise(constraint),
+    ) = &objective
+    {
+        let constraint = constraint.as_ref().clone().canonicalize();
+        all.retain(|other| *other != constraint);
+    }
+    match objective {
+        Some(goal) => all.into_iter().rev().fold(
+            ConstraintProgramExpression::Solve(Box::new(goal)),
+            |rest, constraint| {
+                ConstraintProgramExpression::ConstrainAnd(Box::new(constraint), Box::new(rest))
+            },
+        ),
+        None => program_of(all),
+    }
+}
+
+/// 64-bit FNV-1a. Unlike the standard library hasher it is unkeyed, so
+/// its output does not change between runs.
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// A hash of the canonical form of the program, suitable as a key for
+/// caching solved results: programs that `canonicalize` to the same
+/// expression share a fingerprint. The value is stable across runs of
+/// the same crate version but may change between versions.
+pub fn program_fingerprint(program: &ConstraintProgramExpression) -> u64 {
+    let mut hasher = Fnv1a(0xcbf2_9ce4_8422_2325);
+    canonicalize(program.clone()).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Reasons the solver could not search a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveError {
+    /// The variable has no finite domain to enumerate.
+    Unbounded(Symbol),
+    /// A `minimise` or `maximise` goal whose constraint is not an integer
+    /// comparison, so that it has no integer expression to optimise.
+    NoObjective(ConstraintLogicExpression),
+    /// Writing a solution out failed.
+    Write(std::io::ErrorKind),
+    /// No strategy is registered under the name.
+    UnknownStrategy(String),
+    /// The incremental solver holds no constraint with the handle.
+    UnknownConstraint(ConstraintId),
+    /// The name is used for variables of two types, as `free_variables`
+    /// reports in more detail.
+    Conflict(String),
+}
+
+/// Whether and how the solver learns no-goods, combinations of values
+/// that it has found to admit no solution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Learning {
+    Off,
+    /// Keep learned no-goods of at most `max_size` values. Once more
+    /// than `capacity` are held, the least recently useful half is
+    /// dropped.
+    NoGoods {
+        capacity: usize,
+        max_size: usize,
+    },
+}
+
+/// How the solver narrows the variable domains before it searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    Off,
+    /// Make every constraint over exactly two variables arc consistent
+    /// with AC-3, removing each value that no value of the other variable
+    /// supports. Bounds on a distance, `abs(x - y) < d` or
+    /// `abs(x - y) > d`, look for support among the nearest or furthest
+    /// values only.
+    Ac3,
+}
+
+/// The order in which the search tries the values of a variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueOrder {
+    /// Ascending, or descending for the variables `solve_lexicographic`
+    /// maximises.
+    Ascending,
+    /// When optimising, the values of the variables the objective
+    /// mentions are tried best first: each is scored by the objective
+    /// with that variable at the value and the others at their smallest
+    /// values, so the first solutions found tend to be good incumbents.
+    /// Without an objective, the same as `Ascending`.
+    ObjectiveBiased,
+}
+
+/// What `solve_with` reports about the solutions of a program without an
+/// objective. A program with one is always searched for its optimum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveGoal {
+    /// Stop at the first solution.
+    First,
+    /// Every solution, or the first `limit` of them.
+    All { limit: Option<usize> },
+    /// Only the number of solutions, without keeping them.
+    Count,
+}
+
+/// Options for `solve_with`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolveConfig {
+    pub goal: SolveGoal,
+    pub learning: Learning,
+    /// The most values the search tries before giving up, unlimited when
+    /// `None`.
+    pub node_limit: Option<usize>,
+    pub propagation: Propagation,
+    pub value_order: ValueOrder,
+    /// Domains for free variables of the same name, searched in addition
+    /// to the domains the program gives them. This is how a variable
+    /// ranges over a `Domain::Custom`.
+    pub domains: Vec<Variable>,
+    /// How `Divide` and `Modulo` round with negative operands while
+    /// searching.
+    pub division: DivisionMode,
+    /// How many values an integer variable that no domain bounds tries,
+    /// in the order `0, 1, -1, 2, -2, ...`. Such a variable is an
+    /// `Unbounded` error when `None`. This is a heuristic for finding
+    /// witnesses near zero: a search that tries it is never complete,
+    /// since a solution may need a value further out.
+    pub widening: Option<usize>,
+}
+
+impl Default for SolveConfig {
+    fn default() -> SolveConfig {
+        SolveConfig {
+            goal: SolveGoal::All { limit: None },
+            learning: Learning::Off,
+            node_limit: None,
+            propagation: Propagation::Off,
+            value_order: ValueOrder::Ascending,
+            domains: Vec::new(),
+            division: DivisionMode::TruncateTowardZero,
+            widening: None,
+        }
+    }
+}
+
+/// Counters describing the work done by one search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SolveStats {
+    /// Values tried for a variable.
+    pub nodes: usize,
+    /// Partial assignments refuted by a constraint or a no-good.
+    pub conflicts: usize,
+    /// No-goods learned in total.
+    pub learned: usize,
+    /// The largest number of no-goods held at one time.
+    pub peak_learned: usize,
+    /// Times the no-good database was cleaned up.
+    pub cleanups: usize,
+}
+
+/// The best answer a search found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolveOutcome {
+    /// For optimisation the best solution found, otherwise the first
+    /// solution or, when the search ran out of budget before finding one,
+    /// the longest partial assignment that no constraint refutes. Empty
+    /// when there is none.
+    pub solutions: Vec<Solution>,
+    /// Whether the search finished, so that an optimum is proven optimal
+    /// and a missing solution proven not to exist.
+    pub complete: bool,
+}
+
+/// The result of `solve_with`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolveReport {
+    /// Every solution found or, for optimisation, the best one. Empty
+    /// under `SolveGoal::Count`.
+    pub solutions: Vec<Vec<Solution>>,
+    /// The number of solutions found, or 1 for an optimum.
+    pub count: usize,
+    pub outcome: SolveOutcome,
+    pub stats: SolveStats,
+}
+
+/// Every satisfying assignment of the program, each given as one
+/// `Solution::Variable` per free variable.
+pub fn solve_all(program: &ConstraintProgramExpression) -> Result<Vec<Vec<Solution>>, SolveError> {
+    Ok(solve_with(program, &SolveConfig::default())?.solutions)
+}
+
+/// The number of satisfying assignments of the program, counted without
+/// keeping them. As for `solutions`, a `minimise` or `maximise` goal
+/// counts as a constraint, so every solution counts and not only the
+/// best.
+pub fn count_models(program: &ConstraintProgramExpression) -> Result<usize, SolveError> {
+    Ok(solutions(program)?.count())
+}
+
+/// How many solutions a program has, as far as `count_up_to` counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountResult {
+    /// The program has exactly this many solutions.
+    Exact(u64),
+    /// The program has more solutions than the cap, which this holds.
+    AtLeast(u64),
+}
+
+/// The number of satisfying assignments of the program if it is at most
+/// `cap`, counted without keeping them. The search stops at the first
+/// solution beyond the cap, so telling whether a program has more than
+/// `cap` solutions costs about as much as finding `cap + 1` of them.
+/// Goals count as `count_models` counts them.
+pub fn count_up_to(
+    program: &ConstraintProgramExpression,
+    cap: u64,
+) -> Result<CountResult, SolveError> {
+    let mut count: u64 = 0;
+    for _ in solutions(program)? {
+        count += 1;
+        if count > cap {
+            break;
+        }
+    }
+    Ok(if count > cap {
+        CountResult::AtLeast(cap)
+    } else {
+        CountResult::Exact(count)
+    })
+}
+
+/// Whether a program has a solution, as far as the solver could tell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Satisfiability {
+    /// A solution, or for optimisation the best one.
+    Sat(Vec<Solution>),
+    /// No solution exists. `core` holds the indices, in `constraints`
+    /// order, of constraints that have no solution together.
+    Unsat { core: Vec<usize> },
+    /// The solver gave up, for example at the node limit or on a variable
+    /// without a finite domain.
+    Unknown { reason: String },
+}
+
+/// Why a search that failed with `error` could not decide a program.
+fn unknown_reason(error: SolveError) -> String {
+    match error {
+        SolveError::Unbounded(symbol) => format!("`{}` has no finite domain", symbol),
+        SolveError::NoObjective(goal) => format!("`{}` has no integer objective", goal),
+        SolveError::Write(kind) => format!("writing failed: {}", kind),
+        SolveError::UnknownStrategy(name) => format!("no strategy is called `{}`", name),
+        SolveError::UnknownConstraint(id) => format!("no constraint has the handle {:?}", id),
+        SolveError::Conflict(name) => format!("`{}` is used with two types", name),
+    }
+}
+
+/// Decides whether the program has a solution with the default search.
+pub fn check(program: &ConstraintProgramExpression) -> Satisfiability {
+    check_with(program, &SolveConfig::default())
+}
+
+/// Decides whether the program has a solution, searching as `config`
+/// asks but stopping at the first solution. The core of an unsatisfiable
+/// program is shrunk by leaving out one constraint at a time, with the
+/// same configuration, whenever the rest is still shown unsatisfiable;
+/// a constraint whose removal leaves a variable unbounded stays in.
+pub fn check_with(program: &ConstraintProgramExpression, config: &SolveConfig) -> Satisfiability {
+    let config = SolveConfig {
+        goal: SolveGoal::First,
+        ..config.clone()
+    };
+    let report = match solve_with(program, &config) {
+        Ok(report) => report,
+        Err(error) => {
+            return Satisfiability::Unknown {
+                reason: unknown_reason(error),
+            }
+        }
+    };
+    if let Some(solution) = report.solutions.into_iter().next() {
+        return Satisfiability::Sat(solution);
+    }
+    if !report.outcome.complete {
+        return Satisfiability::Unknown {
+            reason: "the search was not exhaustive".to_string(),
+        };
+    }
+    Satisfiability::Unsat {
+        core: unsat_core(program, &config),
+    }
+}
+
+/// The indices, in `constraints` order, of constraints of a program
+/// shown unsatisfiable that still have no solution together once any
+/// one of them is left out, searching as `config` asks.
+fn unsat_core(program: &ConstraintProgramExpression, config: &SolveConfig) -> Vec<usize> {
+    let unsat = |program: &ConstraintProgramExpression| {
+        matches!(
+            solve_with(program, config),
+            Ok(SolveReport {
+                count: 0,
+                outcome: SolveOutcome { complete: true, .. },
+                ..
+            })
+        )
+    };
+    let all: Vec<ConstraintLogicExpression> = constraints(program).into_iter().cloned().collect();
+    let mut core: Vec<usize> = (0..all.len()).collect();
+    let mut index = 0;
+    while index < core.len() {
+        let rest: Vec<ConstraintLogicExpression> = core
+            .iter()
+            .filter(|kept| **kept != core[index])
+            .map(|kept| all[*kept].clone())
+            .collect();
+        if unsat(&program_of(rest)) {
+            core.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+    core
+}
+
+/// Whether a program has a solution under a set of assumed values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssumeResult {
+    /// A solution agreeing with every assumption. The assumed values of
+    /// the program's variables come first.
+    Sat(Vec<Solution>),
+    /// No solution agrees with the assumptions in `core` together, while
+    /// dropping any one of them leaves some that might. An empty core
+    /// means the program has no solution whatever is assumed.
+    Unsat { core: Vec<Assignment> },
+    /// The solver gave up, as for `Satisfiability::Unknown`.
+    Unknown { reason: String },
+}
+
+/// Decides whether the program has a solution in which the variables
+/// take the values in `assumptions`, and if not, which assumptions
+/// cannot hold together. The assumptions are substituted into the
+/// program, so a value outside a variable's domain conflicts with the
+/// constraint restricting it. The core is shrunk by leaving out one
+/// assumption at a time whenever the rest is still shown unsatisfiable.
+pub fn solve_assuming(
+    program: &ConstraintProgramExpression,
+    assumptions: &[Assignment],
+) -> AssumeResult {
+    let config = SolveConfig {
+        goal: SolveGoal::First,
+        ..SolveConfig::default()
+    };
+    let unsat = |assumed: &[Assignment]| {
+        matches!(
+            solve_with(&ground(program, assumed), &config),
+            Ok(SolveReport {
+                count: 0,
+                outcome: SolveOutcome { complete: true, .. },
+                ..
+            })
+        )
+    };
+    let report = match solve_with(&ground(program, assumptions), &config) {
+        Ok(report) => report,
+        Err(error) => {
+            return AssumeResult::Unknown {
+                reason: unknown_reason(error),
+            }
+        }
+    };
+    if let Some(solution) = report.solutions.into_iter().next() {
+        use crate::expressions::FreeVariable;
+        let free = program.get_free();
+        let assumed = assumptions
+            .iter()
+            .filter(|assumption| {
+                free.iter()
+                    .any(|variable| variable.name() == assumption.name())
+            })
+            .map(|assumption| {
+                Solution::Variable(assumption.name().clone(), assumption.value().clone())
+            });
+        return AssumeResult::Sat(assumed.chain(solution).collect());
+    }
+    if !report.outcome.complete {
+        return AssumeResult::Unknown {
+            reason: "the search was not exhaustive".to_string(),
+        };
+    }
+    let mut core = assumptions.to_vec();
+    let mut index = 0;
+    while index < core.len() {
+        let mut rest = core.clone();
+        rest.remove(index);
+        if unsat(&rest) {
+            core = rest;
+        } else {
+            index += 1;
+        }
+    }
+    AssumeResult::Unsat { core }
+}
+
+/// The domain of exactly the given values, or of the tuples whose
+/// components are among theirs, `None` if the values are not all of one
+/// type.
+fn domain_of(values: &[AssignedValue]) -> Option<Domain> {
+    match values.first()? {
+        AssignedValue::Boolean(_) => values
+            .iter()
+            .all(|value| matches!(value, AssignedValue::Boolean(_)))
+            .then_some(Domain::Boolean(BooleanValueDomainExpression::Universe)),
+        AssignedValue::Integer(_) => values
+            .iter()
+            .map(|value| match value {
+                AssignedValue::Integer(n) => {
+                    Some(IntegerNumberExpression::IntegerNumberValue(n.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .map(|set| Domain::Integer(IntegerNumberDomainExpression::ExplicitSet(set))),
+        AssignedValue::Rational(_) => values
+            .iter()
+            .map(|value| match value {
+                AssignedValue::Rational(q) => Some(RationalExpression::RationalValue(q.clone())),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .map(|set| Domain::Rational(RationalDomainExpression::ExplicitSet(set))),
+        AssignedValue::Tuple(first) => {
+            let tuples: Vec<&Vec<AssignedValue>> = values
+                .iter()
+                .map(|value| match value {
+                    AssignedValue::Tuple(components) if components.len() == first.len() => {
+                        Some(components)
+                    }
+                    _ => None,
+                })
+                .collect::<Option<_>>()?;
+            (0..first.len())
+                .map(|index| {
+                    let components: Vec<AssignedValue> = tuples
+                        .iter()
+                        .map(|components| components[index].clone())
+                        .collect();
+                    domain_of(&components)
+                })
+                .collect::<Option<_>>()
+                .map(Domain::Tuple)
+        }
+    }
+}
+
+/// Solves `base` once for each variation, with the variables the
+/// variation assigns fixed to its values, and gives the first solution of
+/// each, the optimum for a program with an objective, or `None` where
+/// there is none. The program is prepared for search only once: a
+/// variable that some variation fixes ranges over the values the
+/// variations give it, so a parameter needs no domain of its own in
+/// `base`, and each variation narrows a copy of the prepared search. A
+/// parameter given values of two types is a `SolveError::Conflict`.
+pub fn solve_batch(
+    base: &ConstraintProgramExpression,
+    variations: Vec<Vec<Assignment>>,
+) -> Vec<Result<Option<Vec<Solution>>, SolveError>> {
+    let mut fixed: Vec<(Symbol, Vec<AssignedValue>)> = Vec::new();
+    for assignment in variations.iter().flatten() {
+        match fixed.iter_mut().find(|(name, _)| name == assignment.name()) {
+            Some((_, values)) if values.contains(assignment.value()) => (),
+            Some((_, values)) => values.push(assignment.value().clone()),
+            None => fixed.push((assignment.name().clone(), vec![assignment.value().clone()])),
+        }
+    }
+    let domains: Result<Vec<Variable>, SolveError> = fixed
+        .into_iter()
+        .map(|(name, values)| match domain_of(&values) {
+            Some(domain) => Ok(Variable::new(name, domain)),
+            None => Err(SolveError::Conflict(name.to_string())),
+        })
+        .collect();
+    let config = SolveConfig {
+        domains: match domains {
+            Ok(domains) => domains,
+            Err(error) => return variations.iter().map(|_| Err(error.clone())).collect(),
+        },
+        ..SolveConfig::default()
+    };
+    let search = match search::Search::new(base, &config) {
+        Ok(search) => search,
+        Err(error) => return variations.iter().map(|_| Err(error.clone())).collect(),
+    };
+    let optimises = search.optimises();
+    variations
+        .iter()
+        .map(|variation| {
+            let mut search = search.clone();
+            let known: Vec<(Symbol, Vec<AssignedValue>)> = variation
+                .iter()
+                .map(|assignment| (assignment.name().clone(), vec![assignment.value().clone()]))
+                .collect();
+            search.restrict(&known);
+            let mut best = None;
+            search.run(&config, &mut SolveStats::default(), |env| {
+                best = Some(to_solution(env));
+                if optimises {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            });
+            Ok(best)
+        })
+        .collect()
+}
+
+/// The first solution of the program, found by solving the parts
+/// `decompose` splits it into on their own threads, or an empty one
+/// where there is none. The parts are dealt out in turn to at most as
+/// many threads as the machine runs in parallel, so that many small
+/// parts do not each get a thread, and their solutions are joined in the
+/// order of the parts, so the result does not depend on the scheduling.
+/// It is the first solution `solve_with` finds for `SolveGoal::First`, up
+/// to the order of the variables. A program with an objective is solved
+/// on the calling thread, since its parts cannot be optimised apart.
+#[cfg(feature = "parallel")]
+pub fn solve_parallel(program: &ConstraintProgramExpression) -> Result<Vec<Solution>, SolveError> {
+    let first = |program: &ConstraintProgramExpression| {
+        let config = SolveConfig {
+            goal: SolveGoal::First,
+            ..SolveConfig::default()
+        };
+        Ok::<_, SolveError>(solve_with(program, &config)?.solutions.pop())
+    };
+    if search::objective(program)?.is_some() {
+        return Ok(first(program)?.unwrap_or_default());
+    }
+    let parts = decompose(program);
+    let threads = std::thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(parts.len())
+        .max(1);
+    let mut found: Vec<Option<Result<Option<Vec<Solution>>, SolveError>>> = vec![None; parts.len()];
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                let parts = &parts;
+                let first = &first;
+                scope.spawn(move || {
+                    (thread..parts.len())
+                        .step_by(threads)
+                        .map(|index| (index, first(&parts[index])))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            for (index, result) in handle.join().expect("a part's search panicked") {
+                found[index] = Some(result);
+            }
+        }
+    });
+    let mut solution = Vec::new();
+    for result in found.into_iter().flatten() {
+        match result? {
+            Some(part) => solution.extend(part),
+            None => return Ok(Vec::new()),
+        }
+    }
+    Ok(solution)
+}
+
+fn to_solution(env: &[Assignment]) -> Vec<Solution> {
+    env.iter()
+        .map(|assignment| Solution::Variable(assignment.name().clone(), assignment.value().clone()))
+        .collect()
+}
+
+/// Like `solve_all`, with the search configured by `config` and the
+/// solutions reported as its goal asks, along with statistics about the
+/// search. A program with a `minimise` or `maximise` goal is optimised
+/// by branch and bound instead: the left side of the goal's comparison
+/// is the objective, and the goal's constraint must hold as well. When
+/// the node limit cuts the search short, or widening stands in for an
+/// unbounded domain, the outcome holds the best that was found and is
+/// marked incomplete.
+pub fn solve_with(
+    program: &ConstraintProgramExpression,
+    config: &SolveConfig,
+) -> Result<SolveReport, SolveError> {
+    let mut search = search::Search::new(program, config)?;
+    search.propagate(&config.propagation);
+    if config.value_order == ValueOrder::ObjectiveBiased {
+        search.bias_towards_objective();
+    }
+    let mut solutions = Vec::new();
+    let mut count = 0;
+    let mut stats = SolveStats::default();
+    let goal = if search.optimises() {
+        SolveGoal::All { limit: None }
+    } else {
+        config.goal
+    };
+    let ended = search.run(config, &mut stats, |env| match goal {
+        SolveGoal::Count => {
+            count += 1;
+            ControlFlow::Continue(())
+        }
+        SolveGoal::First => {
+            solutions.push(to_solution(env));
+            ControlFlow::Break(())
+        }
+        SolveGoal::All { limit } => {
+            if limit.is_some_and(|limit| solutions.len() >= limit) {
+                return ControlFlow::Break(());
+            }
+            solutions.push(to_solution(env));
+            if limit.is_some_and(|limit| solutions.len() >= limit) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        }
+    });
+    if search.optimises() {
+        solutions = solutions.pop().into_iter().collect();
+    }
+    if goal != SolveGoal::Count {
+        count = solutions.len();
+    }
+    let best = match (solutions.first(), &ended) {
+        (Some(solution), _) => solution.clone(),
+        (None, search::Ended::OutOfBudget(deepest)) => to_solution(deepest),
+        (None, _) => Vec::new(),
+    };
+    Ok(SolveReport {
+        solutions,
+        count,
+        outcome: SolveOutcome {
+            solutions: best,
+            complete: !matches!(ended, search::Ended::OutOfBudget(_)) && !search.widened(),
+        },
+        stats,
+    })
+}
+
+/// The values each free variable may take once the domains are narrowed
+/// as `config` asks, without searching. A variable left without values
+/// shows the program unsatisfiable. Variables computed by a definition
+/// without a finite domain of their own are left out.
+pub fn propagate_only(
+    program: &ConstraintProgramExpression,
+    config: &SolveConfig,
+) -> Result<Vec<(Symbol, Vec<AssignedValue>)>, SolveError> {
+    let mut search = search::Search::new(program, config)?;
+    search.propagate(&config.propagation);
+    Ok(search.bounded_variables())
+}
+
+/// Every solution of the program, each an assignment to all of its free
+/// variables in the order `solve_all` gives them. The solutions are
+/// found by backtracking as the iterator advances, holding only the
+/// current branch, so `take(n)` or `count()` over a large search space
+/// never holds the rest. A `minimise` or `maximise` goal is only a
+/// constraint here: every solution is yielded, not the best one.
+/// Variables need finite domains as for `solve_all`.
+pub fn solutions(
+    program: &ConstraintProgramExpression,
+) -> Result<impl Iterator<Item = Vec<Assignment>> + '_, SolveError> {
+    Ok(search::Search::new(program, &SolveConfig::default())?.solutions())
+}
+
+/// The distinct values the named variable takes across all solutions of
+/// the program, in ascending order; booleans before integers, `false`
+/// before `true` and `NaN` before any number. The solutions are
+/// enumerated, so like `solve_all` this fails with `Unbounded` when some
+/// variable has no finite domain. A variable the program does not
+/// mention takes no values.
+pub fn projected_domain(
+    program: &ConstraintProgramExpression,
+    var: &str,
+) -> Result<Vec<AssignedValue>, SolveError> {
+    let mut values: Vec<AssignedValue> = solve_all(program)?
+        .iter()
+        .flatten()
+        .filter_map(|binding| match binding {
+            Solution::Variable(name, value) if name.name() == var => Some(value.clone()),
+            _ => None,
+        })
+        .collect();
+    values.sort();
+    values.dedup();
+    Ok(values)
+}
+
+/// The program with the named integer variable further restricted to
+/// `domain`, by a constraint `var in domain` placed before the others.
+/// The restrictions already in the program still apply, so the variable
+/// ranges over the intersection.
+pub fn with_domain(
+    program: ConstraintProgramExpression,
+    var: &str,
+    domain: IntegerNumberDomainExpression,
+) -> ConstraintProgramExpression {
+    let variable = IntegerNumberExpression::IntegerNumberVariable(Symbol::new(var.to_string()));
+    ConstraintProgramExpression::ConstrainAnd(
+        Box::new(ConstraintLogicExpression::OfIntegerNumber(Box::new(
+            BooleanIntegerNumberExpression::In(Box::new(variable), Box::new(domain)),
+        ))),
+        Box::new(program),
+    )
+}
+
+/// The order in which `solve_lexicographic` prefers solutions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexDirection {
+    Smallest,
+    Largest,
+}
+
+/// The solution whose values of the variables in `order`, compared in
+/// that order, are the smallest or largest, found as the first solution
+/// of a search that branches on those variables first. Empty when the
+/// program has no solution. Any objective of the program is ignored. The
+/// variables in `order` need finite domains, even those computed by a
+/// definition.
+pub fn solve_lexicographic(
+    program: &ConstraintProgramExpression,
+    order: Vec<Symbol>,
+    direction: LexDirection,
+) -> Result<Vec<Solution>, SolveError> {
+    let mut search = search::Search::new(program, &SolveConfig::default())?;
+    search.prioritise(&order, direction == LexDirection::Largest)?;
+    let mut first = Vec::new();
+    search.run(&SolveConfig::default(), &mut SolveStats::default(), |env| {
+        first = to_solution(env);
+        ControlFlow::Break(())
+    });
+    Ok(first)
+}
+
+/// The variable bindings of a solution keyed by variable name.
+pub fn bindings(solution: &[Solution]) -> HashMap<String, AssignedValue> {
+    solution
+        .iter()
+        .filter_map(|binding| match binding {
+            Solution::Variable(name, value) | Solution::Constant(name, value) => {
+                Some((name.name().to_string(), value.clone()))
+            }
+            Solution::Unsatisfiable(_) => None,
+        })
+        .collect()
+}
+
+/// Aggregate statistics over the values assigned in a solution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolutionAggregate {
+    /// Sum of the integer values other than `NaN`; `NaN` if it overflows.
+    pub integer_sum: IntegerNumber,
+    pub integer_min: Option<i128>,
+    pub integer_max: Option<i128>,
+    pub nan_count: usize,
+    pub true_count: usize,
+    /// Share of the boolean values that are `True`, `None` without booleans.
+    pub true_fraction: Option<f64>,
+}
+
+/// Folds the variable and constant bindings of `solutions` into
+/// aggregate statistics. `Unsatisfiable` entries and tuples are ignored.
+pub fn aggregate(solutions: &[Solution]) -> SolutionAggregate {
+    let mut aggregate = SolutionAggregate {
+        integer_sum: IntegerNumber::Value(0),
+        integer_min: None,
+        integer_max: None,
+        nan_count: 0,
+        true_count: 0,
+        true_fraction: None,
+    };
+    let mut boolean_count = 0;
+    for solution in solutions {
+        let value = match solution {
+            Solution::Variable(_, value) | Solution::Constant(_, value) => value,
+            Solution::Unsatisfiable(_) => continue,
+        };
+        match value {
+            AssignedValue::Integer(IntegerNumber::Value(n)) => {
+                aggregate.integer_sum = match aggregate.integer_sum {
+                    IntegerNumber::Value(sum) => sum
+                        .checked_add(*n)
+                        .map_or(IntegerNumber::NaN, IntegerNumber::Value),
+                    IntegerNumber::NaN => IntegerNumber::NaN,
+                };
+                aggregate.integer_min = Some(aggregate.integer_min.map_or(*n, |min| min.min(*n)));
+                aggregate.integer_max = Some(aggregate.integer_max.map_or(*n, |max| max.max(*n)));
+            }
+            AssignedValue::Integer(IntegerNumber::NaN) => aggregate.nan_count += 1,
+            AssignedValue::Boolean(value) => {
+                boolean_count += 1;
+                if *value == BooleanValue::True {
+                    aggregate.true_count += 1;
+                }
+            }
+            AssignedValue::Rational(_) | AssignedValue::Tuple(_) => (),
+        }
+    }
+    if boolean_count > 0 {
+        aggregate.true_fraction = Some(aggregate.true_count as f64 / boolean_count as f64);
+    }
+    aggregate
+}
+
+/// Solves the program like `solve_all` and maps the bindings of each
+/// solution through `f`.
+pub fn solve_into<T, F: Fn(&HashMap<String, AssignedValue>) -> T>(
+    program: &ConstraintProgramExpression,
+    f: F,
+) -> Result<Vec<T>, SolveError> {
+    Ok(solve_all(program)?
+        .iter()
+        .map(|solution| f(&bindings(solution)))
+        .collect())
+}
+
+fn push_json(value: &AssignedValue, line: &mut String) {
+    match value {
+        AssignedValue::Boolean(BooleanValue::False) => line.push_str("false"),
+        AssignedValue::Boolean(BooleanValue::True) => line.push_str("true"),
+        AssignedValue::Integer(IntegerNumber::Value(n)) => line.push_str(&n.to_string()),
+        AssignedValue::Integer(IntegerNumber::NaN) => line.push_str("null"),
+        AssignedValue::Rational(value) if value.is_nan() => line.push_str("null"),
+        AssignedValue::Rational(value) => line.push_str(&format!("\"{}\"", value)),
+        AssignedValue::Tuple(values) => {
+            line.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    line.push_str(", ");
+                }
+                push_json(value, line);
+            }
+            line.push(']');
+        }
+    }
+}
+
+/// A solution as a JSON object from variable names to values, with
+/// `NaN` as `null`, rationals as strings such as `"3/4"` and tuples as
+/// arrays.
+fn write_json<W: Write>(env: &[Assignment], w: &mut W) -> std::io::Result<()> {
+    let mut line = String::from("{");
+    for (i, assignment) in env.iter().enumerate() {
+        if i > 0 {
+            line.push_str(", ");
+        }
+        line.push('"');
+        for c in assignment.name().name().chars() {
+            match c {
+                '"' | '\\' => {
+                    line.push('\\');
+                    line.push(c);
+                }
+                c if c.is_control() => line.push_str(&format!("\\u{:04x}", c as u32)),
+                c => line.push(c),
+            }
+        }
+        line.push_str("\": ");
+        push_json(assignment.value(), &mut line);
+    }
+    line.push('}');
+    writeln!(w, "{}", line)
+}
+
+/// Writes the solutions `solve_all` would return to `w` as they are
+/// found, one JSON object per line, and returns how many there were.
+/// Only the solution being written is held in memory; an optimised
+/// program holds its best solution so far and writes it at the end.
+pub fn for_each_solution_json<W: Write>(
+    program: &ConstraintProgramExpression,
+    w: &mut W,
+) -> Result<u64, SolveError> {
+    let search = search::Search::new(program, &SolveConfig::default())?;
+    let mut count = 0;
+    let mut error = None;
+    let mut best = None;
+    let optimises = search.optimises();
+    search.run(&SolveConfig::default(), &mut SolveStats::default(), |env| {
+        if optimises {
+            best = Some(env.to_vec());
+            return ControlFlow::Continue(());
+        }
+        match write_json(env, w) {
+            Ok(()) => {
+                count += 1;
+                ControlFlow::Continue(())
+            }
+            Err(e) => {
+                error = Some(e.kind());
+                ControlFlow::Break(())
+            }
+        }
+    });
+    if let Some(env) = best {
+        write_json(&env, w).map_err(|e| SolveError::Write(e.kind()))?;
+        count += 1;
+    }
+    match error {
+        Some(kind) => Err(SolveError::Write(kind)),
+        None => Ok(count),
+    }
+}
+
+/// A plain text account of the program and one of its solutions, for
+/// documentation or for sharing a model with its answer. It has three
+/// sections: the program as it prints, the free variables in name order
+/// with the domains `variable_domains` gives them, and the bindings of
+/// `solutions`. The last is headed `unsatisfiable` when `solutions`
+/// explains why there is no solution, and says `none` when it is empty.
+pub fn report(program: &ConstraintProgramExpression, solutions: &[Solution]) -> String {
+    let indented = |text: String| {
+        text.lines()
+            .map(|line| format!("    {}\n", line))
+            .collect::<String>()
+    };
+    let mut domains: Vec<(String, Domain)> = match variable_domains(program) {
+        Ok(domains) => domains.into_iter().collect(),
+        Err(_) => {
+            use crate::expressions::FreeVariable;
+            program
+                .get_free()
+                .into_iter()
+                .map(|variable| (variable.name().to_string(), variable.domain().clone()))
+                .collect()
+        }
+    };
+    domains.sort_by(|(name_a, _), (name_b, _)| name_a.cmp(name_b));
+    domains.dedup_by(|(name_a, _), (name_b, _)| name_a == name_b);
+    let mut text = format!("program:\n{}\nvariables:\n", indented(program.to_string()));
+    for (name, domain) in &domains {
+        text.push_str(&format!("    {} in {}\n", name, domain));
+    }
+    let unsatisfiable = solutions
+        .iter()
+        .any(|binding| matches!(binding, Solution::Unsatisfiable(_)));
+    text.push_str(if unsatisfiable {
+        "\nunsatisfiable:\n"
+    } else {
+        "\nsolution:\n"
+    });
+    if solutions.is_empty() {
+        text.push_str("    none\n");
+    }
+    for binding in solutions {
+        text.push_str(&format!("    {}\n", binding));
+    }
+    text
 }
 
 #[cfg(test)]
 mod tests {
+    use super::aggregate;
     use super::apply;
     use super::free_variables;
     use super::generate_attempt;
+    use super::minimal_violation;
+    use super::solve_into;
     use super::ConstraintProgramExpression;
+    use crate::expressions::boolean::{BooleanExpression, BooleanValue};
+    use crate::expressions::integer::{
+        BooleanIntegerNumberExpression, IntegerNumber, IntegerNumberExpression,
+    };
+    use crate::expressions::{
+        AssignedValue, Assignment, ConstraintLogicExpression, SatisfactionExpression, Symbol,
+    };
+    use quickcheck::TestResult;
+
+    fn int_var(name: &str) -> Box<IntegerNumberExpression> {
+        Box::new(IntegerNumberExpression::IntegerNumberVariable(Symbol::new(
+            name.to_string(),
+        )))
+    }
+    fn bool_var(name: &str) -> Box<BooleanExpression> {
+        Box::new(BooleanExpression::BooleanVariable(Symbol::new(
+            name.to_string(),
+        )))
+    }
+    fn int(name: &str, value: i128) -> Assignment {
+        Assignment::new(
+            Symbol::new(name.to_string()),
+            AssignedValue::Integer(IntegerNumber::Value(value)),
+        )
+    }
+    fn boolean(name: &str, value: BooleanValue) -> Assignment {
+        Assignment::new(Symbol::new(name.to_string()), AssignedValue::Boolean(value))
+    }
+
+    #[test]
+    fn minimal_violation_keeps_only_the_conflicting_bindings() {
+        let program = ConstraintProgramExpression::ConstrainAnd(
+            Box::new(ConstraintLogicExpression::OfIntegerNumber(Box::new(
+                BooleanIntegerNumberExpression::Less(int_var("x"), int_var("y")),
+            ))),
+            Box::new(ConstraintProgramExpression::Solve(Box::new(
+                SatisfactionExpression::Satisfy(Box::new(ConstraintLogicExpression::Boolean(
+                    Box::new(BooleanExpression::Or(bool_var("p"), bool_var("q"))),
+                ))),
+            ))),
+        );
+        let env = vec![
+            boolean("p", BooleanValue::True),
+            int("x", 5),
+            boolean("q", BooleanValue::False),
+            int("y", 3),
+            int("z", 7),
+        ];
+        assert_eq!(
+            minimal_violation(&program, &env),
+            Some(vec![int("x", 5), int("y", 3)])
+        );
+        assert_eq!(
+            minimal_violation(&program, &[int("x", 1), int("y", 3)]),
+            None
+        );
+    }
+
+    #[test]
+    fn solve_into_maps_every_solution() {
+        use std::collections::HashSet;
+        let program = ConstraintProgramExpression::Solve(Box::new(
+            SatisfactionExpression::Satisfy(Box::new(ConstraintLogicExpression::Boolean(
+                Box::new(BooleanExpression::Or(bool_var("p"), bool_var("q"))),
+            ))),
+        ));
+        let truth = |value: Option<&AssignedValue>| {
+            value == Some(&AssignedValue::Boolean(BooleanValue::True))
+        };
+        let pairs: HashSet<(bool, bool)> = solve_into(&program, |bindings| {
+            (truth(bindings.get("p")), truth(bindings.get("q")))
+        })
+        .unwrap()
+        .into_iter()
+        .collect();
+        assert_eq!(
+            pairs,
+            HashSet::from([(false, true), (true, false), (true, true)])
+        );
+    }
+
+    #[test]
+    fn aggregate_summarises_a_mixed_solution() {
+        use super::{Solution, SolutionAggregate};
+        let binding = |assignment: Assignment| {
+            Solution::Variable(assignment.name().clone(), assignment.value().clone())
+        };
+        let solutions = vec![
+            binding(int("x", 4)),
+            binding(boolean("p", BooleanValue::True)),
+            binding(int("y", -7)),
+            Solution::Variable(
+                Symbol::new("z".to_string()),
+                AssignedValue::Integer(IntegerNumber::NaN),
+            ),
+            binding(boolean("q", BooleanValue::False)),
+            Solution::Constant(
+                Symbol::new("c".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(10)),
+            ),
+            binding(boolean("r", BooleanValue::True)),
+            binding(boolean("s", BooleanValue::True)),
+        ];
+        assert_eq!(
+            aggregate(&solutions),
+            SolutionAggregate {
+                integer_sum: IntegerNumber::Value(7),
+                integer_min: Some(-7),
+                integer_max: Some(10),
+                nan_count: 1,
+                true_count: 3,
+                true_fraction: Some(0.75),
+            }
+        );
+    }
 
     #[quickcheck_macros::quickcheck]
     fn a_solution_covers_all_free_variables(p: ConstraintProgramExpression) -> bool {
-        let free = free_variables(&p);
+        let Ok(free) = free_variables(&p) else {
+            return true;
+        };
         println!("{:?}", free);
         if let Some(attempt) = generate_attempt(free) {
             let update_program = apply(p, attempt);
-            let free_after_apply = free_variables(&update_program);
+            let free_after_apply = free_variables(&update_program).unwrap();
             free_after_apply.is_empty()
         } else {
             true
         }
     }
+
+    #[test]
+    fn reordered_programs_share_a_fingerprint() {
+        use super::program_fingerprint;
+        let satisfy = |expr: BooleanExpression| {
+            ConstraintProgramExpression::Solve(Box::new(SatisfactionExpression::Satisfy(Box::new(
+                ConstraintLogicExpression::Boolean(Box::new(expr)),
+            ))))
+        };
+        let and = |a, b| BooleanExpression::And(Box::new(a), Box::new(b));
+        let p = || *bool_var("p");
+        let q = || *bool_var("q");
+        let r = || *bool_var("r");
+        let left = satisfy(and(and(p(), q()), r()));
+        let right = satisfy(and(
+            r(),
+            BooleanExpression::Parenthesis(Box::new(and(q(), p()))),
+        ));
+        let other = satisfy(BooleanExpression::Or(
+            Box::new(and(p(), q())),
+            Box::new(r()),
+        ));
+        assert_eq!(program_fingerprint(&left), program_fingerprint(&right));
+        assert_ne!(program_fingerprint(&left), program_fingerprint(&other));
+    }
+
+    #[test]
+    fn apply_tracking_reports_the_newly_bound_variable() {
+        use super::apply_tracking;
+        let program = ConstraintProgramExpression::ConstrainAnd(
+            Box::new(ConstraintLogicExpression::OfIntegerNumber(Box::new(
+                BooleanIntegerNumberExpression::Less(int_var("x"), int_var("y")),
+            ))),
+            Box::new(ConstraintProgramExpression::Solve(Box::new(
+                SatisfactionExpression::Satisfy(Box::new(ConstraintLogicExpression::Boolean(
+                    bool_var("p"),
+                ))),
+            ))),
+        );
+        let (program, bound) = apply_tracking(program, &int("x", 3));
+        assert_eq!(bound, vec!["x".to_string()]);
+        let (program, bound) = apply_tracking(program, &int("x", 4));
+        assert!(bound.is_empty());
+        let (_, bound) = apply_tracking(program, &int("p", 1));
+        assert!(bound.is_empty());
+    }
+
+    #[test]
+    fn learned_no_goods_stay_under_the_capacity() {
+        use super::{solve_with, Learning, SolveConfig};
+        use crate::expressions::parser::parse_program;
+        // Five pigeons cannot sit in four holes, one to a hole.
+        let (pigeons, holes) = (5, 4);
+        let mut source = String::new();
+        for hole in 0..holes {
+            for a in 0..pigeons {
+                for b in a + 1..pigeons {
+                    source += &format!("not (p{}h{} and p{}h{});\n", a, hole, b, hole);
+                }
+            }
+        }
+        let sits = |pigeon| {
+            (0..holes)
+                .map(|hole| format!("p{}h{}", pigeon, hole))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        };
+        for pigeon in 0..pigeons - 1 {
+            source += &format!("{};\n", sits(pigeon));
+        }
+        source += &format!("satisfy {};", sits(pigeons - 1));
+        let program = parse_program(&source).unwrap();
+        let config = SolveConfig {
+            learning: Learning::NoGoods {
+                capacity: 8,
+                max_size: 20,
+            },
+            ..SolveConfig::default()
+        };
+        let report = solve_with(&program, &config).unwrap();
+        assert!(report.solutions.is_empty());
+        assert!(report.stats.conflicts > 100);
+        assert!(report.stats.cleanups > 0);
+        assert!(report.stats.peak_learned <= 8);
+    }
+
+    #[test]
+    fn objectives_decided_early_blame_only_assigned_variables() {
+        use super::{solve_with, Learning, SolveConfig};
+        use crate::expressions::parser::parse_program;
+        let program =
+            parse_program("a or not a;\nb or not b;\nx in [0, 3];\nmaximise ite(b, 0, x) > -1;")
+                .unwrap();
+        let config = SolveConfig {
+            learning: Learning::NoGoods {
+                capacity: 100,
+                max_size: 10,
+            },
+            ..SolveConfig::default()
+        };
+        let learned = solve_with(&program, &config).unwrap();
+        let plain = solve_with(&program, &SolveConfig::default()).unwrap();
+        assert_eq!(learned.outcome.solutions, plain.outcome.solutions);
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn learning_finds_the_same_solutions(p: ConstraintProgramExpression) -> TestResult {
+        use super::{solve_with, Learning, SolveConfig};
+        if free_variables(&p).map_or(true, |free| free.len() > 12) {
+            return TestResult::discard();
+        }
+        let learning = SolveConfig {
+            learning: Learning::NoGoods {
+                capacity: 4,
+                max_size: 3,
+            },
+            ..SolveConfig::default()
+        };
+        match (
+            solve_with(&p, &SolveConfig::default()),
+            solve_with(&p, &learning),
+        ) {
+            (Ok(plain), Ok(learned)) => TestResult::from_bool(plain.solutions == learned.solutions),
+            (Err(_), Err(_)) => TestResult::discard(),
+            _ => TestResult::failed(),
+        }
+    }
+
+    #[test]
+    fn a_tight_budget_keeps_the_incumbent() {
+        use super::{bindings, solve_with, SolveConfig};
+        use crate::expressions::parser::parse_program;
+        let program =
+            parse_program("x in [0, 9];\ny in [0, 9];\nmaximise x + 2 * y < 100;").unwrap();
+        let objective = |solution: &[super::Solution]| {
+            let bindings = bindings(solution);
+            match (bindings.get("x"), bindings.get("y")) {
+                (
+                    Some(AssignedValue::Integer(IntegerNumber::Value(x))),
+                    Some(AssignedValue::Integer(IntegerNumber::Value(y))),
+                ) => x + 2 * y,
+                _ => panic!("not a solution: {:?}", solution),
+            }
+        };
+        let complete = solve_with(&program, &SolveConfig::default()).unwrap();
+        assert!(complete.outcome.complete);
+        assert_eq!(objective(&complete.outcome.solutions), 27);
+        let config = SolveConfig {
+            node_limit: Some(15),
+            ..SolveConfig::default()
+        };
+        let partial = solve_with(&program, &config).unwrap();
+        assert!(!partial.outcome.complete);
+        assert!(objective(&partial.outcome.solutions) < 27);
+        assert_eq!(partial.solutions, vec![partial.outcome.solutions.clone()]);
+    }
+
+    #[test]
+    fn satisfaction_degree_counts_the_decided_constraints() {
+        use super::satisfaction_degree;
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x < y;\nx + y = 10;\np or q;\nsatisfy z > x;").unwrap();
+        let env = [int("x", 3), int("y", 5), boolean("p", BooleanValue::True)];
+        assert_eq!(satisfaction_degree(&program, &env), (2, 3));
+    }
+
+    #[test]
+    fn projected_domain_collects_the_values_of_one_variable() {
+        use super::projected_domain;
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [0, 3];\ny in {0, 1};\nsatisfy x != 1;").unwrap();
+        let values = |ns: &[i128]| {
+            ns.iter()
+                .map(|n| AssignedValue::Integer(IntegerNumber::Value(*n)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(projected_domain(&program, "x").unwrap(), values(&[0, 2, 3]));
+        assert_eq!(projected_domain(&program, "y").unwrap(), values(&[0, 1]));
+    }
+
+    #[test]
+    fn with_domain_narrows_only_its_variable() {
+        use super::{projected_domain, with_domain};
+        use crate::expressions::integer::IntegerNumberDomainExpression::ClosedRange;
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [0, 9];\nsatisfy y in [0, 3];").unwrap();
+        let value = |n| {
+            Box::new(IntegerNumberExpression::IntegerNumberValue(
+                IntegerNumber::Value(n),
+            ))
+        };
+        let values = |ns: std::ops::RangeInclusive<i128>| {
+            ns.map(|n| AssignedValue::Integer(IntegerNumber::Value(n)))
+                .collect::<Vec<_>>()
+        };
+        let tightened = with_domain(program.clone(), "x", ClosedRange(value(5), value(20)));
+        assert_eq!(projected_domain(&program, "x").unwrap(), values(0..=9));
+        assert_eq!(projected_domain(&tightened, "x").unwrap(), values(5..=9));
+        assert_eq!(projected_domain(&tightened, "y").unwrap(), values(0..=3));
+    }
+
+    #[test]
+    fn solve_lexicographic_prefers_the_first_variable() {
+        use super::{bindings, solve_lexicographic, LexDirection};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("a in [0, 3];\nb in [0, 3];\nsatisfy a + b = 4;").unwrap();
+        let pair = |direction, order: &[&str]| {
+            let order = order
+                .iter()
+                .map(|name| Symbol::new(name.to_string()))
+                .collect();
+            let bindings = bindings(&solve_lexicographic(&program, order, direction).unwrap());
+            (bindings["a"].clone(), bindings["b"].clone())
+        };
+        let value = |n| AssignedValue::Integer(IntegerNumber::Value(n));
+        assert_eq!(
+            pair(LexDirection::Smallest, &["a", "b"]),
+            (value(1), value(3))
+        );
+        assert_eq!(
+            pair(LexDirection::Smallest, &["b", "a"]),
+            (value(3), value(1))
+        );
+        assert_eq!(
+            pair(LexDirection::Largest, &["a", "b"]),
+            (value(3), value(1))
+        );
+    }
+
+    /// The days of the week as the integers 0 (Monday) to 6 (Sunday).
+    #[derive(Debug, Clone)]
+    struct Weekday;
+
+    impl crate::expressions::Sample for Weekday {
+        fn try_sample(&self) -> Result<AssignedValue, crate::expressions::SampleError> {
+            Ok(AssignedValue::Integer(IntegerNumber::Value(0)))
+        }
+    }
+
+    impl crate::expressions::ValueDomain for Weekday {
+        fn candidates(&self) -> Option<Vec<AssignedValue>> {
+            Some(
+                (0..7)
+                    .map(|day| AssignedValue::Integer(IntegerNumber::Value(day)))
+                    .collect(),
+            )
+        }
+        fn contains(&self, value: &AssignedValue) -> bool {
+            matches!(value, AssignedValue::Integer(IntegerNumber::Value(0..=6)))
+        }
+        fn cardinality(&self) -> Option<u128> {
+            Some(7)
+        }
+        fn clone_box(&self) -> Box<dyn crate::expressions::ValueDomain> {
+            Box::new(self.clone())
+        }
+        fn identity(&self) -> String {
+            "weekday".to_string()
+        }
+    }
+
+    #[test]
+    fn custom_domains_bound_the_search() {
+        use super::{bindings, solve_with, SolveConfig, SolveError};
+        use crate::expressions::parser::parse_program;
+        use crate::expressions::{Domain, Variable};
+        let program = parse_program("satisfy d > 4;").unwrap();
+        assert!(matches!(
+            solve_with(&program, &SolveConfig::default()),
+            Err(SolveError::Unbounded(_))
+        ));
+        let config = SolveConfig {
+            domains: vec![Variable::new(
+                Symbol::new("d".to_string()),
+                Domain::Custom(Box::new(Weekday)),
+            )],
+            ..SolveConfig::default()
+        };
+        let weekend: Vec<AssignedValue> = solve_with(&program, &config)
+            .unwrap()
+            .solutions
+            .iter()
+            .map(|solution| bindings(solution)["d"].clone())
+            .collect();
+        assert_eq!(
+            weekend,
+            vec![
+                AssignedValue::Integer(IntegerNumber::Value(5)),
+                AssignedValue::Integer(IntegerNumber::Value(6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn solutions_stream_as_json_lines() {
+        use super::for_each_solution_json;
+        use crate::expressions::parser::parse_program;
+        use std::io::Cursor;
+        let program = parse_program("x in [0, 3];\ny in [0, 3];\nsatisfy x + y = 3;").unwrap();
+        let mut out = Cursor::new(Vec::new());
+        let count = for_each_solution_json(&program, &mut out).unwrap();
+        let text = String::from_utf8(out.into_inner()).unwrap();
+        assert_eq!(count, 4);
+        assert_eq!(text.lines().count() as u64, count);
+        assert!(text.lines().any(|line| line == "{\"x\": 2, \"y\": 1}"));
+    }
+
+    #[test]
+    fn ac3_narrows_chains_of_comparisons() {
+        use super::{propagate_only, solve_with, Propagation, SolveConfig};
+        use crate::expressions::parser::parse_program;
+        let program =
+            parse_program("x in [0, 2];\ny in [0, 2];\nz in [0, 2];\nx < y;\nsatisfy y < z;")
+                .unwrap();
+        let ac3 = SolveConfig {
+            propagation: Propagation::Ac3,
+            ..SolveConfig::default()
+        };
+        let domains = |config: &SolveConfig| -> Vec<(String, usize)> {
+            propagate_only(&program, config)
+                .unwrap()
+                .into_iter()
+                .map(|(symbol, values)| (symbol.name().to_string(), values.len()))
+                .collect()
+        };
+        let sized = |size| vec![("x".into(), size), ("y".into(), size), ("z".into(), size)];
+        assert_eq!(domains(&SolveConfig::default()), sized(3));
+        assert_eq!(domains(&ac3), sized(1));
+        assert_eq!(
+            solve_with(&program, &ac3).unwrap().solutions,
+            solve_with(&program, &SolveConfig::default())
+                .unwrap()
+                .solutions
+        );
+    }
+
+    #[test]
+    fn disjunctions_split_into_independent_programs() {
+        use super::{solve_all, split_disjunctions};
+        use crate::expressions::parser::{parse_program, to_source};
+        let program = parse_program("p or (q and r);\nsatisfy not p;").unwrap();
+        let parts = split_disjunctions(program);
+        assert_eq!(
+            parts.iter().map(to_source).collect::<Vec<_>>(),
+            vec!["p;\nsatisfy not p;", "q and r;\nsatisfy not p;"]
+        );
+        let first = parts
+            .iter()
+            .position(|part| !solve_all(part).unwrap().is_empty());
+        assert_eq!(first, Some(1));
+        let program = parse_program("satisfy p or (q or r);").unwrap();
+        assert_eq!(split_disjunctions(program).len(), 3);
+    }
+
+    #[test]
+    fn repeated_subexpressions_are_bound_once() {
+        use super::{constraints, hoist_common_subexpressions, solve_all};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program(
+            "x in [0, 2];\ny in [0, 2];\nsatisfy (x * y + 1) + (x * y + 1) + (x * y + 1) > 6;",
+        )
+        .unwrap();
+        let hoisted = hoist_common_subexpressions(program.clone());
+        let goal = constraints(&hoisted)[2].to_string();
+        assert_eq!(goal, "(let t0 = x * y + 1 in t0 + t0 + t0) > 6");
+        assert_eq!(goal.matches("let").count(), 1);
+        assert_eq!(goal.matches("t0").count(), 4);
+        assert_eq!(solve_all(&hoisted).unwrap(), solve_all(&program).unwrap());
+    }
+
+    #[test]
+    fn variable_domains_include_restrictions() {
+        use super::{variable_domains, DomainConflict};
+        use crate::expressions::boolean::BooleanValueDomainExpression;
+        use crate::expressions::integer::IntegerNumberDomainExpression::{ClosedRange, Universe};
+        use crate::expressions::parser::parse_program;
+        use crate::expressions::Domain;
+        let program = parse_program("x in [0, 5];\nsatisfy x > y;").unwrap();
+        let domains = variable_domains(&program).unwrap();
+        assert_eq!(domains.len(), 2);
+        assert_eq!(
+            domains["x"],
+            Domain::Integer(ClosedRange(
+                Box::new(IntegerNumberExpression::IntegerNumberValue(
+                    IntegerNumber::Value(0)
+                )),
+                Box::new(IntegerNumberExpression::IntegerNumberValue(
+                    IntegerNumber::Value(5)
+                ))
+            ))
+        );
+        assert_eq!(domains["y"], Domain::Integer(Universe));
+        let program = parse_program("p;\nsatisfy p > 1;").unwrap();
+        assert_eq!(
+            variable_domains(&program),
+            Err(DomainConflict {
+                name: "p".to_string(),
+                first: Domain::Boolean(BooleanValueDomainExpression::Universe),
+                second: Domain::Integer(Universe),
+            })
+        );
+    }
+
+    #[test]
+    fn the_goal_decides_what_is_reported() {
+        use super::{count_models, solve_with, SolveConfig, SolveGoal};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [0, 3];\ny in [0, 3];\nsatisfy x < y;").unwrap();
+        let report = |goal| {
+            solve_with(
+                &program,
+                &SolveConfig {
+                    goal,
+                    ..SolveConfig::default()
+                },
+            )
+            .unwrap()
+        };
+        let first = report(SolveGoal::First);
+        assert_eq!((first.solutions.len(), first.count), (1, 1));
+        assert!(first.outcome.complete);
+        let all = report(SolveGoal::All { limit: None });
+        assert_eq!((all.solutions.len(), all.count), (6, 6));
+        assert_eq!(all.solutions[0], first.solutions[0]);
+        let limited = report(SolveGoal::All { limit: Some(4) });
+        assert_eq!(limited.solutions, all.solutions[..4]);
+        let count = report(SolveGoal::Count);
+        assert_eq!((count.solutions.len(), count.count), (0, 6));
+        assert_eq!(count_models(&program), Ok(6));
+    }
+
+    #[test]
+    fn check_tells_the_three_answers_apart() {
+        use super::{check, check_with, Satisfiability, Solution, SolveConfig};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [0, 3];\nsatisfy x > 2;").unwrap();
+        assert_eq!(
+            check(&program),
+            Satisfiability::Sat(vec![Solution::Variable(
+                Symbol::new("x".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(3))
+            )])
+        );
+        let program = parse_program("x in [0, 3];\ny in [0, 3];\nx > 2;\nsatisfy x < 1;").unwrap();
+        assert_eq!(
+            check(&program),
+            Satisfiability::Unsat {
+                core: vec![0, 2, 3]
+            }
+        );
+        let config = SolveConfig {
+            node_limit: Some(2),
+            ..SolveConfig::default()
+        };
+        assert!(matches!(
+            check_with(&program, &config),
+            Satisfiability::Unknown { .. }
+        ));
+        let program = parse_program("satisfy x > 2;").unwrap();
+        assert!(matches!(check(&program), Satisfiability::Unknown { .. }));
+    }
+
+    #[test]
+    fn defined_variables_are_computed_not_branched_on() {
+        use super::{solve_with, SolveConfig};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program(
+            "x in [0, 3];\ny in [0, 10];\nz in [0, 10];\nz = y * 2;\nsatisfy y = x + 1;",
+        )
+        .unwrap();
+        let report = solve_with(&program, &SolveConfig::default()).unwrap();
+        assert_eq!(report.stats.nodes, 4);
+        assert_eq!(report.count, 4);
+        let program = parse_program("x in [0, 3];\ny in [0, 3];\nsatisfy y = x + 1;").unwrap();
+        let report = solve_with(&program, &SolveConfig::default()).unwrap();
+        assert_eq!((report.stats.nodes, report.count), (4, 3));
+        // Without a domain of their own, defined variables take any value.
+        let program = parse_program("x in [0, 3];\nz = y * 2;\nsatisfy y = x + 1;").unwrap();
+        let report = solve_with(&program, &SolveConfig::default()).unwrap();
+        assert_eq!((report.stats.nodes, report.count), (4, 4));
+        let config = SolveConfig {
+            propagation: super::Propagation::Ac3,
+            ..SolveConfig::default()
+        };
+        assert_eq!(solve_with(&program, &config).unwrap().count, 4);
+        assert_eq!(super::count_models(&program), Ok(4));
+        let domains = super::propagate_only(&program, &SolveConfig::default()).unwrap();
+        assert_eq!(domains.len(), 1);
+        assert_eq!(
+            super::solve_lexicographic(
+                &program,
+                vec![Symbol::new("z".to_string())],
+                super::LexDirection::Smallest
+            ),
+            Err(super::SolveError::Unbounded(Symbol::new("z".to_string())))
+        );
+    }
+
+    #[test]
+    fn reordered_programs_normalize_to_the_same_tree() {
+        use super::{constraints, normalize_program};
+        use crate::expressions::parser::{parse_program, to_source};
+        let program_a =
+            parse_program("x in [0, 3];\ny + x > 2;\np and q;\nsatisfy x != y;").unwrap();
+        let program_b =
+            parse_program("q and p;\nx != y;\nx + y > 2;\nq and p;\nsatisfy x in [0, 3];").unwrap();
+        assert_ne!(program_a, program_b);
+        assert_eq!(
+            normalize_program(program_a.clone()),
+            normalize_program(program_b)
+        );
+        assert_eq!(constraints(&normalize_program(program_a)).len(), 4);
+        let program = parse_program("y > 1;\nmaximise x > 2;\nsatisfy x > 0;").unwrap();
+        assert_eq!(
+            to_source(&normalize_program(program)),
+            "0 < x;\n1 < y;\nmaximise x > 2;"
+        );
+        let program = parse_program("x != x;\nsatisfy x > 0;").unwrap();
+        assert_eq!(to_source(&normalize_program(program)), "satisfy false;");
+    }
+
+    #[test]
+    fn the_division_mode_reaches_the_search() {
+        use super::{solve_with, Solution, SolveConfig};
+        use crate::expressions::integer::{DivisionMode, IntegerNumber};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [-5, 5];\nsatisfy x = -7 % 2;").unwrap();
+        let solve = |division| {
+            let config = SolveConfig {
+                division,
+                ..SolveConfig::default()
+            };
+            solve_with(&program, &config).unwrap().solutions
+        };
+        let x = |n| {
+            vec![vec![Solution::Variable(
+                Symbol::new("x".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(n)),
+            )]]
+        };
+        assert_eq!(solve(DivisionMode::TruncateTowardZero), x(-1));
+        assert_eq!(solve(DivisionMode::FloorDivision), x(1));
+        // The bounds of domains round as the mode says too.
+        let program = parse_program("x in [-7 / 2, 0];\nsatisfy x < 0 - 3;").unwrap();
+        let solve = |division| {
+            let config = SolveConfig {
+                division,
+                ..SolveConfig::default()
+            };
+            solve_with(&program, &config).unwrap().solutions
+        };
+        assert_eq!(
+            solve(DivisionMode::TruncateTowardZero),
+            Vec::<Vec<Solution>>::new()
+        );
+        assert_eq!(solve(DivisionMode::FloorDivision), x(-4));
+    }
+
+    #[test]
+    fn solutions_ground_to_a_satisfied_program() {
+        use super::{ground, is_satisfied_ground, solve_all, Solution};
+        use crate::expressions::parser::parse_program;
+        let program =
+            parse_program("x in [0, 4];\ny in [0, 4];\nsatisfy x + y = 4;\nsatisfy x > y;")
+                .unwrap();
+        let solutions = solve_all(&program).unwrap();
+        assert_eq!(solutions.len(), 2);
+        for solution in solutions {
+            let env: Vec<Assignment> = solution
+                .iter()
+                .filter_map(|binding| match binding {
+                    Solution::Variable(name, value) => {
+                        Some(Assignment::new(name.clone(), value.clone()))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let grounded = ground(&program, &env);
+            assert_eq!(free_variables(&grounded), Ok(vec![]));
+            assert!(is_satisfied_ground(&grounded));
+            assert!(!is_satisfied_ground(&ground(&program, &env[..1])));
+        }
+        let assign = |name: &str, n| {
+            Assignment::new(
+                Symbol::new(name.to_string()),
+                AssignedValue::Integer(crate::expressions::integer::IntegerNumber::Value(n)),
+            )
+        };
+        assert!(!is_satisfied_ground(&ground(
+            &program,
+            &[assign("x", 1), assign("y", 3)]
+        )));
+    }
+
+    #[test]
+    fn the_assumption_core_holds_only_the_conflicting_ones() {
+        use super::{solve_assuming, AssumeResult, Solution};
+        use crate::expressions::parser::parse_program;
+        let program =
+            parse_program("x in [0, 3];\ny in [0, 3];\nz in [0, 3];\nsatisfy x < y;").unwrap();
+        let assign = |name: &str, n| {
+            Assignment::new(
+                Symbol::new(name.to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(n)),
+            )
+        };
+        let assumptions = [assign("x", 2), assign("z", 0), assign("y", 1)];
+        assert_eq!(
+            solve_assuming(&program, &assumptions),
+            AssumeResult::Unsat {
+                core: vec![assign("x", 2), assign("y", 1)]
+            }
+        );
+        let AssumeResult::Sat(solution) = solve_assuming(&program, &assumptions[..2]) else {
+            panic!("expected a solution");
+        };
+        assert_eq!(solution.len(), 3);
+        assert_eq!(
+            solution[0],
+            Solution::Variable(
+                Symbol::new("x".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(2))
+            )
+        );
+        assert!(solution.contains(&Solution::Variable(
+            Symbol::new("y".to_string()),
+            AssignedValue::Integer(IntegerNumber::Value(3))
+        )));
+    }
+
+    #[test]
+    fn objective_biased_values_reach_the_optimum_sooner() {
+        use super::{bindings, solve_with, SolveConfig, ValueOrder};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program(
+            "x in [0, 9];\ny in [0, 9];\nz in [0, 9];\ny + z = 15;\nmaximise x < 100;",
+        )
+        .unwrap();
+        let solve = |value_order| {
+            let config = SolveConfig {
+                value_order,
+                ..SolveConfig::default()
+            };
+            solve_with(&program, &config).unwrap()
+        };
+        let ascending = solve(ValueOrder::Ascending);
+        let biased = solve(ValueOrder::ObjectiveBiased);
+        let x = AssignedValue::Integer(IntegerNumber::Value(9));
+        assert_eq!(bindings(&ascending.outcome.solutions).get("x"), Some(&x));
+        assert_eq!(bindings(&biased.outcome.solutions).get("x"), Some(&x));
+        assert_eq!(biased.solutions.len(), 1);
+        assert!(biased.stats.nodes < ascending.stats.nodes);
+    }
+
+    #[test]
+    fn counting_stops_past_the_cap() {
+        use super::{count_up_to, CountResult};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [0, 4];\ny in [0, 4];\nsatisfy x < y;").unwrap();
+        assert_eq!(count_up_to(&program, 5), Ok(CountResult::AtLeast(5)));
+        assert_eq!(count_up_to(&program, 20), Ok(CountResult::Exact(10)));
+        assert_eq!(count_up_to(&program, 10), Ok(CountResult::Exact(10)));
+        assert_eq!(count_up_to(&program, 0), Ok(CountResult::AtLeast(0)));
+    }
+
+    #[test]
+    fn objectives_count_as_constraints() {
+        use super::{count_models, count_up_to, CountResult};
+        use crate::expressions::parser::parse_program;
+        for goal in ["minimise", "maximise"] {
+            let source = format!("x in [0, 5];\n{} x > -1;", goal);
+            let program = parse_program(&source).unwrap();
+            assert_eq!(count_up_to(&program, 10), Ok(CountResult::Exact(6)));
+            assert_eq!(count_models(&program), Ok(6));
+            let program = parse_program(&format!("x in [0, 5];\n{} x > 3;", goal)).unwrap();
+            assert_eq!(count_up_to(&program, 10), Ok(CountResult::Exact(2)));
+        }
+    }
+
+    #[test]
+    fn widening_finds_witnesses_for_unbounded_variables() {
+        use super::{solve_with, Solution, SolveConfig, SolveError, SolveGoal};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("satisfy x > 0;").unwrap();
+        let x = Symbol::new("x".to_string());
+        assert_eq!(
+            solve_with(&program, &SolveConfig::default()),
+            Err(SolveError::Unbounded(x.clone()))
+        );
+        let config = SolveConfig {
+            goal: SolveGoal::First,
+            widening: Some(16),
+            ..SolveConfig::default()
+        };
+        let report = solve_with(&program, &config).unwrap();
+        assert_eq!(
+            report.solutions,
+            vec![vec![Solution::Variable(
+                x,
+                AssignedValue::Integer(IntegerNumber::Value(1))
+            )]]
+        );
+        assert!(!report.outcome.complete);
+        let program = parse_program("satisfy x < -3;").unwrap();
+        let report = solve_with(&program, &config).unwrap();
+        assert_eq!(
+            report.solutions[0],
+            vec![Solution::Variable(
+                Symbol::new("x".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(-4))
+            )]
+        );
+    }
+
+    #[test]
+    fn distance_bounds_narrow_the_other_variable() {
+        use super::{propagate_only, Propagation, SolveConfig};
+        use crate::expressions::parser::parse_program;
+        let ac3 = SolveConfig {
+            propagation: Propagation::Ac3,
+            ..SolveConfig::default()
+        };
+        let y_values = |source: &str| -> Vec<i128> {
+            let program = parse_program(source).unwrap();
+            let domains = propagate_only(&program, &ac3).unwrap();
+            let (_, values) = domains
+                .into_iter()
+                .find(|(symbol, _)| symbol.name() == "y")
+                .unwrap();
+            values
+                .into_iter()
+                .map(|value| match value {
+                    AssignedValue::Integer(IntegerNumber::Value(n)) => n,
+                    value => panic!("not a number: {:?}", value),
+                })
+                .collect()
+        };
+        assert_eq!(
+            y_values("x in [4, 4];\ny in [0, 10];\nsatisfy abs(x - y) < 3;"),
+            vec![2, 3, 4, 5, 6]
+        );
+        assert_eq!(
+            y_values("x in {0, 10};\ny in [0, 10];\nsatisfy 3 > abs(y - x);"),
+            vec![0, 1, 2, 8, 9, 10]
+        );
+        assert_eq!(
+            y_values("x in [4, 4];\ny in [0, 10];\nsatisfy abs(x - y) > 2;"),
+            vec![0, 1, 7, 8, 9, 10]
+        );
+    }
+
+    #[test]
+    fn the_report_shows_domains_and_values() {
+        use super::{report, solve_all};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [0, 4];\np or not p;\nsatisfy x > 3;").unwrap();
+        let solution = &solve_all(&program).unwrap()[0];
+        let text = report(&program, solution);
+        assert!(text.starts_with("program:\n    x in [0, 4];\n"));
+        assert!(text.contains("variables:\n    p in {false, true}\n    x in [0, 4]\n"));
+        assert!(text.contains("solution:\n"));
+        assert!(text.contains("    x = 4\n"));
+        assert!(text.contains("    p = "));
+        assert!(report(&program, &[]).ends_with("solution:\n    none\n"));
+    }
+
+    #[test]
+    fn each_variation_of_a_batch_is_solved_on_its_own() {
+        use super::{bindings, solve_batch};
+        use crate::expressions::parser::parse_program;
+        let base = parse_program("x in [0, 10];\nsatisfy x * k = 12;").unwrap();
+        let k = |n| {
+            vec![Assignment::new(
+                Symbol::new("k".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(n)),
+            )]
+        };
+        let results = solve_batch(&base, vec![k(3), k(5), k(2)]);
+        assert_eq!(results.len(), 3);
+        let solution = |result: &Result<Option<Vec<super::Solution>>, super::SolveError>| {
+            bindings(result.as_ref().unwrap().as_ref().unwrap())
+        };
+        assert_eq!(
+            solution(&results[0]).get("x"),
+            Some(&AssignedValue::Integer(IntegerNumber::Value(4)))
+        );
+        assert_eq!(results[1], Ok(None));
+        assert_eq!(
+            solution(&results[2]).get("x"),
+            Some(&AssignedValue::Integer(IntegerNumber::Value(6)))
+        );
+        assert_eq!(
+            solution(&results[2]).get("k"),
+            Some(&AssignedValue::Integer(IntegerNumber::Value(2)))
+        );
+        let base = parse_program("x in [0, 3];\np;\nsatisfy x > 2;").unwrap();
+        let p = |value| {
+            vec![Assignment::new(
+                Symbol::new("p".to_string()),
+                AssignedValue::Boolean(value),
+            )]
+        };
+        let results = solve_batch(&base, vec![p(BooleanValue::True), p(BooleanValue::False)]);
+        assert_eq!(
+            solution(&results[0]).get("x"),
+            Some(&AssignedValue::Integer(IntegerNumber::Value(3)))
+        );
+        assert_eq!(results[1], Ok(None));
+        let mixed = vec![
+            p(BooleanValue::True),
+            vec![Assignment::new(
+                Symbol::new("p".to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(1)),
+            )],
+        ];
+        assert_eq!(
+            solve_batch(&base, mixed),
+            vec![
+                Err(super::SolveError::Conflict("p".to_string())),
+                Err(super::SolveError::Conflict("p".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn independent_constraints_decompose_into_parts() {
+        use super::decompose;
+        use crate::expressions::parser::parse_program;
+        let program =
+            parse_program("x in [0, 3];\ny in [0, 3];\nz in [0, 3];\np or q;\nsatisfy x < z;")
+                .unwrap();
+        let parts: Vec<String> = decompose(&program)
+            .iter()
+            .map(|part| part.to_string())
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                "x in [0, 3];\nz in [0, 3];\nsatisfy x < z;",
+                "satisfy y in [0, 3];",
+                "satisfy p or q;"
+            ]
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_solving_matches_the_sequential_search() {
+        use super::{bindings, decompose, solve_parallel, solve_with, SolveConfig, SolveGoal};
+        use crate::expressions::parser::parse_program;
+        let text: String = (0..12)
+            .map(|i| {
+                format!(
+                    "a{i} in [0, 5];\nb{i} in [0, 5];\na{i} + b{i} = {};\n",
+                    i % 6 + 2
+                )
+            })
+            .collect();
+        let program = parse_program(&format!("{}satisfy a0 > b0;", text)).unwrap();
+        assert_eq!(decompose(&program).len(), 12);
+        let config = SolveConfig {
+            goal: SolveGoal::First,
+            ..SolveConfig::default()
+        };
+        let sequential = solve_with(&program, &config)
+            .unwrap()
+            .solutions
+            .pop()
+            .unwrap();
+        let parallel = solve_parallel(&program).unwrap();
+        assert_eq!(parallel.len(), 24);
+        assert_eq!(bindings(&parallel), bindings(&sequential));
+        assert_eq!(solve_parallel(&program).unwrap(), parallel);
+        let unsatisfiable = parse_program(&format!("{}satisfy a3 > 5;", text)).unwrap();
+        assert_eq!(solve_parallel(&unsatisfiable), Ok(vec![]));
+    }
+
+    #[test]
+    fn free_variables_are_listed_once_per_name() {
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [0, 4];\np or q;\nsatisfy x < y + x;").unwrap();
+        let names: Vec<String> = free_variables(&program)
+            .unwrap()
+            .iter()
+            .map(|variable| variable.name().to_string())
+            .collect();
+        assert_eq!(names, vec!["x", "p", "q", "y"]);
+        let program = parse_program("x or p;\nsatisfy x > 2;").unwrap();
+        let conflict = free_variables(&program).unwrap_err();
+        assert_eq!(conflict.name, "x");
+        assert!(matches!(
+            conflict.first,
+            crate::expressions::Domain::Boolean(_)
+        ));
+        assert!(matches!(
+            conflict.second,
+            crate::expressions::Domain::Integer(_)
+        ));
+        assert_eq!(
+            super::solve_all(&program),
+            Err(super::SolveError::Conflict("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn apply_substitutes_only_the_assigned_variables() {
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("p or q;\nsatisfy x < y;").unwrap();
+        let applied = apply(program, vec![boolean("p", BooleanValue::True), int("x", 3)]);
+        let names: Vec<String> = free_variables(&applied)
+            .unwrap()
+            .iter()
+            .map(|variable| variable.name().to_string())
+            .collect();
+        assert_eq!(names, ["q", "y"]);
+        let ground = apply(
+            applied,
+            vec![boolean("q", BooleanValue::False), int("y", 4)],
+        );
+        assert!(free_variables(&ground).unwrap().is_empty());
+    }
+
+    #[test]
+    fn solve_returns_the_first_solution_or_why_there_is_none() {
+        use super::{solve, Solution, UnsatReason};
+        use crate::expressions::parser::parse_program;
+        let x = Symbol::new("x".to_string());
+        let program = parse_program("x in [0, 9];\np;\nsatisfy x > 6;").unwrap();
+        assert_eq!(
+            solve(program)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["x = 7", "p = true"]
+        );
+        let program = parse_program("x in [0, 3];\nsatisfy x > 6;").unwrap();
+        assert_eq!(
+            solve(program),
+            [Solution::Unsatisfiable(UnsatReason::EmptyDomain(x.clone()))]
+        );
+        let program =
+            parse_program("x in [0, 3];\ny in {1, 2};\nq;\nx != y;\nsatisfy x % 3 = y % 3;")
+                .unwrap();
+        let unsatisfiable = solve(program);
+        assert_eq!(
+            unsatisfiable,
+            [Solution::Unsatisfiable(
+                UnsatReason::ConflictingConstraints(vec![x.clone(), Symbol::new("y".to_string())])
+            )]
+        );
+        assert_eq!(
+            unsatisfiable[0].to_string(),
+            "the constraints on x, y conflict"
+        );
+        let unbounded = solve(parse_program("satisfy x > 6;").unwrap());
+        assert_eq!(
+            unbounded,
+            [Solution::Unsatisfiable(UnsatReason::UnboundedSearch(x))]
+        );
+        assert_eq!(unbounded[0].to_string(), "`x` has no finite domain");
+    }
+
+    #[test]
+    fn solutions_are_found_as_they_are_asked_for() {
+        use super::{solutions, solve_all, to_solution, Solution};
+        use crate::expressions::parser::parse_program;
+        let models = |source: &str| solutions(&parse_program(source).unwrap()).unwrap().count();
+        assert_eq!(models("satisfy p or true or q;"), 4);
+        assert_eq!(models("satisfy p or q;"), 3);
+        assert_eq!(models("satisfy 1 > 2;"), 0);
+        assert_eq!(models("satisfy 2 > 1;"), 1);
+        let program = parse_program(
+            "x in [0, 99];\ny in [0, 99];\nz in [0, 99];\ny = x + 1;\nsatisfy z > x + y;",
+        )
+        .unwrap();
+        let first: Vec<Vec<Solution>> = solutions(&program)
+            .unwrap()
+            .take(3)
+            .map(|env| to_solution(&env))
+            .collect();
+        assert_eq!(first, solve_all(&program).unwrap()[..3]);
+        assert_eq!(
+            first[2].iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["x = 0", "z = 4", "y = 1"]
+        );
+    }
+
+    #[test]
+    fn solve_packs_the_most_valuable_knapsack() {
+        use super::{solve, Solution, SolveError, UnsatReason};
+        use crate::expressions::parser::parse_program;
+        let items = "a in [0, 1];\nb in [0, 1];\nc in [0, 1];\n3 * a + 4 * b + 5 * c < 9;\n";
+        let packed = |goal: &str| {
+            solve(parse_program(&format!("{}{}", items, goal)).unwrap())
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            packed("maximise 4 * a + 5 * b + 7 * c > 0;"),
+            ["a = 1", "b = 0", "c = 1"]
+        );
+        assert_eq!(
+            packed("satisfy 4 * a + 5 * b + 7 * c > 8;\nminimise 3 * a + 4 * b + 5 * c < 9;"),
+            ["a = 1", "b = 1", "c = 0"]
+        );
+        assert_eq!(
+            solve(parse_program("maximise x < 10;").unwrap()),
+            [Solution::Unsatisfiable(UnsatReason::UnboundedSearch(
+                Symbol::new("x".to_string())
+            ))]
+        );
+        let program = parse_program("maximise p or q;").unwrap();
+        assert!(matches!(
+            &solve(program)[..],
+            [Solution::Unsatisfiable(UnsatReason::Failed(
+                SolveError::NoObjective(_)
+            ))]
+        ));
+    }
+
+    #[test]
+    fn validation_reports_every_conflict_and_empty_domain() {
+        use super::{validate, ValidationError};
+        use crate::expressions::parser::parse_program;
+        let symbol = |name: &str| Symbol::new(name.to_string());
+        let program = parse_program("x in [0, 5];\nsatisfy x > y;").unwrap();
+        assert_eq!(validate(&program), Ok(Vec::new()));
+        let program =
+            parse_program("p;\nq;\nx in [3, 5];\nx in [6, 9];\ny in {};\nq < 0;\nsatisfy p > 1;")
+                .unwrap();
+        assert_eq!(
+            validate(&program),
+            Err(vec![
+                ValidationError::ConflictingDomain(symbol("p")),
+                ValidationError::ConflictingDomain(symbol("q")),
+                ValidationError::EmptyDomain(symbol("x")),
+                ValidationError::EmptyDomain(symbol("y")),
+            ])
+        );
+    }
+
+    #[test]
+    fn validation_warns_of_degenerate_constraints() {
+        use super::{validate, ValidationWarning};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program(
+            "x in [0, 5];\nalldifferent(x, 1);\nalldifferent(x);\nsatisfy sum() < x;",
+        )
+        .unwrap();
+        assert_eq!(
+            validate(&program),
+            Ok(vec![
+                ValidationWarning::TrivialAllDifferent(2),
+                ValidationWarning::EmptySum(3),
+            ])
+        );
+        let empty = parse_program("x in {};\nsatisfy alldifferent(x);").unwrap();
+        assert!(validate(&empty).is_err());
+        let sum = IntegerNumberExpression::Sum(vec![]);
+        assert_eq!(
+            sum.simplify(),
+            IntegerNumberExpression::IntegerNumberValue(IntegerNumber::Value(0))
+        );
+        assert_eq!(
+            sum.evaluate_ref(&crate::expressions::Environment::new(&[])),
+            Ok(IntegerNumber::Value(0))
+        );
+        assert_eq!(super::solve_all(&program).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn variable_sets_keep_the_first_variable_of_each_name() {
+        use super::{DomainConflict, VariableSet};
+        use crate::expressions::boolean::BooleanValueDomainExpression;
+        use crate::expressions::integer::IntegerNumberDomainExpression::{Empty, Universe};
+        use crate::expressions::{Domain, Variable};
+        let variable = |name: &str, domain| Variable::new(Symbol::new(name.to_string()), domain);
+        let mut set = VariableSet::new();
+        assert_eq!(
+            set.insert(variable("x", Domain::Integer(Universe))),
+            Ok(true)
+        );
+        assert_eq!(
+            set.insert(variable(
+                "p",
+                Domain::Boolean(BooleanValueDomainExpression::Universe)
+            )),
+            Ok(true)
+        );
+        assert_eq!(set.insert(variable("x", Domain::Integer(Empty))), Ok(false));
+        assert_eq!(
+            set.insert(variable("p", Domain::Integer(Universe))),
+            Err(DomainConflict {
+                name: "p".to_string(),
+                first: Domain::Boolean(BooleanValueDomainExpression::Universe),
+                second: Domain::Integer(Universe),
+            })
+        );
+        assert!(set.contains(&Symbol::new("p".to_string())));
+        assert!(!set.contains(&Symbol::new("y".to_string())));
+        assert_eq!(
+            set.into_vec(),
+            vec![
+                variable("x", Domain::Integer(Universe)),
+                variable("p", Domain::Boolean(BooleanValueDomainExpression::Universe)),
+            ]
+        );
+    }
+
+    #[test]
+    fn normalizing_keeps_the_solutions_of_a_term_differing_from_itself() {
+        use super::{normalize_program, solve_all};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [0, 1];\ny in [0, 1];\nsatisfy x / y != x / y;").unwrap();
+        let solutions = solve_all(&program).unwrap();
+        let normalized = solve_all(&normalize_program(program)).unwrap();
+        assert_eq!(solutions.len(), 2);
+        assert_eq!(normalized.len(), solutions.len());
+        assert!(solutions
+            .iter()
+            .all(|solution| normalized.contains(solution)));
+    }
 }
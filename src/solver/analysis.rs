@@ -0,0 +1,602 @@
+//! Static checks over constraint programs that run without searching.
+use std::collections::HashMap;
+
+use crate::expressions::boolean::{BooleanExpression, BooleanValue};
+use crate::expressions::integer::{
+    BooleanIntegerNumberExpression, DivisionMode, Hull, IntegerNumber,
+    IntegerNumberDomainExpression, IntegerNumberExpression,
+};
+use crate::expressions::{
+    ConstraintLogicExpression, ConstraintProgramExpression, Domain, Evaluate, FreeVariable, Symbol,
+    Variable,
+};
+
+fn conjuncts<'a>(expr: &'a BooleanExpression, found: &mut Vec<&'a BooleanExpression>) {
+    match expr {
+        BooleanExpression::And(expr_a, expr_b) => {
+            conjuncts(expr_a, found);
+            conjuncts(expr_b, found);
+        }
+        BooleanExpression::Parenthesis(expr) => conjuncts(expr, found),
+        _ => found.push(expr),
+    }
+}
+
+fn contradicts_boolean(expr: &BooleanExpression) -> bool {
+    use BooleanExpression::*;
+    match expr {
+        And(_, _) => {
+            let mut found = Vec::new();
+            conjuncts(expr, &mut found);
+            found.iter().any(|conjunct| match conjunct {
+                Not(negated) => found.contains(&negated.as_ref()),
+                _ => contradicts_boolean(conjunct),
+            })
+        }
+        Or(expr_a, expr_b) => contradicts_boolean(expr_a) && contradicts_boolean(expr_b),
+        Parenthesis(expr) => contradicts_boolean(expr),
+        _ => expr.evaluate(&[]) == Ok(self::BooleanValue::False),
+    }
+}
+
+/// Whether the expression evaluates to a number under every assignment,
+/// judging from its shape alone: it neither divides, takes a remainder
+/// or a power, nor performs arithmetic that can overflow.
+fn never_nan(expr: &IntegerNumberExpression) -> bool {
+    use IntegerNumberExpression::*;
+    match expr {
+        IntegerNumberVariable(_) | OfBoolean(_) => true,
+        IntegerNumberValue(value) => *value != IntegerNumber::NaN,
+        Parenthesis(expr) => never_nan(expr),
+        Let(_, value, body) => never_nan(value) && never_nan(body),
+        IfThenElse(_, then, otherwise) => never_nan(then) && never_nan(otherwise),
+        _ => false,
+    }
+}
+
+fn contradicts_integer(expr: &BooleanIntegerNumberExpression) -> bool {
+    use BooleanIntegerNumberExpression::*;
+    match expr {
+        // `NaN` differs from itself, so only a term that is never `NaN`
+        // contradicts `!=` with itself.
+        Different(expr_a, expr_b) if expr_a == expr_b => never_nan(expr_a),
+        Greater(expr_a, expr_b) | Less(expr_a, expr_b) if expr_a == expr_b => true,
+        In(_, domain) if **domain == IntegerNumberDomainExpression::Empty => true,
+        _ => expr.evaluate(&[]) == Ok(BooleanValue::False),
+    }
+}
+
+/// Whether the constraint can never hold, whatever the values of its
+/// variables, judging from its shape alone.
+pub(crate) fn trivially_contradictory(constraint: &ConstraintLogicExpression) -> bool {
+    match constraint {
+        ConstraintLogicExpression::Boolean(expr) => contradicts_boolean(expr),
+        ConstraintLogicExpression::OfIntegerNumber(expr) => contradicts_integer(expr),
+        ConstraintLogicExpression::OfRational(expr) => {
+            expr.evaluate(&[]) == Ok(BooleanValue::False)
+        }
+        // `alldifferent` passes over `NaN` operands, so only a repeated
+        // term that is never `NaN` refutes it.
+        ConstraintLogicExpression::AllDifferent(exprs) => exprs
+            .iter()
+            .enumerate()
+            .any(|(position, expr)| never_nan(expr) && exprs[..position].contains(expr)),
+    }
+}
+
+fn disjuncts<'a>(expr: &'a BooleanExpression, found: &mut Vec<&'a BooleanExpression>) {
+    match expr {
+        BooleanExpression::Or(expr_a, expr_b) => {
+            disjuncts(expr_a, found);
+            disjuncts(expr_b, found);
+        }
+        BooleanExpression::Parenthesis(expr) => disjuncts(expr, found),
+        _ => found.push(expr),
+    }
+}
+
+/// Whether the constraint holds whatever the values of its variables,
+/// judging from its shape alone: a ground constraint that is true, a
+/// formula implied by or equal to itself, a disjunction holding both a
+/// formula and its negation, or membership of a variable in `universe`.
+fn trivially_satisfied(constraint: &ConstraintLogicExpression) -> bool {
+    use BooleanExpression::*;
+    match constraint {
+        ConstraintLogicExpression::Boolean(expr) => match expr.as_ref() {
+            Or(_, _) => {
+                let mut found = Vec::new();
+                disjuncts(expr, &mut found);
+                found.iter().any(|disjunct| match disjunct {
+                    Not(negated) => found.contains(&negated.as_ref()),
+                    _ => false,
+                })
+            }
+            Implies(expr_a, expr_b) | Equals(expr_a, expr_b) if expr_a == expr_b => true,
+            _ => expr.evaluate(&[]) == Ok(self::BooleanValue::True),
+        },
+        ConstraintLogicExpression::OfIntegerNumber(expr) => match expr.as_ref() {
+            BooleanIntegerNumberExpression::In(expr, domain) => {
+                let mut expr = expr.as_ref();
+                while let IntegerNumberExpression::Parenthesis(inner) = expr {
+                    expr = inner;
+                }
+                matches!(expr, IntegerNumberExpression::IntegerNumberVariable(_))
+                    && **domain == IntegerNumberDomainExpression::Universe
+            }
+            _ => expr.evaluate(&[]) == Ok(self::BooleanValue::True),
+        },
+        ConstraintLogicExpression::OfRational(_) | ConstraintLogicExpression::AllDifferent(_) => {
+            constraint.evaluate(&[]) == Ok(self::BooleanValue::True)
+        }
+    }
+}
+
+/// The free variables of the program that no constraint restricts: each
+/// constraint they occur in holds whatever their value, as judged by
+/// shape alone, like `x in universe` or `p or not p`. They can take any
+/// value of their domain in a solution. This is a cheap lint; a variable
+/// that is not reported may still be irrelevant.
+pub fn unconstrained_variables(program: &ConstraintProgramExpression) -> Vec<Variable> {
+    let restricting: Vec<Variable> = super::constraints(program)
+        .into_iter()
+        .filter(|constraint| !trivially_satisfied(constraint))
+        .flat_map(|constraint| constraint.get_free())
+        .collect();
+    let mut unconstrained: Vec<Variable> = Vec::new();
+    for variable in program.get_free() {
+        let restricted = restricting
+            .iter()
+            .any(|other| other.name() == variable.name());
+        if !restricted && !unconstrained.contains(&variable) {
+            unconstrained.push(variable);
+        }
+    }
+    unconstrained
+}
+
+/// The solver features a program relies on, found without searching.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeatureSet {
+    /// Some constraint compares integers.
+    pub uses_integers: bool,
+    /// Some integer expression divides or takes a remainder.
+    pub uses_division: bool,
+    /// Some variable has a domain without a finite number of values, even
+    /// after the constraints `x in D` restricting it.
+    pub uses_infinite_domains: bool,
+    /// The program has a `minimise` or `maximise` goal.
+    pub uses_optimization: bool,
+    /// Some integer expression is not linear, in the sense of `is_linear`.
+    pub uses_nonlinear: bool,
+    /// The language has no quantifiers yet, so this is always `false`.
+    pub uses_quantifiers: bool,
+}
+
+/// Reports which features of the solver the program needs, so that a
+/// caller can decide whether to solve it, export it or turn it away.
+pub fn required_features(program: &ConstraintProgramExpression) -> FeatureSet {
+    use crate::expressions::integer::is_linear;
+    let mut features = FeatureSet {
+        uses_integers: super::constraints(program).iter().any(|constraint| {
+            matches!(
+                constraint,
+                ConstraintLogicExpression::OfIntegerNumber(_)
+                    | ConstraintLogicExpression::AllDifferent(_)
+            )
+        }),
+        uses_optimization: !matches!(super::search::objective(program), Ok(None)),
+        ..FeatureSet::default()
+    };
+    crate::export::visit_program(program, &mut |expr| {
+        features.uses_division |= matches!(
+            expr,
+            IntegerNumberExpression::Divide(_, _) | IntegerNumberExpression::Modulo(_, _)
+        );
+        features.uses_nonlinear |= !is_linear(expr);
+    });
+    let infinite = |domain: &Domain| domain.as_value_domain().cardinality().is_none();
+    features.uses_infinite_domains = match super::variable_domains(program) {
+        Ok(domains) => domains.values().any(infinite),
+        Err(_) => program
+            .get_free()
+            .iter()
+            .any(|variable| infinite(variable.domain())),
+    };
+    features
+}
+
+/// The indices, in `constraints` order, of the constraints that are
+/// unsatisfiable on their own: a term compared against itself as in
+/// `x > x`, or as in `x != x` when it cannot be `NaN`, a ground
+/// comparison that is false, membership of the empty
+/// domain, or a conjunction holding both a formula and its negation.
+/// This is a cheap lint; an empty result does not mean the program is
+/// satisfiable.
+pub fn find_trivial_contradictions(program: &ConstraintProgramExpression) -> Vec<usize> {
+    super::constraints(program)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, constraint)| trivially_contradictory(constraint))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// An arithmetic subexpression whose result may fall outside the range
+/// of `i128`, where it would evaluate to `NaN` and quietly fail every
+/// comparison it takes part in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverflowWarning {
+    /// The index, in `constraints` order, of the constraint holding it.
+    pub constraint: usize,
+    pub expr: IntegerNumberExpression,
+}
+
+/// The interval of values an integer expression can take, or `None`
+/// when it is not known.
+type Bounds = Option<(i128, i128)>;
+
+/// The smallest and largest of `values`, or `None` if one of them is.
+fn spanning(values: &[Option<i128>]) -> Bounds {
+    let values: Vec<i128> = values.iter().copied().collect::<Option<_>>()?;
+    Some((*values.iter().min()?, *values.iter().max()?))
+}
+
+/// The bounds of `expr`, given the bounds of the variables `let` binds in
+/// `scope`, innermost last, and the program's `domains`. Each sum,
+/// difference, product or power whose operands are bounded but whose
+/// result may not fit in an `i128` is pushed to `risks`; its own bounds
+/// are then unknown, so that only the innermost such subexpression is
+/// reported.
+fn bounds<'a>(
+    expr: &'a IntegerNumberExpression,
+    scope: &mut Vec<(&'a Symbol, Bounds)>,
+    domains: &HashMap<String, Domain>,
+    risks: &mut Vec<&'a IntegerNumberExpression>,
+) -> Bounds {
+    use IntegerNumberExpression::*;
+    let risky = |risks: &mut Vec<&'a IntegerNumberExpression>, result: Bounds| {
+        if result.is_none() {
+            risks.push(expr);
+        }
+        result
+    };
+    match expr {
+        IntegerNumberVariable(symbol) => {
+            match scope.iter().rev().find(|(bound, _)| *bound == symbol) {
+                Some((_, bounds)) => *bounds,
+                None => match domains.get(symbol.name()) {
+                    Some(Domain::Integer(domain)) => {
+                        match domain.hull(&[], DivisionMode::default()) {
+                            Ok(Hull::Interval(low, high)) => Some((low, high)),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                },
+            }
+        }
+        IntegerNumberValue(IntegerNumber::Value(n)) => Some((*n, *n)),
+        IntegerNumberValue(IntegerNumber::NaN) => None,
+        Parenthesis(expr) => bounds(expr, scope, domains, risks),
+        Negate(expr) => {
+            let (low, high) = bounds(expr, scope, domains, risks)?;
+            Some((high.checked_neg()?, low.checked_neg()?))
+        }
+        Abs(expr) => {
+            let (low, high) = bounds(expr, scope, domains, risks)?;
+            let (low_abs, high_abs) = (low.checked_abs()?, high.checked_abs()?);
+            let least = if low <= 0 && 0 <= high {
+                0
+            } else {
+                low_abs.min(high_abs)
+            };
+            Some((least, low_abs.max(high_abs)))
+        }
+        Add(expr_a, expr_b) | Minus(expr_a, expr_b) | Times(expr_a, expr_b) => {
+            let a = bounds(expr_a, scope, domains, risks);
+            let b = bounds(expr_b, scope, domains, risks);
+            let ((a_low, a_high), (b_low, b_high)) = (a?, b?);
+            risky(
+                risks,
+                match expr {
+                    Add(_, _) => spanning(&[a_low.checked_add(b_low), a_high.checked_add(b_high)]),
+                    Minus(_, _) => {
+                        spanning(&[a_low.checked_sub(b_high), a_high.checked_sub(b_low)])
+                    }
+                    _ => spanning(&[
+                        a_low.checked_mul(b_low),
+                        a_low.checked_mul(b_high),
+                        a_high.checked_mul(b_low),
+                        a_high.checked_mul(b_high),
+                    ]),
+                },
+            )
+        }
+        Sum(exprs) | Product(exprs) => {
+            let operands: Vec<Bounds> = exprs
+                .iter()
+                .map(|expr| bounds(expr, scope, domains, risks))
+                .collect();
+            let operands: Vec<(i128, i128)> = operands.into_iter().collect::<Option<_>>()?;
+            let identity: i128 = if matches!(expr, Sum(_)) { 0 } else { 1 };
+            risky(
+                risks,
+                operands.into_iter().try_fold(
+                    (identity, identity),
+                    |(low, high), (b_low, b_high)| match expr {
+                        Sum(_) => Some((low.checked_add(b_low)?, high.checked_add(b_high)?)),
+                        _ => spanning(&[
+                            low.checked_mul(b_low),
+                            low.checked_mul(b_high),
+                            high.checked_mul(b_low),
+                            high.checked_mul(b_high),
+                        ]),
+                    },
+                ),
+            )
+        }
+        Pow(expr_a, expr_b) => {
+            let a = bounds(expr_a, scope, domains, risks);
+            let b = bounds(expr_b, scope, domains, risks);
+            let ((a_low, a_high), (_, b_high)) = (a?, b?);
+            // A negative exponent is `NaN`, so the largest exponent decides.
+            let largest = a_low.unsigned_abs().max(a_high.unsigned_abs());
+            let power = match u32::try_from(b_high.max(0)) {
+                Ok(exponent) => largest
+                    .checked_pow(exponent)
+                    .and_then(|power| i128::try_from(power).ok()),
+                Err(_) if largest <= 1 => Some(largest as i128),
+                Err(_) => None,
+            };
+            risky(
+                risks,
+                power.map(|power| {
+                    if a_low >= 0 {
+                        (0, power)
+                    } else {
+                        (-power, power)
+                    }
+                }),
+            )
+        }
+        Divide(expr_a, expr_b) => {
+            // A quotient is never further from zero than its dividend.
+            let a = bounds(expr_a, scope, domains, risks);
+            bounds(expr_b, scope, domains, risks);
+            let (low, high) = a?;
+            let most = low.checked_abs()?.max(high.checked_abs()?);
+            Some((-most, most))
+        }
+        Modulo(expr_a, expr_b) => {
+            bounds(expr_a, scope, domains, risks);
+            let (low, high) = bounds(expr_b, scope, domains, risks)?;
+            let most = low.checked_abs()?.max(high.checked_abs()?);
+            Some((-most, most))
+        }
+        PowMod(expr_a, expr_b, expr_c) => {
+            bounds(expr_a, scope, domains, risks);
+            bounds(expr_b, scope, domains, risks);
+            let (_, high) = bounds(expr_c, scope, domains, risks)?;
+            Some((0, high.max(0)))
+        }
+        TupleGet(_, _) => None,
+        Let(symbol, value, body) => {
+            let value = bounds(value, scope, domains, risks);
+            scope.push((symbol, value));
+            let body = bounds(body, scope, domains, risks);
+            scope.pop();
+            body
+        }
+        OfBoolean(_) => Some((0, 1)),
+        IfThenElse(_, then, otherwise) => {
+            let then = bounds(then, scope, domains, risks);
+            let otherwise = bounds(otherwise, scope, domains, risks);
+            let ((then_low, then_high), (otherwise_low, otherwise_high)) = (then?, otherwise?);
+            Some((then_low.min(otherwise_low), then_high.max(otherwise_high)))
+        }
+    }
+}
+
+/// The arithmetic subexpressions of the program whose result can exceed
+/// the range of `i128`, judging from interval bounds on the variables'
+/// domains. Sums, differences, products and powers are checked, `sum`
+/// and `product` included; they are reported only when the bounds of
+/// their operands are known, so an expression over a variable without a
+/// finite domain is never flagged.
+/// Overflow evaluates to `NaN`, which makes solutions vanish without an
+/// error, so this is worth running on a model before solving it.
+pub fn overflow_risk(program: &ConstraintProgramExpression) -> Vec<OverflowWarning> {
+    use BooleanIntegerNumberExpression::*;
+    let domains = super::variable_domains(program).unwrap_or_default();
+    let mut warnings = Vec::new();
+    for (index, constraint) in super::constraints(program).into_iter().enumerate() {
+        let ConstraintLogicExpression::OfIntegerNumber(expr) = constraint else {
+            continue;
+        };
+        let mut risks = Vec::new();
+        match expr.as_ref() {
+            Equals(expr_a, expr_b)
+            | Different(expr_a, expr_b)
+            | Greater(expr_a, expr_b)
+            | Less(expr_a, expr_b) => {
+                bounds(expr_a, &mut Vec::new(), &domains, &mut risks);
+                bounds(expr_b, &mut Vec::new(), &domains, &mut risks);
+            }
+            In(expr, _) => {
+                bounds(expr, &mut Vec::new(), &domains, &mut risks);
+            }
+        }
+        warnings.extend(risks.into_iter().map(|expr| OverflowWarning {
+            constraint: index,
+            expr: expr.clone(),
+        }));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_trivial_contradictions;
+    use crate::expressions::boolean::BooleanExpression;
+    use crate::expressions::integer::{
+        BooleanIntegerNumberExpression, IntegerNumber, IntegerNumberDomainExpression,
+        IntegerNumberExpression,
+    };
+    use crate::expressions::{
+        ConstraintLogicExpression, ConstraintProgramExpression, SatisfactionExpression, Symbol,
+    };
+
+    fn int_var(name: &str) -> Box<IntegerNumberExpression> {
+        Box::new(IntegerNumberExpression::IntegerNumberVariable(Symbol::new(
+            name.to_string(),
+        )))
+    }
+    fn int(value: i128) -> Box<IntegerNumberExpression> {
+        Box::new(IntegerNumberExpression::IntegerNumberValue(
+            IntegerNumber::Value(value),
+        ))
+    }
+    fn bool_var(name: &str) -> Box<BooleanExpression> {
+        Box::new(BooleanExpression::BooleanVariable(Symbol::new(
+            name.to_string(),
+        )))
+    }
+    fn of_integer(expr: BooleanIntegerNumberExpression) -> ConstraintLogicExpression {
+        ConstraintLogicExpression::OfIntegerNumber(Box::new(expr))
+    }
+    fn program(constraints: Vec<ConstraintLogicExpression>) -> ConstraintProgramExpression {
+        let goal = ConstraintProgramExpression::Solve(Box::new(SatisfactionExpression::Satisfy(
+            Box::new(of_integer(BooleanIntegerNumberExpression::Less(
+                int_var("x"),
+                int_var("y"),
+            ))),
+        )));
+        constraints
+            .into_iter()
+            .rev()
+            .fold(goal, |rest, constraint| {
+                ConstraintProgramExpression::ConstrainAnd(Box::new(constraint), Box::new(rest))
+            })
+    }
+
+    #[test]
+    fn each_kind_of_trivial_contradiction_is_found() {
+        use BooleanIntegerNumberExpression::*;
+        let program = program(vec![
+            of_integer(Different(int_var("x"), int_var("x"))),
+            of_integer(Greater(int(5), int(10))),
+            of_integer(Greater(int_var("x"), int(10))),
+            of_integer(In(
+                int_var("x"),
+                Box::new(IntegerNumberDomainExpression::Empty),
+            )),
+            ConstraintLogicExpression::Boolean(Box::new(BooleanExpression::And(
+                bool_var("b"),
+                Box::new(BooleanExpression::Not(bool_var("b"))),
+            ))),
+        ]);
+        assert_eq!(find_trivial_contradictions(&program), vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn a_clean_program_has_no_trivial_contradictions() {
+        use BooleanIntegerNumberExpression::*;
+        let program = program(vec![
+            of_integer(Different(int_var("x"), int_var("y"))),
+            of_integer(Greater(int(10), int(5))),
+            ConstraintLogicExpression::Boolean(Box::new(BooleanExpression::And(
+                bool_var("b"),
+                Box::new(BooleanExpression::Not(bool_var("c"))),
+            ))),
+        ]);
+        assert!(find_trivial_contradictions(&program).is_empty());
+    }
+
+    #[test]
+    fn only_free_floating_variables_are_unconstrained() {
+        use super::unconstrained_variables;
+        use crate::expressions::parser::parse_program;
+        let program =
+            parse_program("x in [0, 5];\ny in universe;\np or not p;\nsatisfy x > 2;").unwrap();
+        let names: Vec<String> = unconstrained_variables(&program)
+            .iter()
+            .map(|variable| variable.name().to_string())
+            .collect();
+        assert_eq!(names, vec!["y", "p"]);
+    }
+
+    #[test]
+    fn required_features_flag_division_and_maximisation() {
+        use super::{required_features, FeatureSet};
+        use crate::expressions::parser::parse_program;
+        let program = parse_program("x in [1, 9];\ny in [1, 9];\nmaximise x / y > 2;").unwrap();
+        assert_eq!(
+            required_features(&program),
+            FeatureSet {
+                uses_integers: true,
+                uses_division: true,
+                uses_optimization: true,
+                uses_nonlinear: true,
+                ..FeatureSet::default()
+            }
+        );
+        let program = parse_program("p or q;\nsatisfy x > 2 * y;").unwrap();
+        assert_eq!(
+            required_features(&program),
+            FeatureSet {
+                uses_integers: true,
+                uses_infinite_domains: true,
+                ..FeatureSet::default()
+            }
+        );
+        let program = parse_program("satisfy 2 ^ x > 3;").unwrap();
+        assert!(required_features(&program).uses_nonlinear);
+    }
+
+    #[test]
+    fn products_of_wide_domains_risk_overflow() {
+        use super::overflow_risk;
+        use crate::expressions::parser::parse_program;
+        let program = parse_program(
+            "x in [-100000000000000000000000, 100000000000000000000000];
+            y in [-100000000000000000000000, 100000000000000000000000];
+            a in [0, 1000];
+            b in [-50, 50];
+            satisfy x * y > a * b + 1;",
+        )
+        .unwrap();
+        let warnings = overflow_risk(&program);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].constraint, 4);
+        assert_eq!(warnings[0].expr.to_string(), "x * y");
+        let program =
+            parse_program("x in [0, 9];\ny in universe;\nsatisfy x ^ 3 + y * y > 2;").unwrap();
+        assert!(overflow_risk(&program).is_empty());
+        let program = parse_program("x in [0, 99];\nsatisfy 2 ^ (x + 100) > 1;").unwrap();
+        assert_eq!(overflow_risk(&program)[0].expr.to_string(), "2 ^ (x + 100)");
+    }
+
+    #[test]
+    fn a_term_that_may_be_nan_can_differ_from_itself() {
+        use crate::expressions::parser::parse_program;
+        use crate::solver::solve_all;
+        let program = parse_program("x in [0, 1];\ny in [0, 1];\nsatisfy x / y != x / y;").unwrap();
+        assert!(find_trivial_contradictions(&program).is_empty());
+        assert_eq!(solve_all(&program).unwrap().len(), 2);
+        let program = parse_program("x in [0, 1];\nsatisfy x / 1 > x / 1;").unwrap();
+        assert_eq!(find_trivial_contradictions(&program), vec![1]);
+    }
+
+    #[test]
+    fn alldifferent_may_repeat_a_term_that_may_be_nan() {
+        use crate::expressions::parser::parse_program;
+        use crate::solver::solve_all;
+        let program =
+            parse_program("x in [0, 1];\ny in [0, 1];\nsatisfy alldifferent(x / y, x / y);")
+                .unwrap();
+        assert!(find_trivial_contradictions(&program).is_empty());
+        assert_eq!(solve_all(&program).unwrap().len(), 2);
+        let program = parse_program("x in [0, 1];\nsatisfy alldifferent(x, 1, x);").unwrap();
+        assert_eq!(find_trivial_contradictions(&program), vec![1]);
+    }
+}
@@ -0,0 +1,314 @@
+//! A model built up one constraint at a time, for interactive sessions
+//! that want to know after each step whether the model still holds up.
+use super::{Propagation, SolveConfig, SolveError};
+use crate::expressions::{AssignedValue, ConstraintLogicExpression, Symbol};
+
+/// The handle of a constraint added to a `Solver`, by which it is
+/// retracted. Handles are not reused within one solver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConstraintId(usize);
+
+/// What adding a constraint did to the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Consistency {
+    /// The handle of the added constraint.
+    pub id: ConstraintId,
+    /// Whether every variable still has a value left. Propagation does
+    /// not search, so a consistent model may still have no solution.
+    pub consistent: bool,
+    /// The variables that lost values, in search order. Variables the
+    /// constraint introduces are not listed.
+    pub changed: Vec<Symbol>,
+}
+
+/// What propagating after one constraint changed, so that it can be
+/// undone: the variables in search order and the values of those it
+/// narrowed, both as they were before, and the variables it introduced.
+#[derive(Debug, Clone)]
+struct Pruning {
+    order: Vec<Symbol>,
+    narrowed: Vec<(Symbol, Vec<AssignedValue>)>,
+    introduced: Vec<Symbol>,
+}
+
+/// A model to which constraints are added one at a time, narrowing the
+/// variable domains by propagation after each one.
+#[derive(Debug, Clone)]
+pub struct Solver {
+    config: SolveConfig,
+    constraints: Vec<ConstraintLogicExpression>,
+    /// The handle of each constraint, in the same order.
+    ids: Vec<ConstraintId>,
+    /// What propagating after each constraint changed, in the same order.
+    trail: Vec<Pruning>,
+    next_id: usize,
+    domains: Vec<(Symbol, Vec<AssignedValue>)>,
+}
+
+impl Solver {
+    /// An empty model propagating with AC-3.
+    pub fn new() -> Solver {
+        Solver::with_config(SolveConfig {
+            propagation: Propagation::Ac3,
+            ..SolveConfig::default()
+        })
+    }
+
+    /// An empty model propagating and evaluating as `config` says, with
+    /// its `domains` bounding the variables the constraints introduce.
+    pub fn with_config(config: SolveConfig) -> Solver {
+        Solver {
+            config,
+            constraints: Vec::new(),
+            ids: Vec::new(),
+            trail: Vec::new(),
+            next_id: 0,
+            domains: Vec::new(),
+        }
+    }
+
+    /// Adds `constraint` and propagates again, starting from the domains
+    /// the constraints before it left. Every constraint over one variable
+    /// is applied to that variable's values, and those over two are
+    /// propagated as the configuration says. A constraint that cannot be
+    /// searched, such as one over a variable without a finite domain, is
+    /// an error and leaves the model as it was.
+    pub fn add_constraint(
+        &mut self,
+        constraint: ConstraintLogicExpression,
+    ) -> Result<Consistency, SolveError> {
+        let id = ConstraintId(self.next_id);
+        let consistency = self.push(constraint, id)?;
+        self.next_id += 1;
+        Ok(consistency)
+    }
+
+    /// Adds `constraint` under `id`, propagates and records on the trail
+    /// what propagation changed.
+    fn push(
+        &mut self,
+        constraint: ConstraintLogicExpression,
+        id: ConstraintId,
+    ) -> Result<Consistency, SolveError> {
+        self.constraints.push(constraint);
+        let domains = match self.propagated(&self.domains) {
+            Ok(domains) => domains,
+            Err(error) => {
+                self.constraints.pop();
+                return Err(error);
+            }
+        };
+        self.ids.push(id);
+        let narrowed: Vec<(Symbol, Vec<AssignedValue>)> = self
+            .domains
+            .iter()
+            .filter(|(known, before)| {
+                domains
+                    .iter()
+                    .any(|(name, values)| known == name && values.len() < before.len())
+            })
+            .cloned()
+            .collect();
+        let changed = domains
+            .iter()
+            .filter(|(name, _)| narrowed.iter().any(|(known, _)| known == name))
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.trail.push(Pruning {
+            order: self.domains.iter().map(|(name, _)| name.clone()).collect(),
+            narrowed,
+            introduced: domains
+                .iter()
+                .filter(|(name, _)| self.domains.iter().all(|(known, _)| known != name))
+                .map(|(name, _)| name.clone())
+                .collect(),
+        });
+        self.domains = domains;
+        Ok(Consistency {
+            id,
+            consistent: self.is_consistent(),
+            changed,
+        })
+    }
+
+    /// Removes the last constraint and gives back what propagating after
+    /// it changed.
+    fn pop(&mut self) -> Option<(ConstraintLogicExpression, ConstraintId)> {
+        let pruning = self.trail.pop()?;
+        self.domains
+            .retain(|(name, _)| !pruning.introduced.contains(name));
+        for (name, before) in pruning.narrowed {
+            if let Some((_, values)) = self.domains.iter_mut().find(|(known, _)| *known == name) {
+                *values = before;
+            }
+        }
+        self.domains
+            .sort_by_key(|(name, _)| pruning.order.iter().position(|known| known == name));
+        Some((self.constraints.pop()?, self.ids.pop()?))
+    }
+
+    /// Removes the constraint added as `id` and gives back the values it
+    /// pruned. A value may have been pruned for the sake of several
+    /// constraints at once, so the trail is undone back to the domains
+    /// the model had before the constraint, and the constraints added
+    /// after it are propagated again from there, keeping their handles.
+    /// What the constraints before it pruned stays pruned. Retracting a
+    /// constraint the model has no longer, or one without which some
+    /// variable has no finite domain, is an error and leaves the model as
+    /// it was.
+    pub fn retract_constraint(&mut self, id: ConstraintId) -> Result<(), SolveError> {
+        let position = self
+            .ids
+            .iter()
+            .position(|other| *other == id)
+            .ok_or(SolveError::UnknownConstraint(id))?;
+        let mut model = self.clone();
+        let mut later = Vec::new();
+        while model.constraints.len() > position {
+            later.extend(model.pop());
+        }
+        later.pop();
+        for (constraint, id) in later.into_iter().rev() {
+            model.push(constraint, id)?;
+        }
+        *self = model;
+        Ok(())
+    }
+
+    /// The domains of the variables after the constraints added so far,
+    /// propagated from the values they have in `known`.
+    fn propagated(
+        &self,
+        known: &[(Symbol, Vec<AssignedValue>)],
+    ) -> Result<Vec<(Symbol, Vec<AssignedValue>)>, SolveError> {
+        let program = super::program_of(self.constraints.clone());
+        let mut search = super::search::Search::new(&program, &self.config)?;
+        search.restrict(known);
+        search.make_node_consistent();
+        search.propagate(&self.config.propagation);
+        Ok(search.bounded_variables())
+    }
+
+    /// Whether every variable still has a value left.
+    pub fn is_consistent(&self) -> bool {
+        self.domains.iter().all(|(_, values)| !values.is_empty())
+    }
+
+    /// The values each variable has left, in search order.
+    pub fn domains(&self) -> &[(Symbol, Vec<AssignedValue>)] {
+        &self.domains
+    }
+
+    /// The constraints in the order they were added.
+    pub fn constraints(&self) -> &[ConstraintLogicExpression] {
+        &self.constraints
+    }
+}
+
+impl Default for Solver {
+    fn default() -> Solver {
+        Solver::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Consistency, ConstraintId, Solver};
+    use crate::expressions::integer::{
+        IntegerNumber, IntegerNumberDomainExpression, IntegerNumberExpression,
+    };
+    use crate::expressions::parser::parse_program;
+    use crate::expressions::{ConstraintLogicExpression, Domain, Symbol, Variable};
+    use crate::solver::{Propagation, SolveConfig};
+
+    fn constraint(text: &str) -> ConstraintLogicExpression {
+        let program = parse_program(&format!("satisfy {};", text)).unwrap();
+        crate::solver::constraints(&program)[0].clone()
+    }
+
+    fn solver() -> Solver {
+        let value = |n| {
+            Box::new(IntegerNumberExpression::IntegerNumberValue(
+                IntegerNumber::Value(n),
+            ))
+        };
+        let digit = |name: &str| {
+            Variable::new(
+                Symbol::new(name.to_string()),
+                Domain::Integer(IntegerNumberDomainExpression::ClosedRange(
+                    value(0),
+                    value(5),
+                )),
+            )
+        };
+        Solver::with_config(SolveConfig {
+            propagation: Propagation::Ac3,
+            domains: vec![digit("x"), digit("y")],
+            ..SolveConfig::default()
+        })
+    }
+
+    #[test]
+    fn the_third_constraint_makes_the_model_inconsistent() {
+        let mut solver = solver();
+        let x = Symbol::new("x".to_string());
+        let y = Symbol::new("y".to_string());
+        assert_eq!(
+            solver.add_constraint(constraint("x < y")),
+            Ok(Consistency {
+                id: ConstraintId(0),
+                consistent: true,
+                changed: vec![],
+            })
+        );
+        assert_eq!(solver.domains()[0].1.len(), 5);
+        assert_eq!(
+            solver.add_constraint(constraint("y < 3")),
+            Ok(Consistency {
+                id: ConstraintId(1),
+                consistent: true,
+                changed: vec![x.clone(), y.clone()],
+            })
+        );
+        let consistency = solver.add_constraint(constraint("x > 1")).unwrap();
+        assert!(!consistency.consistent);
+        assert!(consistency.changed.contains(&x));
+        assert!(!solver.is_consistent());
+        assert_eq!(solver.constraints().len(), 3);
+    }
+
+    #[test]
+    fn retracting_a_constraint_restores_what_it_pruned() {
+        let mut model = solver();
+        model.add_constraint(constraint("x < y")).unwrap();
+        let bound = model.add_constraint(constraint("y < 3")).unwrap().id;
+        assert_eq!(model.domains()[1].1.len(), 2);
+        model.retract_constraint(bound).unwrap();
+        let mut only = solver();
+        only.add_constraint(constraint("x < y")).unwrap();
+        assert_eq!(model.domains(), only.domains());
+        assert_eq!(model.constraints(), only.constraints());
+        assert_eq!(
+            model.retract_constraint(bound),
+            Err(crate::solver::SolveError::UnknownConstraint(bound))
+        );
+    }
+
+    #[test]
+    fn retracting_an_earlier_constraint_keeps_the_later_ones() {
+        let mut model = solver();
+        let first = model.add_constraint(constraint("x < y")).unwrap().id;
+        let middle = model.add_constraint(constraint("y < 3")).unwrap().id;
+        let last = model.add_constraint(constraint("x + y > 3")).unwrap().id;
+        model.retract_constraint(middle).unwrap();
+        let mut without = solver();
+        without.add_constraint(constraint("x < y")).unwrap();
+        without.add_constraint(constraint("x + y > 3")).unwrap();
+        assert_eq!(model.domains(), without.domains());
+        assert_eq!(model.constraints(), without.constraints());
+        model.retract_constraint(last).unwrap();
+        model.retract_constraint(first).unwrap();
+        assert!(model.constraints().is_empty());
+        assert!(model.domains().is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+//! Incomplete local search in the style of WalkSAT.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::SolveError;
+use crate::expressions::boolean::BooleanValue;
+use crate::expressions::memo::MemoCache;
+use crate::expressions::{Assignment, ConstraintProgramExpression};
+
+/// Options for `solve_local`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalSearchConfig {
+    /// Seed of the random choices, so that runs can be repeated.
+    pub seed: u64,
+    /// The most values changed before giving up.
+    pub max_flips: usize,
+    /// Probability, from 0 to 1, of changing a random variable of the chosen
+    /// violated constraint rather than the best one. Values outside that
+    /// range are taken as its nearest end, and `NaN` as 0.
+    pub noise: f64,
+}
+
+impl Default for LocalSearchConfig {
+    fn default() -> LocalSearchConfig {
+        LocalSearchConfig {
+            seed: 0,
+            max_flips: 10_000,
+            noise: 0.2,
+        }
+    }
+}
+
+/// Searches for a solution by repairing a random complete assignment.
+/// Each step picks a constraint the assignment does not satisfy and
+/// changes the value of one of its variables: with probability `noise`
+/// a random one, otherwise the change that satisfies the most
+/// constraints, the first part of `satisfaction_degree` for a complete
+/// assignment. Constraints are evaluated through a `MemoCache`, since
+/// most keep the values of their variables from one step to the next.
+/// Returns `None` when `max_flips` changes find no solution, which does
+/// not mean that there is none. Variables need finite domains, unlike in
+/// `solve_all` even those computed by a definition.
+pub fn solve_local(
+    program: &ConstraintProgramExpression,
+    config: LocalSearchConfig,
+) -> Result<Option<Vec<Assignment>>, SolveError> {
+    let search = super::search::Search::new(program, &super::SolveConfig::default())?;
+    let variables = search.variables();
+    if let Some(position) = (0..variables.len()).find(|position| search.is_unbounded(*position)) {
+        return Err(SolveError::Unbounded(variables[position].0.clone()));
+    }
+    if variables.iter().any(|(_, values)| values.is_empty()) {
+        return Ok(None);
+    }
+    let noise = if config.noise.is_nan() {
+        0.0
+    } else {
+        config.noise.clamp(0.0, 1.0)
+    };
+    let mut memo = MemoCache::new();
+    let constraints: Vec<_> = super::constraints(program)
+        .into_iter()
+        .map(|constraint| memo.prepare(constraint))
+        .collect();
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut env: Vec<Assignment> = variables
+        .iter()
+        .map(|(name, values)| {
+            Assignment::new(name.clone(), values[rng.gen_range(0..values.len())].clone())
+        })
+        .collect();
+    for _ in 0..config.max_flips {
+        let violated: Vec<_> = constraints
+            .iter()
+            .filter(|constraint| memo.evaluate(constraint, &env) != Ok(BooleanValue::True))
+            .collect();
+        let Some(constraint) = violated.get(rng.gen_range(0..violated.len().max(1))) else {
+            return Ok(Some(env));
+        };
+        let positions: Vec<usize> = (0..variables.len())
+            .filter(|position| {
+                variables[*position].1.len() > 1
+                    && constraint.free().contains(&variables[*position].0)
+            })
+            .collect();
+        if positions.is_empty() {
+            // A constraint nothing can repair, such as a false ground one.
+            return Ok(None);
+        }
+        let flip = |position: usize, value: usize| {
+            let mut changed = env.clone();
+            changed[position] = Assignment::new(
+                variables[position].0.clone(),
+                variables[position].1[value].clone(),
+            );
+            changed
+        };
+        env = if rng.gen_bool(noise) {
+            let position = positions[rng.gen_range(0..positions.len())];
+            let current = env[position].value();
+            let others: Vec<usize> = (0..variables[position].1.len())
+                .filter(|value| variables[position].1[*value] != *current)
+                .collect();
+            flip(position, others[rng.gen_range(0..others.len())])
+        } else {
+            positions
+                .iter()
+                .flat_map(|position| {
+                    (0..variables[*position].1.len()).map(move |value| (*position, value))
+                })
+                .filter(|(position, value)| {
+                    variables[*position].1[*value] != *env[*position].value()
+                })
+                .map(|(position, value)| flip(position, value))
+                .max_by_key(|changed| {
+                    constraints
+                        .iter()
+                        .filter(|constraint| {
+                            memo.evaluate(constraint, changed) == Ok(BooleanValue::True)
+                        })
+                        .count()
+                })
+                .expect("a variable with several values can change")
+        };
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{solve_local, LocalSearchConfig};
+    use crate::expressions::boolean::BooleanValue;
+    use crate::expressions::parser::parse_program;
+    use crate::expressions::Evaluate;
+
+    #[test]
+    fn local_search_solves_a_satisfiable_instance() {
+        let program = parse_program(
+            "a or b or not c;
+            not a or c or d;
+            not b or not d or e;
+            c or not e or f;
+            not f or g or a;
+            not g or not a or h;
+            not h or b or not c;
+            d or e or not g;
+            not d or f or not h;
+            satisfy a or not b or g;",
+        )
+        .unwrap();
+        let config = LocalSearchConfig {
+            seed: 7,
+            max_flips: 1_000,
+            noise: 0.3,
+        };
+        let env = solve_local(&program, config).unwrap().unwrap();
+        assert!(crate::solver::constraints(&program)
+            .iter()
+            .all(|constraint| constraint.evaluate(&env) == Ok(BooleanValue::True)));
+    }
+
+    #[test]
+    fn noise_out_of_range_is_clamped() {
+        let program = parse_program("a or b;\nnot a or b;\nsatisfy a or not b;").unwrap();
+        for noise in [-1.0, 2.0, f64::NAN] {
+            let config = LocalSearchConfig {
+                seed: 1,
+                max_flips: 100,
+                noise,
+            };
+            assert!(solve_local(&program, config).unwrap().is_some());
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Bounds propagation that narrows the integer variables of a program
+//! without searching.
+use std::collections::HashMap;
+
+use crate::expressions::integer::{
+    BooleanIntegerNumberExpression, DivisionMode, Hull, IntegerNumber,
+    IntegerNumberDomainExpression, IntegerNumberExpression,
+};
+use crate::expressions::{
+    Apply, AssignedValue, Assignment, ConstraintLogicExpression, ConstraintProgramExpression,
+    Domain, Symbol,
+};
+
+/// The most passes `reduce` makes over the constraints. Bounds that
+/// chase each other, as those of `x < y` and `y < x` do, move by one a
+/// pass, and could otherwise take as many passes as there are integers.
+const MAX_PASSES: usize = 256;
+
+/// What `reduce` makes of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reduction {
+    /// A program with the same solutions as the original.
+    Reduced(ConstraintProgramExpression),
+    /// The variable has no value left, so the program has no solutions.
+    Unsatisfiable(Symbol),
+}
+
+/// The values other than `NaN` an integer expression may take, from the
+/// first to the second, and empty when the first is greater. The ends of
+/// `i128` double as unbounded ends, which is exact, since every value
+/// lies between them.
+type Interval = (i128, i128);
+
+const UNBOUNDED: Interval = (i128::MIN, i128::MAX);
+
+/// `a / b` rounded toward negative infinity, saturating when the
+/// quotient does not fit.
+fn floor_div(a: i128, b: i128) -> i128 {
+    match a.checked_div(b) {
+        Some(quotient) if a % b != 0 && (a < 0) != (b < 0) => quotient - 1,
+        Some(quotient) => quotient,
+        None => i128::MAX,
+    }
+}
+
+/// `a / b` rounded toward positive infinity, saturating when the
+/// quotient does not fit.
+fn ceil_div(a: i128, b: i128) -> i128 {
+    match a.checked_div(b) {
+        Some(quotient) if a % b != 0 && (a < 0) == (b < 0) => quotient + 1,
+        Some(quotient) => quotient,
+        None => i128::MAX,
+    }
+}
+
+/// The operands of a `sum`, with those of the sums nested in it in place
+/// of the nested sums, as evaluation counts them.
+fn summands<'e>(
+    exprs: &'e [IntegerNumberExpression],
+    found: &mut Vec<&'e IntegerNumberExpression>,
+) {
+    for expr in exprs {
+        let mut expr = expr;
+        while let IntegerNumberExpression::Parenthesis(inner) = expr {
+            expr = inner;
+        }
+        match expr {
+            IntegerNumberExpression::Sum(nested) => summands(nested, found),
+            expr => found.push(expr),
+        }
+    }
+}
+
+/// The bounds of the integer variables, narrowed constraint by
+/// constraint. A comparison only holds when neither side is `NaN`, and
+/// `NaN` propagates through every operation, so the values that overflow
+/// are left out of every interval.
+struct Bounds<'a> {
+    intervals: HashMap<&'a Symbol, Interval>,
+    /// Whether an interval has narrowed during the current pass.
+    changed: bool,
+}
+
+impl<'a> Bounds<'a> {
+    /// An interval holding every value of `expr` other than `NaN`.
+    /// Operators without a rule here, and `let`, which may shadow a
+    /// variable, are unbounded.
+    fn interval(&self, expr: &IntegerNumberExpression) -> Interval {
+        use IntegerNumberExpression::*;
+        match expr {
+            IntegerNumberVariable(symbol) => {
+                self.intervals.get(symbol).copied().unwrap_or(UNBOUNDED)
+            }
+            IntegerNumberValue(IntegerNumber::Value(n)) => (*n, *n),
+            Parenthesis(expr) => self.interval(expr),
+            Negate(expr) => {
+                let (low, high) = self.interval(expr);
+                (high.saturating_neg(), low.saturating_neg())
+            }
+            Add(expr_a, expr_b) => {
+                let ((a_low, a_high), (b_low, b_high)) =
+                    (self.interval(expr_a), self.interval(expr_b));
+                (a_low.saturating_add(b_low), a_high.saturating_add(b_high))
+            }
+            Minus(expr_a, expr_b) => {
+                let ((a_low, a_high), (b_low, b_high)) =
+                    (self.interval(expr_a), self.interval(expr_b));
+                (a_low.saturating_sub(b_high), a_high.saturating_sub(b_low))
+            }
+            Sum(exprs) => {
+                let mut operands = Vec::new();
+                summands(exprs, &mut operands);
+                operands.into_iter().fold((0, 0), |(low, high), expr| {
+                    let (expr_low, expr_high) = self.interval(expr);
+                    (low.saturating_add(expr_low), high.saturating_add(expr_high))
+                })
+            }
+            Times(expr_a, expr_b) => {
+                let ((a_low, a_high), (b_low, b_high)) =
+                    (self.interval(expr_a), self.interval(expr_b));
+                let corners = [
+                    a_low.saturating_mul(b_low),
+                    a_low.saturating_mul(b_high),
+                    a_high.saturating_mul(b_low),
+                    a_high.saturating_mul(b_high),
+                ];
+                (
+                    corners.into_iter().min().unwrap_or(i128::MIN),
+                    corners.into_iter().max().unwrap_or(i128::MAX),
+                )
+            }
+            _ => UNBOUNDED,
+        }
+    }
+
+    /// Narrows the variables of `expr` to the values for which it can
+    /// lie in `target`, failing with the first variable left without any.
+    /// Saturating at the ends of `i128` only ever widens an interval, so
+    /// the narrowing never loses a solution.
+    fn project(
+        &mut self,
+        expr: &'a IntegerNumberExpression,
+        (low, high): Interval,
+    ) -> Result<(), &'a Symbol> {
+        use IntegerNumberExpression::*;
+        match expr {
+            IntegerNumberVariable(symbol) => {
+                let Some(interval) = self.intervals.get_mut(symbol) else {
+                    return Ok(());
+                };
+                let narrowed = (interval.0.max(low), interval.1.min(high));
+                if narrowed != *interval {
+                    *interval = narrowed;
+                    self.changed = true;
+                }
+                if narrowed.0 > narrowed.1 {
+                    Err(symbol)
+                } else {
+                    Ok(())
+                }
+            }
+            Parenthesis(expr) => self.project(expr, (low, high)),
+            Negate(expr) => self.project(expr, (high.saturating_neg(), low.saturating_neg())),
+            Add(expr_a, expr_b) => {
+                let ((a_low, a_high), (b_low, b_high)) =
+                    (self.interval(expr_a), self.interval(expr_b));
+                self.project(
+                    expr_a,
+                    (low.saturating_sub(b_high), high.saturating_sub(b_low)),
+                )?;
+                self.project(
+                    expr_b,
+                    (low.saturating_sub(a_high), high.saturating_sub(a_low)),
+                )
+            }
+            Minus(expr_a, expr_b) => {
+                let ((a_low, a_high), (b_low, b_high)) =
+                    (self.interval(expr_a), self.interval(expr_b));
+                self.project(
+                    expr_a,
+                    (low.saturating_add(b_low), high.saturating_add(b_high)),
+                )?;
+                self.project(
+                    expr_b,
+                    (a_low.saturating_sub(high), a_high.saturating_sub(low)),
+                )
+            }
+            Sum(exprs) => {
+                let mut operands = Vec::new();
+                summands(exprs, &mut operands);
+                let intervals: Vec<Interval> =
+                    operands.iter().map(|expr| self.interval(expr)).collect();
+                for (index, expr) in operands.into_iter().enumerate() {
+                    let (others_low, others_high) = intervals
+                        .iter()
+                        .enumerate()
+                        .filter(|(other, _)| *other != index)
+                        .fold((0i128, 0i128), |(low, high), (_, (expr_low, expr_high))| {
+                            (
+                                low.saturating_add(*expr_low),
+                                high.saturating_add(*expr_high),
+                            )
+                        });
+                    self.project(
+                        expr,
+                        (
+                            low.saturating_sub(others_high),
+                            high.saturating_sub(others_low),
+                        ),
+                    )?;
+                }
+                Ok(())
+            }
+            Times(expr_a, expr_b) => {
+                for (factor, other) in [(expr_a, expr_b), (expr_b, expr_a)] {
+                    match self.interval(other) {
+                        (c, d) if c == d && c > 0 => {
+                            return self.project(factor, (ceil_div(low, c), floor_div(high, c)))
+                        }
+                        (c, d) if c == d && c < 0 => {
+                            return self.project(factor, (ceil_div(high, c), floor_div(low, c)))
+                        }
+                        _ => (),
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Narrows the variables of an integer comparison to the values for
+    /// which it can hold. `!=` narrows nothing.
+    fn constrain(&mut self, constraint: &'a ConstraintLogicExpression) -> Result<(), &'a Symbol> {
+        use BooleanIntegerNumberExpression::*;
+        let ConstraintLogicExpression::OfIntegerNumber(expr) = constraint else {
+            return Ok(());
+        };
+        let below = |(_, high): Interval| (i128::MIN, high.saturating_sub(1));
+        let above = |(low, _): Interval| (low.saturating_add(1), i128::MAX);
+        match expr.as_ref() {
+            Equals(expr_a, expr_b) => {
+                self.project(expr_a, self.interval(expr_b))?;
+                self.project(expr_b, self.interval(expr_a))
+            }
+            Less(expr_a, expr_b) => {
+                self.project(expr_a, below(self.interval(expr_b)))?;
+                self.project(expr_b, above(self.interval(expr_a)))
+            }
+            Greater(expr_a, expr_b) => {
+                self.project(expr_a, above(self.interval(expr_b)))?;
+                self.project(expr_b, below(self.interval(expr_a)))
+            }
+            In(expr, domain) => match domain.hull(&[], DivisionMode::default()) {
+                Ok(Hull::Interval(low, high)) => self.project(expr, (low, high)),
+                Ok(Hull::Empty) => self.project(expr, (i128::MAX, i128::MIN)),
+                Ok(Hull::Unbounded) | Err(_) => Ok(()),
+            },
+            Different(_, _) => Ok(()),
+        }
+    }
+}
+
+/// Narrows the integer variables of the program by bounds propagation:
+/// each `=`, `<`, `>` and `in` over sums, differences, negations and
+/// products by constants bounds the variables on either side by the
+/// bounds of the other, pass after pass until nothing narrows, or for at
+/// most `MAX_PASSES` passes. A variable whose bounds narrowed, both of
+/// them finite, is restricted to them by a constraint `x in [low, high]`
+/// in front of the others, and one narrowed to a single value is
+/// replaced by it in every constraint but its own restrictions, so the
+/// reduced program has the same solutions over the same variables. A
+/// variable left without values makes the program `Unsatisfiable`.
+/// Booleans, rationals and the other integer operators are left alone,
+/// as is a program that uses a name for variables of two types.
+pub fn reduce(program: ConstraintProgramExpression) -> Reduction {
+    let Ok(free) = super::free_variables(&program) else {
+        return Reduction::Reduced(program);
+    };
+    let declared = super::narrowed_domains(free.clone(), &program);
+    let integers: Vec<(Symbol, Interval)> = free
+        .iter()
+        .filter_map(|variable| match declared.get(variable.name().name()) {
+            Some(Domain::Integer(domain)) => Some((
+                variable.name().clone(),
+                match domain.hull(&[], DivisionMode::default()) {
+                    Ok(Hull::Interval(low, high)) => (low, high),
+                    _ => UNBOUNDED,
+                },
+            )),
+            _ => None,
+        })
+        .collect();
+    let constraints = super::constraints(&program);
+    let mut bounds = Bounds {
+        intervals: integers
+            .iter()
+            .map(|(symbol, _)| (symbol, UNBOUNDED))
+            .collect(),
+        changed: true,
+    };
+    for _ in 0..MAX_PASSES {
+        if !bounds.changed {
+            break;
+        }
+        bounds.changed = false;
+        for constraint in &constraints {
+            if let Err(symbol) = bounds.constrain(constraint) {
+                return Reduction::Unsatisfiable(symbol.clone());
+            }
+        }
+    }
+    let mut restrictions = Vec::new();
+    let mut fixed = Vec::new();
+    for (symbol, hull) in &integers {
+        let (low, high) = bounds.intervals[symbol];
+        if (low, high) == *hull || low == i128::MIN || high == i128::MAX {
+            continue;
+        }
+        if low == high {
+            fixed.push(Assignment::new(
+                symbol.clone(),
+                AssignedValue::Integer(IntegerNumber::Value(low)),
+            ));
+        }
+        let value = |n| {
+            Box::new(IntegerNumberExpression::IntegerNumberValue(
+                IntegerNumber::Value(n),
+            ))
+        };
+        restrictions.push(ConstraintLogicExpression::OfIntegerNumber(Box::new(
+            BooleanIntegerNumberExpression::In(
+                Box::new(IntegerNumberExpression::IntegerNumberVariable(
+                    symbol.clone(),
+                )),
+                Box::new(IntegerNumberDomainExpression::ClosedRange(
+                    value(low),
+                    value(high),
+                )),
+            ),
+        )));
+    }
+    let reduced = super::map_constraints(program, &mut |constraint| {
+        let own = super::search::restriction(&constraint)
+            .is_some_and(|(symbol, _)| fixed.iter().any(|assignment| assignment.name() == symbol));
+        if own {
+            constraint
+        } else {
+            constraint.apply(&fixed)
+        }
+    });
+    Reduction::Reduced(
+        restrictions
+            .into_iter()
+            .rev()
+            .fold(reduced, |rest, restriction| {
+                ConstraintProgramExpression::ConstrainAnd(Box::new(restriction), Box::new(rest))
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reduce, Reduction};
+    use crate::expressions::parser::parse_program;
+    use crate::expressions::Symbol;
+    use crate::solver::solve_all;
+
+    #[test]
+    fn bounds_narrow_to_a_fixpoint() {
+        let program = parse_program(
+            "x in [0, 9];\n\
+             y in [0, 9];\n\
+             x + y = 17;\n\
+             z > 2 * y - 20;\n\
+             z in [-10, 10];\n\
+             satisfy y < x;",
+        )
+        .unwrap();
+        let Reduction::Reduced(reduced) = reduce(program.clone()) else {
+            panic!("the program has solutions");
+        };
+        assert_eq!(
+            reduced.to_string(),
+            "x in [9, 9];\n\
+             y in [8, 8];\n\
+             z in [-3, 10];\n\
+             x in [0, 9];\n\
+             y in [0, 9];\n\
+             9 + 8 = 17;\n\
+             z > 2 * 8 - 20;\n\
+             z in [-10, 10];\n\
+             satisfy 8 < 9;"
+        );
+        assert_eq!(solve_all(&reduced), solve_all(&program));
+        assert_eq!(reduce(reduced.clone()), Reduction::Reduced(reduced));
+
+        let program = parse_program("x in [0, 5];\nsatisfy sum(x, x) > 10;").unwrap();
+        assert_eq!(
+            reduce(program),
+            Reduction::Unsatisfiable(Symbol::new("x".to_string()))
+        );
+    }
+}
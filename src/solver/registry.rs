@@ -0,0 +1,142 @@
+//! Solver strategies chosen by name, so that a configuration string can
+//! say how a program is to be solved.
+use std::collections::BTreeMap;
+
+use super::{LocalSearchConfig, Propagation, Solution, SolveConfig, SolveError, SolveGoal};
+use crate::expressions::ConstraintProgramExpression;
+
+/// A way of finding one solution of a program. An empty solution means
+/// that none was found.
+pub type Strategy =
+    Box<dyn Fn(&ConstraintProgramExpression) -> Result<Vec<Solution>, SolveError> + Send + Sync>;
+
+/// Strategies by name.
+#[derive(Default)]
+pub struct StrategyRegistry {
+    strategies: BTreeMap<String, Strategy>,
+}
+
+impl StrategyRegistry {
+    /// A registry without any strategies.
+    pub fn new() -> StrategyRegistry {
+        StrategyRegistry {
+            strategies: BTreeMap::new(),
+        }
+    }
+
+    /// A registry holding the strategies the crate provides:
+    ///
+    /// - `backtracking`, the systematic search of `solve_with`, checking
+    ///   each constraint as soon as its variables have values;
+    /// - `ac3`, the same search after making the domains arc consistent;
+    /// - `local`, `solve_local` with its default configuration, which may
+    ///   miss a solution that exists.
+    pub fn with_builtins() -> StrategyRegistry {
+        let mut registry = StrategyRegistry::new();
+        registry.register("backtracking", |program| {
+            first_solution(program, Propagation::Off)
+        });
+        registry.register("ac3", |program| first_solution(program, Propagation::Ac3));
+        registry.register("local", |program| {
+            Ok(super::solve_local(program, LocalSearchConfig::default())?
+                .map(|env| super::to_solution(&env))
+                .unwrap_or_default())
+        });
+        registry
+    }
+
+    /// Adds `strategy` under `name`, replacing any strategy registered
+    /// under it before.
+    pub fn register<F>(&mut self, name: &str, strategy: F)
+    where
+        F: Fn(&ConstraintProgramExpression) -> Result<Vec<Solution>, SolveError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.strategies.insert(name.to_string(), Box::new(strategy));
+    }
+
+    /// The names of the registered strategies, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.strategies.keys().map(String::as_str)
+    }
+
+    /// Solves `program` with the strategy registered under `name`.
+    pub fn solve(
+        &self,
+        program: &ConstraintProgramExpression,
+        name: &str,
+    ) -> Result<Vec<Solution>, SolveError> {
+        match self.strategies.get(name) {
+            Some(strategy) => strategy(program),
+            None => Err(SolveError::UnknownStrategy(name.to_string())),
+        }
+    }
+}
+
+fn first_solution(
+    program: &ConstraintProgramExpression,
+    propagation: Propagation,
+) -> Result<Vec<Solution>, SolveError> {
+    let config = SolveConfig {
+        goal: SolveGoal::First,
+        propagation,
+        ..SolveConfig::default()
+    };
+    Ok(super::solve_with(program, &config)?
+        .solutions
+        .pop()
+        .unwrap_or_default())
+}
+
+/// Solves `program` with the built-in strategy called `strategy`, as
+/// listed by `StrategyRegistry::with_builtins`.
+pub fn solve_named(
+    program: &ConstraintProgramExpression,
+    strategy: &str,
+) -> Result<Vec<Solution>, SolveError> {
+    StrategyRegistry::with_builtins().solve(program, strategy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{solve_named, StrategyRegistry};
+    use crate::expressions::parser::parse_program;
+    use crate::expressions::Assignment;
+    use crate::solver::{ground, is_satisfied_ground, Solution, SolveError};
+
+    #[test]
+    fn named_strategies_find_valid_solutions() {
+        let program = parse_program(
+            "x in [0, 9];
+            y in [0, 9];
+            satisfy x + y = 12;
+            satisfy x - y > 3;",
+        )
+        .unwrap();
+        let registry = StrategyRegistry::with_builtins();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec!["ac3", "backtracking", "local"]
+        );
+        for name in ["backtracking", "ac3"] {
+            let solution = solve_named(&program, name).unwrap();
+            assert!(!solution.is_empty());
+            let env: Vec<Assignment> = solution
+                .into_iter()
+                .filter_map(|binding| match binding {
+                    Solution::Variable(name, value) => Some(Assignment::new(name, value)),
+                    _ => None,
+                })
+                .collect();
+            assert!(is_satisfied_ground(&ground(&program, &env)));
+        }
+        assert_eq!(
+            solve_named(&program, "simulated-annealing"),
+            Err(SolveError::UnknownStrategy(
+                "simulated-annealing".to_string()
+            ))
+        );
+    }
+}
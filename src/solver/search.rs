@@ -0,0 +1,1246 @@
+//! Depth first backtracking search over the free variables of a program.
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+
+use super::{Learning, Propagation, SolveConfig, SolveError, SolveStats};
+use crate::expressions::boolean::{BooleanExpression, BooleanValue};
+use crate::expressions::integer::{
+    clash, BooleanIntegerNumberExpression, DivisionMode, IntegerNumber,
+    IntegerNumberDomainExpression, IntegerNumberExpression,
+};
+use crate::expressions::rational::{
+    BooleanRationalExpression, RationalDomainExpression, RationalExpression,
+};
+use crate::expressions::{
+    AssignedValue, Assignment, ConstraintLogicExpression, ConstraintProgramExpression, Domain,
+    Environment, Evaluate, SatisfactionExpression, Symbol, ValueDomain, Variable,
+};
+
+/// The values a variable may take during search, in the order they are
+/// tried: the members of its own domain, of the domain declared for it,
+/// if any, and of every domain in `restrictions`, integer or rational.
+/// The candidates of the
+/// first of these that can enumerate its values are tried, so one of
+/// them must be bounded, unless `widening` lets an integer variable try
+/// that many values around zero instead. `widened` is set when it does.
+/// Divisions in the bounds of the domains round as `division` says.
+fn candidates(
+    variable: &Variable,
+    declared: Option<&Domain>,
+    restrictions: &[&dyn ValueDomain],
+    widening: Option<usize>,
+    division: DivisionMode,
+    widened: &mut bool,
+) -> Result<Vec<AssignedValue>, SolveError> {
+    let domains: Vec<&dyn ValueDomain> = restrictions
+        .iter()
+        .copied()
+        .chain(declared.map(Domain::as_value_domain))
+        .chain([variable.domain().as_value_domain()])
+        .collect();
+    let values = match (
+        domains
+            .iter()
+            .find_map(|domain| domain.candidates_with(division)),
+        widening,
+    ) {
+        (Some(values), _) => values,
+        (None, Some(limit)) if matches!(variable.domain(), Domain::Integer(_)) => {
+            *widened = true;
+            (0..limit as i128)
+                .map(|i| {
+                    AssignedValue::Integer(IntegerNumber::Value(if i % 2 == 0 {
+                        -i / 2
+                    } else {
+                        (i + 1) / 2
+                    }))
+                })
+                .collect()
+        }
+        (None, _) => return Err(SolveError::Unbounded(variable.name().clone())),
+    };
+    Ok(values
+        .into_iter()
+        .filter(|value| {
+            domains
+                .iter()
+                .all(|domain| domain.contains_with(value, division))
+        })
+        .collect())
+}
+
+/// The expression inside any parentheses.
+fn bare(mut expr: &IntegerNumberExpression) -> &IntegerNumberExpression {
+    while let IntegerNumberExpression::Parenthesis(inner) = expr {
+        expr = inner;
+    }
+    expr
+}
+
+/// Whether `expr` mentions `symbol`, directly or through the definitions
+/// of the variables it mentions.
+fn depends_on(
+    expr: &IntegerNumberExpression,
+    symbol: &Symbol,
+    definitions: &[(&Symbol, &IntegerNumberExpression)],
+) -> bool {
+    use crate::expressions::FreeVariable;
+    expr.get_free().iter().any(|variable| {
+        variable.name() == symbol
+            || definitions
+                .iter()
+                .find(|(defined, _)| *defined == variable.name())
+                .is_some_and(|(_, expr)| depends_on(expr, symbol, definitions))
+    })
+}
+
+/// The functional dependencies among the constraints: integer variables
+/// alone on one side of an equation `y = expr` whose other side does not
+/// depend on them. A variable is defined by its first such equation, and
+/// an equation that would make definitions circular is passed over. The
+/// definitions are ordered so that each comes after those of the
+/// variables it mentions.
+fn definitions<'a>(
+    constraints: &[&'a ConstraintLogicExpression],
+) -> Vec<(&'a Symbol, &'a IntegerNumberExpression)> {
+    use crate::expressions::FreeVariable;
+    let mut found: Vec<(&Symbol, &IntegerNumberExpression)> = Vec::new();
+    for constraint in constraints {
+        let ConstraintLogicExpression::OfIntegerNumber(expr) = constraint else {
+            continue;
+        };
+        let BooleanIntegerNumberExpression::Equals(expr_a, expr_b) = expr.as_ref() else {
+            continue;
+        };
+        for (side, other) in [(expr_a, expr_b), (expr_b, expr_a)] {
+            let IntegerNumberExpression::IntegerNumberVariable(symbol) = bare(side) else {
+                continue;
+            };
+            if found.iter().all(|(defined, _)| *defined != symbol)
+                && !depends_on(other, symbol, &found)
+            {
+                found.push((symbol, other));
+                break;
+            }
+        }
+    }
+    let mut ordered: Vec<(&Symbol, &IntegerNumberExpression)> = Vec::new();
+    let mut pending: Vec<(&Symbol, &IntegerNumberExpression)> =
+        found.iter().rev().copied().collect();
+    while let Some((symbol, expr)) = pending.pop() {
+        if ordered.iter().any(|(defined, _)| *defined == symbol) {
+            continue;
+        }
+        let unmet: Vec<(&Symbol, &IntegerNumberExpression)> = expr
+            .get_free()
+            .iter()
+            .filter_map(|variable| {
+                found
+                    .iter()
+                    .find(|(defined, _)| *defined == variable.name())
+                    .filter(|(defined, _)| ordered.iter().all(|(done, _)| done != defined))
+                    .copied()
+            })
+            .collect();
+        if unmet.is_empty() {
+            ordered.push((symbol, expr));
+        } else {
+            pending.push((symbol, expr));
+            pending.extend(unmet);
+        }
+    }
+    ordered
+}
+
+/// The variable and ground domain of a constraint `x in D`.
+pub(super) fn restriction(
+    constraint: &ConstraintLogicExpression,
+) -> Option<(&Symbol, &IntegerNumberDomainExpression)> {
+    use crate::expressions::FreeVariable;
+    let ConstraintLogicExpression::OfIntegerNumber(expr) = constraint else {
+        return None;
+    };
+    let BooleanIntegerNumberExpression::In(expr, domain) = expr.as_ref() else {
+        return None;
+    };
+    match bare(expr) {
+        IntegerNumberExpression::IntegerNumberVariable(symbol) if domain.get_free().is_empty() => {
+            Some((symbol, domain))
+        }
+        _ => None,
+    }
+}
+
+/// The variable and ground domain of a rational constraint `q in D`.
+pub(super) fn rational_restriction(
+    constraint: &ConstraintLogicExpression,
+) -> Option<(&Symbol, &RationalDomainExpression)> {
+    use crate::expressions::FreeVariable;
+    let ConstraintLogicExpression::OfRational(expr) = constraint else {
+        return None;
+    };
+    let BooleanRationalExpression::In(expr, domain) = expr.as_ref() else {
+        return None;
+    };
+    let mut expr = expr.as_ref();
+    while let RationalExpression::Parenthesis(inner) = expr {
+        expr = inner;
+    }
+    match expr {
+        RationalExpression::RationalVariable(symbol) if !domain.has_free() => {
+            Some((symbol, domain))
+        }
+        _ => None,
+    }
+}
+
+/// The two variables and the bound of a constraint `abs(x - y) < d`, or
+/// `abs(x - y) > d` when the last field is false, with `d` ground. The
+/// comparison may be written either way round.
+fn abs_difference(
+    constraint: &ConstraintLogicExpression,
+) -> Option<(&Symbol, &Symbol, i128, bool)> {
+    use crate::expressions::FreeVariable;
+    use IntegerNumberExpression::*;
+    let ConstraintLogicExpression::OfIntegerNumber(expr) = constraint else {
+        return None;
+    };
+    let (distance, bound, less) = match expr.as_ref() {
+        BooleanIntegerNumberExpression::Less(expr_a, expr_b) if matches!(bare(expr_a), Abs(_)) => {
+            (expr_a, expr_b, true)
+        }
+        BooleanIntegerNumberExpression::Less(expr_a, expr_b) => (expr_b, expr_a, false),
+        BooleanIntegerNumberExpression::Greater(expr_a, expr_b)
+            if matches!(bare(expr_a), Abs(_)) =>
+        {
+            (expr_a, expr_b, false)
+        }
+        BooleanIntegerNumberExpression::Greater(expr_a, expr_b) => (expr_b, expr_a, true),
+        _ => return None,
+    };
+    let Abs(difference) = bare(distance) else {
+        return None;
+    };
+    let Minus(x, y) = bare(difference) else {
+        return None;
+    };
+    let (IntegerNumberVariable(x), IntegerNumberVariable(y)) = (bare(x), bare(y)) else {
+        return None;
+    };
+    if !bound.get_free().is_empty() {
+        return None;
+    }
+    match bound.evaluate(&[]) {
+        Ok(IntegerNumber::Value(bound)) => Some((x, y, bound, less)),
+        _ => None,
+    }
+}
+
+/// Whether some value in `others`, sorted, is nearer to `value` than
+/// `bound` when `less`, or further from it otherwise. Only the values
+/// around `value` or at the ends of `others` need to be looked at.
+fn within_distance(value: i128, others: &[i128], bound: i128, less: bool) -> bool {
+    let distance = |other: &i128| value.checked_sub(*other).and_then(i128::checked_abs);
+    let candidates = if less {
+        let split = others.partition_point(|other| *other < value);
+        [split.checked_sub(1), Some(split)]
+    } else {
+        [Some(0), others.len().checked_sub(1)]
+    };
+    candidates
+        .into_iter()
+        .flatten()
+        .filter_map(|index| others.get(index).and_then(distance))
+        .any(|distance| {
+            if less {
+                distance < bound
+            } else {
+                distance > bound
+            }
+        })
+}
+
+/// The integer expression to optimise and whether to maximise it, from
+/// the first `minimise` or `maximise` goal of the program: the left side
+/// of its comparison.
+pub(super) fn objective(
+    program: &ConstraintProgramExpression,
+) -> Result<Option<(bool, &IntegerNumberExpression)>, SolveError> {
+    use BooleanIntegerNumberExpression::*;
+    let mut rest = Some(program);
+    while let Some(program) = rest {
+        let goal = match program {
+            ConstraintProgramExpression::Solve(goal) => {
+                rest = None;
+                goal
+            }
+            ConstraintProgramExpression::SolveAnd(goal, next) => {
+                rest = Some(next);
+                goal
+            }
+            ConstraintProgramExpression::ConstrainAnd(_, next) => {
+                rest = Some(next);
+                continue;
+            }
+        };
+        let (maximise, constraint) = match goal.as_ref() {
+            SatisfactionExpression::Satisfy(_) => continue,
+            SatisfactionExpression::Minimise(constraint) => (false, constraint),
+            SatisfactionExpression::Maximise(constraint) => (true, constraint),
+        };
+        return match constraint.as_ref() {
+            ConstraintLogicExpression::OfIntegerNumber(expr) => match expr.as_ref() {
+                Equals(expr, _)
+                | Different(expr, _)
+                | Greater(expr, _)
+                | Less(expr, _)
+                | In(expr, _) => Ok(Some((maximise, expr))),
+            },
+            ConstraintLogicExpression::Boolean(_)
+            | ConstraintLogicExpression::OfRational(_)
+            | ConstraintLogicExpression::AllDifferent(_) => {
+                Err(SolveError::NoObjective(constraint.as_ref().clone()))
+            }
+        };
+    }
+    Ok(None)
+}
+
+/// Collects the boolean literals that `expr` forces when it must take
+/// `value`: `x` forces `x` to be true and `not x` forces it false, also
+/// when they are conjuncts of a constraint.
+fn forced_literals(
+    expr: &BooleanExpression,
+    value: BooleanValue,
+    forced: &mut Vec<(Symbol, BooleanValue)>,
+) {
+    use BooleanExpression::*;
+    match expr {
+        BooleanVariable(symbol) => forced.push((symbol.clone(), value)),
+        Not(expr) => {
+            let negated = self::BooleanValue::from_bool(value == self::BooleanValue::False);
+            forced_literals(expr, negated, forced)
+        }
+        And(expr_a, expr_b) if value == self::BooleanValue::True => {
+            forced_literals(expr_a, value.clone(), forced);
+            forced_literals(expr_b, value, forced);
+        }
+        Parenthesis(expr) => forced_literals(expr, value, forced),
+        _ => (),
+    }
+}
+
+/// A program prepared for search: its constraints and the candidate
+/// values of every free variable.
+#[derive(Clone)]
+pub(crate) struct Search<'a> {
+    constraints: Vec<&'a ConstraintLogicExpression>,
+    /// For every constraint, the positions in `variables` of the
+    /// variables it mentions.
+    scopes: Vec<Vec<usize>>,
+    variables: Vec<(Symbol, Vec<AssignedValue>)>,
+    /// For every variable, the expression that computes it, if any.
+    definitions: Vec<Option<Definition<'a>>>,
+    objective: Option<Objective<'a>>,
+    division: DivisionMode,
+    /// Whether some variable tries only the values `widening` allows,
+    /// so that finishing the search proves nothing.
+    widened: bool,
+}
+
+/// The expression an equation `y = expr` gives for its variable `y`.
+#[derive(Clone)]
+struct Definition<'a> {
+    expr: &'a IntegerNumberExpression,
+    /// The positions in `variables` of the variables it mentions.
+    scope: Vec<usize>,
+    /// Whether the variable has a finite domain of its own that the
+    /// computed value must be in. Without one it has no candidate values.
+    bounded: bool,
+}
+
+/// An integer expression to optimise by branch and bound.
+#[derive(Clone)]
+struct Objective<'a> {
+    maximise: bool,
+    expr: &'a IntegerNumberExpression,
+    /// The positions in `variables` of the variables it mentions.
+    scope: Vec<usize>,
+}
+
+impl Objective<'_> {
+    fn improves(&self, value: i128, bound: i128) -> bool {
+        if self.maximise {
+            value > bound
+        } else {
+            value < bound
+        }
+    }
+}
+
+/// How a run of the search ended.
+pub(crate) enum Ended {
+    /// Every solution was visited.
+    Finished,
+    /// The visitor stopped the search.
+    Stopped,
+    /// The node limit was reached. Holds the longest partial assignment
+    /// found that no constraint refutes.
+    OutOfBudget(Vec<Assignment>),
+}
+
+/// How a subtree of the search ended. `Failed` carries the positions of
+/// the variables whose values caused the failure: no solution extends
+/// their current values.
+enum Outcome {
+    Found,
+    Failed(Vec<usize>),
+    Stopped,
+}
+
+/// A combination of values, one per listed variable position, that no
+/// solution contains.
+struct NoGood {
+    literals: Vec<(usize, AssignedValue)>,
+    /// The conflict count when the no-good last pruned the search.
+    last_used: usize,
+}
+
+/// The mutable state of one run of the search.
+struct Run<'r, F> {
+    env: Vec<Assignment>,
+    learned: Vec<NoGood>,
+    learning: &'r Learning,
+    node_limit: Option<usize>,
+    out_of_budget: bool,
+    /// The longest partial assignment no constraint refuted so far.
+    deepest: Vec<Assignment>,
+    /// The objective value of the best solution so far.
+    bound: Option<i128>,
+    stats: &'r mut SolveStats,
+    visit: F,
+}
+
+impl<F> Run<'_, F> {
+    /// The first learned no-good violated by the current assignment.
+    fn violated_no_good(&mut self) -> Option<Vec<usize>> {
+        let env = &self.env;
+        let no_good = self.learned.iter_mut().find(|no_good| {
+            no_good.literals.iter().all(|(position, value)| {
+                env.get(*position)
+                    .is_some_and(|assignment| assignment.value() == value)
+            })
+        })?;
+        no_good.last_used = self.stats.conflicts;
+        Some(
+            no_good
+                .literals
+                .iter()
+                .map(|(position, _)| *position)
+                .collect(),
+        )
+    }
+
+    /// Records that the current values of the variables at `conflict`
+    /// admit no solution, dropping the least recently useful no-goods
+    /// once the database outgrows its capacity. Positions not assigned
+    /// yet cannot be to blame, and are left out.
+    fn learn(&mut self, conflict: &[usize]) {
+        let Learning::NoGoods { capacity, max_size } = *self.learning else {
+            return;
+        };
+        let literals: Vec<(usize, AssignedValue)> = conflict
+            .iter()
+            .filter_map(|position| {
+                let assignment = self.env.get(*position)?;
+                Some((*position, assignment.value().clone()))
+            })
+            .collect();
+        if literals.is_empty() || literals.len() > max_size {
+            return;
+        }
+        self.learned.push(NoGood {
+            literals,
+            last_used: self.stats.conflicts,
+        });
+        self.stats.learned += 1;
+        if self.learned.len() > capacity {
+            self.learned
+                .sort_by_key(|no_good| std::cmp::Reverse(no_good.last_used));
+            self.learned.truncate(capacity / 2);
+            self.stats.cleanups += 1;
+        }
+        self.stats.peak_learned = self.stats.peak_learned.max(self.learned.len());
+    }
+}
+
+impl<'a> Search<'a> {
+    /// The search over the free variables of `program`, each further
+    /// restricted to the domain of the variable of the same name in
+    /// `config.domains`, if there is one, and evaluating as `config`
+    /// says. A variable computed by a definition needs no finite domain.
+    pub(crate) fn new(
+        program: &'a ConstraintProgramExpression,
+        config: &SolveConfig,
+    ) -> Result<Search<'a>, SolveError> {
+        use crate::expressions::FreeVariable;
+        let constraints = super::constraints(program);
+        let mut forced = Vec::new();
+        for constraint in &constraints {
+            if let ConstraintLogicExpression::Boolean(expr) = constraint {
+                forced_literals(expr, BooleanValue::True, &mut forced);
+            }
+        }
+        let restrictions: Vec<(&Symbol, &dyn ValueDomain)> = constraints
+            .iter()
+            .filter_map(|constraint| {
+                restriction(constraint)
+                    .map(|(symbol, domain)| (symbol, domain as &dyn ValueDomain))
+                    .or_else(|| {
+                        rational_restriction(constraint)
+                            .map(|(symbol, domain)| (symbol, domain as &dyn ValueDomain))
+                    })
+            })
+            .collect();
+        let definitions = definitions(&constraints);
+        let mut variables: Vec<(Symbol, Vec<AssignedValue>)> = Vec::new();
+        let mut unbounded: Vec<Symbol> = Vec::new();
+        let mut widened = false;
+        for variable in super::free_variables(program)
+            .map_err(|conflict| SolveError::Conflict(conflict.name))?
+        {
+            let domains: Vec<&dyn ValueDomain> = restrictions
+                .iter()
+                .filter(|(symbol, _)| *symbol == variable.name())
+                .map(|(_, domain)| *domain)
+                .collect();
+            let domain = config
+                .domains
+                .iter()
+                .find(|declared| declared.name() == variable.name())
+                .map(Variable::domain);
+            let defined = definitions
+                .iter()
+                .any(|(defined, _)| *defined == variable.name());
+            let widening = config.widening.filter(|_| !defined);
+            let mut values = match candidates(
+                &variable,
+                domain,
+                &domains,
+                widening,
+                config.division,
+                &mut widened,
+            ) {
+                Err(SolveError::Unbounded(name)) if defined => {
+                    unbounded.push(name);
+                    Vec::new()
+                }
+                values => values?,
+            };
+            if let Domain::Boolean(_) = variable.domain() {
+                // Restrict the domain to the values every forcing literal allows.
+                for (symbol, value) in &forced {
+                    if symbol == variable.name() {
+                        values.retain(|candidate| {
+                            *candidate == AssignedValue::Boolean(value.clone())
+                        });
+                    }
+                }
+            }
+            variables.push((variable.name().clone(), values));
+        }
+        // Computed variables go last, each after those it is computed from.
+        variables
+            .sort_by_key(|(name, _)| definitions.iter().position(|(defined, _)| *defined == name));
+        let scope = |free: Vec<Variable>| -> Vec<usize> {
+            (0..variables.len())
+                .filter(|position| {
+                    free.iter()
+                        .any(|variable| *variable.name() == variables[*position].0)
+                })
+                .collect()
+        };
+        let scopes = constraints
+            .iter()
+            .map(|constraint| scope(constraint.get_free()))
+            .collect();
+        let definitions = variables
+            .iter()
+            .map(|(name, _)| {
+                definitions
+                    .iter()
+                    .find(|(defined, _)| *defined == name)
+                    .map(|(_, expr)| Definition {
+                        expr,
+                        scope: scope(expr.get_free()),
+                        bounded: !unbounded.contains(name),
+                    })
+            })
+            .collect();
+        let objective = objective(program)?.map(|(maximise, expr)| Objective {
+            maximise,
+            expr,
+            scope: scope(expr.get_free()),
+        });
+        Ok(Search {
+            constraints,
+            scopes,
+            variables,
+            definitions,
+            objective,
+            division: config.division,
+            widened,
+        })
+    }
+
+    /// Moves the variables named in `order` to the front of the search in
+    /// that order, trying their values in descending order if
+    /// `descending`, so that the first solution found is the smallest or
+    /// largest in the lexicographic order of those variables. Names the
+    /// program does not use are skipped. A variable computed by a
+    /// definition without a finite domain has no values to branch on, so
+    /// naming it is an `Unbounded` error.
+    pub(crate) fn prioritise(
+        &mut self,
+        order: &[Symbol],
+        descending: bool,
+    ) -> Result<(), SolveError> {
+        let mut positions: Vec<usize> = Vec::new();
+        for symbol in order {
+            for (position, (name, _)) in self.variables.iter().enumerate() {
+                if name == symbol && !positions.contains(&position) {
+                    if self.is_unbounded(position) {
+                        return Err(SolveError::Unbounded(name.clone()));
+                    }
+                    positions.push(position);
+                }
+            }
+        }
+        let prioritised = positions.len();
+        let rest: Vec<usize> = (0..self.variables.len())
+            .filter(|position| !positions.contains(position))
+            .collect();
+        positions.extend(rest);
+        // `moved[old]` is the position of the variable that was at `old`.
+        let mut moved = vec![0; positions.len()];
+        for (new, old) in positions.iter().enumerate() {
+            moved[*old] = new;
+        }
+        let mut variables: Vec<Option<(Symbol, Vec<AssignedValue>)>> =
+            self.variables.drain(..).map(Some).collect();
+        self.variables = positions
+            .iter()
+            .map(|old| variables[*old].take().expect("each position moves once"))
+            .collect();
+        let mut definitions: Vec<Option<Definition>> = self.definitions.drain(..).collect();
+        self.definitions = positions
+            .iter()
+            .map(|old| definitions[*old].take())
+            .collect();
+        if descending {
+            for (_, values) in &mut self.variables[..prioritised] {
+                values.reverse();
+            }
+        }
+        let remap = |scope: &mut Vec<usize>| {
+            for position in scope.iter_mut() {
+                *position = moved[*position];
+            }
+            scope.sort_unstable();
+        };
+        self.scopes.iter_mut().for_each(remap);
+        for definition in self.definitions.iter_mut().flatten() {
+            remap(&mut definition.scope);
+        }
+        if let Some(objective) = &mut self.objective {
+            remap(&mut objective.scope);
+        }
+        Ok(())
+    }
+
+    /// Reorders the values of the variables the objective mentions so
+    /// that those giving it a better value come first, each scored with
+    /// the other variables of the objective at their first values. Values
+    /// the objective cannot be evaluated at go last, and values scoring
+    /// the same keep their order.
+    pub(crate) fn bias_towards_objective(&mut self) {
+        let Some(objective) = &self.objective else {
+            return;
+        };
+        let Some(base) = objective
+            .scope
+            .iter()
+            .map(|position| {
+                let (name, values) = &self.variables[*position];
+                Some(Assignment::new(name.clone(), values.first()?.clone()))
+            })
+            .collect::<Option<Vec<Assignment>>>()
+        else {
+            return;
+        };
+        let mut reordered = Vec::new();
+        for (index, position) in objective.scope.iter().enumerate() {
+            let (name, values) = &self.variables[*position];
+            let mut env = base.clone();
+            let mut scored: Vec<(AssignedValue, Option<i128>)> = values
+                .iter()
+                .map(|value| {
+                    env[index] = Assignment::new(name.clone(), value.clone());
+                    match objective.expr.evaluate_with(&env, self.division) {
+                        Ok(IntegerNumber::Value(score)) => (value.clone(), Some(score)),
+                        _ => (value.clone(), None),
+                    }
+                })
+                .collect();
+            scored.sort_by(|(_, a), (_, b)| match (a, b) {
+                (Some(a), Some(b)) if objective.maximise => b.cmp(a),
+                (Some(a), Some(b)) => a.cmp(b),
+                _ => b.is_some().cmp(&a.is_some()),
+            });
+            reordered.push((
+                *position,
+                scored.into_iter().map(|(value, _)| value).collect(),
+            ));
+        }
+        for (position, values) in reordered {
+            self.variables[position].1 = values;
+        }
+    }
+
+    /// Narrows the variable domains as `propagation` asks.
+    pub(crate) fn propagate(&mut self, propagation: &Propagation) {
+        match propagation {
+            Propagation::Off => (),
+            Propagation::Ac3 => self.make_arc_consistent(),
+        }
+    }
+
+    /// Removes the values of the variable at `tail` for which no value of
+    /// the variable at `head` satisfies constraint `index`, and returns
+    /// whether any were removed. A bound on the distance between the two,
+    /// `abs(x - y) < d` or `abs(x - y) > d`, is checked against the
+    /// nearest or furthest values of `head` only, rather than all of them.
+    fn revise(&mut self, index: usize, tail: usize, head: usize) -> bool {
+        let constraint = self.constraints[index];
+        let (tail_name, tail_values) = &self.variables[tail];
+        let (head_name, head_values) = &self.variables[head];
+        let supported: Vec<AssignedValue> = match abs_difference(constraint) {
+            Some((x, y, bound, less))
+                if (x, y) == (tail_name, head_name) || (x, y) == (head_name, tail_name) =>
+            {
+                let mut others: Vec<i128> = head_values
+                    .iter()
+                    .filter_map(|value| match value {
+                        AssignedValue::Integer(IntegerNumber::Value(n)) => Some(*n),
+                        _ => None,
+                    })
+                    .collect();
+                others.sort_unstable();
+                tail_values
+                    .iter()
+                    .filter(|value| match value {
+                        AssignedValue::Integer(IntegerNumber::Value(n)) => {
+                            within_distance(*n, &others, bound, less)
+                        }
+                        _ => false,
+                    })
+                    .cloned()
+                    .collect()
+            }
+            _ => tail_values
+                .iter()
+                .filter(|a| {
+                    head_values.iter().any(|b| {
+                        let env = [
+                            Assignment::new(tail_name.clone(), (*a).clone()),
+                            Assignment::new(head_name.clone(), b.clone()),
+                        ];
+                        constraint.evaluate_with(&env, self.division) == Ok(BooleanValue::True)
+                    })
+                })
+                .cloned()
+                .collect(),
+        };
+        let revised = supported.len() < tail_values.len();
+        self.variables[tail].1 = supported;
+        revised
+    }
+
+    /// AC-3: revises every arc of the constraints over exactly two
+    /// variables, queueing the arcs into a variable again whenever its
+    /// domain shrinks, until no domain changes. Variables without a
+    /// domain, which their definitions compute, are left out.
+    fn make_arc_consistent(&mut self) {
+        let binary: Vec<(usize, usize, usize)> = self
+            .scopes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, scope)| match scope[..] {
+                [a, b] if !self.is_unbounded(a) && !self.is_unbounded(b) => Some((index, a, b)),
+                _ => None,
+            })
+            .collect();
+        let mut arcs: VecDeque<(usize, usize, usize)> = binary
+            .iter()
+            .flat_map(|&(index, a, b)| [(index, a, b), (index, b, a)])
+            .collect();
+        while let Some((index, tail, head)) = arcs.pop_front() {
+            if !self.revise(index, tail, head) {
+                continue;
+            }
+            for &(other, a, b) in &binary {
+                let arc = match (a == tail, b == tail) {
+                    (true, _) => (other, b, a),
+                    (_, true) => (other, a, b),
+                    _ => continue,
+                };
+                if other != index && !arcs.contains(&arc) {
+                    arcs.push_back(arc);
+                }
+            }
+        }
+    }
+
+    /// Keeps only the values each variable also has in `known`, so that a
+    /// search over a grown program starts from the domains propagation
+    /// left the smaller one with. Variables `known` does not name are
+    /// left alone.
+    pub(crate) fn restrict(&mut self, known: &[(Symbol, Vec<AssignedValue>)]) {
+        for (name, values) in &mut self.variables {
+            if let Some((_, allowed)) = known.iter().find(|(known, _)| known == name) {
+                values.retain(|value| allowed.contains(value));
+            }
+        }
+    }
+
+    /// Removes the values of each variable that a constraint over that
+    /// variable alone does not hold for.
+    pub(crate) fn make_node_consistent(&mut self) {
+        for (index, scope) in self.scopes.iter().enumerate() {
+            let [position] = scope[..] else {
+                continue;
+            };
+            if self.is_unbounded(position) {
+                continue;
+            }
+            let constraint = self.constraints[index];
+            let (name, values) = &mut self.variables[position];
+            values.retain(|value| {
+                let env = [Assignment::new(name.clone(), value.clone())];
+                constraint.evaluate_with(&env, self.division) == Ok(BooleanValue::True)
+            });
+        }
+    }
+
+    /// The free variables of the program with the values each may take.
+    /// A variable computed by a definition without a finite domain has
+    /// none listed.
+    pub(crate) fn variables(&self) -> &[(Symbol, Vec<AssignedValue>)] {
+        &self.variables
+    }
+
+    /// The variables with values to enumerate, leaving out those computed
+    /// by a definition without a finite domain.
+    pub(crate) fn bounded_variables(&self) -> Vec<(Symbol, Vec<AssignedValue>)> {
+        (0..self.variables.len())
+            .filter(|position| !self.is_unbounded(*position))
+            .map(|position| self.variables[position].clone())
+            .collect()
+    }
+
+    /// Whether the variable at `position` is computed by a definition and
+    /// has no finite domain, so that it has no candidate values.
+    pub(crate) fn is_unbounded(&self, position: usize) -> bool {
+        self.definitions[position]
+            .as_ref()
+            .is_some_and(|definition| !definition.bounded)
+    }
+
+    /// Whether some variable without a bounded domain tries only a few
+    /// values around zero.
+    pub(crate) fn widened(&self) -> bool {
+        self.widened
+    }
+
+    /// Whether the search optimises an objective rather than visiting
+    /// every solution.
+    pub(crate) fn optimises(&self) -> bool {
+        self.objective.is_some()
+    }
+
+    /// The assigned variables of the objective when its value under
+    /// `env` is known and no better than the best solution so far. Through
+    /// `ite` the value can be known before all of them are assigned.
+    fn bounded(&self, env: &[Assignment], bound: Option<i128>) -> Option<Vec<usize>> {
+        let objective = self.objective.as_ref()?;
+        match objective.expr.evaluate_with(env, self.division) {
+            Ok(IntegerNumber::Value(value))
+                if bound.is_some_and(|bound| !objective.improves(value, bound)) =>
+            {
+                Some(
+                    objective
+                        .scope
+                        .iter()
+                        .copied()
+                        .filter(|position| *position < env.len())
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// The assigned variables of some constraint that is already known
+    /// to be `False` under the partial assignment `env`. For an
+    /// `alldifferent` only those of the two clashing expressions are to
+    /// blame, so backjumping can skip past the others.
+    fn refuted(&self, env: &[Assignment]) -> Option<Vec<usize>> {
+        use crate::expressions::FreeVariable;
+        let index = self.constraints.iter().position(|constraint| {
+            constraint.evaluate_with(env, self.division) == Ok(BooleanValue::False)
+        })?;
+        let assigned = self.scopes[index]
+            .iter()
+            .copied()
+            .filter(|position| *position < env.len());
+        if let ConstraintLogicExpression::AllDifferent(exprs) = self.constraints[index] {
+            let environment = Environment::new(env).with_division(self.division);
+            if let Some((a, b)) = clash(exprs, &environment) {
+                let blamed: Vec<Variable> = exprs[a]
+                    .get_free()
+                    .into_iter()
+                    .chain(exprs[b].get_free())
+                    .collect();
+                return Some(
+                    assigned
+                        .filter(|position| {
+                            blamed
+                                .iter()
+                                .any(|variable| *variable.name() == self.variables[*position].0)
+                        })
+                        .collect(),
+                );
+            }
+        }
+        Some(assigned.collect())
+    }
+
+    /// The value of the variable at `depth` when it is computed by a
+    /// definition from the values in `env`, or the definition's
+    /// variables when the variable has a finite domain and that value is
+    /// not among its candidates.
+    fn computed(
+        &self,
+        depth: usize,
+        env: &[Assignment],
+    ) -> Option<Result<AssignedValue, Vec<usize>>> {
+        let definition = self.definitions[depth].as_ref()?;
+        let value = AssignedValue::Integer(definition.expr.evaluate_with(env, self.division).ok()?);
+        if !definition.bounded || self.variables[depth].1.contains(&value) {
+            Some(Ok(value))
+        } else {
+            Some(Err(definition.scope.clone()))
+        }
+    }
+
+    /// Whether every constraint evaluates to `True` under `env`.
+    fn satisfied(&self, env: &[Assignment]) -> bool {
+        self.constraints.iter().all(|constraint| {
+            constraint.evaluate_with(env, self.division) == Ok(BooleanValue::True)
+        })
+    }
+
+    /// Calls `visit` with every satisfying assignment until it breaks,
+    /// searching as configured by `config` and counting the work in
+    /// `stats`. With an objective, only solutions better than every one
+    /// before are visited, so the last is optimal. Failed subtrees are
+    /// skipped by conflict-directed backjumping and, when learning is on,
+    /// recorded as no-goods. A variable with a definition whose variables
+    /// are already assigned takes the value it computes instead of being
+    /// branched on, and does not count as a node.
+    pub(crate) fn run(
+        &self,
+        config: &SolveConfig,
+        stats: &mut SolveStats,
+        visit: impl FnMut(&[Assignment]) -> ControlFlow<()>,
+    ) -> Ended {
+        let mut run = Run {
+            env: Vec::with_capacity(self.variables.len()),
+            learned: Vec::new(),
+            learning: &config.learning,
+            node_limit: config.node_limit,
+            out_of_budget: false,
+            deepest: Vec::new(),
+            bound: None,
+            stats,
+            visit,
+        };
+        match self.descend(&mut run) {
+            Outcome::Stopped if run.out_of_budget => Ended::OutOfBudget(run.deepest),
+            Outcome::Stopped => Ended::Stopped,
+            Outcome::Found | Outcome::Failed(_) => Ended::Finished,
+        }
+    }
+
+    fn descend<F: FnMut(&[Assignment]) -> ControlFlow<()>>(&self, run: &mut Run<F>) -> Outcome {
+        let depth = run.env.len();
+        let Some((name, values)) = self.variables.get(depth) else {
+            if !self.satisfied(&run.env) {
+                return Outcome::Failed((0..depth).collect());
+            }
+            if let Some(objective) = &self.objective {
+                match objective.expr.evaluate_with(&run.env, self.division) {
+                    Ok(IntegerNumber::Value(value)) => run.bound = Some(value),
+                    _ => return Outcome::Failed(objective.scope.clone()),
+                }
+            }
+            return match (run.visit)(&run.env) {
+                ControlFlow::Continue(()) => Outcome::Found,
+                ControlFlow::Break(()) => Outcome::Stopped,
+            };
+        };
+        let computed = self.computed(depth, &run.env);
+        let (values, because) = match &computed {
+            Some(Ok(value)) => (
+                std::slice::from_ref(value),
+                self.definitions[depth].as_ref(),
+            ),
+            Some(Err(scope)) => {
+                run.stats.conflicts += 1;
+                return Outcome::Failed(scope.clone());
+            }
+            None => (&values[..], None),
+        };
+        let mut found = false;
+        let mut conflict: Vec<usize> = Vec::new();
+        for value in values {
+            if because.is_none() {
+                if run.node_limit.is_some_and(|limit| run.stats.nodes >= limit) {
+                    run.out_of_budget = true;
+                    return Outcome::Stopped;
+                }
+                run.stats.nodes += 1;
+            }
+            run.env.push(Assignment::new(name.clone(), value.clone()));
+            let reason = self
+                .refuted(&run.env)
+                .or_else(|| self.bounded(&run.env, run.bound))
+                .or_else(|| run.violated_no_good());
+            let outcome = match reason {
+                Some(reason) => {
+                    run.stats.conflicts += 1;
+                    Outcome::Failed(reason)
+                }
+                None => {
+                    if run.env.len() > run.deepest.len() {
+                        run.deepest = run.env.clone();
+                    }
+                    self.descend(run)
+                }
+            };
+            run.env.pop();
+            match outcome {
+                Outcome::Stopped => return Outcome::Stopped,
+                Outcome::Found => found = true,
+                // The failure does not depend on this variable, so none
+                // of its other values can succeed either.
+                Outcome::Failed(reason) if !found && !reason.contains(&depth) => {
+                    return Outcome::Failed(reason)
+                }
+                Outcome::Failed(reason) => {
+                    // A computed value fails because of the variables
+                    // that computed it.
+                    let sources = because.map_or(&[][..], |definition| &definition.scope[..]);
+                    for position in reason.into_iter().chain(sources.iter().copied()) {
+                        if position != depth && !conflict.contains(&position) {
+                            conflict.push(position);
+                        }
+                    }
+                }
+            }
+        }
+        if found {
+            return Outcome::Found;
+        }
+        conflict.sort_unstable();
+        run.learn(&conflict);
+        Outcome::Failed(conflict)
+    }
+
+    /// The satisfying assignments, found by chronological backtracking as
+    /// they are asked for. Variables with definitions take their computed
+    /// values as in `run`, but the objective, if any, is only checked as a
+    /// constraint.
+    pub(crate) fn solutions(self) -> Solutions<'a> {
+        let trivial = self.variables.is_empty() && self.satisfied(&[]);
+        let frames = if self.variables.is_empty() {
+            Vec::new()
+        } else {
+            vec![Frame {
+                computed: self.computed(0, &[]),
+                next: 0,
+            }]
+        };
+        Solutions {
+            search: self,
+            env: Vec::new(),
+            frames,
+            trivial,
+        }
+    }
+}
+
+/// The values still to try for one variable on the current branch.
+struct Frame {
+    /// The value the variable's definition computes, if it has one.
+    computed: Option<Result<AssignedValue, Vec<usize>>>,
+    /// The position of the next value to try.
+    next: usize,
+}
+
+/// An iterator over the solutions of a search that holds only the
+/// current branch: one frame for every assigned variable and one for the
+/// variable being tried.
+pub(crate) struct Solutions<'a> {
+    search: Search<'a>,
+    env: Vec<Assignment>,
+    frames: Vec<Frame>,
+    /// Whether a program without variables is still to yield its one
+    /// solution.
+    trivial: bool,
+}
+
+impl Iterator for Solutions<'_> {
+    type Item = Vec<Assignment>;
+
+    fn next(&mut self) -> Option<Vec<Assignment>> {
+        if std::mem::take(&mut self.trivial) {
+            return Some(Vec::new());
+        }
+        while let Some(depth) = self.frames.len().checked_sub(1) {
+            let frame = &mut self.frames[depth];
+            let (name, values) = &self.search.variables[depth];
+            let value = match &frame.computed {
+                Some(Ok(value)) if frame.next == 0 => Some(value.clone()),
+                Some(_) => None,
+                None => values.get(frame.next).cloned(),
+            };
+            frame.next += 1;
+            let Some(value) = value else {
+                // Every value failed, so the variable before it moves on.
+                self.frames.pop();
+                self.env.pop();
+                continue;
+            };
+            self.env.push(Assignment::new(name.clone(), value));
+            if self.search.refuted(&self.env).is_some() {
+                self.env.pop();
+            } else if self.env.len() < self.search.variables.len() {
+                self.frames.push(Frame {
+                    computed: self.search.computed(depth + 1, &self.env),
+                    next: 0,
+                });
+            } else {
+                let solution = self.search.satisfied(&self.env).then(|| self.env.clone());
+                self.env.pop();
+                if solution.is_some() {
+                    return solution;
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Search;
+    use crate::expressions::boolean::{BooleanExpression, BooleanValue};
+    use crate::expressions::{
+        AssignedValue, ConstraintLogicExpression, ConstraintProgramExpression,
+        SatisfactionExpression, Symbol,
+    };
+    use crate::solver::SolveConfig;
+
+    fn bool_var(name: &str) -> Box<BooleanExpression> {
+        Box::new(BooleanExpression::BooleanVariable(Symbol::new(
+            name.to_string(),
+        )))
+    }
+
+    #[test]
+    fn bare_boolean_constraints_fix_their_variables() {
+        let program = ConstraintProgramExpression::ConstrainAnd(
+            Box::new(ConstraintLogicExpression::Boolean(bool_var("x"))),
+            Box::new(ConstraintProgramExpression::Solve(Box::new(
+                SatisfactionExpression::Satisfy(Box::new(ConstraintLogicExpression::Boolean(
+                    Box::new(BooleanExpression::Or(
+                        bool_var("x"),
+                        Box::new(BooleanExpression::Not(bool_var("y"))),
+                    )),
+                ))),
+            ))),
+        );
+        let search = Search::new(&program, &SolveConfig::default()).unwrap();
+        assert_eq!(
+            search.variables,
+            vec![
+                (
+                    Symbol::new("x".to_string()),
+                    vec![AssignedValue::Boolean(BooleanValue::True)]
+                ),
+                (
+                    Symbol::new("y".to_string()),
+                    vec![
+                        AssignedValue::Boolean(BooleanValue::False),
+                        AssignedValue::Boolean(BooleanValue::True)
+                    ]
+                ),
+            ]
+        );
+        let not_x = ConstraintProgramExpression::Solve(Box::new(SatisfactionExpression::Satisfy(
+            Box::new(ConstraintLogicExpression::Boolean(Box::new(
+                BooleanExpression::Not(bool_var("x")),
+            ))),
+        )));
+        assert_eq!(
+            Search::new(&not_x, &SolveConfig::default())
+                .unwrap()
+                .variables[0]
+                .1,
+            vec![AssignedValue::Boolean(BooleanValue::False)]
+        );
+    }
+
+    #[test]
+    fn alldifferent_blames_only_the_clashing_variables() {
+        use crate::expressions::integer::IntegerNumber;
+        use crate::expressions::parser::parse_program;
+        use crate::expressions::Assignment;
+        let program = parse_program(
+            "x in [1, 3];\n\
+             y in [1, 3];\n\
+             z in [1, 3];\n\
+             satisfy alldifferent(x, y + 0, z);",
+        )
+        .unwrap();
+        let search = Search::new(&program, &SolveConfig::default()).unwrap();
+        let env = |values: &[i128]| -> Vec<Assignment> {
+            ["x", "y", "z"]
+                .iter()
+                .zip(values)
+                .map(|(name, value)| {
+                    Assignment::new(
+                        Symbol::new(name.to_string()),
+                        AssignedValue::Integer(IntegerNumber::Value(*value)),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(search.refuted(&env(&[1])), None);
+        assert_eq!(search.refuted(&env(&[2, 2])), Some(vec![0, 1]));
+        assert_eq!(search.refuted(&env(&[1, 2, 1])), Some(vec![0, 2]));
+        assert_eq!(search.refuted(&env(&[1, 2, 3])), None);
+        assert_eq!(crate::solver::count_models(&program), Ok(6));
+    }
+}
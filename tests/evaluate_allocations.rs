@@ -0,0 +1,80 @@
+//! Borrowed evaluation allocates nothing. The allocator counting this
+//! replaces the global one, so the check has a test binary of its own,
+//! and runs without the test harness so that no other thread allocates
+//! while it measures.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clp::expressions::boolean::{BooleanExpression, BooleanValue};
+use clp::expressions::integer::{IntegerNumber, IntegerNumberExpression};
+use clp::expressions::tuple::{TupleComponent, TupleExpression};
+use clp::expressions::{AssignedValue, Assignment, Environment, Symbol};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// `let t = x * y + 3 in abs(t - z) % 5 + t ^ 2 - (x, p).0 / 2`, with
+/// the tuple component read through `TupleGet`.
+fn let_expression() -> IntegerNumberExpression {
+    use IntegerNumberExpression::*;
+    let var = |name: &str| Box::new(IntegerNumberVariable(Symbol::new(name.to_string())));
+    let value = |n: i128| Box::new(IntegerNumberValue(IntegerNumber::Value(n)));
+    let pair = TupleExpression::Tuple(vec![
+        TupleComponent::Integer(*var("x")),
+        TupleComponent::Boolean(BooleanExpression::BooleanVariable(Symbol::new(
+            "p".to_string(),
+        ))),
+    ]);
+    Let(
+        Symbol::new("t".to_string()),
+        Box::new(Add(Box::new(Times(var("x"), var("y"))), value(3))),
+        Box::new(Minus(
+            Box::new(Add(
+                Box::new(Modulo(
+                    Box::new(Abs(Box::new(Minus(var("t"), var("z"))))),
+                    value(5),
+                )),
+                Box::new(Pow(var("t"), value(2))),
+            )),
+            Box::new(Divide(Box::new(TupleGet(Box::new(pair), 0)), value(2))),
+        )),
+    )
+}
+
+fn main() {
+    let expr = let_expression();
+    let env: Vec<Assignment> = [("x", 3), ("y", -2), ("z", 11)]
+        .iter()
+        .map(|(name, n)| {
+            Assignment::new(
+                Symbol::new(name.to_string()),
+                AssignedValue::Integer(IntegerNumber::Value(*n)),
+            )
+        })
+        .chain([Assignment::new(
+            Symbol::new("p".to_string()),
+            AssignedValue::Boolean(BooleanValue::False),
+        )])
+        .collect();
+    let env = Environment::new(&env);
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..10_000 {
+        assert_eq!(expr.evaluate_ref(&env), Ok(IntegerNumber::Value(12)));
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::Relaxed), before);
+    println!("borrowed evaluation allocates nothing");
+}